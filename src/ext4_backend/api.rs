@@ -2,19 +2,23 @@ use alloc::string::String;
 use alloc::vec::Vec;
 
 use crate::ext4_backend::blockdev::*;
+use crate::ext4_backend::buffer_cache::CacheConfig;
 use crate::ext4_backend::dir::*;
 use crate::ext4_backend::disknode::*;
 use crate::ext4_backend::ext4::*;
 use crate::ext4_backend::file::*;
-use crate::ext4_backend::loopfile::*;
+use crate::ext4_backend::namei::*;
+use crate::ext4_backend::readahead::*;
 use crate::ext4_backend::error::*;
 use crate::ext4_backend::*;
-use crate::BLOCK_SIZE;
 /// 文件句柄
 pub struct OpenFile {
     pub path: String,
+    pub ino: u32,
     pub inode: Ext4Inode,
     pub offset: u64,
+    /// 顺序读预读状态
+    pub ra: Readahead,
 }
 
 ///挂载Ext4文件系统
@@ -26,6 +30,51 @@ pub fn fs_mount<B: BlockDevice>(dev: &mut Jbd2Dev<B>) -> BlockDevResult<Ext4File
 pub fn fs_umount<B: BlockDevice>(fs: Ext4FileSystem, dev: &mut Jbd2Dev<B>) -> BlockDevResult<()> {
     ext4::umount(fs, dev)
 }
+
+///按指定的缓存大小挂载Ext4文件系统
+pub fn fs_mount_with_config<B: BlockDevice>(
+    dev: &mut Jbd2Dev<B>,
+    cache: &CacheConfig,
+) -> BlockDevResult<Ext4FileSystem> {
+    ext4::mount_with_config(dev, cache)
+}
+
+///写回全部缓存并提交日志，返回前数据已经落盘
+pub fn sync_fs<B: BlockDevice>(dev: &mut Jbd2Dev<B>, fs: &mut Ext4FileSystem) -> BlockDevResult<()> {
+    fs.sync_fs(dev)
+}
+
+///持久化打开的文件
+pub fn sync_file<B: BlockDevice>(
+    dev: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    file: &OpenFile,
+) -> BlockDevResult<()> {
+    fs.sync_file(dev, file.ino)
+}
+
+///对不短于 min_len 个块的空闲区域下发discard，返回discard的块数
+pub fn fstrim<B: BlockDevice>(
+    dev: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    min_len: u32,
+) -> BlockDevResult<u64> {
+    fs.fstrim(dev, min_len)
+}
+
+///同步文件系统修改：启用 fast commit 时只写 fast commit 记录，否则完整提交日志
+pub fn fsync<B: BlockDevice>(dev: &mut Jbd2Dev<B>, fs: &mut Ext4FileSystem) -> BlockDevResult<()> {
+    fast_commit::fsync(fs, dev)
+}
+
+///打开或关闭 fast commit（需要启用日志）
+pub fn fs_set_fast_commit<B: BlockDevice>(
+    dev: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    enable: bool,
+) -> BlockDevResult<()> {
+    fast_commit::set_fast_commit(fs, dev, enable)
+}
 pub fn lseek(
     file:&mut OpenFile,
     location: u64
@@ -39,10 +88,7 @@ fn refresh_open_file_inode<B: BlockDevice>(
     fs: &mut Ext4FileSystem,
     file: &mut OpenFile,
 ) -> BlockDevResult<()> {
-    let Some((_ino, inode)) = get_file_inode(fs, dev, &file.path)? else {
-        return Err(BlockDevError::InvalidInput);
-    };
-    file.inode = inode;
+    file.inode = fs.get_inode_by_num(dev, file.ino)?;
     Ok(())
 }
 
@@ -54,13 +100,48 @@ pub fn open<B: BlockDevice>(
     create: bool,
 ) -> BlockDevResult<OpenFile> {
     let norm_path = split_paren_child_and_tranlatevalid(path);
+    let cwd = fs.cwd;
+    if !create || found(namei(fs, dev, cwd, &norm_path, true))?.is_some() {
+        return openat(dev, fs, cwd, &norm_path, false);
+    }
+    // 悬空的符号链接由 openat 创建它指向的文件
+    if found(namei(fs, dev, cwd, &norm_path, false))?.is_some() {
+        return openat(dev, fs, cwd, &norm_path, true);
+    }
 
-    if let Ok(Some(inode)) = get_file_inode(fs, dev, &norm_path) {
-        let real_inode = inode.1;
+    // 按路径打开时连同不存在的父目录一起创建
+    let (ino, inode) = match mkfile_with_ino(dev, fs, &norm_path, None, None) {
+        Some(v) => v,
+        None => return Err(BlockDevError::WriteError),
+    };
+
+    Ok(OpenFile {
+        path: norm_path,
+        ino,
+        inode,
+        offset: 0,
+        ra: Readahead::new(),
+    })
+}
+
+///相对目录 dir_ino 打开文件：可选自动创建，path 为绝对路径时忽略 dir_ino
+pub fn openat<B: BlockDevice>(
+    dev: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    dir_ino: u32,
+    path: &str,
+    create: bool,
+) -> BlockDevResult<OpenFile> {
+    let norm_path = split_paren_child_and_tranlatevalid(path);
+
+    // 路径最后一个分量是符号链接时打开它指向的文件
+    if let Some((ino, inode)) = found(namei(fs, dev, dir_ino, &norm_path, true))? {
         return Ok(OpenFile {
             path: norm_path,
-            inode: real_inode,
+            ino,
+            inode,
             offset: 0,
+            ra: Readahead::new(),
         });
     }
 
@@ -68,18 +149,185 @@ pub fn open<B: BlockDevice>(
         return Err(BlockDevError::WriteError);
     }
 
-    let inode = match mkfile(dev, fs, &norm_path, None,None) {
-        Some(ino) => ino,
+    let (parent_ino, parent_inode, name) = create_target(dev, fs, dir_ino, &norm_path)?;
+    let (ino, inode) = match mkfile_in(dev, fs, parent_ino, parent_inode, &name, None, None) {
+        Some(v) => v,
         None => return Err(BlockDevError::WriteError),
     };
 
     Ok(OpenFile {
         path: norm_path,
+        ino,
         inode,
         offset: 0,
+        ra: Readahead::new(),
     })
 }
 
+///找到 O_CREAT 要创建的目录项：最后一个分量是悬空的符号链接时创建它指向的文件，
+///返回 (父目录 inode 号, 父目录 inode, 文件名)，名字已经存在时返回 AlreadyExists
+fn create_target<B: BlockDevice>(
+    dev: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    dir_ino: u32,
+    path: &str,
+) -> BlockDevResult<(u32, Ext4Inode, String)> {
+    let mut base = dir_ino;
+    let mut path = String::from(path);
+    for _ in 0..MAX_SYMLINK_FOLLOWS {
+        let (parent_ino, parent_inode, name) = namei_parent(fs, dev, base, &path)?;
+        let Some((_, mut inode)) = found(namei(fs, dev, parent_ino, &name, false))? else {
+            return Ok((parent_ino, parent_inode, name));
+        };
+        if !inode.is_symlink() {
+            return Err(BlockDevError::AlreadyExists);
+        }
+        let target = read_symlink_target(dev, fs, &mut inode)?;
+        path = String::from_utf8(target).map_err(|_| BlockDevError::InvalidInput)?;
+        base = parent_ino;
+    }
+    Err(BlockDevError::SymlinkLoop)
+}
+
+///切换当前工作目录，之后的相对路径从这里开始解析
+pub fn chdir<B: BlockDevice>(
+    dev: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    path: &str,
+) -> BlockDevResult<()> {
+    let (ino, inode) = lookup_path(fs, dev, path, true)?;
+    if !inode.is_dir() {
+        return Err(BlockDevError::NotDirectory);
+    }
+    fs.cwd = ino;
+    Ok(())
+}
+
+/// unlinkat 标志：删除空目录而不是文件
+pub const AT_REMOVEDIR: u32 = 0x200;
+/// fstatat 标志：最后一个分量是符号链接时不跟随
+pub const AT_SYMLINK_NOFOLLOW: u32 = 0x100;
+/// linkat 标志：最后一个分量是符号链接时链接它指向的文件
+pub const AT_SYMLINK_FOLLOW: u32 = 0x400;
+
+///相对目录 dir_ino 创建目录，父目录必须已经存在，返回新目录的 inode 号
+pub fn mkdirat<B: BlockDevice>(
+    dev: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    dir_ino: u32,
+    path: &str,
+) -> BlockDevResult<u32> {
+    let (parent_ino, parent_inode, name) = namei_parent(fs, dev, dir_ino, path)?;
+    if name == "." || name == ".." {
+        return Err(BlockDevError::AlreadyExists);
+    }
+    if find_dir_entry(fs, dev, &parent_inode, name.as_bytes())?.is_some() {
+        return Err(BlockDevError::AlreadyExists);
+    }
+    casefold::check_name(&fs.superblock, &parent_inode, name.as_bytes())?;
+    match mkdir_in(dev, fs, parent_ino, parent_inode, &name) {
+        Some((ino, _)) => Ok(ino),
+        None => Err(BlockDevError::WriteError),
+    }
+}
+
+///相对目录 dir_ino 删除文件；flags 含 AT_REMOVEDIR 时删除空目录
+pub fn unlinkat<B: BlockDevice>(
+    dev: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    dir_ino: u32,
+    path: &str,
+    flags: u32,
+) -> BlockDevResult<()> {
+    if flags & !AT_REMOVEDIR != 0 {
+        return Err(BlockDevError::InvalidInput);
+    }
+    let (parent_ino, _, name) = namei_parent(fs, dev, dir_ino, path)?;
+    if flags & AT_REMOVEDIR != 0 {
+        rmdir_with_ino(dev, fs, parent_ino, &name)
+    } else {
+        unlink_with_ino(dev, fs, parent_ino, &name)
+    }
+}
+
+///renameat2：两端分别相对 old_dir_ino 和 new_dir_ino 解析，flags 为 RENAME_* 的组合
+pub fn renameat2<B: BlockDevice>(
+    dev: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    old_dir_ino: u32,
+    old_path: &str,
+    new_dir_ino: u32,
+    new_path: &str,
+    flags: u32,
+) -> BlockDevResult<()> {
+    let (old_parent, _, old_name) = namei_parent(fs, dev, old_dir_ino, old_path)?;
+    let (new_parent, _, new_name) = namei_parent(fs, dev, new_dir_ino, new_path)?;
+    rename_with_ino(dev, fs, old_parent, &old_name, new_parent, &new_name, flags)
+}
+
+///linkat：为 old_path 指向的 inode 新增硬链接 new_path；
+///默认不跟随 old_path 末尾的符号链接，flags 含 AT_SYMLINK_FOLLOW 时跟随
+pub fn linkat<B: BlockDevice>(
+    dev: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    old_dir_ino: u32,
+    old_path: &str,
+    new_dir_ino: u32,
+    new_path: &str,
+    flags: u32,
+) -> BlockDevResult<()> {
+    if flags & !AT_SYMLINK_FOLLOW != 0 {
+        return Err(BlockDevError::InvalidInput);
+    }
+    let follow = flags & AT_SYMLINK_FOLLOW != 0;
+    let (ino, _) = namei(fs, dev, old_dir_ino, old_path, follow)?;
+    let (new_parent, _, new_name) = namei_parent(fs, dev, new_dir_ino, new_path)?;
+    link_with_ino(dev, fs, ino, new_parent, &new_name)
+}
+
+///symlinkat：在 dir_ino 下创建指向 target 的符号链接 path，target 不要求存在
+pub fn symlinkat<B: BlockDevice>(
+    dev: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    target: &str,
+    dir_ino: u32,
+    path: &str,
+) -> BlockDevResult<u32> {
+    let (parent_ino, parent_inode, name) = namei_parent(fs, dev, dir_ino, path)?;
+    if name == "." || name == ".." {
+        return Err(BlockDevError::AlreadyExists);
+    }
+    if find_dir_entry(fs, dev, &parent_inode, name.as_bytes())?.is_some() {
+        return Err(BlockDevError::AlreadyExists);
+    }
+    symlink_in(dev, fs, parent_ino, parent_inode, &name, target)
+}
+
+///fstatat：相对目录 dir_ino 查询 inode；flags 含 AT_SYMLINK_NOFOLLOW 时返回符号链接本身
+pub fn fstatat<B: BlockDevice>(
+    dev: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    dir_ino: u32,
+    path: &str,
+    flags: u32,
+) -> BlockDevResult<(u32, Ext4Inode)> {
+    if flags & !AT_SYMLINK_NOFOLLOW != 0 {
+        return Err(BlockDevError::InvalidInput);
+    }
+    namei(fs, dev, dir_ino, path, flags & AT_SYMLINK_NOFOLLOW == 0)
+}
+
+///chattr：设置 path 指向的 inode 的标志（EXT4_*_FL），路径末尾的符号链接跟随到目标
+pub fn chattr<B: BlockDevice>(
+    dev: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    path: &str,
+    flags: u32,
+) -> BlockDevResult<()> {
+    let (ino, _) = lookup_path(fs, dev, path, true)?;
+    set_flags_with_ino(dev, fs, ino, flags)
+}
+
 ///写入文件:基于当前offset追加写入
 pub fn write_at<B: BlockDevice>(
     dev: &mut Jbd2Dev<B>,
@@ -98,7 +346,7 @@ pub fn write_at<B: BlockDevice>(
     }
 
     let off = file.offset;
-    write_file(dev, fs, &file.path, off, data)?;
+    write_file_with_ino(dev, fs, file.ino, off, data)?;
     file.offset = file.offset.saturating_add(data.len() as u64);
     refresh_open_file_inode(dev, fs, file)?;
     Ok(())
@@ -141,42 +389,59 @@ pub fn read_at<B: BlockDevice>(
         return Err(BlockDevError::Unsupported);
     }
 
-    let block_bytes = BLOCK_SIZE as u64;
     let start_off = file.offset;
     let end_off = start_off + to_read; // exclusive
 
-    let start_lbn = start_off / block_bytes;
-    let end_lbn = (end_off - 1) / block_bytes;
+    let inode = file.inode;
+    let mut out = read_mapped(dev, fs, file.ino, &inode, start_off, end_off, Some(&mut file.ra))?;
 
-    let extent_map = resolve_inode_block_allextend(fs, dev, &mut file.inode)?;
+    out.truncate(to_read as usize);
+    file.offset = file.offset.saturating_add(out.len() as u64);
+    Ok(out)
+}
 
-    let mut out = Vec::with_capacity(to_read as usize);
-    for lbn in start_lbn..=end_lbn {
-        let lbn_start = lbn * block_bytes;
-        let lbn_end = lbn_start + block_bytes;
+///从当前offset读满调用者的缓冲区，返回读到的字节数（到文件末尾时可能小于 buf.len()）
+///完整的块直接从设备读到 buf，不经过数据块缓存
+pub fn read_into<B: BlockDevice>(
+    dev: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    file: &mut OpenFile,
+    buf: &mut [u8],
+) -> BlockDevResult<usize> {
+    if buf.is_empty() {
+        return Ok(0);
+    }
 
-        let copy_start = core::cmp::max(start_off, lbn_start) - lbn_start;
-        let copy_end = core::cmp::min(end_off, lbn_end) - lbn_start;
-        let copy_len = copy_end.saturating_sub(copy_start);
-        if copy_len == 0 {
-            continue;
-        }
+    refresh_open_file_inode(dev, fs, file)?;
 
-        if let Some(&phys) = extent_map.get(&(lbn as u32)) {
-            let cached = fs.datablock_cache.get_or_load(dev, phys)?;
-            let data = &cached.data[..block_bytes as usize];
-            out.extend_from_slice(&data[copy_start as usize ..(copy_start + copy_len) as usize]);
-        } else {
-            // Hole: return zeros for the requested logical range.
-            out.extend(core::iter::repeat_n(0u8, copy_len as usize));
-        }
+    let file_size = file.inode.size();
+    if file.offset >= file_size {
+        return Ok(0);
+    }
 
-        if out.len() as u64 >= to_read {
-            break;
-        }
+    if !file.inode.have_extend_header_and_use_extend() {
+        return Err(BlockDevError::Unsupported);
     }
 
-    out.truncate(to_read as usize);
-    file.offset = file.offset.saturating_add(out.len() as u64);
-    Ok(out)
+    let n = read_mapped_into(dev, fs, file.ino, &file.inode, file.offset, buf, ReadMode::Direct)?;
+    file.offset = file.offset.saturating_add(n as u64);
+    Ok(n)
+}
+
+///把调用者的缓冲区写到当前offset，返回写入的字节数
+///完整覆盖的块直接写到设备，不经过数据块缓存
+pub fn write_from<B: BlockDevice>(
+    dev: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    file: &mut OpenFile,
+    buf: &[u8],
+) -> BlockDevResult<usize> {
+    if buf.is_empty() {
+        return Ok(0);
+    }
+
+    write_file_direct_with_ino(dev, fs, file.ino, file.offset, buf)?;
+    file.offset = file.offset.saturating_add(buf.len() as u64);
+    refresh_open_file_inode(dev, fs, file)?;
+    Ok(buf.len())
 }
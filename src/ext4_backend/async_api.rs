@@ -0,0 +1,723 @@
+//! 异步文件系统接口
+//!
+//! `AsyncExt4` 在 `AsyncBlockDevice` 上提供 `api.rs` 主要操作的异步版本：
+//! - 读：先试运行查出要读的块（包括预读窗口），缺的块合并成一次 `read_vectored` 取回，
+//!   再运行同步的读取；中途仍有未命中时取回后重试，不会阻塞执行器线程；
+//! - 写、flush、日志提交：同步代码的写入暂存在 `AsyncStaging` 里，操作结束后按 flush 屏障分段，
+//!   每段一次 `write_vectored` 提交；提交失败时没写完的段留在暂存里，下次提交时重试；
+//! - 修改元数据的操作不能中途重试，先试运行把要用到的块（inode 表、extent 树节点、
+//!   分配会用到的位图、父目录块，挂载时还有日志区）取回，再运行同步代码；超级块、块组描述符表和
+//!   日志超级块挂载后常驻。预取没覆盖到的未命中不在当前任务里轮询设备，操作返回 `WouldBlock`
+
+use alloc::vec::Vec;
+
+use crate::ext4_backend::api::*;
+use crate::ext4_backend::async_dev::*;
+use crate::ext4_backend::blockdev::*;
+use crate::ext4_backend::bitmap_cache::*;
+use crate::ext4_backend::buffer_cache::*;
+use crate::ext4_backend::config::*;
+use crate::ext4_backend::disknode::*;
+use crate::ext4_backend::endian::*;
+use crate::ext4_backend::error::*;
+use crate::ext4_backend::ext4::*;
+use crate::ext4_backend::extent_status::*;
+use crate::ext4_backend::extents_tree::*;
+use crate::ext4_backend::file::*;
+use crate::ext4_backend::jbd2::jbdstruct::*;
+use crate::ext4_backend::loopfile::*;
+use crate::ext4_backend::namei::*;
+use crate::ext4_backend::readahead::*;
+
+/// 建在异步设备上的 `Jbd2Dev`
+pub type AsyncJbd2Dev<A> = Jbd2Dev<AsyncStaging<A>>;
+
+/// 异步文件系统句柄
+pub struct AsyncExt4<A: AsyncBlockDevice> {
+    fs: Ext4FileSystem,
+    dev: AsyncJbd2Dev<A>,
+}
+
+impl<A: AsyncBlockDevice> AsyncExt4<A> {
+    /// 挂载，dev 由 `Jbd2Dev::initial_jbd2dev(0, AsyncStaging::new(async_dev), true)` 构造
+    pub async fn mount(mut dev: AsyncJbd2Dev<A>, cache: &CacheConfig) -> BlockDevResult<Self> {
+        // 超级块、块组描述符、根目录和日志超级块只读，试运行时取回；需要重放的日志区一次取回
+        let journal_runs = probe_dev(&mut dev, |dev| {
+            let mut fs = Ext4FileSystem::load_with_config(dev, cache)
+                .map_err(|_| BlockDevError::Corrupted)?;
+            mount_reads(&mut fs, dev)
+        })
+        .await?;
+        let ranges = dev_ranges(&mut dev, &journal_runs, |_| false);
+        fetch(dev.device_mut().inner_mut(), &ranges).await?;
+
+        let fs = mount_with_config(&mut dev, cache);
+        if let Ok(fs) = &fs {
+            pin_metadata(&mut dev, fs);
+        }
+        submit_staged(&mut dev).await?;
+        Ok(Self { fs: fs?, dev })
+    }
+
+    /// 卸载，返回异步设备
+    pub async fn umount(self) -> BlockDevResult<A> {
+        let Self { fs, mut dev } = self;
+        let ret = umount(fs, &mut dev);
+        submit_staged(&mut dev).await?;
+        ret?;
+        Ok(dev.into_inner().into_inner())
+    }
+
+    /// 独占访问同步的文件系统，执行其它 `api.rs` 操作，结束后提交暂存的写入
+    /// f 只运行一次；用到的块不在暂存里时返回 `WouldBlock`，缺的块已经取回，再次调用即可。
+    /// 能安全重试的操作用 `probe`，取回后自动重试
+    pub async fn with_fs<R>(
+        &mut self,
+        f: impl FnOnce(&mut Ext4FileSystem, &mut AsyncJbd2Dev<A>) -> R,
+    ) -> BlockDevResult<R> {
+        self.dev.device_mut().inner_mut().take_missing();
+        let ret = f(&mut self.fs, &mut self.dev);
+        submit_staged(&mut self.dev).await?;
+        let staging = self.dev.device_mut().inner_mut();
+        let missing = staging.take_missing();
+        if !missing.is_empty() {
+            fetch(staging, &missing).await?;
+            return Err(BlockDevError::WouldBlock);
+        }
+        Ok(ret)
+    }
+
+    /// 打开文件：可选自动创建
+    pub async fn open(&mut self, path: &str, create: bool) -> BlockDevResult<OpenFile> {
+        let existing = self.probe(|fs, dev| open(dev, fs, path, false)).await;
+        let ret = match existing {
+            Err(_) if create => {
+                // 新 inode 的位图和 inode 表块、父目录的目录块先取回
+                let runs = self
+                    .probe(|fs, dev| {
+                        fs.preload_next_inode(dev)?;
+                        fs.preload_block_bitmaps(dev, 1)?;
+                        let cwd = fs.cwd;
+                        let Some((parent, parent_inode, _)) = found(namei_parent(fs, dev, cwd, path))?
+                        else {
+                            return Ok(Vec::new());
+                        };
+                        if !parent_inode.have_extend_header_and_use_extend() {
+                            return Ok(Vec::new());
+                        }
+                        let blocks = parent_inode.size().div_ceil(BLOCK_SIZE as u64);
+                        mapped_runs(fs, dev, parent, &parent_inode, 0, blocks)
+                    })
+                    .await?;
+                self.fetch_blocks(&runs).await?;
+                open(&mut self.dev, &mut self.fs, path, true)
+            }
+            other => other,
+        };
+        submit_staged(&mut self.dev).await?;
+        ret
+    }
+
+    /// 从当前 offset 读满 buf（不超过文件末尾），返回读到的字节数
+    pub async fn read_at(&mut self, file: &mut OpenFile, buf: &mut [u8]) -> BlockDevResult<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let (ino, offset, len) = (file.ino, file.offset, buf.len() as u64);
+        let ra = file.ra.clone();
+
+        // 要读的块连同预读窗口一起取回
+        let runs = self
+            .probe(|fs, dev| {
+                let inode = fs.get_inode_by_num(dev, ino)?;
+                let end_off = offset.saturating_add(len).min(inode.size());
+                if offset >= end_off || !inode.have_extend_header_and_use_extend() {
+                    return Ok(Vec::new());
+                }
+                let block_bytes = BLOCK_SIZE as u64;
+                let prefetch =
+                    ra.clone()
+                        .advance(offset, end_off, inode.size().div_ceil(block_bytes));
+                let load_end = end_off.div_ceil(block_bytes).max(prefetch.end);
+                mapped_runs(fs, dev, ino, &inode, offset / block_bytes, load_end)
+            })
+            .await?;
+        self.fetch_blocks(&runs).await?;
+
+        let n = self
+            .probe(|fs, dev| {
+                file.inode = fs.get_inode_by_num(dev, ino)?;
+                if !file.inode.have_extend_header_and_use_extend() {
+                    return Err(BlockDevError::Unsupported);
+                }
+                // 重试时预读状态从头算
+                file.ra = ra.clone();
+                let inode = file.inode;
+                read_mapped_into(
+                    dev,
+                    fs,
+                    ino,
+                    &inode,
+                    offset,
+                    buf,
+                    ReadMode::Readahead(&mut file.ra),
+                )
+            })
+            .await;
+        submit_staged(&mut self.dev).await?;
+        let n = n?;
+        file.offset = offset.saturating_add(n as u64);
+        Ok(n)
+    }
+
+    /// 把 data 写到当前 offset，返回写入的字节数
+    pub async fn write_at(&mut self, file: &mut OpenFile, data: &[u8]) -> BlockDevResult<usize> {
+        if data.is_empty() {
+            return Ok(0);
+        }
+        let (ino, offset) = (file.ino, file.offset);
+        let end_off = offset.saturating_add(data.len() as u64);
+
+        // 部分覆盖的首尾块需要先读出旧内容；空洞要分配新块并插入 extent，
+        // 用到的块位图和 extent 树路径上的节点一并读进来
+        let runs = self
+            .probe(|fs, dev| {
+                let inode = fs.get_inode_by_num(dev, ino)?;
+                if !inode.have_extend_header_and_use_extend() {
+                    return Ok(Vec::new());
+                }
+                let block_bytes = BLOCK_SIZE as u64;
+                let (first, last) = (offset / block_bytes, (end_off - 1) / block_bytes);
+                let mut holes = Vec::new();
+                let mut lbn = first;
+                while lbn <= last {
+                    let (phys, len) = map_blocks(fs, dev, ino, &inode, lbn as u32)?;
+                    let n = (len as u64).min(last + 1 - lbn);
+                    if phys.is_none() {
+                        holes.push((lbn, n));
+                    }
+                    lbn += n;
+                }
+                if !holes.is_empty() {
+                    // 新块逐块分配，extent 树分裂时每层还要一个节点块
+                    let new_blocks: u64 = holes.iter().map(|&(_, n)| n).sum();
+                    fs.preload_block_bitmaps(dev, new_blocks.saturating_add(5).min(u32::MAX as u64) as u32)?;
+                    let mut tree_inode = inode;
+                    let tree = ExtentTree::new(&mut tree_inode);
+                    for &(lbn, _) in &holes {
+                        tree.extents_from(dev, lbn as u32, 1)?;
+                    }
+                }
+                let mut runs = Vec::new();
+                for (lbn, partial) in [
+                    (
+                        offset / block_bytes,
+                        offset % block_bytes != 0 || end_off - offset < block_bytes,
+                    ),
+                    ((end_off - 1) / block_bytes, end_off % block_bytes != 0),
+                ] {
+                    if partial {
+                        runs.extend(mapped_runs(fs, dev, ino, &inode, lbn, lbn + 1)?);
+                    }
+                }
+                Ok(runs)
+            })
+            .await?;
+        self.fetch_blocks(&runs).await?;
+
+        let ret = write_file_with_ino(&mut self.dev, &mut self.fs, ino, offset, data)
+            .and_then(|_| self.fs.get_inode_by_num(&mut self.dev, ino));
+        submit_staged(&mut self.dev).await?;
+        file.inode = ret?;
+        file.offset = end_off;
+        Ok(data.len())
+    }
+
+    /// 持久化单个文件
+    pub async fn sync_file(&mut self, file: &OpenFile) -> BlockDevResult<()> {
+        let ret = self.fs.sync_file(&mut self.dev, file.ino);
+        submit_staged(&mut self.dev).await?;
+        ret
+    }
+
+    /// 写回所有缓存并提交日志
+    pub async fn sync_fs(&mut self) -> BlockDevResult<()> {
+        let ret = self.fs.sync_fs(&mut self.dev);
+        submit_staged(&mut self.dev).await?;
+        ret
+    }
+
+    /// 运行可以安全重试的操作（只读或幂等），缺块时取回后重试；
+    /// op 可能因为未命中被中途打断，重试前自己恢复修改过的状态
+    pub async fn probe<R>(
+        &mut self,
+        mut op: impl FnMut(&mut Ext4FileSystem, &mut AsyncJbd2Dev<A>) -> BlockDevResult<R>,
+    ) -> BlockDevResult<R> {
+        let fs = &mut self.fs;
+        probe_dev(&mut self.dev, |dev| op(fs, dev)).await
+    }
+
+    /// 取回若干段文件系统块中还不在缓存里的部分
+    async fn fetch_blocks(&mut self, runs: &[(u64, u64)]) -> BlockDevResult<()> {
+        let cache = &self.fs.datablock_cache;
+        let ranges = dev_ranges(&mut self.dev, runs, |block| cache.get(block).is_some());
+        fetch(self.dev.device_mut().inner_mut(), &ranges).await
+    }
+}
+
+/// 运行操作，缺块时取回后重试
+async fn probe_dev<A: AsyncBlockDevice, R>(
+    dev: &mut AsyncJbd2Dev<A>,
+    mut op: impl FnMut(&mut AsyncJbd2Dev<A>) -> BlockDevResult<R>,
+) -> BlockDevResult<R> {
+    loop {
+        let ret = op(dev);
+        let staging = dev.device_mut().inner_mut();
+        // 有些路径会把读错误转成别的结果，以是否记下了未命中为准
+        let missing = staging.take_missing();
+        if missing.is_empty() {
+            return ret;
+        }
+        fetch(staging, &missing).await?;
+    }
+}
+
+/// 文件系统块段换算成设备扇区段，跳过 skip 为 true 以及已经在暂存里的块
+fn dev_ranges<A: AsyncBlockDevice>(
+    dev: &mut AsyncJbd2Dev<A>,
+    runs: &[(u64, u64)],
+    skip: impl Fn(u64) -> bool,
+) -> Vec<(u64, u32)> {
+    let sector_dev = dev.device_mut();
+    let start = sector_dev.start_sector();
+    let per_block = sector_dev.sectors_per_block();
+    let staging = sector_dev.inner_mut();
+    let mut ranges: Vec<(u64, u32)> = Vec::new();
+    for &(phys, n) in runs {
+        for block in phys..phys + n {
+            let sector = start + block * per_block as u64;
+            if skip(block) || (0..per_block as u64).all(|i| staging.is_resident(sector + i)) {
+                continue;
+            }
+            match ranges.last_mut() {
+                Some((s, c)) if *s + *c as u64 == sector => *c += per_block,
+                _ => ranges.push((sector, per_block)),
+            }
+        }
+    }
+    ranges
+}
+
+/// 挂载时要读的根目录、lost+found、0 号块组位图和日志超级块读进来，
+/// 返回日志里要扫描的部分：需要恢复时是整个日志区，否则只有 fast commit 区域
+fn mount_reads<A: AsyncBlockDevice>(
+    fs: &mut Ext4FileSystem,
+    dev: &mut AsyncJbd2Dev<A>,
+) -> BlockDevResult<Vec<(u64, u64)>> {
+    fs.get_root(dev)?;
+    found(lookup_path(fs, dev, "/lost+found", false))?;
+    if let Some(g0) = fs.group_descs.first() {
+        let (inode_bitmap, block_bitmap) = (g0.inode_bitmap(), g0.block_bitmap());
+        fs.bitmap_cache
+            .get_or_load(dev, CacheKey::new_inode(0), inode_bitmap)?;
+        fs.bitmap_cache
+            .get_or_load(dev, CacheKey::new_block(0), block_bitmap)?;
+    }
+
+    let sb = fs.superblock;
+    if !dev.is_use_journal() || !sb.has_journal() || sb.has_external_journal() {
+        return Ok(Vec::new());
+    }
+    let ino = JOURNAL_FILE_INODE as u32;
+    let mut j_inode = fs.get_inode_by_num(dev, ino)?;
+    if j_inode.i_mode == 0 || !j_inode.have_extend_header_and_use_extend() {
+        return Ok(Vec::new());
+    }
+    let Some(first) = resolve_inode_block(dev, &mut j_inode, 0)? else {
+        return Ok(Vec::new());
+    };
+    let j_sb = JournalSuperBllockS::from_disk_bytes(&fs.datablock_cache.get_or_load(dev, first)?.data);
+    let maxlen = j_sb.s_maxlen as u64;
+    let num_fc = j_sb.num_fc_blocks() as u64;
+    let start = if j_sb.s_start != 0 {
+        0
+    } else if num_fc >= 2 {
+        maxlen - num_fc + 1
+    } else {
+        maxlen
+    };
+    mapped_runs(fs, dev, ino, &j_inode, start, maxlen)
+}
+
+/// 超级块、块组描述符表和日志超级块每次提交都要读，挂载后常驻
+fn pin_metadata<A: AsyncBlockDevice>(dev: &mut AsyncJbd2Dev<A>, fs: &Ext4FileSystem) {
+    let desc_bytes = fs.group_count as u64 * fs.superblock.get_desc_size() as u64;
+    // 超级块在 0 号块，块组描述符表从下一块开始
+    let mut runs = alloc::vec![(0u64, 1 + desc_bytes.div_ceil(BLOCK_SIZE as u64))];
+    if let Some(j_sb) = fs.journal_sb_block_start
+        && !fs.superblock.has_external_journal()
+    {
+        runs.push((j_sb, 1));
+    }
+    let sector_dev = dev.device_mut();
+    let start = sector_dev.start_sector();
+    let per_block = sector_dev.sectors_per_block() as u64;
+    for (block, n) in runs {
+        sector_dev
+            .inner_mut()
+            .pin(start + block * per_block, (n * per_block) as u32);
+    }
+}
+
+/// 逻辑块 [start_lbn, end_lbn) 映射到的物理块段 (起始物理块, 块数)
+fn mapped_runs<A: AsyncBlockDevice>(
+    fs: &mut Ext4FileSystem,
+    dev: &mut AsyncJbd2Dev<A>,
+    ino: u32,
+    inode: &Ext4Inode,
+    start_lbn: u64,
+    end_lbn: u64,
+) -> BlockDevResult<Vec<(u64, u64)>> {
+    let mut runs = Vec::new();
+    let mut lbn = start_lbn;
+    while lbn < end_lbn {
+        let (phys, len) = map_blocks(fs, dev, ino, inode, lbn as u32)?;
+        let n = (len as u64).min(end_lbn - lbn);
+        if let Some(phys) = phys {
+            runs.push((phys, n));
+        }
+        lbn += n;
+    }
+    Ok(runs)
+}
+
+/// 用一次 read_vectored 取回若干段设备块
+async fn fetch<A: AsyncBlockDevice>(
+    staging: &mut AsyncStaging<A>,
+    ranges: &[(u64, u32)],
+) -> BlockDevResult<()> {
+    if ranges.is_empty() {
+        return Ok(());
+    }
+    let block_size = staging.block_size() as usize;
+    let mut bufs: Vec<Vec<u8>> = ranges
+        .iter()
+        .map(|&(_, count)| alloc::vec![0u8; count as usize * block_size])
+        .collect();
+    let mut reqs: Vec<(u64, &mut [u8])> = ranges
+        .iter()
+        .zip(bufs.iter_mut())
+        .map(|(&(start, _), buf)| (start, buf.as_mut_slice()))
+        .collect();
+    staging.inner_mut().read_vectored(&mut reqs).await?;
+    for (&(start, count), buf) in ranges.iter().zip(bufs.iter()) {
+        staging.insert_fetched(start, buf, count);
+    }
+    Ok(())
+}
+
+/// 按 flush 屏障分段提交暂存的写入，每段一次 write_vectored，段与段之间 flush
+async fn submit_staged<A: AsyncBlockDevice>(dev: &mut AsyncJbd2Dev<A>) -> BlockDevResult<()> {
+    let staging = dev.device_mut().inner_mut();
+    staging.clear_fetched();
+    let mut epochs = staging.take_epochs();
+    for i in 0..epochs.len() {
+        if let Err(e) = submit_epoch(staging, &epochs[i]).await {
+            // 这一段和之后的段放回暂存，下次提交时从这一段重新开始
+            staging.requeue_epochs(epochs.split_off(i));
+            return Err(e);
+        }
+    }
+    Ok(())
+}
+
+/// 提交一段：先下发 discard，再一次 write_vectored 写出，需要时 flush
+async fn submit_epoch<A: AsyncBlockDevice>(
+    staging: &mut AsyncStaging<A>,
+    epoch: &StagedEpoch,
+) -> BlockDevResult<()> {
+    let block_size = staging.block_size() as usize;
+    for &(start, count) in &epoch.discards {
+        staging.inner_mut().discard(start, count).await?;
+    }
+    // 块号连续的写入合并成一段
+    let mut runs: Vec<(u64, Vec<u8>)> = Vec::new();
+    for (&block, data) in &epoch.writes {
+        match runs.last_mut() {
+            Some((start, buf)) if *start + (buf.len() / block_size) as u64 == block => {
+                buf.extend_from_slice(data)
+            }
+            _ => runs.push((block, data.clone())),
+        }
+    }
+    if !runs.is_empty() {
+        let reqs: Vec<(u64, &[u8])> = runs
+            .iter()
+            .map(|(start, buf)| (*start, buf.as_slice()))
+            .collect();
+        staging.inner_mut().write_vectored(&reqs).await?;
+    }
+    if epoch.flush {
+        staging.inner_mut().flush().await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use core::future::Future;
+    use core::pin::Pin;
+    use core::task::{Context, Poll};
+    use std::cell::{Cell, RefCell};
+    use std::rc::Rc;
+    use crate::ext4_backend::dir::mkdir;
+    use crate::ext4_backend::memdev::MemDev;
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    enum Op {
+        /// 一次 read_vectored 的段数
+        ReadVectored(usize),
+        /// 一次 write_vectored 的块数
+        WriteVectored(usize),
+        /// 单独的一次读
+        Read,
+        Flush,
+    }
+
+    /// 第一次轮询时返回 Pending
+    struct YieldOnce(bool);
+
+    impl Future for YieldOnce {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            if self.0 {
+                return Poll::Ready(());
+            }
+            self.0 = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+
+    /// 每个请求都要让出一次的异步设备，记录收到的批量请求
+    struct YieldDev {
+        inner: SyncAdapter<MemDev>,
+        log: Rc<RefCell<Vec<Op>>>,
+        /// 置位时下一次 write_vectored 失败
+        fail_write: Rc<Cell<bool>>,
+    }
+
+    impl AsyncBlockDevice for YieldDev {
+        async fn write(&mut self, buffer: &[u8], block_id: u64, count: u32) -> BlockDevResult<()> {
+            YieldOnce(false).await;
+            self.inner.write(buffer, block_id, count).await
+        }
+
+        async fn read(
+            &mut self,
+            buffer: &mut [u8],
+            block_id: u64,
+            count: u32,
+        ) -> BlockDevResult<()> {
+            self.log.borrow_mut().push(Op::Read);
+            YieldOnce(false).await;
+            self.inner.read(buffer, block_id, count).await
+        }
+
+        fn total_blocks(&self) -> u64 {
+            self.inner.total_blocks()
+        }
+
+        async fn flush(&mut self) -> BlockDevResult<()> {
+            self.log.borrow_mut().push(Op::Flush);
+            Ok(())
+        }
+
+        async fn read_vectored(&mut self, reqs: &mut [(u64, &mut [u8])]) -> BlockDevResult<()> {
+            self.log.borrow_mut().push(Op::ReadVectored(reqs.len()));
+            YieldOnce(false).await;
+            for (block_id, buf) in reqs.iter_mut() {
+                let count = (buf.len() / BLOCK_SIZE) as u32;
+                self.inner.inner_mut().read(buf, *block_id, count)?;
+            }
+            Ok(())
+        }
+
+        async fn write_vectored(&mut self, reqs: &[(u64, &[u8])]) -> BlockDevResult<()> {
+            if self.fail_write.replace(false) {
+                return Err(BlockDevError::WriteError);
+            }
+            let blocks = reqs.iter().map(|(_, buf)| buf.len() / BLOCK_SIZE).sum();
+            self.log.borrow_mut().push(Op::WriteVectored(blocks));
+            YieldOnce(false).await;
+            for (block_id, buf) in reqs {
+                let count = (buf.len() / BLOCK_SIZE) as u32;
+                self.inner.write(buf, *block_id, count).await?;
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_async_read_write_batches_io() {
+        let mut dev = Jbd2Dev::initial_jbd2dev(
+            0,
+            MemDev::new(16 * 1024),
+            false,
+        );
+        mkfs(&mut dev).unwrap();
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let async_dev = YieldDev {
+            inner: SyncAdapter::new(dev.into_inner()),
+            log: log.clone(),
+            fail_write: Rc::new(Cell::new(false)),
+        };
+        let dev = Jbd2Dev::initial_jbd2dev(0, AsyncStaging::new(async_dev), true);
+
+        // 挂载、创建和写入要读的块都预先批量取回，不在当前任务里轮询设备
+        let data: Vec<u8> = (0..64 * BLOCK_SIZE + 100).map(|i| (i / 13) as u8).collect();
+        let mut fs = poll_inline(AsyncExt4::mount(dev, &CacheConfig::default())).unwrap();
+        let mut f = poll_inline(fs.open("/big", true)).unwrap();
+        assert_eq!(poll_inline(fs.write_at(&mut f, &data)).unwrap(), data.len());
+        assert!(!log.borrow().contains(&Op::Read));
+
+        // 日志提交按 flush 屏障分段批量写出
+        log.borrow_mut().clear();
+        poll_inline(fs.sync_fs()).unwrap();
+        let ops = log.borrow().clone();
+        assert!(ops.contains(&Op::Flush));
+        assert!(
+            ops.iter()
+                .any(|op| matches!(op, Op::WriteVectored(n) if *n > 1))
+        );
+        let device = poll_inline(fs.umount()).unwrap();
+
+        // 重新挂载后顺序读：缺的块连同预读窗口一次取回
+        let dev = Jbd2Dev::initial_jbd2dev(0, AsyncStaging::new(device), true);
+        let mut fs = poll_inline(AsyncExt4::mount(dev, &CacheConfig::default())).unwrap();
+        let mut f = poll_inline(fs.open("/big", false)).unwrap();
+        log.borrow_mut().clear();
+        let mut out = alloc::vec![0u8; data.len() + 10];
+        let mut read = 0;
+        while read < out.len() {
+            let end = (read + 8 * BLOCK_SIZE).min(out.len());
+            let n = poll_inline(fs.read_at(&mut f, &mut out[read..end])).unwrap();
+            if n == 0 {
+                break;
+            }
+            read += n;
+        }
+        assert_eq!(read, data.len());
+        assert_eq!(&out[..read], &data[..]);
+        let reads = log
+            .borrow()
+            .iter()
+            .filter(|op| matches!(op, Op::ReadVectored(_)))
+            .count();
+        assert!(reads <= 8, "{reads} batched reads for 65 blocks");
+        // 读取全程没有在当前任务里轮询设备
+        assert!(!log.borrow().contains(&Op::Read));
+
+        // 覆盖写一块中间的部分
+        f.offset = 10;
+        poll_inline(fs.write_at(&mut f, &[0xaa; 20])).unwrap();
+        let device = poll_inline(fs.umount()).unwrap();
+
+        // 冷缓存下新建文件、隔块写入让 extent 树分裂，卸载时提交日志，全程没有轮询设备
+        let dev = Jbd2Dev::initial_jbd2dev(0, AsyncStaging::new(device), true);
+        let mut fs = poll_inline(AsyncExt4::mount(dev, &CacheConfig::default())).unwrap();
+        let mut g = poll_inline(fs.open("/sparse", true)).unwrap();
+        for i in 0..12u64 {
+            g.offset = i * 2 * BLOCK_SIZE as u64 + 7;
+            poll_inline(fs.write_at(&mut g, &[i as u8 + 1; 100])).unwrap();
+        }
+        let device = poll_inline(fs.umount()).unwrap();
+        assert!(!log.borrow().contains(&Op::Read));
+
+        // 通过同步接口核对
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, device.inner.into_inner(), true);
+        let mut sync_fs = mount(&mut dev).unwrap();
+        let content = read_file(&mut dev, &mut sync_fs, "/big").unwrap().unwrap();
+        assert_eq!(&content[..10], &data[..10]);
+        assert!(content[10..30].iter().all(|&b| b == 0xaa));
+        assert_eq!(&content[30..], &data[30..]);
+        let sparse = read_file(&mut dev, &mut sync_fs, "/sparse").unwrap().unwrap();
+        for i in 0..12usize {
+            let off = i * 2 * BLOCK_SIZE + 7;
+            assert!(sparse[off..off + 100].iter().all(|&b| b == i as u8 + 1));
+        }
+        umount(sync_fs, &mut dev).unwrap();
+    }
+
+    #[test]
+    fn test_with_fs_miss_returns_would_block() {
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, MemDev::new(16 * 1024), false);
+        mkfs(&mut dev).unwrap();
+        let data: Vec<u8> = (0..3 * BLOCK_SIZE).map(|i| (i % 241) as u8).collect();
+        let mut fs = mount(&mut dev).unwrap();
+        mkdir(&mut dev, &mut fs, "/d");
+        mkfile(&mut dev, &mut fs, "/d/f", Some(&data), None).unwrap();
+        umount(fs, &mut dev).unwrap();
+
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let async_dev = YieldDev {
+            inner: SyncAdapter::new(dev.into_inner()),
+            log: log.clone(),
+            fail_write: Rc::new(Cell::new(false)),
+        };
+        let dev = Jbd2Dev::initial_jbd2dev(0, AsyncStaging::new(async_dev), true);
+        let mut fs = poll_inline(AsyncExt4::mount(dev, &CacheConfig::default())).unwrap();
+
+        // 冷缓存下闭包读到没取回的块：返回 WouldBlock，缺的块批量取回，不在当前任务里读设备
+        log.borrow_mut().clear();
+        let mut rounds = 0;
+        let content = loop {
+            match poll_inline(fs.with_fs(|fs, dev| read_file(dev, fs, "/d/f"))) {
+                Err(BlockDevError::WouldBlock) => rounds += 1,
+                other => break other.unwrap().unwrap().unwrap(),
+            }
+            assert!(rounds < 16);
+        };
+        assert!(rounds > 0);
+        assert_eq!(content, data);
+        assert!(!log.borrow().contains(&Op::Read));
+        assert!(log.borrow().contains(&Op::ReadVectored(1)));
+        poll_inline(fs.umount()).unwrap();
+    }
+
+    #[test]
+    fn test_async_submit_failure_keeps_staged_writes() {
+        let mut dev = Jbd2Dev::initial_jbd2dev(
+            0,
+            MemDev::new(16 * 1024),
+            false,
+        );
+        mkfs(&mut dev).unwrap();
+        let fail_write = Rc::new(Cell::new(false));
+        let async_dev = YieldDev {
+            inner: SyncAdapter::new(dev.into_inner()),
+            log: Rc::new(RefCell::new(Vec::new())),
+            fail_write: fail_write.clone(),
+        };
+        let dev = Jbd2Dev::initial_jbd2dev(0, AsyncStaging::new(async_dev), true);
+        let mut fs = poll_inline(AsyncExt4::mount(dev, &CacheConfig::default())).unwrap();
+        let mut f = poll_inline(fs.open("/f", true)).unwrap();
+        poll_inline(fs.write_at(&mut f, &[0x3c; 3 * BLOCK_SIZE])).unwrap();
+
+        // 日志提交的第一段写失败，剩下的段留在暂存里，下次提交按原来的顺序写出
+        fail_write.set(true);
+        assert_eq!(poll_inline(fs.sync_fs()), Err(BlockDevError::WriteError));
+        poll_inline(fs.sync_fs()).unwrap();
+        let device = poll_inline(fs.umount()).unwrap();
+
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, device.inner.into_inner(), true);
+        let mut sync_fs = mount(&mut dev).unwrap();
+        let content = read_file(&mut dev, &mut sync_fs, "/f").unwrap().unwrap();
+        assert_eq!(content, alloc::vec![0x3c; 3 * BLOCK_SIZE]);
+        umount(sync_fs, &mut dev).unwrap();
+    }
+}
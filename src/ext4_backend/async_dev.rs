@@ -0,0 +1,390 @@
+//! 异步块设备
+//!
+//! `AsyncBlockDevice` 是 `BlockDevice` 的异步版本，读写返回 Future，
+//! `read_vectored`/`write_vectored` 一次提交多段 I/O，驱动可以把它们放进同一个请求队列。
+//! `SyncAdapter` 把同步设备包装成异步设备。
+//!
+//! 文件系统本身仍然是同步实现，`AsyncStaging` 作为同步设备放在 `Jbd2Dev` 下面衔接两边：
+//! - 写入先按 flush 屏障分段暂存，操作结束后由 `AsyncExt4` 一段一段批量提交，段与段之间 flush；
+//! - 读取由已经取回的块满足；未命中返回 `WouldBlock` 并记下缺的范围，调用者异步取回后重试，
+//!   不在同步代码里轮询设备；
+//! - 超级块、块组描述符这类每次提交都要读的块可以常驻，不随每次操作结束丢掉
+
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::vec::Vec;
+use core::future::Future;
+use core::pin::pin;
+use core::task::{Context, Poll, Waker};
+
+use crate::ext4_backend::blockdev::*;
+use crate::ext4_backend::config::*;
+use crate::ext4_backend::error::*;
+
+/// 异步块设备接口，块号与块大小的含义与 `BlockDevice` 相同
+pub trait AsyncBlockDevice {
+    /// 写入 count 个块
+    fn write(
+        &mut self,
+        buffer: &[u8],
+        block_id: u64,
+        count: u32,
+    ) -> impl Future<Output = BlockDevResult<()>>;
+
+    /// 读取 count 个块
+    fn read(
+        &mut self,
+        buffer: &mut [u8],
+        block_id: u64,
+        count: u32,
+    ) -> impl Future<Output = BlockDevResult<()>>;
+
+    /// 获取块设备的总块数
+    fn total_blocks(&self) -> u64;
+
+    /// 获取块大小（字节）
+    fn block_size(&self) -> u32 {
+        BLOCK_SIZE_U32
+    }
+
+    /// 刷新缓存到磁盘
+    fn flush(&mut self) -> impl Future<Output = BlockDevResult<()>> {
+        async { Ok(()) }
+    }
+
+    /// 一次读取多段，每段为 (起始块号, 缓冲区)，缓冲区长度是块大小的整数倍
+    /// 默认实现逐段读取，能同时处理多个请求的驱动应当覆盖
+    fn read_vectored(
+        &mut self,
+        reqs: &mut [(u64, &mut [u8])],
+    ) -> impl Future<Output = BlockDevResult<()>> {
+        async move {
+            let block_size = self.block_size() as usize;
+            for (block_id, buf) in reqs.iter_mut() {
+                let count = (buf.len() / block_size) as u32;
+                self.read(buf, *block_id, count).await?;
+            }
+            Ok(())
+        }
+    }
+
+    /// 一次写入多段，默认实现逐段写入
+    fn write_vectored(
+        &mut self,
+        reqs: &[(u64, &[u8])],
+    ) -> impl Future<Output = BlockDevResult<()>> {
+        async move {
+            let block_size = self.block_size() as usize;
+            for (block_id, buf) in reqs {
+                let count = (buf.len() / block_size) as u32;
+                self.write(buf, *block_id, count).await?;
+            }
+            Ok(())
+        }
+    }
+
+    /// 通知设备这些块不再保存有效数据，默认实现为空操作
+    fn discard(&mut self, _block_id: u64, _count: u32) -> impl Future<Output = BlockDevResult<()>> {
+        async { Ok(()) }
+    }
+
+    /// 检查设备是否只读
+    fn is_readonly(&self) -> bool {
+        false
+    }
+}
+
+/// 把同步块设备包装成异步设备，每次调用立即完成
+pub struct SyncAdapter<D: BlockDevice> {
+    dev: D,
+}
+
+impl<D: BlockDevice> SyncAdapter<D> {
+    pub fn new(dev: D) -> Self {
+        Self { dev }
+    }
+
+    pub fn inner_mut(&mut self) -> &mut D {
+        &mut self.dev
+    }
+
+    pub fn into_inner(self) -> D {
+        self.dev
+    }
+}
+
+impl<D: BlockDevice> AsyncBlockDevice for SyncAdapter<D> {
+    fn write(
+        &mut self,
+        buffer: &[u8],
+        block_id: u64,
+        count: u32,
+    ) -> impl Future<Output = BlockDevResult<()>> {
+        core::future::ready(self.dev.write(buffer, block_id, count))
+    }
+
+    fn read(
+        &mut self,
+        buffer: &mut [u8],
+        block_id: u64,
+        count: u32,
+    ) -> impl Future<Output = BlockDevResult<()>> {
+        core::future::ready(self.dev.read(buffer, block_id, count))
+    }
+
+    fn total_blocks(&self) -> u64 {
+        self.dev.total_blocks()
+    }
+
+    fn block_size(&self) -> u32 {
+        self.dev.block_size()
+    }
+
+    fn flush(&mut self) -> impl Future<Output = BlockDevResult<()>> {
+        core::future::ready(self.dev.flush())
+    }
+
+    fn read_vectored(
+        &mut self,
+        reqs: &mut [(u64, &mut [u8])],
+    ) -> impl Future<Output = BlockDevResult<()>> {
+        core::future::ready(self.dev.read_vectored(reqs))
+    }
+
+    fn write_vectored(&mut self, reqs: &[(u64, &[u8])]) -> impl Future<Output = BlockDevResult<()>> {
+        core::future::ready(self.dev.write_vectored(reqs))
+    }
+
+    fn discard(&mut self, block_id: u64, count: u32) -> impl Future<Output = BlockDevResult<()>> {
+        core::future::ready(self.dev.discard(block_id, count))
+    }
+
+    fn is_readonly(&self) -> bool {
+        self.dev.is_readonly()
+    }
+}
+
+/// 在当前任务里轮询 Future 直到完成
+pub fn poll_inline<F: Future>(fut: F) -> F::Output {
+    let mut fut = pin!(fut);
+    let mut cx = Context::from_waker(Waker::noop());
+    loop {
+        if let Poll::Ready(out) = fut.as_mut().poll(&mut cx) {
+            return out;
+        }
+        core::hint::spin_loop();
+    }
+}
+
+/// 两次 flush 之间的写入
+#[derive(Default)]
+pub struct StagedEpoch {
+    /// 块号 -> 最新内容
+    pub writes: BTreeMap<u64, Vec<u8>>,
+    /// 这一段提交之后是否需要 flush
+    pub flush: bool,
+    /// 在这一段的写入之前下发的 discard (起始块号, 块数)
+    pub discards: Vec<(u64, u32)>,
+}
+
+/// 放在 `Jbd2Dev` 下面的同步设备，衔接同步的文件系统和异步设备
+pub struct AsyncStaging<A: AsyncBlockDevice> {
+    dev: A,
+    /// 已经从设备取回的块（本次操作内有效，常驻的块除外）
+    fetched: BTreeMap<u64, Vec<u8>>,
+    /// 常驻的块号，写入时同步更新取回的内容
+    pinned: BTreeSet<u64>,
+    /// 尚未提交的写入，最后一段是当前段
+    epochs: Vec<StagedEpoch>,
+    /// 未命中的 (起始块号, 块数)
+    missing: Vec<(u64, u32)>,
+}
+
+impl<A: AsyncBlockDevice> AsyncStaging<A> {
+    pub fn new(dev: A) -> Self {
+        Self {
+            dev,
+            fetched: BTreeMap::new(),
+            pinned: BTreeSet::new(),
+            epochs: alloc::vec![StagedEpoch::default()],
+            missing: Vec::new(),
+        }
+    }
+
+    pub fn inner_mut(&mut self) -> &mut A {
+        &mut self.dev
+    }
+
+    /// 取回异步设备，暂存的写入被丢弃
+    pub fn into_inner(self) -> A {
+        self.dev
+    }
+
+    /// 取走记下的未命中范围，按块号排序并合并重叠部分
+    pub fn take_missing(&mut self) -> Vec<(u64, u32)> {
+        let mut missing = core::mem::take(&mut self.missing);
+        missing.sort_unstable();
+        let mut merged: Vec<(u64, u32)> = Vec::with_capacity(missing.len());
+        for (start, count) in missing {
+            match merged.last_mut() {
+                Some((s, c)) if start <= *s + *c as u64 => {
+                    let end = (*s + *c as u64).max(start + count as u64);
+                    *c = (end - *s) as u32;
+                }
+                _ => merged.push((start, count)),
+            }
+        }
+        merged
+    }
+
+    /// 块号是否已经有可用的内容（暂存的写入或已取回）
+    pub fn is_resident(&self, block_id: u64) -> bool {
+        self.staged(block_id).is_some()
+    }
+
+    /// 记录从设备取回的 count 个块，暂存里有更新内容的块不覆盖
+    pub fn insert_fetched(&mut self, block_id: u64, data: &[u8], count: u32) {
+        let block_size = self.dev.block_size() as usize;
+        for i in 0..count as usize {
+            let id = block_id + i as u64;
+            if self.epochs.iter().any(|e| e.writes.contains_key(&id)) {
+                continue;
+            }
+            let chunk = &data[i * block_size..(i + 1) * block_size];
+            self.fetched.insert(id, chunk.to_vec());
+        }
+    }
+
+    /// 丢掉本次操作取回的块，它们已经进入文件系统自己的缓存；常驻的块保留
+    pub fn clear_fetched(&mut self) {
+        let pinned = &self.pinned;
+        self.fetched.retain(|id, _| pinned.contains(id));
+    }
+
+    /// 让 [block_id, block_id+count) 常驻，已经暂存或取回的内容留在 fetched 里
+    pub fn pin(&mut self, block_id: u64, count: u32) {
+        for id in block_id..block_id + count as u64 {
+            if let Some(data) = self.staged(id).cloned() {
+                self.fetched.insert(id, data);
+            }
+            self.pinned.insert(id);
+        }
+    }
+
+    /// 取走所有暂存的写入，按提交顺序排列
+    pub fn take_epochs(&mut self) -> Vec<StagedEpoch> {
+        let mut epochs = core::mem::replace(&mut self.epochs, alloc::vec![StagedEpoch::default()]);
+        epochs.retain(|e| !e.writes.is_empty() || !e.discards.is_empty() || e.flush);
+        epochs
+    }
+
+    /// 提交失败时把还没提交完的段放回队首，排在之后暂存的写入前面
+    pub fn requeue_epochs(&mut self, mut epochs: Vec<StagedEpoch>) {
+        epochs.append(&mut self.epochs);
+        self.epochs = epochs;
+    }
+
+    fn staged(&self, block_id: u64) -> Option<&Vec<u8>> {
+        self.epochs
+            .iter()
+            .rev()
+            .find_map(|e| e.writes.get(&block_id))
+            .or_else(|| self.fetched.get(&block_id))
+    }
+}
+
+impl<A: AsyncBlockDevice> BlockDevice for AsyncStaging<A> {
+    fn write(&mut self, buffer: &[u8], block_id: u64, count: u32) -> BlockDevResult<()> {
+        if self.dev.is_readonly() {
+            return Err(BlockDevError::ReadOnly);
+        }
+        let block_size = self.dev.block_size() as usize;
+        let required = count as usize * block_size;
+        if buffer.len() < required {
+            return Err(BlockDevError::BufferTooSmall {
+                provided: buffer.len(),
+                required,
+            });
+        }
+        let Some(epoch) = self.epochs.last_mut() else {
+            return Err(BlockDevError::Unknown);
+        };
+        for i in 0..count as usize {
+            let id = block_id + i as u64;
+            let chunk = &buffer[i * block_size..(i + 1) * block_size];
+            epoch.writes.insert(id, chunk.to_vec());
+            if self.pinned.contains(&id) {
+                self.fetched.insert(id, chunk.to_vec());
+            } else {
+                self.fetched.remove(&id);
+            }
+        }
+        Ok(())
+    }
+
+    fn read(&mut self, buffer: &mut [u8], block_id: u64, count: u32) -> BlockDevResult<()> {
+        let block_size = self.dev.block_size() as usize;
+        let required = count as usize * block_size;
+        if buffer.len() < required {
+            return Err(BlockDevError::BufferTooSmall {
+                provided: buffer.len(),
+                required,
+            });
+        }
+        let all_resident = (0..count as u64).all(|i| self.is_resident(block_id + i));
+        if !all_resident {
+            self.missing.push((block_id, count));
+            return Err(BlockDevError::WouldBlock);
+        }
+        // 暂存里的内容比设备上的新
+        for i in 0..count as usize {
+            if let Some(data) = self.staged(block_id + i as u64) {
+                buffer[i * block_size..(i + 1) * block_size].copy_from_slice(data);
+            }
+        }
+        Ok(())
+    }
+
+    fn open(&mut self) -> BlockDevResult<()> {
+        Ok(())
+    }
+
+    fn close(&mut self) -> BlockDevResult<()> {
+        Ok(())
+    }
+
+    fn total_blocks(&self) -> u64 {
+        self.dev.total_blocks()
+    }
+
+    fn block_size(&self) -> u32 {
+        self.dev.block_size()
+    }
+
+    /// 结束当前段，之前的写入提交完之后才能提交后面的
+    fn flush(&mut self) -> BlockDevResult<()> {
+        if let Some(epoch) = self.epochs.last_mut() {
+            epoch.flush = true;
+        }
+        self.epochs.push(StagedEpoch::default());
+        Ok(())
+    }
+
+    /// discard 要排在之前的写入之后，当前段已有写入时另起一段
+    fn discard(&mut self, block_id: u64, count: u32) -> BlockDevResult<()> {
+        if self.epochs.last().is_none_or(|e| !e.writes.is_empty()) {
+            self.epochs.push(StagedEpoch::default());
+        }
+        for i in 0..count as u64 {
+            self.fetched.remove(&(block_id + i));
+        }
+        let Some(epoch) = self.epochs.last_mut() else {
+            return Err(BlockDevError::Unknown);
+        };
+        epoch.discards.push((block_id, count));
+        Ok(())
+    }
+
+    fn is_readonly(&self) -> bool {
+        self.dev.is_readonly()
+    }
+}
@@ -1,10 +1,11 @@
 //! 位图缓存模块
 
 use crate::ext4_backend::blockdev::*;
-use alloc::collections::BTreeMap;
+use crate::ext4_backend::buffer_cache::*;
+use alloc::collections::BTreeSet;
 use alloc::vec::Vec;
 use crate::ext4_backend::error::*;
-use crate::BITMAP_CACHE_MAX;
+use crate::{BITMAP_CACHE_MAX, BLOCK_SIZE};
 use log::debug;
 
 /// 位图类型
@@ -48,8 +49,6 @@ pub struct CachedBitmap {
     pub dirty: bool,
     /// 磁盘块号
     pub block_num: u64,
-    /// 最后访问时间戳（用于LRU）
-    pub last_access: u64,
 }
 
 impl CachedBitmap {
@@ -58,7 +57,6 @@ impl CachedBitmap {
             data,
             dirty: false,
             block_num,
-            last_access: 0,
         }
     }
 
@@ -71,23 +69,28 @@ impl CachedBitmap {
 /// 位图缓存管理器
 pub struct BitmapCache {
     /// 缓存的位图
-    cache: BTreeMap<CacheKey, CachedBitmap>,
+    cache: LruCache<CacheKey, CachedBitmap>,
     /// 最大缓存条目数（LRU淘汰）
     max_entries: usize,
-    /// 访问计数器（用于LRU）
-    access_counter: u64,
+    /// start_tracking 之后被改脏的位图
+    tracked: Option<BTreeSet<CacheKey>>,
 }
 
 impl BitmapCache {
     /// 创建位图缓存
     pub fn new(max_entries: usize) -> Self {
         Self {
-            cache: BTreeMap::new(),
-            max_entries,
-            access_counter: 0,
+            cache: LruCache::new(),
+            max_entries: max_entries.max(1),
+            tracked: None,
         }
     }
 
+    /// 按字节预算创建位图缓存，每个位图占一个块
+    pub fn with_budget(budget_bytes: usize) -> Self {
+        Self::new(entries_for_budget(budget_bytes, BLOCK_SIZE))
+    }
+
     /// 创建默认配置的缓存
     pub fn default() -> Self {
         Self::new(BITMAP_CACHE_MAX)
@@ -103,25 +106,7 @@ impl BitmapCache {
         key: CacheKey,
         block_num: u64,
     ) -> BlockDevResult<&CachedBitmap> {
-        if !self.cache.contains_key(&key) {
-            if self.cache.len() >= self.max_entries {
-                self.evict_lru(block_dev)?;
-            }
-
-            block_dev.read_block(block_num as u32)?;
-            let buffer = block_dev.buffer();
-            let data = buffer.to_vec();
-
-            let bitmap = CachedBitmap::new(data, block_num);
-            self.cache.insert(key, bitmap);
-        }
-
-        self.access_counter += 1;
-        if let Some(bitmap) = self.cache.get_mut(&key) {
-            bitmap.last_access = self.access_counter;
-        }
-
-        self.cache.get(&key).ok_or(BlockDevError::Corrupted)
+        self.get_or_load_mut(block_dev, key, block_num).map(|bitmap| &*bitmap)
     }
 
     /// 内部使用：获取可变引用（如果不存在则从磁盘加载）
@@ -132,11 +117,14 @@ impl BitmapCache {
         block_num: u64,
     ) -> BlockDevResult<&mut CachedBitmap> {
         if !self.cache.contains_key(&key) {
-            if self.cache.len() >= self.max_entries {
-                self.evict_lru(block_dev)?;
+            while self.cache.len() >= self.max_entries {
+                let Some(lru) = self.cache.lru_key() else {
+                    break;
+                };
+                self.evict(block_dev, &lru)?;
             }
 
-            block_dev.read_block(block_num as u32)?;
+            block_dev.read_block(block_num)?;
             let buffer = block_dev.buffer();
             let data = buffer.to_vec();
 
@@ -144,13 +132,7 @@ impl BitmapCache {
             self.cache.insert(key, bitmap);
         }
 
-        self.access_counter += 1;
-        if let Some(bitmap) = self.cache.get_mut(&key) {
-            bitmap.last_access = self.access_counter;
-            Ok(bitmap)
-        } else {
-            Err(BlockDevError::Corrupted)
-        }
+        self.cache.get_mut(&key).ok_or(BlockDevError::Corrupted)
     }
 
     /// 获取已缓存的位图（不加载）
@@ -165,11 +147,24 @@ impl BitmapCache {
 
     /// 标记位图为脏
     pub fn mark_dirty(&mut self, key: &CacheKey) {
-        if let Some(bitmap) = self.cache.get_mut(key) {
+        if let Some(bitmap) = self.cache.peek_mut(key) {
             bitmap.mark_dirty();
+            if let Some(tracked) = self.tracked.as_mut() {
+                tracked.insert(*key);
+            }
         }
     }
 
+    /// 开始记录之后被改脏的位图
+    pub fn start_tracking(&mut self) {
+        self.tracked = Some(BTreeSet::new());
+    }
+
+    /// 停止记录，返回 start_tracking 之后被改脏的位图
+    pub fn take_tracked(&mut self) -> BTreeSet<CacheKey> {
+        self.tracked.take().unwrap_or_default()
+    }
+
     /// 使用闭包修改指定位图，并自动标记为脏
     pub fn modify<B, F>(
         &mut self,
@@ -189,7 +184,7 @@ impl BitmapCache {
         );
 
         f(&mut bitmap.data);
-        bitmap.mark_dirty();
+        self.mark_dirty(&key);
 
         debug!(
             "BitmapCache::modify: key=({}:{:?}) block_num={} marked_dirty=true (bitmap updated in cache, writeback deferred)",
@@ -198,41 +193,24 @@ impl BitmapCache {
         Ok(())
     }
 
-    /// LRU淘汰：找到最久未访问的并写回（如果脏）
-    fn evict_lru<B: BlockDevice>(&mut self, block_dev: &mut Jbd2Dev<B>) -> BlockDevResult<()> {
-        let lru_key = self
-            .cache
-            .iter()
-            .min_by_key(|(_, bitmap)| bitmap.last_access)
-            .map(|(key, _)| *key);
-
-        if let Some(key) = lru_key {
-            self.evict(block_dev, &key)?;
-        }
-
-        Ok(())
-    }
-
     /// 淘汰指定的位图
     pub fn evict<B: BlockDevice>(
         &mut self,
         block_dev: &mut Jbd2Dev<B>,
         key: &CacheKey,
     ) -> BlockDevResult<()> {
-        if let Some(bitmap) = self.cache.remove(key)
-            && bitmap.dirty {
-                Self::write_bitmap_static(block_dev, bitmap.block_num, &bitmap.data)?;
-            }
+        self.flush(block_dev, key)?;
+        self.cache.remove(key);
         Ok(())
     }
 
     /// 刷新所有脏位图到磁盘
     pub fn flush_all<B: BlockDevice>(&mut self, block_dev: &mut Jbd2Dev<B>) -> BlockDevResult<()> {
-        let mut dirty_bitmaps: Vec<(CacheKey, u64, Vec<u8>)> = self
+        let mut dirty_bitmaps: Vec<(u64, CacheKey)> = self
             .cache
             .iter()
             .filter(|(_, bitmap)| bitmap.dirty)
-            .map(|(key, bitmap)| (*key, bitmap.block_num, bitmap.data.clone()))
+            .map(|(key, bitmap)| (bitmap.block_num, *key))
             .collect();
 
         if dirty_bitmaps.is_empty() {
@@ -240,24 +218,41 @@ impl BitmapCache {
         }
 
         // 按物理块号排序，尽量让写入顺序更顺滑
-        dirty_bitmaps.sort_by_key(|(_, block_num, _)| *block_num);
+        dirty_bitmaps.sort_unstable();
 
         debug!(
             "BitmapCache::flush_all: dirty_entries={} (will write all dirty bitmaps to disk)",
             dirty_bitmaps.len()
         );
 
-        for (key, block_num, data) in dirty_bitmaps {
+        // 位图可能不满一块，先读出整块再覆盖，所有位图一次 write_vectored 写出
+        let mut blocks: Vec<(u64, Vec<u8>)> = Vec::with_capacity(dirty_bitmaps.len());
+        for (block_num, key) in &dirty_bitmaps {
+            let Some(bitmap) = self.cache.get(key) else {
+                continue;
+            };
             debug!(
                 "BitmapCache::flush_all: writing bitmap key=({}:{:?}) block_num={} to disk",
                 key.group_id, key.bitmap_type, block_num
             );
-
-            Self::write_bitmap_static(block_dev, block_num, &data)?;
+            let mut block = alloc::vec![0u8; BLOCK_SIZE];
+            if bitmap.data.len() < BLOCK_SIZE {
+                block_dev.read_block(*block_num)?;
+                block.copy_from_slice(&block_dev.buffer()[..BLOCK_SIZE]);
+            }
+            block[..bitmap.data.len()].copy_from_slice(&bitmap.data);
+            blocks.push((*block_num, block));
         }
+        let reqs: Vec<(u64, &[u8])> = blocks
+            .iter()
+            .map(|(block_num, block)| (*block_num, block.as_slice()))
+            .collect();
+        block_dev.write_vectored(&reqs, true)?;
 
-        for bitmap in self.cache.values_mut() {
-            bitmap.dirty = false;
+        for (_, key) in dirty_bitmaps {
+            if let Some(bitmap) = self.cache.peek_mut(&key) {
+                bitmap.dirty = false;
+            }
         }
 
         Ok(())
@@ -269,19 +264,14 @@ impl BitmapCache {
         block_dev: &mut Jbd2Dev<B>,
         key: &CacheKey,
     ) -> BlockDevResult<()> {
-        if let Some(bitmap) = self.cache.get(key)
-            && bitmap.dirty {
-                let block_num = bitmap.block_num;
-                let data = bitmap.data.clone();
-
-                // 写回磁盘
-                Self::write_bitmap_static(block_dev, block_num, &data)?;
-
-                // 清除脏标记
-                if let Some(bitmap) = self.cache.get_mut(key) {
-                    bitmap.dirty = false;
-                }
-            }
+        if let Some(bitmap) = self.cache.peek_mut(key)
+            && bitmap.dirty
+        {
+            // 写回磁盘
+            Self::write_bitmap_static(block_dev, bitmap.block_num, &bitmap.data)?;
+            // 清除脏标记
+            bitmap.dirty = false;
+        }
         Ok(())
     }
 
@@ -291,10 +281,10 @@ impl BitmapCache {
         block_num: u64,
         data: &[u8],
     ) -> BlockDevResult<()> {
-        block_dev.read_block(block_num as u32)?;
+        block_dev.read_block(block_num)?;
         let buffer = block_dev.buffer_mut();
         buffer[..data.len()].copy_from_slice(data);
-        block_dev.write_block(block_num as u32, true)?;
+        block_dev.write_block(block_num, true)?;
         Ok(())
     }
 
@@ -1,10 +1,11 @@
+use alloc::boxed::Box;
 use alloc::vec::Vec;
 use log::{error, trace, warn};
 
 use crate::ext4_backend::config::*;
 use crate::ext4_backend::jbd2::jbdstruct::*;
+use crate::ext4_backend::jbd2::logdump::*;
 use crate::ext4_backend::error::*;
-use crate::ext4_backend::config::JBD2_BUFFER_MAX;
 
 
 ///可以调用block write的函数标记 有序管理写,jbd2需要
@@ -16,13 +17,13 @@ pub trait BlockDevice {
     /// * `buffer` - 要写入的数据
     /// * `block_id` - 起始块号
     /// * `count` - 块数量
-    fn write(&mut self, buffer: &[u8], block_id: u32, count: u32) -> BlockDevResult<()>;
+    fn write(&mut self, buffer: &[u8], block_id: u64, count: u32) -> BlockDevResult<()>;
 
     /// 从块设备读取数据
     /// * `buffer` - 读取数据的目标缓冲区
     /// * `block_id` - 起始块号
     /// * `count` - 块数量
-    fn read(&mut self, buffer: &mut [u8], block_id: u32, count: u32) -> BlockDevResult<()>;
+    fn read(&mut self, buffer: &mut [u8], block_id: u64, count: u32) -> BlockDevResult<()>;
 
     /// 打开块设备
     fn open(&mut self) -> BlockDevResult<()>;
@@ -33,9 +34,10 @@ pub trait BlockDevice {
     /// 获取块设备的总块数
     fn total_blocks(&self) -> u64;
 
-    /// 获取块大小（字节）
+    /// 获取块大小（字节），`block_id` 与 `total_blocks` 都以它为单位
+    /// 默认与文件系统块大小相同；只能按扇区访问的设备返回扇区大小，由 `SectorDev` 负责换算
     fn block_size(&self) -> u32 {
-        512 // 默认512字节
+        BLOCK_SIZE_U32
     }
 
     /// 刷新缓存到磁盘
@@ -43,6 +45,40 @@ pub trait BlockDevice {
         Ok(()) // 默认实现为空操作
     }
 
+    /// 强制落盘写（FUA），返回时数据必须已经持久化
+    /// 默认实现为写入后整体刷新，支持原生FUA的设备可以覆盖
+    fn write_fua(&mut self, buffer: &[u8], block_id: u64, count: u32) -> BlockDevResult<()> {
+        self.write(buffer, block_id, count)?;
+        self.flush()
+    }
+
+    /// 一次读取多段，每段为 (起始块号, 缓冲区)，缓冲区长度是块大小的整数倍
+    /// 默认实现逐段调用 read，能把多段合成一个请求提交的驱动可以覆盖
+    fn read_vectored(&mut self, reqs: &mut [(u64, &mut [u8])]) -> BlockDevResult<()> {
+        let block_size = self.block_size() as usize;
+        for (block_id, buf) in reqs.iter_mut() {
+            let count = (buf.len() / block_size) as u32;
+            self.read(buf, *block_id, count)?;
+        }
+        Ok(())
+    }
+
+    /// 一次写入多段，段与段之间没有先后顺序要求；默认实现逐段调用 write
+    fn write_vectored(&mut self, reqs: &[(u64, &[u8])]) -> BlockDevResult<()> {
+        let block_size = self.block_size() as usize;
+        for (block_id, buf) in reqs {
+            let count = (buf.len() / block_size) as u32;
+            self.write(buf, *block_id, count)?;
+        }
+        Ok(())
+    }
+
+    /// 通知设备 [block_id, block_id+count) 不再保存有效数据（discard/TRIM）
+    /// 默认实现为空操作，SSD/eMMC 等闪存设备可以覆盖以便回收空间
+    fn discard(&mut self, _block_id: u64, _count: u32) -> BlockDevResult<()> {
+        Ok(())
+    }
+
     /// 检查设备是否已打开
     fn is_open(&self) -> bool {
         true // 默认认为已打开
@@ -94,13 +130,180 @@ impl Default for BlockBuffer {
     }
 }
 
+/// 扇区适配层：把文件系统块号换算成设备扇区号，并加上分区起始偏移
+/// 设备扇区必须是不超过 BLOCK_SIZE 的 2 的幂，否则所有读写返回 InvalidBlockSize
+pub struct SectorDev<B: BlockDevice> {
+    dev: B,
+    start_sector: u64,      // 分区起始扇区
+    sectors_per_block: u32, // 每个文件系统块的扇区数，0 表示扇区大小不合法
+    total_blocks: u64,      // 分区内完整的文件系统块数
+}
+
+impl<B: BlockDevice> SectorDev<B> {
+    /// 使用整个设备
+    pub fn new(dev: B) -> Self {
+        let sectors_per_block = Self::sectors_per_block_of(&dev).unwrap_or(0);
+        let total_blocks = match sectors_per_block {
+            0 => 0,
+            n => dev.total_blocks() / n as u64,
+        };
+        Self {
+            dev,
+            start_sector: 0,
+            sectors_per_block,
+            total_blocks,
+        }
+    }
+
+    /// 使用设备上从 `start_sector` 开始、长 `sector_count` 个扇区的分区
+    pub fn with_partition(dev: B, start_sector: u64, sector_count: u64) -> BlockDevResult<Self> {
+        let sectors_per_block = Self::sectors_per_block_of(&dev)?;
+        let end = start_sector.checked_add(sector_count);
+        if end.is_none_or(|end| end > dev.total_blocks()) {
+            return Err(BlockDevError::BlockOutOfRange {
+                block_id: start_sector,
+                max_blocks: dev.total_blocks(),
+            });
+        }
+        Ok(Self {
+            dev,
+            start_sector,
+            sectors_per_block,
+            total_blocks: sector_count / sectors_per_block as u64,
+        })
+    }
+
+    fn sectors_per_block_of(dev: &B) -> BlockDevResult<u32> {
+        let sector = dev.block_size();
+        if !(512..=BLOCK_SIZE_U32).contains(&sector) || !sector.is_power_of_two() {
+            return Err(BlockDevError::InvalidBlockSize {
+                size: sector as usize,
+                expected: BLOCK_SIZE,
+            });
+        }
+        Ok(BLOCK_SIZE_U32 / sector)
+    }
+
+    /// 文件系统块 [block_id, block_id+count) 对应的起始扇区和扇区数
+    fn map(&self, block_id: u64, count: u32) -> BlockDevResult<(u64, u32)> {
+        if self.sectors_per_block == 0 {
+            return Err(BlockDevError::InvalidBlockSize {
+                size: self.dev.block_size() as usize,
+                expected: BLOCK_SIZE,
+            });
+        }
+        if block_id + count as u64 > self.total_blocks {
+            return Err(BlockDevError::BlockOutOfRange {
+                block_id,
+                max_blocks: self.total_blocks,
+            });
+        }
+        let sectors = count
+            .checked_mul(self.sectors_per_block)
+            .ok_or(BlockDevError::InvalidInput)?;
+        Ok((
+            self.start_sector + block_id * self.sectors_per_block as u64,
+            sectors,
+        ))
+    }
+
+    /// 分区起始扇区
+    pub fn start_sector(&self) -> u64 {
+        self.start_sector
+    }
+
+    /// 每个文件系统块对应的扇区数
+    pub fn sectors_per_block(&self) -> u32 {
+        self.sectors_per_block
+    }
+
+    /// 底层设备
+    pub fn inner(&self) -> &B {
+        &self.dev
+    }
+
+    /// 底层设备（可变）
+    pub fn inner_mut(&mut self) -> &mut B {
+        &mut self.dev
+    }
+
+    /// 取回底层设备
+    pub fn into_inner(self) -> B {
+        self.dev
+    }
+}
+
+impl<B: BlockDevice> BlockDevice for SectorDev<B> {
+    fn write(&mut self, buffer: &[u8], block_id: u64, count: u32) -> BlockDevResult<()> {
+        let (sector, sectors) = self.map(block_id, count)?;
+        self.dev.write(buffer, sector, sectors)
+    }
+
+    fn read(&mut self, buffer: &mut [u8], block_id: u64, count: u32) -> BlockDevResult<()> {
+        let (sector, sectors) = self.map(block_id, count)?;
+        self.dev.read(buffer, sector, sectors)
+    }
+
+    fn open(&mut self) -> BlockDevResult<()> {
+        self.dev.open()
+    }
+
+    fn close(&mut self) -> BlockDevResult<()> {
+        self.dev.close()
+    }
+
+    fn total_blocks(&self) -> u64 {
+        self.total_blocks
+    }
+
+    fn flush(&mut self) -> BlockDevResult<()> {
+        self.dev.flush()
+    }
+
+    fn write_fua(&mut self, buffer: &[u8], block_id: u64, count: u32) -> BlockDevResult<()> {
+        let (sector, sectors) = self.map(block_id, count)?;
+        self.dev.write_fua(buffer, sector, sectors)
+    }
+
+    fn read_vectored(&mut self, reqs: &mut [(u64, &mut [u8])]) -> BlockDevResult<()> {
+        let mut mapped = Vec::with_capacity(reqs.len());
+        for (block_id, buf) in reqs.iter_mut() {
+            let (sector, _) = self.map(*block_id, (buf.len() / BLOCK_SIZE) as u32)?;
+            mapped.push((sector, &mut **buf));
+        }
+        self.dev.read_vectored(&mut mapped)
+    }
+
+    fn write_vectored(&mut self, reqs: &[(u64, &[u8])]) -> BlockDevResult<()> {
+        let mut mapped = Vec::with_capacity(reqs.len());
+        for &(block_id, buf) in reqs {
+            let (sector, _) = self.map(block_id, (buf.len() / BLOCK_SIZE) as u32)?;
+            mapped.push((sector, buf));
+        }
+        self.dev.write_vectored(&mapped)
+    }
+
+    fn discard(&mut self, block_id: u64, count: u32) -> BlockDevResult<()> {
+        let (sector, sectors) = self.map(block_id, count)?;
+        self.dev.discard(sector, sectors)
+    }
+
+    fn is_open(&self) -> bool {
+        self.dev.is_open()
+    }
+
+    fn is_readonly(&self) -> bool {
+        self.dev.is_readonly()
+    }
+}
+
 /// 块设备封装
 /// 提供缓存和便捷的块设备操作接口
 struct BlockDev<B: BlockDevice> {
-    dev: B,
+    dev: SectorDev<B>,
     buffer: BlockBuffer,
     is_dirty: bool,            // 缓冲区是否已修改
-    cached_block: Option<u32>, // 当前缓存的块号
+    cached_block: Option<u64>, // 当前缓存的块号
 }
 pub enum Jbd2RunState {
     Commit,
@@ -112,6 +315,11 @@ pub struct Jbd2Dev<B: BlockDevice> {
     journal_use: bool, //是否启用日志系统
     _state: Jbd2RunState,
     systeam: Option<JBD2DEVSYSTEM>,
+    journal_dev: Option<Box<dyn BlockDevice + Send>>, //外部日志设备，None表示日志在journal inode中
+    journal_buffer_max: usize, //事务缓存块数上限，超过后自动提交
+    handle_depth: u32,         //打开的原子操作句柄数，非0时不自动提交
+    discard: bool,                      //在线discard，释放的块在提交后通知设备
+    pending_discards: Vec<(u64, u32)>, //等待提交后下发的 (起始块号, 块数)
 }
 
 ///jbd2代理blockdev
@@ -119,7 +327,25 @@ pub struct Jbd2Dev<B: BlockDevice> {
 /// 采用Jouranl超级快注入的思想，必须需要使用mount来给块设备注入超级块，之后才能使用日志。
 impl<B: BlockDevice> Jbd2Dev<B> {
     ///你拿到我之后应该先把超级块给我传进来吧
+    /// 设备扇区大小与 BLOCK_SIZE 不同时自动按扇区换算
     pub fn initial_jbd2dev(_mode: u8, block_dev:B, use_journal: bool) -> Self {
+        Self::with_sector_dev(_mode, SectorDev::new(block_dev), use_journal)
+    }
+
+    /// 文件系统位于设备上从 `start_sector` 开始、长 `sector_count` 个扇区的分区中
+    /// 扇区大小不合法返回 InvalidBlockSize，分区超出设备返回 BlockOutOfRange
+    pub fn with_partition(
+        _mode: u8,
+        block_dev: B,
+        start_sector: u64,
+        sector_count: u64,
+        use_journal: bool,
+    ) -> BlockDevResult<Self> {
+        let dev = SectorDev::with_partition(block_dev, start_sector, sector_count)?;
+        Ok(Self::with_sector_dev(_mode, dev, use_journal))
+    }
+
+    fn with_sector_dev(_mode: u8, block_dev: SectorDev<B>, use_journal: bool) -> Self {
         let block_dev = BlockDev::new(block_dev);
         Self {
             _mode,
@@ -127,6 +353,108 @@ impl<B: BlockDevice> Jbd2Dev<B> {
             journal_use: use_journal,
             _state: Jbd2RunState::Commit,
             systeam: None,
+            journal_dev: None,
+            journal_buffer_max: JBD2_BUFFER_MAX,
+            handle_depth: 0,
+            discard: false,
+            pending_discards: Vec::new(),
+        }
+    }
+
+    /// 设置事务缓存块数上限，缓存超过上限时自动提交事务（默认 JBD2_BUFFER_MAX）
+    /// 实际上限不超过日志区一次能容纳的事务大小
+    pub fn set_journal_buffer_max(&mut self, max: usize) {
+        self.journal_buffer_max = max.max(1);
+    }
+
+    /// 事务缓存块数上限
+    pub fn journal_buffer_max(&self) -> usize {
+        self.journal_buffer_max
+    }
+
+    /// 打开原子操作句柄：句柄关闭前事务缓存超过上限也不自动提交，
+    /// 一次操作写入的元数据因此不会被拆到两个事务里
+    ///
+    /// 最外层句柄打开时预留 credits 块：剩余空间不够就先提交当前事务，
+    /// 一个空事务也放不下就返回 NoSpace
+    pub fn start_handle(&mut self, credits: usize) -> BlockDevResult<()> {
+        if self.handle_depth == 0
+            && self.journal_use
+            && let Some(s) = self.systeam.as_ref()
+        {
+            let hard_max = s.jbd2_super_block.max_transaction_blocks();
+            if credits > hard_max {
+                return Err(BlockDevError::NoSpace);
+            }
+            if s.commit_queue.len() + credits > hard_max {
+                self.commit_journal()?;
+            }
+        }
+        self.handle_depth += 1;
+        Ok(())
+    }
+
+    /// 关闭句柄，最外层句柄关闭时补上被推迟的提交
+    pub fn stop_handle(&mut self) -> BlockDevResult<()> {
+        self.handle_depth = self.handle_depth.saturating_sub(1);
+        if self.journal_use && self.journal_buffer_full() {
+            self.commit_journal()?;
+        }
+        Ok(())
+    }
+
+    /// 事务缓存是否需要在写入下一块之前先提交，句柄打开期间从不提交
+    fn journal_buffer_full(&self) -> bool {
+        self.handle_depth == 0
+            && self.systeam.as_ref().is_some_and(|s| {
+                let len = s.commit_queue.len();
+                len >= s.jbd2_super_block.max_transaction_blocks() || len > self.journal_buffer_max
+            })
+    }
+
+    /// 句柄打开期间事务已经达到日志区容量，再写入一块就超出了预留
+    fn handle_overflow(&self) -> bool {
+        self.handle_depth != 0
+            && self.systeam.as_ref().is_some_and(|s| {
+                s.commit_queue.len() >= s.jbd2_super_block.max_transaction_blocks()
+            })
+    }
+
+    /// [block_id, block_id+count) 中是否有块已经在当前事务里
+    fn in_running_transaction(&self, block_id: u64, count: u32) -> bool {
+        self.journal_use
+            && self.systeam.as_ref().is_some_and(|s| {
+                (0..count).any(|i| s.pending_update(block_id + i as u64).is_some())
+            })
+    }
+
+    /// 挂载前注入外部日志设备，超级块声明外部日志时日志读写都走这个设备
+    /// 与主设备一样按扇区大小换算
+    pub fn set_journal_device<J: BlockDevice + Send + 'static>(&mut self, journal_dev: J) {
+        self.journal_dev = Some(Box::new(SectorDev::new(journal_dev)));
+    }
+
+    /// 是否注入了外部日志设备
+    pub fn has_journal_device(&self) -> bool {
+        self.journal_dev.is_some()
+    }
+
+    /// 外部日志设备
+    pub fn journal_device_mut(&mut self) -> Option<&mut (dyn BlockDevice + Send + 'static)> {
+        self.journal_dev.as_deref_mut()
+    }
+
+    /// 提交当前事务，日志写入外部设备或同一设备，checkpoint写回主盘
+    pub fn commit_journal(&mut self) -> BlockDevResult<bool> {
+        let Some(systeam) = self.systeam.as_mut() else {
+            return Ok(false);
+        };
+        let home_dev = self.inner.device_mut();
+        match self.journal_dev.as_deref_mut() {
+            Some(journal_dev) => systeam.commit_transaction_external(journal_dev, home_dev),
+            None => systeam
+                .commit_transaction(home_dev)
+                .map_err(|_| BlockDevError::WriteError),
         }
     }
 
@@ -142,7 +470,12 @@ impl<B: BlockDevice> Jbd2Dev<B> {
                 .systeam
                 .as_mut()
                 .expect("jbd2dev are not initial,please initial the jbd2dev first!");
-            jbd_sys.replay(&mut *dev);
+            match self.journal_dev.as_deref_mut() {
+                Some(journal_dev) => jbd_sys.replay_external(journal_dev, &mut *dev),
+                None => jbd_sys.replay(&mut *dev),
+            }
+            //重放直接写底层设备，丢弃可能过期的块缓存
+            self.inner.invalidate();
         } else {
             warn!("Jouranl function not turn ,please turn on this function and retry!");
         }
@@ -158,34 +491,244 @@ impl<B: BlockDevice> Jbd2Dev<B> {
     pub fn set_journal_superblock(
         &mut self,
         super_block: JournalSuperBllockS,
-        jouranl_start_block: u32,
+        jouranl_start_block: u64,
     ) {
         let system = JBD2DEVSYSTEM {
             start_block: jouranl_start_block,
-            max_len: super_block.s_maxlen,
+            block_map: Vec::new(),
+            max_len: super_block.log_maxlen(),
             head: 0,
             sequence: super_block.s_sequence,
             jbd2_super_block: super_block,
             commit_queue: Vec::new(),
+            fc_off: 0,
         };
         self.systeam = Some(system);
     }
 
+    /// 设置日志区的块映射（起始相对块号, 物理块号, 块数），日志 inode 的块不连续时使用
+    pub fn set_journal_block_map(&mut self, block_map: Vec<JournalRun>) {
+        match self.systeam.as_mut() {
+            Some(systeam) => systeam.block_map = block_map,
+            None => warn!("Journal systeam uninitial, block map ignored"),
+        }
+    }
+
+    /// 打开/关闭异步提交（JBD2_FEATURE_INCOMPAT_ASYNC_COMMIT）
+    /// 异步提交省去数据块与commit块之间的flush屏障，由commit块校验和保证重放时能识别不完整事务
+    pub fn set_async_commit(&mut self, enable: bool) {
+        match self.systeam.as_mut() {
+            Some(systeam) => systeam.set_async_commit(enable),
+            None => warn!("Journal systeam uninitial, async commit setting ignored"),
+        }
+    }
+
+    /// 打开/关闭在线discard（相当于挂载选项 -o discard）
+    /// 打开后释放的块先排队，完整提交把释放它们的元数据落盘之后再批量通知设备
+    pub fn set_discard(&mut self, enable: bool) {
+        self.discard = enable;
+        if !enable {
+            self.pending_discards.clear();
+        }
+    }
+
+    /// 是否打开了在线discard
+    pub fn is_discard(&self) -> bool {
+        self.discard
+    }
+
+    /// 记录释放的 [block_id, block_id+count)，未打开在线discard时忽略
+    pub fn queue_discard(&mut self, block_id: u64, count: u32) {
+        if !self.discard || count == 0 {
+            return;
+        }
+        match self.pending_discards.last_mut() {
+            // 合并后超过 u32 的部分另起一段
+            Some((start, len)) if *start + *len as u64 == block_id => {
+                let room = u32::MAX - *len;
+                *len += count.min(room);
+                if count > room {
+                    self.pending_discards.push((block_id + room as u64, count - room));
+                }
+            }
+            _ => self.pending_discards.push((block_id, count)),
+        }
+    }
+
+    /// 块在下发discard之前又被分配出去，从队列里去掉
+    pub fn cancel_discard(&mut self, block_id: u64, count: u32) {
+        if self.pending_discards.is_empty() {
+            return;
+        }
+        let end = block_id + count as u64;
+        let mut kept = Vec::with_capacity(self.pending_discards.len());
+        for &(start, len) in &self.pending_discards {
+            let stop = start + len as u64;
+            if stop <= block_id || start >= end {
+                kept.push((start, len));
+                continue;
+            }
+            if start < block_id {
+                kept.push((start, (block_id - start) as u32));
+            }
+            if stop > end {
+                kept.push((end, (stop - end) as u32));
+            }
+        }
+        self.pending_discards = kept;
+    }
+
+    /// 排队中的块数
+    pub fn pending_discard_blocks(&self) -> u64 {
+        self.pending_discards.iter().map(|&(_, len)| len as u64).sum()
+    }
+
+    /// 下发排队的discard，调用者保证释放这些块的位图已经提交
+    /// 相邻的范围合并成一次请求；discard只是提示，设备出错时丢弃剩余的请求
+    pub fn issue_discards(&mut self) -> BlockDevResult<()> {
+        if self.pending_discards.is_empty() {
+            return Ok(());
+        }
+        // 之前的写入先落盘，设备不会把discard排到它们前面
+        self.inner.flush()?;
+        let mut ranges = core::mem::take(&mut self.pending_discards);
+        ranges.sort_unstable();
+        let mut merged: Vec<(u64, u32)> = Vec::with_capacity(ranges.len());
+        for (start, len) in ranges {
+            let mut start = start;
+            let mut end = start + len as u64;
+            if let Some((s, l)) = merged.last_mut()
+                && start <= *s + *l as u64
+            {
+                // 与上一段重叠或相邻：上一段最多长到 u32::MAX，剩下的另起一段
+                end = end.max(*s + *l as u64);
+                let grown = (end - *s).min(u32::MAX as u64);
+                *l = grown as u32;
+                start = *s + grown;
+            }
+            while start < end {
+                let len = (end - start).min(u32::MAX as u64);
+                merged.push((start, len as u32));
+                start += len;
+            }
+        }
+        for (start, len) in merged {
+            if let Err(e) = self.discard(start, len) {
+                warn!("discard [{start}, +{len}) failed: {e}, dropping remaining requests");
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// 立即对 [block_id, block_id+count) 下发discard
+    pub fn discard(&mut self, block_id: u64, count: u32) -> BlockDevResult<()> {
+        if self.inner.dev.is_readonly() {
+            return Err(BlockDevError::ReadOnly);
+        }
+        self.inner.invalidate_range(block_id, count);
+        self.inner.dev.discard(block_id, count)
+    }
+
+    /// 为 64 位文件系统打开 journal 的 JBD2_FEATURE_INCOMPAT_64BIT
+    /// 先提交事务缓存，保证已有描述符都按旧的 tag 格式解析
+    pub fn enable_journal_64bit(&mut self) -> BlockDevResult<()> {
+        let Some(systeam) = self.systeam.as_ref() else {
+            warn!("Journal systeam uninitial, 64bit journal unavailable");
+            return Err(BlockDevError::Unsupported);
+        };
+        if systeam.is_64bit() {
+            return Ok(());
+        }
+        self.commit_journal()?;
+        let systeam = self.systeam.as_mut().unwrap();
+        systeam.set_64bit();
+        match self.journal_dev.as_deref_mut() {
+            Some(journal_dev) => systeam.write_superblock(journal_dev),
+            None => systeam.write_superblock(self.inner.device_mut()),
+        }
+    }
+
+    /// 是否启用了 fast commit
+    pub fn is_fast_commit(&self) -> bool {
+        self.journal_use && self.systeam.as_ref().is_some_and(|s| s.is_fast_commit())
+    }
+
+    /// 打开/关闭 fast commit（JBD2_FEATURE_INCOMPAT_FAST_COMMIT）
+    /// 先提交事务缓存，然后重新划分日志区域并立即写回 journal 超级块
+    pub fn set_fast_commit(&mut self, enable: bool) -> BlockDevResult<()> {
+        if self.systeam.is_none() {
+            warn!("Journal systeam uninitial, fast commit unavailable");
+            return Err(BlockDevError::Unsupported);
+        }
+        self.commit_journal()?;
+        let systeam = self.systeam.as_mut().unwrap();
+        systeam.set_fast_commit(enable);
+        match self.journal_dev.as_deref_mut() {
+            Some(journal_dev) => systeam.write_superblock(journal_dev),
+            None => systeam.write_superblock(self.inner.device_mut()),
+        }
+    }
+
+    /// 当前运行事务的 tid 以及该事务已写入的 fast commit 块数
+    pub fn fast_commit_state(&self) -> Option<(u32, u32)> {
+        self.systeam
+            .as_ref()
+            .filter(|s| s.is_fast_commit())
+            .map(|s| (s.sequence, s.fc_off))
+    }
+
+    /// 写入一次 fast commit，写之前先让已经写回主盘的数据块落盘
+    /// 返回 false 表示 fast commit 区域不可用或已满
+    pub fn write_fast_commit(&mut self, blocks: &[u8]) -> BlockDevResult<bool> {
+        let Some(systeam) = self.systeam.as_mut() else {
+            return Ok(false);
+        };
+        self.inner.flush()?;
+        let home_dev = self.inner.device_mut();
+        match self.journal_dev.as_deref_mut() {
+            Some(journal_dev) => systeam.write_fast_commit(journal_dev, blocks),
+            None => systeam.write_fast_commit(home_dev, blocks),
+        }
+    }
+
+    /// 读取整个 fast commit 区域，用于挂载时重放
+    pub fn read_fast_commit_area(&mut self) -> BlockDevResult<Vec<u8>> {
+        let Some(systeam) = self.systeam.as_ref() else {
+            return Ok(Vec::new());
+        };
+        match self.journal_dev.as_deref_mut() {
+            Some(journal_dev) => systeam.read_fast_commit_area(journal_dev),
+            None => systeam.read_fast_commit_area(self.inner.device_mut()),
+        }
+    }
+
+    /// 只读扫描主设备上的日志区（journal inode 中的日志），不经过事务缓存
+    pub fn scan_home_journal(
+        &mut self,
+        sb_block: u64,
+        block_map: &[JournalRun],
+        mode: JournalScanMode,
+    ) -> BlockDevResult<JournalDump> {
+        self.inner.flush()?;
+        scan_journal(self.inner.device_mut(), sb_block, block_map, mode)
+    }
+
     ///防止滥用，仅仅umount调用，确保事务缓存全部提交完毕
     pub fn umount_commit(&mut self) {
         if self.journal_use {
-            self.systeam
-                .as_mut()
-                .unwrap()
-                .commit_transaction(&mut self.inner.dev).expect("Translation commit failed!!!");
+            self.commit_journal().expect("Translation commit failed!!!");
         } else {
             warn!("Jouranl not use , no thing to commit")
         }
     }
 
-    pub fn write_block(&mut self, block_id: u32, is_metadata: bool) -> BlockDevResult<()> {
+    pub fn write_block(&mut self, block_id: u64, is_metadata: bool) -> BlockDevResult<()> {
         //error!("write block :{} ,use journal?:{} ismetadata:{}",block_id,self.journal_use,is_metadata);
 
+        // 已经进入当前事务的块必须继续走日志，否则checkpoint会用事务里的旧内容覆盖它
+        let is_metadata = is_metadata || self.in_running_transaction(block_id, 1);
+
         // 1) 非元数据 或 未开启日志：直接写回到底层块设备
         if !self.journal_use || !is_metadata {
             // BlockDev 内部的 buffer 已经被上层写好，直接把当前 buffer 写到 block_id
@@ -198,7 +741,7 @@ impl<B: BlockDevice> Jbd2Dev<B> {
 
         let meta_vec = self.inner.buffer();
         let updates = Jbd2Update( //把缓存变成事务
-            block_id as u64,
+            block_id,
             meta_vec
                 .try_into()
                 .expect("Data can;t into [u8;BLOCK_SIZE] panic!,os should process"),
@@ -212,33 +755,33 @@ impl<B: BlockDevice> Jbd2Dev<B> {
             return self.inner.write_block(block_id);
         }
 
-        let systeam = self.systeam.as_mut().unwrap();
-
-        // 使用原始底层块设备提交事务
-        let raw_dev = self.inner.device_mut();
-
+        //句柄内不能提交，事务放不下就让这次操作失败
+        if self.handle_overflow() {
+            return Err(BlockDevError::NoSpace);
+        }
         //先写入缓存
-        if systeam.commit_queue.len() > JBD2_BUFFER_MAX {
+        if self.journal_buffer_full() {
             //缓存已满 直接提交，然后再塞入缓存
-            let _ = systeam.commit_transaction(raw_dev);
-            //赛入缓存
-            systeam.commit_queue.push(updates);
+            let _ = self.commit_journal();
             trace!("[JBD2 BUFFER] BUFFER IS FULL ,FLUSHED!")
-        } else {
-            //赛入缓存
-            systeam.commit_queue.push(updates);
-        }
-
-        if self._mode == 0 {//ordered模式
-            //再写入主盘
-            self.inner.write_block(block_id)?;
         }
+        //赛入缓存
+        self.systeam.as_mut().unwrap().commit_queue.push(updates);
 
-
+        //ordered模式：普通数据块已经直接落盘，元数据只有在commit块持久化之后
+        //才由checkpoint写回主盘，这里只把缓冲区标记为干净，避免被当成脏块直接写回
+        self.inner.mark_clean();
 
         Ok(())
     }
-    pub fn read_block(&mut self, block_id: u32) -> BlockDevResult<()> {
+    pub fn read_block(&mut self, block_id: u64) -> BlockDevResult<()> {
+        //尚未checkpoint的元数据以事务缓存中的最新版本为准
+        if self.journal_use
+            && let Some(systeam) = self.systeam.as_ref()
+            && let Some(update) = systeam.pending_update(block_id)
+        {
+            return self.inner.load_block(block_id, &update.1);
+        }
         self.inner.read_block(block_id)
     }
     pub fn buffer(&self) -> &[u8] {
@@ -247,21 +790,39 @@ impl<B: BlockDevice> Jbd2Dev<B> {
     pub fn buffer_mut(&mut self) -> &mut [u8] {
         self.inner.buffer_mut()
     }
-    pub fn read_blocks(&mut self, buf: &mut [u8], block_id: u32, count: u32) -> BlockDevResult<()> {
-        self.inner.read_blocks(buf, block_id, count)
+    pub fn read_blocks(&mut self, buf: &mut [u8], block_id: u64, count: u32) -> BlockDevResult<()> {
+        self.inner.read_blocks(buf, block_id, count)?;
+        if !self.journal_use {
+            return Ok(());
+        }
+        if let Some(systeam) = self.systeam.as_ref() {
+            for i in 0..count {
+                let off = (i as usize) * BLOCK_SIZE;
+                if off + BLOCK_SIZE > buf.len() {
+                    break;
+                }
+                if let Some(update) = systeam.pending_update(block_id + i as u64) {
+                    buf[off..off + BLOCK_SIZE].copy_from_slice(&update.1);
+                }
+            }
+        }
+        Ok(())
     }
     pub fn write_blocks(
         &mut self,
         buf: &[u8],
-        block_id: u32,
+        block_id: u64,
         count: u32,
         is_metadata: bool,
     ) -> BlockDevResult<()> {
         //error!("write block :{} ,use journal?:{} ismetadata:{}",block_id,self.journal_use,is_metadata);
 
+        let is_metadata = is_metadata || self.in_running_transaction(block_id, count);
+
         // 1) 非元数据 或 未开启日志：直接写回到底层块设备
         if !self.journal_use || !is_metadata {
-            // BlockDev 内部的 buffer 已经被上层写好，直接把当前 buffer 写到 block_id
+            // 直接写回到 block_id，单块缓冲区里这些块的旧内容作废
+            self.inner.invalidate_range(block_id, count);
             return self.inner.write_blocks(buf, block_id, count);
         }
 
@@ -279,31 +840,29 @@ impl<B: BlockDevice> Jbd2Dev<B> {
             return self.inner.write_blocks(buf, block_id, count);
         }
 
-        let systeam = self.systeam.as_mut().unwrap();
-
-        // 使用原始底层块设备提交事务
-        let raw_dev = self.inner.device_mut();
-
+        // 单块缓冲区里可能还留着这些块的旧内容
+        self.inner.invalidate_range(block_id, count);
 
         for i in 0..count {
               let off = (i as usize) * (BLOCK_SIZE as usize);
             let block_bytes: [u8; BLOCK_SIZE] = buf[off..off + (BLOCK_SIZE as usize)]
                 .try_into()
                 .expect("slice len must be BLOCK_SIZE");
-            let updates = Jbd2Update((block_id + i) as u64, block_bytes);
+            let updates = Jbd2Update(block_id + i as u64, block_bytes);
             
 
+            //句柄内不能提交，事务放不下就让这次操作失败
+            if self.handle_overflow() {
+                return Err(BlockDevError::NoSpace);
+            }
             //先写入缓存
-            if systeam.commit_queue.len() > JBD2_BUFFER_MAX {
+            if self.journal_buffer_full() {
                 //缓存已满 直接提交，然后再塞入缓存
-                let _ = systeam.commit_transaction(raw_dev);
-                //赛入缓存
-                systeam.commit_queue.push(updates);
+                let _ = self.commit_journal();
                 trace!("[JBD2 BUFFER] BUFFER IS FULL ,FLUSHED!")
-            } else {
-                //赛入缓存
-                systeam.commit_queue.push(updates);
             }
+            //赛入缓存
+            self.systeam.as_mut().unwrap().commit_queue.push(updates);
         }
 
        
@@ -311,6 +870,25 @@ impl<B: BlockDevice> Jbd2Dev<B> {
 
         Ok(())
     }
+    /// 一次写入多段整块数据，每段为 (起始块号, 缓冲区)
+    /// 走日志的元数据段逐块进入事务缓存，其余的段合成一次 write_vectored 直接写到设备
+    pub fn write_vectored(&mut self, reqs: &[(u64, &[u8])], is_metadata: bool) -> BlockDevResult<()> {
+        let mut direct = Vec::with_capacity(reqs.len());
+        for &(block_id, buf) in reqs {
+            let count = (buf.len() / BLOCK_SIZE) as u32;
+            let journaled = self.journal_use
+                && self.systeam.is_some()
+                && (is_metadata || self.in_running_transaction(block_id, count));
+            if journaled {
+                self.write_blocks(buf, block_id, count, true)?;
+            } else {
+                self.inner.invalidate_range(block_id, count);
+                direct.push((block_id, buf));
+            }
+        }
+        self.inner.write_vectored(&direct)
+    }
+
     pub fn cantflush(&mut self) -> BlockDevResult<()> {
         if !self.journal_use {
             return self.inner.flush();
@@ -325,11 +903,21 @@ impl<B: BlockDevice> Jbd2Dev<B> {
     pub fn block_size(&self) -> u32 {
         self.inner.block_size()
     }
+
+    /// 按文件系统块寻址的底层设备
+    pub fn device_mut(&mut self) -> &mut SectorDev<B> {
+        self.inner.device_mut()
+    }
+
+    /// 取回底层设备，调用前应当已经卸载或写回
+    pub fn into_inner(self) -> B {
+        self.inner.dev.into_inner()
+    }
 }
 
 impl<B: BlockDevice> BlockDev<B> {
     /// 创建新的块设备封装
-    pub fn new(dev:B) -> Self {
+    pub fn new(dev: SectorDev<B>) -> Self {
         Self {
             dev,
             buffer: BlockBuffer::new(),
@@ -339,7 +927,7 @@ impl<B: BlockDevice> BlockDev<B> {
     }
 
     /// 使用指定缓冲区初始化块设备
-    pub fn _with_buffer(dev:B, buffer: BlockBuffer) -> BlockDevResult<Self> {
+    pub fn _with_buffer(dev: SectorDev<B>, buffer: BlockBuffer) -> BlockDevResult<Self> {
         if buffer.len() < 512 {
             return Err(BlockDevError::BufferTooSmall {
                 provided: buffer.len(),
@@ -367,7 +955,7 @@ impl<B: BlockDevice> BlockDev<B> {
     }
 
     /// 读取指定块到内部缓冲区
-    pub fn read_block(&mut self, block_id: u32) -> BlockDevResult<()> {
+    pub fn read_block(&mut self, block_id: u64) -> BlockDevResult<()> {
         // 检查是否需要刷新脏数据
         if self.is_dirty && self.cached_block != Some(block_id) {
             self.flush()?;
@@ -388,7 +976,7 @@ impl<B: BlockDevice> BlockDev<B> {
 
     /// 写入内部缓冲区到指定块
     ///
-    pub fn write_block(&mut self, block_id: u32) -> BlockDevResult<()> {
+    pub fn write_block(&mut self, block_id: u64) -> BlockDevResult<()> {
         if self.dev.is_readonly() {
             return Err(BlockDevError::ReadOnly);
         }
@@ -401,9 +989,8 @@ impl<B: BlockDevice> BlockDev<B> {
     }
 
     /// 直接读取多个块
-    pub fn read_blocks(&mut self, buffer: &mut [u8], block_id: u32, count: u32) -> BlockDevResult<()> {
-        let block_size = self.dev.block_size() as usize;
-        let required_size = block_size * count as usize;
+    pub fn read_blocks(&mut self, buffer: &mut [u8], block_id: u64, count: u32) -> BlockDevResult<()> {
+        let required_size = BLOCK_SIZE * count as usize;
 
         if buffer.len() < required_size {
             return Err(BlockDevError::BufferTooSmall {
@@ -412,17 +999,24 @@ impl<B: BlockDevice> BlockDev<B> {
             });
         }
 
+        // 内部缓冲区里还没写回的块落在读取范围内时先写回
+        if self.is_dirty
+            && let Some(cached) = self.cached_block
+            && (block_id..block_id + count as u64).contains(&cached)
+        {
+            self.flush()?;
+        }
+
         self.dev.read(buffer, block_id, count)
     }
 
     /// 直接写入多个块
-    pub fn write_blocks(&mut self, buffer: &[u8], block_id: u32, count: u32) -> BlockDevResult<()> {
+    pub fn write_blocks(&mut self, buffer: &[u8], block_id: u64, count: u32) -> BlockDevResult<()> {
         if self.dev.is_readonly() {
             return Err(BlockDevError::ReadOnly);
         }
 
-        let block_size = self.dev.block_size() as usize;
-        let required_size = block_size * count as usize;
+        let required_size = BLOCK_SIZE * count as usize;
 
         if buffer.len() < required_size {
             return Err(BlockDevError::BufferTooSmall {
@@ -434,6 +1028,57 @@ impl<B: BlockDevice> BlockDev<B> {
         self.dev.write(buffer, block_id, count)
     }
 
+    /// 一次写入多段
+    pub fn write_vectored(&mut self, reqs: &[(u64, &[u8])]) -> BlockDevResult<()> {
+        if reqs.is_empty() {
+            return Ok(());
+        }
+        if self.dev.is_readonly() {
+            return Err(BlockDevError::ReadOnly);
+        }
+        self.dev.write_vectored(reqs)
+    }
+
+    /// 用给定内容填充内部缓冲区，视为已读取 block_id
+    pub fn load_block(&mut self, block_id: u64, data: &[u8]) -> BlockDevResult<()> {
+        if self.is_dirty && self.cached_block != Some(block_id) {
+            self.flush()?;
+        }
+
+        if self.cached_block == Some(block_id) {
+            return Ok(());
+        }
+
+        let len = data.len().min(BLOCK_SIZE);
+        self.buffer.as_mut_slice()[..len].copy_from_slice(&data[..len]);
+        self.cached_block = Some(block_id);
+        self.is_dirty = false;
+
+        Ok(())
+    }
+
+    /// 缓冲区内容已经交给上层（例如日志事务）处理，不再需要写回
+    pub fn mark_clean(&mut self) {
+        self.is_dirty = false;
+    }
+
+    /// 如果缓存的块落在 [block_id, block_id+count) 内则丢弃
+    pub fn invalidate_range(&mut self, block_id: u64, count: u32) {
+        if let Some(cached) = self.cached_block
+            && cached >= block_id
+            && cached < block_id + count as u64
+        {
+            self.cached_block = None;
+            self.is_dirty = false;
+        }
+    }
+
+    /// 丢弃缓存的块
+    pub fn invalidate(&mut self) {
+        self.cached_block = None;
+        self.is_dirty = false;
+    }
+
     /// 获取缓冲区引用
     pub fn buffer(&self) -> &[u8] {
         self.buffer.as_slice()
@@ -465,13 +1110,13 @@ impl<B: BlockDevice> BlockDev<B> {
     }
 
     /// 检查块号是否有效
-    pub fn _is_valid_block(&self, block_id: u32) -> bool {
-        (block_id as u64) < self.total_blocks()
+    pub fn _is_valid_block(&self, block_id: u64) -> bool {
+        block_id < self.total_blocks()
     }
 
     /// 验证块范围
-    pub fn _validate_block_range(&self, block_id: u32, count: u32) -> BlockDevResult<()> {
-        let end_block = block_id as u64 + count as u64;
+    pub fn _validate_block_range(&self, block_id: u64, count: u32) -> BlockDevResult<()> {
+        let end_block = block_id + count as u64;
         if end_block > self.total_blocks() {
             return Err(BlockDevError::BlockOutOfRange {
                 block_id,
@@ -483,11 +1128,346 @@ impl<B: BlockDevice> BlockDev<B> {
 
     /// 获取内部设备引用
     pub fn _device(&self) -> &B {
-        &self.dev
+        self.dev.inner()
     }
 
-    /// 获取内部设备可变引用
-    pub fn device_mut(&mut self) -> &mut B {
+    /// 获取按文件系统块寻址的设备，日志直接读写主盘时使用
+    pub fn device_mut(&mut self) -> &mut SectorDev<B> {
         &mut self.dev
     }
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::ext4_backend::ext4::{mkfs, mount, umount};
+    use crate::ext4_backend::file::{mkfile, read_file};
+    use core::cell::RefCell;
+    use std::rc::Rc;
+
+    /// 只能按扇区访问的内存设备
+    #[derive(Clone)]
+    struct SectorMemDev {
+        data: Rc<RefCell<Vec<u8>>>,
+        sector_size: u32,
+        /// 每次 write_vectored 的段数
+        vectored: Rc<RefCell<Vec<usize>>>,
+        /// 收到的 discard (起始扇区, 扇区数)
+        discards: Rc<RefCell<Vec<(u64, u32)>>>,
+    }
+
+    impl SectorMemDev {
+        fn new(sectors: u64, sector_size: u32) -> Self {
+            Self {
+                data: Rc::new(RefCell::new(alloc::vec![0xA5u8; sectors as usize * sector_size as usize])),
+                sector_size,
+                vectored: Rc::new(RefCell::new(Vec::new())),
+                discards: Rc::new(RefCell::new(Vec::new())),
+            }
+        }
+    }
+
+    impl BlockDevice for SectorMemDev {
+        fn write(&mut self, buffer: &[u8], block_id: u64, count: u32) -> BlockDevResult<()> {
+            let start = block_id as usize * self.sector_size as usize;
+            let len = count as usize * self.sector_size as usize;
+            self.data.borrow_mut()[start..start + len].copy_from_slice(&buffer[..len]);
+            Ok(())
+        }
+
+        fn read(&mut self, buffer: &mut [u8], block_id: u64, count: u32) -> BlockDevResult<()> {
+            let start = block_id as usize * self.sector_size as usize;
+            let len = count as usize * self.sector_size as usize;
+            buffer[..len].copy_from_slice(&self.data.borrow()[start..start + len]);
+            Ok(())
+        }
+
+        fn open(&mut self) -> BlockDevResult<()> {
+            Ok(())
+        }
+
+        fn close(&mut self) -> BlockDevResult<()> {
+            Ok(())
+        }
+
+        fn total_blocks(&self) -> u64 {
+            (self.data.borrow().len() / self.sector_size as usize) as u64
+        }
+
+        fn block_size(&self) -> u32 {
+            self.sector_size
+        }
+
+        fn write_vectored(&mut self, reqs: &[(u64, &[u8])]) -> BlockDevResult<()> {
+            self.vectored.borrow_mut().push(reqs.len());
+            for (sector, buf) in reqs {
+                self.write(buf, *sector, (buf.len() / self.sector_size as usize) as u32)?;
+            }
+            Ok(())
+        }
+
+        /// 像真实闪存一样丢掉内容，误discard的有效数据会被发现
+        fn discard(&mut self, block_id: u64, count: u32) -> BlockDevResult<()> {
+            self.discards.borrow_mut().push((block_id, count));
+            let start = block_id as usize * self.sector_size as usize;
+            let len = count as usize * self.sector_size as usize;
+            self.data.borrow_mut()[start..start + len].fill(0xDD);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_512_byte_sectors_in_unaligned_partition() {
+        // 分区从第 63 扇区开始（老式 MBR 布局），不与 4K 对齐；尾部留出 8 个扇区
+        const START: u64 = 63;
+        const SECTORS: u64 = 4096 * 8;
+        let storage = SectorMemDev::new(START + SECTORS + 8, 512);
+        let mut dev = Jbd2Dev::with_partition(0, storage.clone(), START, SECTORS, false).unwrap();
+        assert_eq!(dev.total_blocks(), 4096);
+        assert_eq!(dev.block_size(), BLOCK_SIZE_U32);
+        mkfs(&mut dev).unwrap();
+        let fs = mount(&mut dev).unwrap();
+        umount(fs, &mut dev).unwrap();
+
+        dev.set_journal_use(true);
+        let mut fs = mount(&mut dev).unwrap();
+        let data: Vec<u8> = (0..2 * BLOCK_SIZE + 100).map(|i| (i % 253) as u8).collect();
+        mkfile(&mut dev, &mut fs, "/f", Some(&data), None).unwrap();
+        umount(fs, &mut dev).unwrap();
+
+        let mut dev = Jbd2Dev::with_partition(0, storage.clone(), START, SECTORS, true).unwrap();
+        let mut fs = mount(&mut dev).unwrap();
+        assert_eq!(read_file(&mut dev, &mut fs, "/f").unwrap().unwrap(), data);
+        umount(fs, &mut dev).unwrap();
+
+        // ext4 超级块 magic 位于分区起始偏移 1024 + 0x38
+        let raw = storage.data.borrow();
+        let sb_magic = START as usize * 512 + 1024 + 0x38;
+        assert_eq!(raw[sb_magic..sb_magic + 2], 0xEF53u16.to_le_bytes());
+        // 分区之外的扇区没有被碰过
+        let end = (START + SECTORS) as usize * 512;
+        assert!(raw[..START as usize * 512].iter().all(|&b| b == 0xA5));
+        assert!(raw[end..].iter().all(|&b| b == 0xA5));
+    }
+
+    #[test]
+    fn test_vectored_writes_are_translated_and_batched() {
+        const START: u64 = 63;
+        let storage = SectorMemDev::new(START + 4096 * 8, 512);
+        let mut dev = Jbd2Dev::with_partition(0, storage.clone(), START, 4096 * 8, false).unwrap();
+
+        // 不走日志时多段合成一次请求，块号换算成分区内的扇区号
+        let a = alloc::vec![1u8; BLOCK_SIZE];
+        let b = alloc::vec![2u8; 2 * BLOCK_SIZE];
+        dev.write_vectored(&[(5, &a), (9, &b)], false).unwrap();
+        assert_eq!(*storage.vectored.borrow(), [2]);
+        let sector = |block: u64| (START + block * 8) as usize * 512;
+        {
+            let raw = storage.data.borrow();
+            assert!(raw[sector(5)..sector(6)].iter().all(|&x| x == 1));
+            assert!(raw[sector(9)..sector(11)].iter().all(|&x| x == 2));
+            assert!(raw[sector(6)..sector(9)].iter().all(|&x| x == 0xA5));
+        }
+
+        // 日志提交的 descriptor 和日志块、checkpoint 以及缓存写回都按批提交
+        mkfs(&mut dev).unwrap();
+        dev.set_journal_use(true);
+        let mut fs = mount(&mut dev).unwrap();
+        storage.vectored.borrow_mut().clear();
+        for i in 0..4 {
+            let name = alloc::format!("/f{i}");
+            mkfile(&mut dev, &mut fs, &name, Some(&a), None).unwrap();
+        }
+        umount(fs, &mut dev).unwrap();
+        assert!(storage.vectored.borrow().iter().any(|&n| n > 2));
+
+        let mut dev = Jbd2Dev::with_partition(0, storage.clone(), START, 4096 * 8, true).unwrap();
+        let mut fs = mount(&mut dev).unwrap();
+        for i in 0..4 {
+            let name = alloc::format!("/f{i}");
+            assert_eq!(read_file(&mut dev, &mut fs, &name).unwrap().unwrap(), a);
+        }
+        umount(fs, &mut dev).unwrap();
+    }
+
+    #[test]
+    fn test_online_discard_and_fstrim() {
+        use crate::ext4_backend::api::fstrim;
+        use crate::ext4_backend::file::delete_file;
+
+        const START: u64 = 63;
+        const SECTORS: u64 = 4096 * 8;
+        let storage = SectorMemDev::new(START + SECTORS, 512);
+        let mut dev = Jbd2Dev::with_partition(0, storage.clone(), START, SECTORS, false).unwrap();
+        mkfs(&mut dev).unwrap();
+        dev.set_journal_use(true);
+        dev.set_discard(true);
+        let mut fs = mount(&mut dev).unwrap();
+
+        let keep: Vec<u8> = (0..3 * BLOCK_SIZE).map(|i| (i % 251) as u8).collect();
+        let big = alloc::vec![0x5Au8; 64 * BLOCK_SIZE];
+        mkfile(&mut dev, &mut fs, "/keep", Some(&keep), None).unwrap();
+        mkfile(&mut dev, &mut fs, "/big", Some(&big), None).unwrap();
+        fs.sync_fs(&mut dev).unwrap();
+        assert!(storage.discards.borrow().is_empty());
+
+        // 释放的块等到提交之后才下发，下发时换算成分区内的扇区
+        delete_file(&mut fs, &mut dev, "/big");
+        assert!(dev.pending_discard_blocks() >= 64);
+        assert!(storage.discards.borrow().is_empty());
+        fs.sync_fs(&mut dev).unwrap();
+        assert_eq!(dev.pending_discard_blocks(), 0);
+        {
+            let discards = storage.discards.borrow();
+            let sectors: u64 = discards.iter().map(|&(_, n)| n as u64).sum();
+            assert!(sectors >= 64 * 8);
+            assert!(discards.iter().all(|&(s, n)| s >= START && (s - START) % 8 == 0 && n % 8 == 0));
+        }
+
+        // 刚释放又分配出去的块不会被discard
+        dev.queue_discard(100, 10);
+        dev.cancel_discard(103, 2);
+        assert_eq!(dev.pending_discard_blocks(), 8);
+        dev.set_discard(false);
+        assert_eq!(dev.pending_discard_blocks(), 0);
+
+        // fstrim 处理所有空闲区域，已经处理过的块组再次运行时跳过
+        storage.discards.borrow_mut().clear();
+        let trimmed = fstrim(&mut dev, &mut fs, 1).unwrap();
+        assert!(trimmed > 0);
+        let sectors: u64 = storage.discards.borrow().iter().map(|&(_, n)| n as u64).sum();
+        assert_eq!(sectors, trimmed * 8);
+        assert_eq!(fstrim(&mut dev, &mut fs, 1).unwrap(), 0);
+
+        // 再释放块之后所在块组重新需要处理；min_len 过滤掉短的空闲区域
+        delete_file(&mut fs, &mut dev, "/keep");
+        mkfile(&mut dev, &mut fs, "/keep", Some(&keep), None).unwrap();
+        assert_eq!(fstrim(&mut dev, &mut fs, u32::MAX).unwrap(), 0);
+        assert!(fstrim(&mut dev, &mut fs, 1).unwrap() > 0);
+
+        // 已trim标志写在块组描述符里，重新挂载后仍然跳过
+        assert!(fs.group_descs[0].is_trimmed());
+        umount(fs, &mut dev).unwrap();
+        let mut dev = Jbd2Dev::with_partition(0, storage.clone(), START, SECTORS, true).unwrap();
+        let mut fs = mount(&mut dev).unwrap();
+        assert!(fs.group_descs[0].is_trimmed());
+        assert_eq!(fstrim(&mut dev, &mut fs, 1).unwrap(), 0);
+
+        // 释放块时清掉的标志同样落盘；重新挂载后再跑一遍，有效数据没有被discard
+        delete_file(&mut fs, &mut dev, "/keep");
+        mkfile(&mut dev, &mut fs, "/keep", Some(&keep), None).unwrap();
+        umount(fs, &mut dev).unwrap();
+        let mut dev = Jbd2Dev::with_partition(0, storage.clone(), START, SECTORS, true).unwrap();
+        let mut fs = mount(&mut dev).unwrap();
+        assert!(!fs.group_descs[0].is_trimmed());
+        assert!(fstrim(&mut dev, &mut fs, 1).unwrap() > 0);
+        assert_eq!(read_file(&mut dev, &mut fs, "/keep").unwrap().unwrap(), keep);
+        umount(fs, &mut dev).unwrap();
+
+        // 合并后的范围按 u32::MAX 切开
+        dev.set_discard(true);
+        dev.queue_discard(0, u32::MAX - 1);
+        dev.queue_discard(u32::MAX as u64 - 1, 3);
+        assert_eq!(dev.pending_discard_blocks(), u32::MAX as u64 + 2);
+        dev.set_discard(false);
+    }
+
+    #[test]
+    fn test_fstrim_block_uninit_group() {
+        use crate::ext4_backend::api::fstrim;
+        use crate::ext4_backend::blockgroup_description::Ext4GroupDesc;
+        use crate::ext4_backend::ext4::mkfs_with_journal_device;
+        use crate::ext4_backend::jbd2::jbd2::mkfs_journal_device;
+
+        // 两个块组，日志放在外部设备上，第二组只有元数据；标成 BLOCK_UNINIT 后按布局算出空闲区域
+        const BLOCKS: u64 = 65536;
+        let storage = SectorMemDev::new(BLOCKS * 8, 512);
+        let mut dev = Jbd2Dev::with_partition(0, storage.clone(), 0, BLOCKS * 8, false).unwrap();
+        let mut journal_dev = SectorMemDev::new(32, BLOCK_SIZE as u32);
+        mkfs_journal_device(&mut journal_dev).unwrap();
+        mkfs_with_journal_device(&mut dev, &mut journal_dev).unwrap();
+        let mut fs = mount(&mut dev).unwrap();
+        assert_eq!(fs.group_count, 2);
+        fs.sync_fs(&mut dev).unwrap();
+
+        fs.group_descs[1].bg_flags |= Ext4GroupDesc::EXT4_BG_BLOCK_UNINIT;
+        fs.group_descs[0].bg_flags |= Ext4GroupDesc::EXT4_BG_TRIMMED;
+        let free = fs.group_descs[1].free_blocks_count() as u64;
+        storage.discards.borrow_mut().clear();
+        assert_eq!(fstrim(&mut dev, &mut fs, 1).unwrap(), free);
+        let discards = storage.discards.borrow();
+        assert_eq!(discards.iter().map(|&(_, n)| n as u64).sum::<u64>(), free * 8);
+        assert!(discards.iter().all(|&(s, _)| s >= 32768 * 8));
+    }
+
+    #[test]
+    fn test_handle_reserves_journal_credits() {
+        use crate::ext4_backend::memdev::MemDev;
+
+        let storage = MemDev::new(16 * 1024);
+        let scratch = storage.total_blocks() - 1;
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, storage, false);
+        mkfs(&mut dev).unwrap();
+        dev.set_journal_use(true);
+        let fs = mount(&mut dev).unwrap();
+        dev.set_journal_buffer_max(usize::MAX);
+        dev.commit_journal().unwrap();
+        let hard_max = dev.systeam.as_ref().unwrap().jbd2_super_block.max_transaction_blocks();
+        let state = |dev: &Jbd2Dev<MemDev>| {
+            let s = dev.systeam.as_ref().unwrap();
+            (s.sequence, s.commit_queue.len())
+        };
+        let blk = [0x3Cu8; BLOCK_SIZE];
+        let (seq, _) = state(&dev);
+
+        // 剩余空间放不下预留的块数：打开句柄之前先提交
+        for _ in 0..hard_max - 10 {
+            dev.write_blocks(&blk, scratch, 1, true).unwrap();
+        }
+        assert_eq!(state(&dev), (seq, hard_max - 10));
+        dev.start_handle(64).unwrap();
+        assert_eq!(state(&dev), (seq + 1, 0));
+
+        // 句柄内写满日志区容量也不提交，超出的写入返回 NoSpace
+        for _ in 0..hard_max {
+            dev.write_blocks(&blk, scratch, 1, true).unwrap();
+        }
+        assert_eq!(dev.write_blocks(&blk, scratch, 1, true), Err(BlockDevError::NoSpace));
+        assert_eq!(state(&dev), (seq + 1, hard_max));
+        dev.stop_handle().unwrap();
+        assert_eq!(state(&dev), (seq + 2, 0));
+
+        // 一个空事务都放不下的预留直接失败
+        assert_eq!(dev.start_handle(hard_max + 1), Err(BlockDevError::NoSpace));
+        assert_eq!(dev.handle_depth, 0);
+        umount(fs, &mut dev).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_sector_geometry() {
+        let err = Jbd2Dev::with_partition(0, SectorMemDev::new(64, 768), 0, 64, false).err();
+        assert_eq!(err, Some(BlockDevError::InvalidBlockSize { size: 768, expected: BLOCK_SIZE }));
+        let err = Jbd2Dev::with_partition(0, SectorMemDev::new(64, 8192), 0, 64, false).err();
+        assert_eq!(err, Some(BlockDevError::InvalidBlockSize { size: 8192, expected: BLOCK_SIZE }));
+        let err = Jbd2Dev::with_partition(0, SectorMemDev::new(64, 512), 8, 64, false).err();
+        assert_eq!(err, Some(BlockDevError::BlockOutOfRange { block_id: 8, max_blocks: 64 }));
+
+        // 整盘设备扇区大小不合法时，读写返回 InvalidBlockSize
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, SectorMemDev::new(64, 1000), false);
+        assert_eq!(
+            dev.read_block(0),
+            Err(BlockDevError::InvalidBlockSize { size: 1000, expected: BLOCK_SIZE })
+        );
+
+        // 分区内按文件系统块做边界检查，不会越过分区末尾
+        let mut dev = Jbd2Dev::with_partition(0, SectorMemDev::new(64, 512), 8, 20, false).unwrap();
+        assert_eq!(dev.total_blocks(), 2);
+        assert!(dev.read_block(1).is_ok());
+        assert_eq!(
+            dev.read_block(2),
+            Err(BlockDevError::BlockOutOfRange { block_id: 2, max_blocks: 2 })
+        );
+    }
+}
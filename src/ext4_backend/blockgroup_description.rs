@@ -60,6 +60,24 @@ impl Ext4GroupDesc {
         (self.bg_inode_table_hi as u64) << 32 | self.bg_inode_table_lo as u64
     }
 
+    /// 设置块位图块号（64位）
+    pub fn set_block_bitmap(&mut self, block: u64) {
+        self.bg_block_bitmap_lo = block as u32;
+        self.bg_block_bitmap_hi = (block >> 32) as u32;
+    }
+
+    /// 设置inode位图块号（64位）
+    pub fn set_inode_bitmap(&mut self, block: u64) {
+        self.bg_inode_bitmap_lo = block as u32;
+        self.bg_inode_bitmap_hi = (block >> 32) as u32;
+    }
+
+    /// 设置inode表起始块号（64位）
+    pub fn set_inode_table(&mut self, block: u64) {
+        self.bg_inode_table_lo = block as u32;
+        self.bg_inode_table_hi = (block >> 32) as u32;
+    }
+
     /// 获取空闲块数（32位）
     pub fn free_blocks_count(&self) -> u32 {
         (self.bg_free_blocks_count_hi as u32) << 16 | self.bg_free_blocks_count_lo as u32
@@ -114,6 +132,11 @@ impl Ext4GroupDesc {
     pub fn is_inode_table_zeroed(&self) -> bool {
         self.bg_flags & Self::EXT4_BG_INODE_ZEROED != 0
     }
+
+    /// 检查块组的空闲块是否已经被 fstrim 处理过
+    pub fn is_trimmed(&self) -> bool {
+        self.bg_flags & Self::EXT4_BG_TRIMMED != 0
+    }
 }
 
 // 块组描述符标志常量
@@ -126,6 +149,9 @@ impl Ext4GroupDesc {
 
     /// Inode表已清零
     pub const EXT4_BG_INODE_ZEROED: u16 = 0x0004;
+
+    /// 空闲块已被 fstrim 处理过，之后有块释放时清除
+    pub const EXT4_BG_TRIMMED: u16 = 0x0008;
 }
 
 /// 块组描述符表
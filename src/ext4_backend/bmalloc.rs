@@ -144,6 +144,41 @@ impl BlockAllocator {
         })
     }
 
+    /// 在指定块组中分配第一段连续空闲块，最多 max_count 块
+    /// 返回分配位置和实际分配的块数，用于日志等需要尽量连续的大块分配
+    pub fn alloc_first_free_run(
+        &self,
+        bitmap_data: &mut [u8],
+        group_idx: u32,
+        max_count: u32,
+    ) -> Result<(BlockAlloc, u32), AllocError> {
+        if max_count == 0 {
+            return Err(AllocError::InvalidParameter);
+        }
+
+        let mut bitmap = BlockBitmapMut::new(bitmap_data, self.blocks_per_group);
+
+        let start = self.find_free_block(&bitmap)?.ok_or(AllocError::NoSpace)?;
+        let mut count = 0u32;
+        while count < max_count
+            && start + count < self.blocks_per_group
+            && bitmap.is_allocated(start + count) == Some(false)
+        {
+            count += 1;
+        }
+
+        bitmap.allocate_range(start, count)?;
+
+        Ok((
+            BlockAlloc {
+                group_idx,
+                block_in_group: start,
+                global_block: self.block_to_global(group_idx, start),
+            },
+            count,
+        ))
+    }
+
     /// 释放一个块
     /// * `bitmap_data` - 块位图数据
     /// * `block_in_group` - 块组内的块索引
@@ -271,6 +306,15 @@ impl InodeAllocator {
         })
     }
 
+    /// 下一次 alloc_inode_in_group 会分配的全局inode号，不修改位图
+    pub fn peek_free_inode(&self, bitmap_data: &[u8], group_idx: u32) -> Option<u32> {
+        let bitmap = InodeBitmap::new(bitmap_data, self.inodes_per_group);
+        let start_idx = self.first_inode.saturating_sub(1);
+        (start_idx..self.inodes_per_group)
+            .find(|&idx| bitmap.is_allocated(idx) == Some(false))
+            .map(|idx| self.inode_to_global(group_idx, idx))
+    }
+
     /// 释放一个inode
     /// * `bitmap_data` - inode位图数据
     /// * `inode_in_group` - 块组内的inode索引
@@ -377,6 +421,33 @@ mod tests {
         assert_eq!(alloc.block_in_group, 0);
     }
 
+    #[test]
+    fn test_block_allocator_first_free_run() {
+        let mut sb = Ext4Superblock::default();
+        sb.s_blocks_per_group = 1024;
+        sb.s_first_data_block = 0;
+
+        let allocator = BlockAllocator::new(&sb);
+
+        let mut bitmap_data = vec![0u8; 128];
+        // 块 0..8 和 20 已占用，第一段空闲为 8..20
+        bitmap_data[0] = 0xFF;
+        bitmap_data[2] = 0x10;
+
+        let (alloc, count) = allocator
+            .alloc_first_free_run(&mut bitmap_data, 1, 100)
+            .unwrap();
+        assert_eq!(alloc.block_in_group, 8);
+        assert_eq!(alloc.global_block, 1024 + 8);
+        assert_eq!(count, 12);
+
+        let (alloc, count) = allocator
+            .alloc_first_free_run(&mut bitmap_data, 1, 5)
+            .unwrap();
+        assert_eq!(alloc.block_in_group, 21);
+        assert_eq!(count, 5);
+    }
+
     #[test]
     fn test_inode_allocator() {
         let mut sb = Ext4Superblock::default();
@@ -0,0 +1,294 @@
+//! 缓存公共部分
+//!
+//! 数据块、inode表、位图三种缓存共用的 LRU 容器和挂载时的缓存配置。
+//! LRU 顺序用下标串成的双向链表维护，调整顺序和淘汰都是 O(1)；按键查找走 BTreeMap 索引，是 O(log n)。
+//! 淘汰时直接取链表尾部，不再扫描全部条目找最久未访问的
+
+use alloc::collections::BTreeMap;
+use core::ops::RangeBounds;
+use alloc::vec::Vec;
+
+use crate::ext4_backend::config::*;
+
+/// 空链接
+const NIL: usize = usize::MAX;
+
+/// 挂载时指定的缓存大小（字节）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheConfig {
+    /// 数据块缓存（包括目录块、extent 块）
+    pub data_bytes: usize,
+    /// inode 缓存，按 inode 大小折算条目数
+    pub inode_bytes: usize,
+    /// 位图缓存
+    pub bitmap_bytes: usize,
+    /// 脏数据块超过该值时把最久未访问的文件数据先写回
+    pub dirty_data_bytes: usize,
+    /// dentry 缓存条目数（包括负缓存）
+    pub dentry_entries: usize,
+}
+
+impl CacheConfig {
+    /// 按总预算划分：数据块 3/4，inode 与位图各 1/8，脏数据上限为数据块缓存的一半
+    pub fn with_budget(total_bytes: usize) -> Self {
+        let data_bytes = total_bytes / 4 * 3;
+        Self {
+            data_bytes,
+            inode_bytes: total_bytes / 8,
+            bitmap_bytes: total_bytes / 8,
+            dirty_data_bytes: data_bytes / 2,
+            dentry_entries: DENTRY_CACHE_MAX,
+        }
+    }
+
+    /// 缓存占用的总字节数（不含 dentry）
+    pub fn total_bytes(&self) -> usize {
+        self.data_bytes + self.inode_bytes + self.bitmap_bytes
+    }
+}
+
+impl Default for CacheConfig {
+    /// 与原来编译期的条目数上限一致
+    fn default() -> Self {
+        let data_bytes = DATABLOCK_CACHE_MAX * BLOCK_SIZE;
+        Self {
+            data_bytes,
+            inode_bytes: INODE_CACHE_MAX * DEFAULT_INODE_SIZE as usize,
+            bitmap_bytes: BITMAP_CACHE_MAX * BLOCK_SIZE,
+            dirty_data_bytes: data_bytes / 2,
+            dentry_entries: DENTRY_CACHE_MAX,
+        }
+    }
+}
+
+/// 预算能容纳的条目数，至少 1 条
+pub fn entries_for_budget(budget_bytes: usize, entry_bytes: usize) -> usize {
+    (budget_bytes / entry_bytes.max(1)).max(1)
+}
+
+struct LruNode<K, V> {
+    key: K,
+    value: V,
+    prev: usize,
+    next: usize,
+}
+
+/// 带 LRU 顺序的键值容器，不负责写回，淘汰策略由外层缓存决定
+pub struct LruCache<K: Ord + Clone, V> {
+    index: BTreeMap<K, usize>,
+    nodes: Vec<Option<LruNode<K, V>>>,
+    free: Vec<usize>,
+    /// 最近访问
+    head: usize,
+    /// 最久未访问
+    tail: usize,
+}
+
+impl<K: Ord + Clone, V> LruCache<K, V> {
+    pub fn new() -> Self {
+        Self {
+            index: BTreeMap::new(),
+            nodes: Vec::new(),
+            free: Vec::new(),
+            head: NIL,
+            tail: NIL,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.index.contains_key(key)
+    }
+
+    /// 只读访问，不改变 LRU 顺序
+    pub fn get(&self, key: &K) -> Option<&V> {
+        let slot = *self.index.get(key)?;
+        self.nodes[slot].as_ref().map(|n| &n.value)
+    }
+
+    /// 可变访问，不改变 LRU 顺序
+    pub fn peek_mut(&mut self, key: &K) -> Option<&mut V> {
+        let slot = *self.index.get(key)?;
+        self.nodes[slot].as_mut().map(|n| &mut n.value)
+    }
+
+    /// 访问并移到最近使用的位置
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let slot = *self.index.get(key)?;
+        self.unlink(slot);
+        self.push_front(slot);
+        self.nodes[slot].as_mut().map(|n| &mut n.value)
+    }
+
+    /// 插入到最近使用的位置，键已存在时替换并返回旧值
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        if let Some(&slot) = self.index.get(&key) {
+            self.unlink(slot);
+            self.push_front(slot);
+            let node = self.nodes[slot].as_mut()?;
+            return Some(core::mem::replace(&mut node.value, value));
+        }
+        let node = LruNode {
+            key: key.clone(),
+            value,
+            prev: NIL,
+            next: NIL,
+        };
+        let slot = match self.free.pop() {
+            Some(slot) => {
+                self.nodes[slot] = Some(node);
+                slot
+            }
+            None => {
+                self.nodes.push(Some(node));
+                self.nodes.len() - 1
+            }
+        };
+        self.index.insert(key, slot);
+        self.push_front(slot);
+        None
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let slot = self.index.remove(key)?;
+        self.unlink(slot);
+        self.free.push(slot);
+        self.nodes[slot].take().map(|n| n.value)
+    }
+
+    /// 最久未访问的键
+    pub fn lru_key(&self) -> Option<K> {
+        self.nodes.get(self.tail)?.as_ref().map(|n| n.key.clone())
+    }
+
+    /// 从最久未访问到最近访问依次遍历
+    pub fn iter_lru(&self) -> impl Iterator<Item = (&K, &V)> {
+        let mut cur = self.tail;
+        core::iter::from_fn(move || {
+            let node = self.nodes.get(cur)?.as_ref()?;
+            cur = node.prev;
+            Some((&node.key, &node.value))
+        })
+    }
+
+    /// 按键的顺序遍历
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.index.iter().filter_map(|(key, &slot)| {
+            self.nodes[slot].as_ref().map(|n| (key, &n.value))
+        })
+    }
+
+    /// 按键的顺序遍历 range 内的条目
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> impl Iterator<Item = (&K, &V)> {
+        self.index.range(range).filter_map(|(key, &slot)| {
+            self.nodes[slot].as_ref().map(|n| (key, &n.value))
+        })
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.iter().map(|(_, v)| v)
+    }
+
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut V> {
+        self.nodes.iter_mut().flatten().map(|n| &mut n.value)
+    }
+
+    pub fn clear(&mut self) {
+        self.index.clear();
+        self.nodes.clear();
+        self.free.clear();
+        self.head = NIL;
+        self.tail = NIL;
+    }
+
+    fn unlink(&mut self, slot: usize) {
+        let (prev, next) = match self.nodes[slot].as_ref() {
+            Some(n) => (n.prev, n.next),
+            None => return,
+        };
+        match self.nodes.get_mut(prev).and_then(|n| n.as_mut()) {
+            Some(p) => p.next = next,
+            None => self.head = next,
+        }
+        match self.nodes.get_mut(next).and_then(|n| n.as_mut()) {
+            Some(n) => n.prev = prev,
+            None => self.tail = prev,
+        }
+    }
+
+    fn push_front(&mut self, slot: usize) {
+        let old_head = self.head;
+        if let Some(n) = self.nodes[slot].as_mut() {
+            n.prev = NIL;
+            n.next = old_head;
+        }
+        match self.nodes.get_mut(old_head).and_then(|n| n.as_mut()) {
+            Some(h) => h.prev = slot,
+            None => self.tail = slot,
+        }
+        self.head = slot;
+    }
+}
+
+impl<K: Ord + Clone, V> Default for LruCache<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lru_order() {
+        let mut lru: LruCache<u64, u32> = LruCache::new();
+        for k in 1..=4 {
+            lru.insert(k, k as u32 * 10);
+        }
+        assert_eq!(lru.lru_key(), Some(1));
+
+        // 访问 1 之后最久未访问的变成 2
+        assert_eq!(lru.get_mut(&1), Some(&mut 10));
+        assert_eq!(lru.lru_key(), Some(2));
+        // get/peek_mut 不改变顺序
+        assert_eq!(lru.get(&2), Some(&20));
+        *lru.peek_mut(&2).unwrap() = 21;
+        assert_eq!(lru.lru_key(), Some(2));
+
+        assert_eq!(lru.remove(&2), Some(21));
+        assert_eq!(lru.remove(&2), None);
+        let order: Vec<u64> = lru.iter_lru().map(|(k, _)| *k).collect();
+        assert_eq!(order, [3, 4, 1]);
+
+        // 复用空出来的槽位，替换已有键返回旧值
+        lru.insert(5, 50);
+        assert_eq!(lru.insert(3, 31), Some(30));
+        let order: Vec<u64> = lru.iter_lru().map(|(k, _)| *k).collect();
+        assert_eq!(order, [4, 1, 5, 3]);
+        let keys: Vec<u64> = lru.iter().map(|(k, _)| *k).collect();
+        assert_eq!(keys, [1, 3, 4, 5]);
+        assert_eq!(lru.len(), 4);
+
+        lru.clear();
+        assert!(lru.is_empty());
+        assert_eq!(lru.lru_key(), None);
+    }
+
+    #[test]
+    fn test_cache_config_budget() {
+        let cfg = CacheConfig::with_budget(1 << 20);
+        assert_eq!(cfg.data_bytes, 768 << 10);
+        assert_eq!(cfg.inode_bytes + cfg.bitmap_bytes, 256 << 10);
+        assert_eq!(cfg.total_bytes(), 1 << 20);
+        assert_eq!(entries_for_budget(cfg.data_bytes, BLOCK_SIZE), 192);
+        assert_eq!(entries_for_budget(100, BLOCK_SIZE), 1);
+    }
+}
@@ -3,8 +3,10 @@ use crate::ext4_backend::superblock::*;
 // ============================================================================
 // Journal 相关配置
 // ============================================================================
-/// JBD2 日志缓冲区最大数量
+/// JBD2 日志缓冲区默认最大数量，运行时可通过 Jbd2Dev::set_journal_buffer_max 调整
 pub const JBD2_BUFFER_MAX: usize = 10; //最多10条缓存
+/// 一个原子操作句柄打开时预留的事务块数
+pub const JBD2_HANDLE_CREDITS: usize = 64;
 
 // ============================================================================
 // 块相关配置
@@ -38,6 +40,7 @@ pub const DEFAULT_INODE_SIZE: u16 = 256;
 
 // ============================================================================
 // 数据结构缓存相关配置,在小的嵌入式系统中可以适当调小防止崩内存
+// 这里只是默认值，挂载时可以用 CacheConfig 按字节指定
 // ============================================================================
 ///Inodecahe数量
 pub const INODE_CACHE_MAX: usize = 128;
@@ -45,6 +48,20 @@ pub const INODE_CACHE_MAX: usize = 128;
 pub const DATABLOCK_CACHE_MAX: usize = 128;
 ///BITMAP cache数量
 pub const BITMAP_CACHE_MAX: usize = 128;
+///Dentry cache数量（包括负缓存条目）
+pub const DENTRY_CACHE_MAX: usize = 512;
+///顺序读时第一次预读的块数
+pub const READAHEAD_MIN_BLOCKS: u32 = 4;
+///预读窗口上限（块）
+pub const READAHEAD_MAX_BLOCKS: u32 = 32;
+///extent 状态缓存记录的 inode 数量
+pub const EXTENT_STATUS_INODES: usize = 64;
+///每个 inode 最多缓存的 extent 数量
+pub const EXTENT_STATUS_PER_INODE: usize = 64;
+///SharedExt4 的 inode 读写锁分片数
+pub const SHARED_INODE_SHARDS: usize = 64;
+///SharedExt4 的数据块缓存分片数
+pub const SHARED_BLOCK_SHARDS: usize = 16;
 
 //============================================================================
 //目录项DirEntry配置
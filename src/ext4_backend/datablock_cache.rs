@@ -1,15 +1,25 @@
 //! 数据块缓存模块
 //!
-//! 提供文件和目录数据块的缓存管理，支持延迟写回和LRU淘汰
+//! 提供文件和目录数据块的缓存管理，支持延迟写回和LRU淘汰。
+//! 缓存大小按字节预算在挂载时确定；文件数据块按所属 inode 记录脏块，
+//! 可以只写回一个文件，脏数据超过上限时先写回最久未访问的文件数据
 
 use crate::ext4_backend::blockdev::*;
+use crate::ext4_backend::buffer_cache::*;
 use crate::ext4_backend::config::*;
 use crate::ext4_backend::error::*;
-use alloc::collections::BTreeMap;
+use alloc::collections::{BTreeMap, BTreeSet};
 use alloc::vec::Vec;
 /// 数据块缓存键（全局块号）
 pub type BlockCacheKey = u64;
 
+/// 一次写回合并的最大连续块数
+const MAX_WRITEBACK_RUN: usize = 128;
+/// 一次 write_vectored 提交的最大段数
+const MAX_WRITEBACK_SEGMENTS: usize = 64;
+/// 一次 read_blocks 读入的最大连续块数
+const MAX_READ_RUN: u64 = 128;
+
 /// 缓存的数据块
 #[derive(Debug, Clone)]
 pub struct CachedBlock {
@@ -19,8 +29,8 @@ pub struct CachedBlock {
     pub dirty: bool,
     /// 块号
     pub block_num: u64,
-    /// 最后访问时间戳（用于LRU）
-    pub last_access: u64,
+    /// 所属文件的 inode 号，目录块、extent 块等为 None
+    pub owner: Option<u32>,
 }
 
 impl CachedBlock {
@@ -29,7 +39,7 @@ impl CachedBlock {
             data,
             dirty: false,
             block_num,
-            last_access: 0,
+            owner: None,
         }
     }
 
@@ -42,11 +52,17 @@ impl CachedBlock {
 /// 数据块缓存管理器
 pub struct DataBlockCache {
     /// 缓存的数据块
-    cache: BTreeMap<BlockCacheKey, CachedBlock>,
-    /// 最大缓存条目数
+    cache: LruCache<BlockCacheKey, CachedBlock>,
+    /// 最大缓存条目数（由字节预算折算）
     max_entries: usize,
-    /// 访问计数器（用于LRU）
-    access_counter: u64,
+    /// 脏块数上限，超过后 balance_dirty 写回文件数据
+    dirty_limit: usize,
+    /// 脏块数
+    dirty_count: usize,
+    /// 每个文件的脏数据块
+    dirty_files: BTreeMap<u32, BTreeSet<u64>>,
+    /// start_tracking 之后被改脏的块
+    tracked: Option<BTreeSet<u64>>,
     /// 块大小
     block_size: usize,
 }
@@ -58,10 +74,20 @@ impl DataBlockCache {
     /// * `max_entries` - 最大缓存条目数，建议32-128个
     /// * `block_size` - 块大小（通常是4096字节）
     pub fn new(max_entries: usize, block_size: usize) -> Self {
+        Self::with_budget(max_entries * block_size, max_entries * block_size / 2, block_size)
+    }
+
+    /// 按字节预算创建数据块缓存
+    /// * `budget_bytes` - 缓存占用上限
+    /// * `dirty_limit_bytes` - 脏数据上限
+    pub fn with_budget(budget_bytes: usize, dirty_limit_bytes: usize, block_size: usize) -> Self {
         Self {
-            cache: BTreeMap::new(),
-            max_entries,
-            access_counter: 0,
+            cache: LruCache::new(),
+            max_entries: entries_for_budget(budget_bytes, block_size),
+            dirty_limit: entries_for_budget(dirty_limit_bytes, block_size),
+            dirty_count: 0,
+            dirty_files: BTreeMap::new(),
+            tracked: None,
             block_size,
         }
     }
@@ -77,7 +103,7 @@ impl DataBlockCache {
         block_dev: &mut Jbd2Dev<B>,
         block_num: u64,
     ) -> BlockDevResult<Vec<u8>> {
-        block_dev.read_block(block_num as u32)?;
+        block_dev.read_block(block_num)?;
         let buffer = block_dev.buffer();
         Ok(buffer.to_vec())
     }
@@ -92,24 +118,7 @@ impl DataBlockCache {
         block_dev: &mut Jbd2Dev<B>,
         block_num: u64,
     ) -> BlockDevResult<&CachedBlock> {
-        // 如果缓存中不存在，则加载
-        if !self.cache.contains_key(&block_num) {
-            if self.cache.len() >= self.max_entries {
-                self.evict_lru(block_dev)?;
-            }
-
-            let data = self.load_block(block_dev, block_num)?;
-            let cached = CachedBlock::new(data, block_num);
-            self.cache.insert(block_num, cached);
-        }
-
-        // 更新访问时间
-        self.access_counter += 1;
-        if let Some(cached) = self.cache.get_mut(&block_num) {
-            cached.last_access = self.access_counter;
-        }
-
-        self.cache.get(&block_num).ok_or(BlockDevError::Corrupted)
+        self.get_or_load_mut(block_dev, block_num).map(|cached| &*cached)
     }
 
     /// 内部使用：获取可变引用（如果不存在则从磁盘加载）
@@ -119,22 +128,51 @@ impl DataBlockCache {
         block_num: u64,
     ) -> BlockDevResult<&mut CachedBlock> {
         if !self.cache.contains_key(&block_num) {
-            if self.cache.len() >= self.max_entries {
-                self.evict_lru(block_dev)?;
-            }
-
+            self.make_room(block_dev)?;
             let data = self.load_block(block_dev, block_num)?;
-            let cached = CachedBlock::new(data, block_num);
-            self.cache.insert(block_num, cached);
+            self.cache.insert(block_num, CachedBlock::new(data, block_num));
         }
+        self.cache.get_mut(&block_num).ok_or(BlockDevError::Corrupted)
+    }
 
-        self.access_counter += 1;
-        if let Some(cached) = self.cache.get_mut(&block_num) {
-            cached.last_access = self.access_counter;
-            Ok(cached)
-        } else {
-            Err(BlockDevError::Corrupted)
+    /// 把 [start, start + count) 中未缓存的块读入缓存，连续未命中的块合并成一次 read_blocks
+    /// 读入的块不超过缓存容量的一半，避免刚读入的块互相淘汰
+    pub fn load_run<B: BlockDevice>(
+        &mut self,
+        block_dev: &mut Jbd2Dev<B>,
+        start: u64,
+        count: u64,
+    ) -> BlockDevResult<()> {
+        let end = start + count.min(self.run_limit());
+        let mut blk = start;
+        while blk < end {
+            if self.cache.contains_key(&blk) {
+                blk += 1;
+                continue;
+            }
+            let mut run_end = blk + 1;
+            while run_end < end
+                && run_end - blk < MAX_READ_RUN
+                && !self.cache.contains_key(&run_end)
+            {
+                run_end += 1;
+            }
+            let n = (run_end - blk) as usize;
+            let mut buf = alloc::vec![0u8; n * BLOCK_SIZE];
+            block_dev.read_blocks(&mut buf, blk, n as u32)?;
+            for (i, chunk) in buf.chunks_exact(BLOCK_SIZE).enumerate() {
+                self.make_room(block_dev)?;
+                let block_num = blk + i as u64;
+                self.cache.insert(block_num, CachedBlock::new(chunk.to_vec(), block_num));
+            }
+            blk = run_end;
         }
+        Ok(())
+    }
+
+    /// load_run 一次最多读入的块数
+    pub fn run_limit(&self) -> u64 {
+        (self.max_entries / 2).max(1) as u64
     }
 
     /// 获取已缓存的数据块（不加载）
@@ -142,39 +180,26 @@ impl DataBlockCache {
         self.cache.get(&block_num)
     }
 
-    /// 获取可变引用
+    /// 获取可变引用，修改内容后需要调用 mark_dirty
     pub fn get_mut(&mut self, block_num: u64) -> Option<&mut CachedBlock> {
-        if let Some(cached) = self.cache.get_mut(&block_num) {
-            self.access_counter += 1;
-            cached.last_access = self.access_counter;
-            Some(cached)
-        } else {
-            None
-        }
+        self.cache.get_mut(&block_num)
     }
 
     /// 创建新的数据块缓存（不立即写入磁盘），并返回可变引用 自动标记为脏
+    /// 这里拿不到块设备，超出预算的部分在下一次加载时淘汰
     pub fn create_new(&mut self, block_num: u64) -> &mut CachedBlock {
-        if self.cache.len() >= self.max_entries {
-            // 这里无法调用需要 block_dev 的 evict_lru，交由调用方控制
-        }
-
         let data = alloc::vec![0u8; self.block_size];
-        let mut cached = CachedBlock::new(data, block_num);
-        cached.dirty = true;
-
-        self.access_counter += 1;
-        cached.last_access = self.access_counter;
-
-        self.cache.insert(block_num, cached);
+        if let Some(old) = self.cache.insert(block_num, CachedBlock::new(data, block_num)) {
+            self.forget_dirty(&old);
+        }
+        self.set_dirty(block_num, None);
         self.cache.get_mut(&block_num).unwrap()
     }
 
     /// 标记数据块为脏
     pub fn mark_dirty(&mut self, block_num: u64) {
-        if let Some(cached) = self.cache.get_mut(&block_num) {
-            cached.mark_dirty();
-        }
+        let owner = self.cache.get(&block_num).and_then(|c| c.owner);
+        self.set_dirty(block_num, owner);
     }
 
     /// 使用闭包修改指定数据块，并自动标记为脏
@@ -190,7 +215,26 @@ impl DataBlockCache {
     {
         let cached = self.get_or_load_mut(block_dev, block_num)?;
         f(&mut cached.data);
-        cached.mark_dirty();
+        let owner = cached.owner;
+        self.set_dirty(block_num, owner);
+        Ok(())
+    }
+
+    /// 修改文件 `ino` 的数据块，脏块记在该文件名下
+    pub fn modify_file<B, F>(
+        &mut self,
+        block_dev: &mut Jbd2Dev<B>,
+        ino: u32,
+        block_num: u64,
+        f: F,
+    ) -> BlockDevResult<()>
+    where
+        B: BlockDevice,
+        F: FnOnce(&mut [u8]),
+    {
+        let cached = self.get_or_load_mut(block_dev, block_num)?;
+        f(&mut cached.data);
+        self.set_dirty(block_num, Some(ino));
         Ok(())
     }
 
@@ -201,22 +245,91 @@ impl DataBlockCache {
     {
         let cached = self.create_new(block_num);
         f(&mut cached.data);
-        cached.mark_dirty();
     }
 
-    /// LRU淘汰：找到最久未访问的并写回（如果脏）
-    fn evict_lru<B: BlockDevice>(&mut self, block_dev: &mut Jbd2Dev<B>) -> BlockDevResult<()> {
-        // 找到最小的last_access
-        let lru_key = self
-            .cache
-            .iter()
-            .min_by_key(|(_, cached)| cached.last_access)
-            .map(|(key, _)| *key);
+    /// 初始化文件 `ino` 新分配的数据块
+    pub fn modify_new_file<F>(&mut self, ino: u32, block_num: u64, f: F)
+    where
+        F: FnOnce(&mut [u8]),
+    {
+        self.modify_new(block_num, f);
+        self.set_dirty(block_num, Some(ino));
+    }
 
-        if let Some(key) = lru_key {
-            self.evict(block_dev, key)?;
+    /// 标记为脏并挂到所属文件的脏块列表上
+    fn set_dirty(&mut self, block_num: u64, owner: Option<u32>) {
+        let Some(cached) = self.cache.peek_mut(&block_num) else {
+            return;
+        };
+        let was_dirty = cached.dirty;
+        let old_owner = cached.owner;
+        cached.dirty = true;
+        cached.owner = owner;
+        if !was_dirty {
+            self.dirty_count += 1;
+        } else if old_owner != owner {
+            self.unlink_owner(old_owner, block_num);
+        }
+        if let Some(ino) = owner {
+            self.dirty_files.entry(ino).or_default().insert(block_num);
+        }
+        if let Some(tracked) = self.tracked.as_mut() {
+            tracked.insert(block_num);
         }
+    }
+
+    /// 开始记录之后被改脏的块
+    pub fn start_tracking(&mut self) {
+        self.tracked = Some(BTreeSet::new());
+    }
+
+    /// 停止记录，返回 start_tracking 之后被改脏的块
+    pub fn take_tracked(&mut self) -> BTreeSet<u64> {
+        self.tracked.take().unwrap_or_default()
+    }
+
+    /// 写回之后清除脏标记
+    fn set_clean(&mut self, block_num: u64) {
+        let Some(cached) = self.cache.peek_mut(&block_num) else {
+            return;
+        };
+        if !cached.dirty {
+            return;
+        }
+        cached.dirty = false;
+        let owner = cached.owner;
+        self.dirty_count -= 1;
+        self.unlink_owner(owner, block_num);
+    }
+
+    /// 条目被移出缓存时同步脏块计数
+    fn forget_dirty(&mut self, cached: &CachedBlock) {
+        if cached.dirty {
+            self.dirty_count -= 1;
+            self.unlink_owner(cached.owner, cached.block_num);
+        }
+    }
 
+    fn unlink_owner(&mut self, owner: Option<u32>, block_num: u64) {
+        let Some(ino) = owner else {
+            return;
+        };
+        if let Some(blocks) = self.dirty_files.get_mut(&ino) {
+            blocks.remove(&block_num);
+            if blocks.is_empty() {
+                self.dirty_files.remove(&ino);
+            }
+        }
+    }
+
+    /// 超出预算时淘汰最久未访问的块，给新块腾出位置
+    fn make_room<B: BlockDevice>(&mut self, block_dev: &mut Jbd2Dev<B>) -> BlockDevResult<()> {
+        while self.cache.len() >= self.max_entries {
+            let Some(key) = self.cache.lru_key() else {
+                break;
+            };
+            self.evict(block_dev, key)?;
+        }
         Ok(())
     }
 
@@ -226,66 +339,119 @@ impl DataBlockCache {
         block_dev: &mut Jbd2Dev<B>,
         block_num: u64,
     ) -> BlockDevResult<()> {
-        if let Some(cached) = self.cache.remove(&block_num)
-            && cached.dirty {
-                // 写回磁盘
-                Self::write_block_static(block_dev, cached.block_num, &cached.data)?;
+        if let Some(cached) = self.cache.get(&block_num)
+            && cached.dirty
+        {
+            // 写回磁盘
+            Self::write_block_static(block_dev, cached.block_num, &cached.data, false)?;
+            self.set_clean(block_num);
+        }
+        self.cache.remove(&block_num);
+        Ok(())
+    }
+
+    /// 把排好序的脏块写回，连续块合并成一段，多段一次 write_vectored
+    fn write_back<B: BlockDevice>(
+        &mut self,
+        block_dev: &mut Jbd2Dev<B>,
+        sorted: &[u64],
+    ) -> BlockDevResult<()> {
+        let mut idx = 0usize;
+        while idx < sorted.len() {
+            // 连续块合成一段，攒够一批之后一次 write_vectored 写出
+            let batch_start = idx;
+            let mut segments: Vec<(u64, Vec<u8>)> = Vec::new();
+            while idx < sorted.len() && segments.len() < MAX_WRITEBACK_SEGMENTS {
+                let start_block = sorted[idx];
+                let mut buf: Vec<u8> = Vec::new();
+                let mut run_len = 0usize;
+                while idx + run_len < sorted.len()
+                    && run_len < MAX_WRITEBACK_RUN
+                    && sorted[idx + run_len] == start_block + run_len as u64
+                {
+                    let Some(cached) = self.cache.get(&sorted[idx + run_len]) else {
+                        break;
+                    };
+                    buf.extend_from_slice(&cached.data);
+                    run_len += 1;
+                }
+                if run_len > 0 {
+                    segments.push((start_block, buf));
+                }
+                idx += run_len.max(1);
             }
+            let reqs: Vec<(u64, &[u8])> = segments
+                .iter()
+                .map(|(block, buf)| (*block, buf.as_slice()))
+                .collect();
+            block_dev.write_vectored(&reqs, false)?;
+            for &block_num in &sorted[batch_start..idx] {
+                self.set_clean(block_num);
+            }
+        }
         Ok(())
     }
 
     /// 刷新所有脏数据块到磁盘
     pub fn flush_all<B: BlockDevice>(&mut self, block_dev: &mut Jbd2Dev<B>) -> BlockDevResult<()> {
-        // 收集需要写回的数据块信息（block_num, data），并按块号排序
-        let mut dirty_blocks: Vec<(u64, Vec<u8>)> = self
+        if self.dirty_count == 0 {
+            return Ok(());
+        }
+        // 按块号顺序收集，便于合并连续块
+        let dirty: Vec<u64> = self
             .cache
-            .values()
-            .filter(|cached| cached.dirty)
-            .map(|cached| (cached.block_num, cached.data.clone()))
+            .iter()
+            .filter(|(_, cached)| cached.dirty)
+            .map(|(block_num, _)| *block_num)
             .collect();
+        self.write_back(block_dev, &dirty)
+    }
 
-        if dirty_blocks.is_empty() {
+    /// 只写回文件 `ino` 的脏数据块
+    pub fn flush_inode<B: BlockDevice>(
+        &mut self,
+        block_dev: &mut Jbd2Dev<B>,
+        ino: u32,
+    ) -> BlockDevResult<()> {
+        let Some(blocks) = self.dirty_files.get(&ino) else {
             return Ok(());
-        }
-
-        dirty_blocks.sort_by_key(|(block_num, _)| *block_num);
-
-        // 将连续块聚合后，使用 write_blocks 一次性写回
-        let max_part_size = BLOCK_SIZE * 100; //最大聚合块数;
-        let block_size = self.block_size;
-        let mut idx = 0usize;
-        while idx < dirty_blocks.len() {
-            let (start_block, _) = dirty_blocks[idx];
-            let mut run_len = 1usize;
-
-            // 统计从 start_block 开始的连续块数量
-            while idx + run_len < dirty_blocks.len() && run_len <= max_part_size {
-                let expected = start_block + run_len as u64;
-                if dirty_blocks[idx + run_len].0 == expected {
-                    run_len += 1;
-                } else {
-                    break;
-                }
-            }
-
-            // 聚合这一段连续块的数据到一个大的 buffer 中
-            let mut buf: Vec<u8> = Vec::with_capacity(block_size * run_len);
-            for off in 0..run_len {
-                buf.extend_from_slice(&dirty_blocks[idx + off].1);
-            }
+        };
+        let dirty: Vec<u64> = blocks.iter().copied().collect();
+        self.write_back(block_dev, &dirty)
+    }
 
-            // 通过底层的 write_blocks 一次性写入连续块
-            block_dev.write_blocks(&buf, start_block as u32, run_len as u32, false)?;
+    /// 写回不属于任何文件的脏块（目录块、extent 块等）
+    pub fn flush_unowned<B: BlockDevice>(&mut self, block_dev: &mut Jbd2Dev<B>) -> BlockDevResult<()> {
+        let dirty: Vec<u64> = self
+            .cache
+            .iter()
+            .filter(|(_, cached)| cached.dirty && cached.owner.is_none())
+            .map(|(block_num, _)| *block_num)
+            .collect();
+        self.write_back(block_dev, &dirty)
+    }
 
-            idx += run_len;
-        }
+    /// 有脏数据块的文件
+    pub fn dirty_inodes(&self) -> impl Iterator<Item = u32> + '_ {
+        self.dirty_files.keys().copied()
+    }
 
-        // 清除脏标记
-        for cached in self.cache.values_mut() {
-            cached.dirty = false;
+    /// 写回节流：脏块超过上限时，从最久未访问的文件数据开始写回到上限的一半
+    /// 只写文件数据块，目录块等元数据仍然随事务一起提交
+    pub fn balance_dirty<B: BlockDevice>(&mut self, block_dev: &mut Jbd2Dev<B>) -> BlockDevResult<()> {
+        if self.dirty_count <= self.dirty_limit {
+            return Ok(());
         }
-
-        Ok(())
+        let target = self.dirty_limit / 2;
+        let mut dirty: Vec<u64> = self
+            .cache
+            .iter_lru()
+            .filter(|(_, cached)| cached.dirty && cached.owner.is_some())
+            .map(|(block_num, _)| *block_num)
+            .take(self.dirty_count - target)
+            .collect();
+        dirty.sort_unstable();
+        self.write_back(block_dev, &dirty)
     }
 
     /// 刷新指定数据块到磁盘
@@ -295,14 +461,28 @@ impl DataBlockCache {
         block_num: u64,
     ) -> BlockDevResult<()> {
         if let Some(cached) = self.cache.get(&block_num)
-            && cached.dirty {
-                let data = cached.data.clone();
-                Self::write_block_static(block_dev, block_num, &data)?;
+            && cached.dirty
+        {
+            Self::write_block_static(block_dev, block_num, &cached.data, false)?;
+            self.set_clean(block_num);
+        }
+        Ok(())
+    }
 
-                if let Some(cached) = self.cache.get_mut(&block_num) {
-                    cached.dirty = false;
-                }
-            }
+    /// 把指定脏块作为元数据写入日志事务
+    ///
+    /// 用于目录块这类需要和 inode、位图修改一起原子提交的数据块
+    pub fn flush_journaled<B: BlockDevice>(
+        &mut self,
+        block_dev: &mut Jbd2Dev<B>,
+        block_num: u64,
+    ) -> BlockDevResult<()> {
+        if let Some(cached) = self.cache.get(&block_num)
+            && cached.dirty
+        {
+            Self::write_block_static(block_dev, block_num, &cached.data, true)?;
+            self.set_clean(block_num);
+        }
         Ok(())
     }
 
@@ -311,11 +491,12 @@ impl DataBlockCache {
         block_dev: &mut Jbd2Dev<B>,
         block_num: u64,
         data: &[u8],
+        is_metadata: bool,
     ) -> BlockDevResult<()> {
-        block_dev.read_block(block_num as u32)?;
+        block_dev.read_block(block_num)?;
         let buffer = block_dev.buffer_mut();
         buffer[..data.len()].copy_from_slice(data);
-        block_dev.write_block(block_num as u32, false)?;
+        block_dev.write_block(block_num, is_metadata)?;
         Ok(())
     }
 
@@ -323,25 +504,27 @@ impl DataBlockCache {
     ///
     /// 用于删除文件或目录时，避免写回已删除的数据
     pub fn invalidate(&mut self, block_num: u64) {
-        self.cache.remove(&block_num);
+        if let Some(cached) = self.cache.remove(&block_num) {
+            self.forget_dirty(&cached);
+        }
     }
 
     /// 清空缓存（不写回）
     pub fn clear(&mut self) {
         self.cache.clear();
+        self.dirty_files.clear();
+        self.dirty_count = 0;
     }
 
     /// 获取缓存统计
     pub fn stats(&self) -> DataBlockCacheStats {
-        let dirty_count = self.cache.values().filter(|c| c.dirty).count();
-
-        let total_size = self.cache.len() * self.block_size;
-
         DataBlockCacheStats {
             total_entries: self.cache.len(),
-            dirty_entries: dirty_count,
+            dirty_entries: self.dirty_count,
             max_entries: self.max_entries,
-            total_size_bytes: total_size,
+            total_size_bytes: self.cache.len() * self.block_size,
+            dirty_size_bytes: self.dirty_count * self.block_size,
+            dirty_files: self.dirty_files.len(),
         }
     }
 }
@@ -353,11 +536,83 @@ pub struct DataBlockCacheStats {
     pub dirty_entries: usize,
     pub max_entries: usize,
     pub total_size_bytes: usize,
+    pub dirty_size_bytes: usize,
+    pub dirty_files: usize,
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::ext4_backend::memdev::MemDev;
+
+    #[test]
+    fn test_budget_eviction_is_lru() {
+        let storage = MemDev::new(64);
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, storage.clone(), false);
+        let mut cache = DataBlockCache::with_budget(3 * BLOCK_SIZE, 3 * BLOCK_SIZE, BLOCK_SIZE);
+        assert_eq!(cache.stats().max_entries, 3);
+
+        cache.modify(&mut dev, 1, |d| d[0] = 1).unwrap();
+        cache.get_or_load(&mut dev, 2).unwrap();
+        cache.get_or_load(&mut dev, 3).unwrap();
+        // 访问 1 之后最久未访问的是 2，干净块直接丢弃
+        cache.get_or_load(&mut dev, 1).unwrap();
+        cache.get_or_load(&mut dev, 4).unwrap();
+        assert!(cache.get(2).is_none());
+        assert!(storage.take_writes().is_empty());
+
+        // 接着淘汰脏块 3 之后的 1 时写回
+        cache.get_or_load(&mut dev, 5).unwrap();
+        cache.get_or_load(&mut dev, 6).unwrap();
+        assert!(cache.get(1).is_none());
+        assert_eq!(storage.take_writes(), [1]);
+        assert_eq!(storage.block(1)[0], 1);
+        assert_eq!(cache.stats().dirty_entries, 0);
+    }
+
+    #[test]
+    fn test_per_inode_flush_and_throttling() {
+        let storage = MemDev::new(64);
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, storage.clone(), false);
+        let mut cache = DataBlockCache::with_budget(32 * BLOCK_SIZE, 8 * BLOCK_SIZE, BLOCK_SIZE);
+
+        for blk in 10..13 {
+            cache.modify_new_file(7, blk, |d| d[0] = 7);
+        }
+        for blk in 20..22 {
+            cache.modify_new_file(8, blk, |d| d[0] = 8);
+        }
+        cache.modify_new(30, |d| d[0] = 1);
+        assert_eq!(cache.dirty_inodes().collect::<Vec<_>>(), [7, 8]);
+
+        cache.flush_inode(&mut dev, 7).unwrap();
+        assert_eq!(storage.take_writes(), [10, 11, 12]);
+        assert_eq!(cache.dirty_inodes().collect::<Vec<_>>(), [8]);
+        let stats = cache.stats();
+        assert_eq!((stats.dirty_entries, stats.dirty_files), (3, 1));
+
+        // 脏块未超过上限时不写回
+        cache.balance_dirty(&mut dev).unwrap();
+        assert!(storage.take_writes().is_empty());
+
+        // 超过 8 块后写回最久未访问的文件数据，直到剩下一半
+        for blk in 40..47 {
+            cache.modify_new_file(9, blk, |d| d[0] = 9);
+        }
+        assert_eq!(cache.stats().dirty_entries, 10);
+        cache.balance_dirty(&mut dev).unwrap();
+        assert_eq!(storage.take_writes(), [20, 21, 40, 41, 42, 43]);
+        assert_eq!(cache.stats().dirty_entries, 4);
+        // 目录块这类不属于文件的块不参与节流
+        assert!(cache.get(30).unwrap().dirty);
+
+        // 失效的脏块不再计数
+        cache.invalidate(46);
+        assert_eq!(cache.stats().dirty_entries, 3);
+        cache.flush_all(&mut dev).unwrap();
+        assert_eq!(storage.take_writes(), [30, 44, 45]);
+        assert_eq!(cache.stats().dirty_files, 0);
+    }
 
     #[test]
     fn test_datablock_cache_basic() {
@@ -0,0 +1,137 @@
+//! 目录项缓存模块
+//!
+//! 缓存 (父目录 inode 号, 名字) -> 子 inode 号 的查找结果，支持负缓存和 LRU 淘汰。
+//! 目录项的增删改都要让对应条目失效，释放的目录 inode 要清掉它下面的所有条目
+
+use alloc::vec::Vec;
+
+use crate::ext4_backend::buffer_cache::LruCache;
+use crate::ext4_backend::config::*;
+
+/// Dentry缓存键（父目录 inode 号, 名字）
+pub type DentryCacheKey = (u32, Vec<u8>);
+
+/// Dentry缓存管理器
+pub struct DentryCache {
+    /// 缓存的子 inode 号，None 表示名字不存在（负缓存）
+    cache: LruCache<DentryCacheKey, Option<u32>>,
+    /// 最大缓存条目数
+    max_entries: usize,
+}
+
+impl DentryCache {
+    /// 创建dentry缓存
+    /// * `max_entries` - 最大缓存条目数
+    pub fn new(max_entries: usize) -> Self {
+        Self {
+            cache: LruCache::new(),
+            max_entries,
+        }
+    }
+
+    /// 查找缓存：未命中返回 None，命中返回 Some(子 inode 号或负缓存的 None)
+    pub fn lookup(&mut self, parent: u32, name: &[u8]) -> Option<Option<u32>> {
+        self.cache.get_mut(&(parent, name.to_vec())).copied()
+    }
+
+    /// 记录一次查找结果，ino 为 None 时记录为负缓存
+    pub fn insert(&mut self, parent: u32, name: &[u8], ino: Option<u32>) {
+        if self.max_entries == 0 {
+            return;
+        }
+        let key = (parent, name.to_vec());
+        if !self.cache.contains_key(&key) && self.cache.len() >= self.max_entries {
+            self.evict_lru();
+        }
+        self.cache.insert(key, ino);
+    }
+
+    /// 目录项 (parent, name) 被修改后让它失效
+    pub fn invalidate(&mut self, parent: u32, name: &[u8]) {
+        self.cache.remove(&(parent, name.to_vec()));
+    }
+
+    /// 目录 parent 被释放后清掉它下面的所有条目，避免 inode 号复用时命中旧条目
+    pub fn invalidate_dir(&mut self, parent: u32) {
+        let start = (parent, Vec::new());
+        let keys: Vec<DentryCacheKey> = self
+            .cache
+            .range(start..)
+            .take_while(|(key, _)| key.0 == parent)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in keys {
+            self.cache.remove(&key);
+        }
+    }
+
+    /// 清空缓存
+    pub fn clear(&mut self) {
+        self.cache.clear();
+    }
+
+    /// 当前缓存条目数
+    pub fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    /// 缓存是否为空
+    pub fn is_empty(&self) -> bool {
+        self.cache.is_empty()
+    }
+
+    /// LRU淘汰：直接取链表尾部
+    fn evict_lru(&mut self) {
+        if let Some(key) = self.cache.lru_key() {
+            self.cache.remove(&key);
+        }
+    }
+}
+
+impl Default for DentryCache {
+    /// 创建默认配置的缓存
+    fn default() -> Self {
+        Self::new(DENTRY_CACHE_MAX)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup_negative_and_lru() {
+        let mut cache = DentryCache::new(2);
+        assert_eq!(cache.lookup(2, b"a"), None);
+
+        cache.insert(2, b"a", Some(12));
+        cache.insert(2, b"missing", None);
+        assert_eq!(cache.lookup(2, b"a"), Some(Some(12)));
+        assert_eq!(cache.lookup(2, b"missing"), Some(None));
+
+        // "a" 比 "missing" 更早被访问，插入第三条时被淘汰
+        cache.lookup(2, b"missing");
+        cache.insert(3, b"b", Some(13));
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.lookup(2, b"a"), None);
+        assert_eq!(cache.lookup(3, b"b"), Some(Some(13)));
+    }
+
+    #[test]
+    fn test_invalidate_dir() {
+        let mut cache = DentryCache::new(16);
+        cache.insert(11, b"x", Some(20));
+        cache.insert(12, b"x", Some(21));
+        cache.insert(12, b"y", None);
+        cache.insert(13, b"x", Some(22));
+
+        cache.invalidate_dir(12);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.lookup(12, b"x"), None);
+        assert_eq!(cache.lookup(11, b"x"), Some(Some(20)));
+        assert_eq!(cache.lookup(13, b"x"), Some(Some(22)));
+
+        cache.invalidate(11, b"x");
+        assert_eq!(cache.lookup(11, b"x"), None);
+    }
+}
@@ -1,16 +1,19 @@
 //创建文件夹功能模块
 
 
-use crate::alloc::string::ToString;
 use crate::ext4_backend::blockdev::*;
+use crate::ext4_backend::casefold::*;
 use crate::ext4_backend::config::*;
 use crate::ext4_backend::disknode::*;
 use crate::ext4_backend::endian::*;
 use crate::ext4_backend::entries::*;
 use crate::ext4_backend::ext4::*;
 use crate::ext4_backend::extents_tree::*;
+use crate::ext4_backend::fast_commit::{crc32c, dir_entry_file_type};
 use crate::ext4_backend::file::*;
+use crate::ext4_backend::hashtree::*;
 use crate::ext4_backend::loopfile::*;
+use crate::ext4_backend::namei::*;
 use crate::ext4_backend::error::*;
 use alloc::string::String;
 use alloc::vec::Vec;
@@ -46,99 +49,371 @@ pub fn split_paren_child_and_tranlatevalid(pat: &str) -> String {
 }
 
 /// 路径解析，返回 (inode_num, inode)
+///
+/// 相对路径从当前工作目录开始，最后一个分量是符号链接时不跟随
 pub fn get_inode_with_num<B: BlockDevice>(
     fs: &mut Ext4FileSystem,
     device: &mut Jbd2Dev<B>,
     path: &str,
 ) -> BlockDevResult<Option<(u32, Ext4Inode)>> {
-    // 根目录特殊处理
-    if path.is_empty() || path == "/" {
-        let inode = fs.get_root(device)?;
-        return Ok(Some((fs.root_inode, inode)));
+    found(lookup_path(fs, device, path, false))
+}
+
+/// 在单个目录块中为新目录项寻找空间：复用空闲条目或切分已有条目的尾部空间
+pub fn insert_entry_into_block(data: &mut [u8], new_entry: &Ext4DirEntry2) -> bool {
+    let block_bytes = data.len();
+    let new_rec_len = Ext4DirEntry2::entry_len(new_entry.name_len) as usize;
+
+    let mut offset = 0usize;
+    while offset + 8 <= block_bytes {
+        let inode = u32::from_le_bytes([
+            data[offset],
+            data[offset + 1],
+            data[offset + 2],
+            data[offset + 3],
+        ]);
+        let rec_len = u16::from_le_bytes([data[offset + 4], data[offset + 5]]) as usize;
+        if rec_len < 8 {
+            return false;
+        }
+        let entry_end = offset + rec_len;
+        if entry_end > block_bytes {
+            return false;
+        }
+
+        // Free entry: directly use it if it can hold the new entry.
+        if inode == 0 && rec_len >= new_rec_len {
+            let mut full_entry = *new_entry;
+            full_entry.rec_len = rec_len as u16;
+            full_entry.to_disk_bytes(&mut data[offset..offset + 8]);
+            let nlen = full_entry.name_len as usize;
+            data[offset + 8..offset + 8 + nlen].copy_from_slice(&full_entry.name[..nlen]);
+            return true;
+        }
+
+        // Occupied entry: try to split tail space.
+        if inode != 0 {
+            let cur_name_len = data[offset + 6] as usize;
+            let ideal = Ext4DirEntry2::entry_len(cur_name_len as u8) as usize;
+            if ideal <= rec_len && rec_len - ideal >= new_rec_len {
+                let tail = rec_len - ideal;
+                let ideal_bytes = (ideal as u16).to_le_bytes();
+                data[offset + 4] = ideal_bytes[0];
+                data[offset + 5] = ideal_bytes[1];
+
+                let new_off = offset + ideal;
+                let mut full_entry = *new_entry;
+                full_entry.rec_len = tail as u16;
+                full_entry.to_disk_bytes(&mut data[new_off..new_off + 8]);
+                let nlen = full_entry.name_len as usize;
+                data[new_off + 8..new_off + 8 + nlen].copy_from_slice(&full_entry.name[..nlen]);
+                return true;
+            }
+        }
+
+        offset = entry_end;
     }
+    false
+}
 
-    // 按 '/' 分割
-    let components = path.split('/').filter(|s| !s.is_empty());
+/// htree 索引块末尾的 dx_tail：dt_reserved + dt_checksum
+const DX_TAIL_LEN: usize = 8;
 
-    // 从根开始
-    let mut current_inode = fs.get_root(device)?;
-    let mut current_ino: u32 = fs.root_inode;
+/// 目录块的校验和尾部：开启 metadata_csum 时占用每个目录叶子块末尾 12 字节，
+/// htree 索引块则在最后一个 dx_entry 之后保留 8 字节的 dx_tail
+#[derive(Debug, Clone, Copy)]
+pub struct DirBlockTail {
+    seed: Option<u32>,
+}
 
-    for name in components {
-        if !current_inode.is_dir() {
-            return Ok(None);
+impl DirBlockTail {
+    /// 由目录的 inode 号和 i_generation 计算校验和种子；未开启 metadata_csum 时不保留尾部
+    pub fn new(fs: &Ext4FileSystem, dir_ino: u32, dir_inode: &Ext4Inode) -> Self {
+        let seed = fs.superblock.has_metadata_csum().then(|| {
+            let seed = crc32c(fs.superblock.csum_seed(), &dir_ino.to_le_bytes());
+            crc32c(seed, &dir_inode.i_generation.to_le_bytes())
+        });
+        Self { seed }
+    }
+
+    /// 目录项可以使用的字节数（块大小减去尾部）
+    pub fn usable(&self) -> usize {
+        match self.seed {
+            Some(_) => BLOCK_SIZE - Ext4DirEntryTail::TAIL_LEN as usize,
+            None => BLOCK_SIZE,
         }
+    }
 
-        if name == "." {
-            continue;
+    /// 在新初始化的目录块末尾写入尾部并计算校验和
+    pub fn init(&self, data: &mut [u8]) {
+        if self.seed.is_none() {
+            return;
         }
-        if name == ".." {
-            // 这里只处理简单情况：根的父仍为根
-            if current_ino == fs.root_inode {
-                continue;
-            }
-            // 更完整的 ".." 解析可以在后续扩展
-            continue;
+        let off = self.usable();
+        Ext4DirEntryTail::new(0).to_disk_bytes(&mut data[off..BLOCK_SIZE]);
+        self.update(data);
+    }
+
+    /// 修改目录块后重新计算校验和；块末尾没有合法尾部（如 htree 索引块）时不处理
+    pub fn update(&self, data: &mut [u8]) {
+        let Some(seed) = self.seed else {
+            return;
+        };
+        let off = self.usable();
+        let mut tail = Ext4DirEntryTail::from_disk_bytes(&data[off..BLOCK_SIZE]);
+        if !tail.is_valid() {
+            return;
+        }
+        tail.det_checksum = crc32c(seed, &data[..off]);
+        tail.to_disk_bytes(&mut data[off..BLOCK_SIZE]);
+    }
+
+    /// htree 索引块（dx_root/dx_node）末尾 dx_tail 占用的字节数
+    pub fn dx_tail_len(&self) -> usize {
+        match self.seed {
+            Some(_) => DX_TAIL_LEN,
+            None => 0,
+        }
+    }
+
+    /// 修改 htree 索引块后重新计算 dx_tail 校验和，count_offset 为 count/limit 在块内的偏移
+    pub fn update_dx(&self, data: &mut [u8], count_offset: usize) {
+        if let Some((off, csum)) = self.dx_csum(data, count_offset) {
+            write_u32_le(csum, &mut data[off + 4..off + 8]);
         }
+    }
+
+    /// htree 索引块的 dx_tail 校验和是否正确；未开启 metadata_csum 时总是正确
+    pub fn verify_dx(&self, data: &[u8], count_offset: usize) -> bool {
+        if self.seed.is_none() {
+            return true;
+        }
+        match self.dx_csum(data, count_offset) {
+            Some((off, csum)) => read_u32_le(&data[off + 4..off + 8]) == csum,
+            None => false,
+        }
+    }
 
-        let target = name.as_bytes();
+    /// 与内核 ext4_dx_csum 相同：覆盖块开头到最后一个 dx_entry，再加上 dt_reserved
+    /// 返回 (dx_tail 偏移, 校验和)，limit 没有给 dx_tail 留出空间时返回 None
+    fn dx_csum(&self, data: &[u8], count_offset: usize) -> Option<(usize, u32)> {
+        let seed = self.seed?;
+        let limit = read_u16_le(&data[count_offset..count_offset + 2]) as usize;
+        let count = read_u16_le(&data[count_offset + 2..count_offset + 4]) as usize;
+        let off = count_offset + limit * 8;
+        if count > limit || off + DX_TAIL_LEN > BLOCK_SIZE {
+            return None;
+        }
+        let csum = crc32c(seed, &data[..count_offset + count * 8]);
+        Some((off, crc32c(csum, &data[off..off + 4])))
+    }
 
-        let total_size = current_inode.size() as usize;
-        let block_bytes = BLOCK_SIZE;
-        let total_blocks = if total_size == 0 {
-            0
-        } else {
-            total_size.div_ceil(block_bytes)
+    /// 目录块的校验和是否正确；未开启 metadata_csum 时总是正确
+    pub fn verify(&self, data: &[u8]) -> bool {
+        let Some(seed) = self.seed else {
+            return true;
         };
+        let off = self.usable();
+        let tail = Ext4DirEntryTail::from_disk_bytes(&data[off..BLOCK_SIZE]);
+        tail.is_valid() && tail.det_checksum == crc32c(seed, &data[..off])
+    }
+}
 
-        let mut found_inode_num: Option<u64> = None;
+/// 写入目录项的 file_type：未开启 filetype 特性时恒为 EXT4_FT_UNKNOWN
+pub fn disk_file_type(fs: &Ext4FileSystem, file_type: u8) -> u8 {
+    if fs.superblock.has_filetype() {
+        file_type
+    } else {
+        Ext4DirEntry2::EXT4_FT_UNKNOWN
+    }
+}
 
-        for lbn in 0..total_blocks {
-            let phys = match resolve_inode_block( device, &mut current_inode, lbn as u32)? {
-                Some(b) => b,
-                None => continue,
-            };
+/// 读出目录项的 file_type：未开启 filetype 特性时按目标 inode 的 i_mode 推导
+pub fn entry_file_type<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    device: &mut Jbd2Dev<B>,
+    ino: u32,
+    file_type: u8,
+) -> BlockDevResult<u8> {
+    if fs.superblock.has_filetype() {
+        return Ok(file_type);
+    }
+    let inode = fs.get_inode_by_num(device, ino)?;
+    Ok(dir_entry_file_type(&inode))
+}
 
-            let cached_block = fs.datablock_cache.get_or_load(device, phys as u64)?;
-            let block_data = &cached_block.data[..block_bytes];
+/// 为目录在末尾追加一个清零的数据块，更新块映射、i_size、i_blocks 并写回 inode
+///
+/// 返回 (逻辑块号, 物理块号)
+pub fn append_dir_block<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    device: &mut Jbd2Dev<B>,
+    dir_ino_num: u32,
+    dir_inode: &mut Ext4Inode,
+) -> BlockDevResult<(u32, u64)> {
+    let new_block = fs.alloc_block(device)?;
 
-            if let Some(entry) = classic_dir::find_entry(block_data, target) {
-                found_inode_num = Some(entry.inode as u64);
-                break;
-            }
-        }
+    // 更新 dir_inode 的块映射（extent 或直接块）和大小统计
+    let block_bytes = BLOCK_SIZE;
+    let total_size = dir_inode.size() as usize;
+    let old_blocks = total_size.div_ceil(block_bytes);
+    let new_lbn = old_blocks as u32; // 新块对应的逻辑块号
 
-        let inode_num = match found_inode_num {
-            Some(n) => n,
-            None => return Ok(None),
+    if fs.superblock.has_extents() && dir_inode.have_extend_header_and_use_extend() {
+        // extent 目录：通过 ExtentTree 追加一个长度为 1 的 extent
+        let new_ext = Ext4Extent::new(new_lbn, new_block, 1);
+        let mut tree = ExtentTree::new(dir_inode);
+        tree.insert_extent(fs, new_ext, device)?;
+    } else {
+        // 传统直接块模式：仅支持追加到前 12 个直接块，且直接块只能存 32 位块号
+        let Ok(new_block) = u32::try_from(new_block) else {
+            return Err(BlockDevError::Unsupported);
         };
+        if old_blocks >= 12 {
+            return Err(BlockDevError::Unsupported);
+        }
+        dir_inode.i_block[old_blocks] = new_block;
+    }
 
-        let (inode_group_idx, _idx_in_group) = fs.inode_allocator.global_to_group(inode_num as u32);
-        let inode_table_start = fs
-            .group_descs
-            .get(inode_group_idx as usize)
-            .ok_or(BlockDevError::Corrupted)?
-            .inode_table();
-
-        let (block_num, offset, _group_idx) = fs.inodetable_cahce.calc_inode_location(
-            inode_num as u32,
-            fs.superblock.s_inodes_per_group,
-            inode_table_start,
-            BLOCK_SIZE,
-        );
+    // 更新 dir_inode 的 i_size / i_blocks，并写回 inode 表
+    let new_size = old_blocks * block_bytes + block_bytes;
+    dir_inode.i_size_lo = new_size as u32;
+    dir_inode.i_size_high = ((new_size as u64) >> 32) as u32;
+    //fix:extend元数据也会占block，不能仅仅靠现有blocks_count计算，需要考虑extent树的开销
+    let cur = dir_inode.blocks_count();
+    let add_sectors = (BLOCK_SIZE as u64 / 512) as u64;
+    let newv = cur.saturating_add(add_sectors);
+    dir_inode.i_blocks_lo = (newv & 0xffff_ffff) as u32;
+    dir_inode.l_i_blocks_high = ((newv >> 32) & 0xffff) as u16;
+
+    fs.modify_inode(device, dir_ino_num, |inode| {
+        inode.i_size_lo = dir_inode.i_size_lo;
+        inode.i_size_high = dir_inode.i_size_high;
+        inode.i_blocks_lo = dir_inode.i_blocks_lo;
+        inode.l_i_blocks_high = dir_inode.l_i_blocks_high;
+        inode.i_flags = dir_inode.i_flags;
+        inode.i_block = dir_inode.i_block;
+    })?;
+
+    fs.datablock_cache.modify(device, new_block, |data| {
+        for b in data.iter_mut() {
+            *b = 0;
+        }
+    })?;
+
+    Ok((new_lbn, new_block))
+}
 
-        let cached_inode = fs
-            .inodetable_cahce
-            .get_or_load(device, inode_num, block_num, offset)?;
-        current_inode = cached_inode.inode;
-        current_ino = inode_num as u32;
+/// 回收目录中的一个空块：把最后一个块的内容搬到 lblk，再截掉最后一个块
+///
+/// 调用方负责保证 lblk 已经不含有效目录项；索引目录还需事先把指向最后一个块的索引改指 lblk
+pub fn reclaim_dir_block<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    device: &mut Jbd2Dev<B>,
+    dir_ino_num: u32,
+    dir_inode: &mut Ext4Inode,
+    lblk: u32,
+) -> BlockDevResult<()> {
+    if !dir_inode.have_extend_header_and_use_extend() {
+        return Ok(());
+    }
+    let total_blocks = dir_inode.size().div_ceil(BLOCK_SIZE as u64) as u32;
+    if lblk == 0 || lblk >= total_blocks {
+        return Err(BlockDevError::InvalidInput);
+    }
+    let last = total_blocks - 1;
+    let last_phys = resolve_inode_block(device, dir_inode, last)?.ok_or(BlockDevError::Corrupted)?;
+
+    if lblk != last {
+        let dst = resolve_inode_block(device, dir_inode, lblk)?.ok_or(BlockDevError::Corrupted)?;
+        let src_data = fs.datablock_cache.get_or_load(device, last_phys as u64)?.data.clone();
+        fs.datablock_cache.modify(device, dst as u64, |data| {
+            data.copy_from_slice(&src_data);
+        })?;
     }
 
-    Ok(Some((current_ino, current_inode)))
+    {
+        let mut tree = ExtentTree::new(dir_inode);
+        tree.remove_extend(fs, Ext4Extent::new(last, 0, 1), device)?;
+    }
+    fs.datablock_cache.invalidate(last_phys as u64);
+
+    let new_size = last as u64 * BLOCK_SIZE as u64;
+    dir_inode.i_size_lo = new_size as u32;
+    dir_inode.i_size_high = (new_size >> 32) as u32;
+    fs.modify_inode(device, dir_ino_num, |inode| {
+        inode.i_size_lo = dir_inode.i_size_lo;
+        inode.i_size_high = dir_inode.i_size_high;
+        inode.i_blocks_lo = dir_inode.i_blocks_lo;
+        inode.l_i_blocks_high = dir_inode.l_i_blocks_high;
+        inode.i_block = dir_inode.i_block;
+    })?;
+    Ok(())
+}
+
+/// 在目录中按名字查找目录项，返回 (inode 号, file_type, 所在物理块)
+pub fn find_dir_entry<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    device: &mut Jbd2Dev<B>,
+    dir_inode: &Ext4Inode,
+    name: &[u8],
+) -> BlockDevResult<Option<(u32, u8, u64)>> {
+    match lookup_directory_entry(fs, device, dir_inode, name) {
+        Ok(result) => {
+            let file_type = entry_file_type(fs, device, result.entry.inode, result.entry.file_type)?;
+            Ok(Some((result.entry.inode, file_type, result.block_num)))
+        }
+        Err(HashTreeError::EntryNotFound) => Ok(None),
+        Err(e) => {
+            error!("find_dir_entry lookup failed: {e}");
+            Err(BlockDevError::Corrupted)
+        }
+    }
+}
+
+/// 目录中除 "." 和 ".." 外是否没有任何有效目录项
+pub fn dir_is_empty<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    device: &mut Jbd2Dev<B>,
+    dir_inode: &Ext4Inode,
+) -> BlockDevResult<bool> {
+    let blocks = resolve_inode_block_allextend(fs, device, &mut dir_inode.clone())?;
+    for &phys in blocks.values() {
+        let cached = fs.datablock_cache.get_or_load(device, phys)?;
+        if DirEntryIterator::new(&cached.data[..BLOCK_SIZE])
+            .any(|(entry, _)| !entry.is_dot() && !entry.is_dotdot())
+        {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// 列出目录中除 "." 和 ".." 外的所有有效目录项：(inode 号, file_type, 名字)
+pub fn dir_entries<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    device: &mut Jbd2Dev<B>,
+    dir_inode: &Ext4Inode,
+) -> BlockDevResult<Vec<(u32, u8, Vec<u8>)>> {
+    let blocks = resolve_inode_block_allextend(fs, device, &mut dir_inode.clone())?;
+    let mut entries = Vec::new();
+    for &phys in blocks.values() {
+        let cached = fs.datablock_cache.get_or_load(device, phys)?;
+        for (entry, _) in DirEntryIterator::new(&cached.data[..BLOCK_SIZE]) {
+            if !entry.is_dot() && !entry.is_dotdot() {
+                entries.push((entry.inode, entry.file_type, entry.name.to_vec()));
+            }
+        }
+    }
+    for entry in entries.iter_mut() {
+        entry.1 = entry_file_type(fs, device, entry.0, entry.1)?;
+    }
+    Ok(entries)
 }
 
 /// 在父目录的所有逻辑块中查找空闲空间并插入一个目录项；
 /// 若所有现有块都无法容纳，则自动为目录分配一个新数据块并扩展 inode 映射和大小。
+/// 已建立 htree 索引的目录按哈希插入对应叶子块；单块目录写满时转换为索引目录。
 pub fn insert_dir_entry<B: BlockDevice>(
     fs: &mut Ext4FileSystem,
     device: &mut Jbd2Dev<B>,
@@ -149,15 +424,33 @@ pub fn insert_dir_entry<B: BlockDevice>(
     file_type: u8,
 ) -> BlockDevResult<()> {
     let name_bytes = child_name.as_bytes();
+    check_name(&fs.superblock, parent_inode, name_bytes)?;
+    let key = DirNameKey::new(&fs.superblock, parent_inode, name_bytes);
+    fs.dentry_cache.invalidate(parent_ino_num, key.hash_name());
     let name_len = core::cmp::min(name_bytes.len(), Ext4DirEntry2::MAX_NAME_LEN as usize);
-    let new_rec_len = Ext4DirEntry2::entry_len(name_len as u8) as usize;
     let new_entry = Ext4DirEntry2::new(
         child_ino,
         Ext4DirEntry2::entry_len(name_len as u8),
-        file_type,
+        disk_file_type(fs, file_type),
         &name_bytes[..name_len],
     );
 
+    // htree 索引目录：按哈希路径插入，索引损坏时清除索引标志并退回线性插入
+    if parent_inode.is_htree_indexed() {
+        match dx_add_entry(fs, device, parent_ino_num, parent_inode, &new_entry)? {
+            true => {
+                fs.fast_commit.track_link(parent_ino_num, child_ino, name_bytes);
+                return Ok(());
+            }
+            false => {
+                parent_inode.i_flags &= !Ext4Inode::EXT4_INDEX_FL;
+                fs.modify_inode(device, parent_ino_num, |inode| {
+                    inode.i_flags &= !Ext4Inode::EXT4_INDEX_FL;
+                })?;
+            }
+        }
+    }
+
     let total_size = parent_inode.size() as usize;
     let block_bytes = BLOCK_SIZE;
     let total_blocks = if total_size == 0 {
@@ -167,6 +460,7 @@ pub fn insert_dir_entry<B: BlockDevice>(
     };
 
     let mut inserted = false;
+    let tail = DirBlockTail::new(fs, parent_ino_num, parent_inode);
 
     let blocks = resolve_inode_block_allextend(fs, device, parent_inode)?;
 
@@ -186,155 +480,44 @@ pub fn insert_dir_entry<B: BlockDevice>(
             }
         };
 
-        let _ = fs.datablock_cache.modify(device, phys as u64, |data| {
+        let _ = fs.datablock_cache.modify(device, phys, |data| {
+            inserted = insert_entry_into_block(&mut data[..tail.usable()], &new_entry);
             if inserted {
-                return;
-            }
-
-            let block_bytes = BLOCK_SIZE;
-
-            let mut offset = 0usize;
-            while offset + 8 <= block_bytes {
-                let inode = u32::from_le_bytes([
-                    data[offset],
-                    data[offset + 1],
-                    data[offset + 2],
-                    data[offset + 3],
-                ]);
-                let rec_len = u16::from_le_bytes([data[offset + 4], data[offset + 5]]) as usize;
-                if rec_len < 8 {
-                    return;
-                }
-                let entry_end = offset + rec_len;
-                if entry_end > block_bytes {
-                    return;
-                }
-
-                // Free entry: directly use it if it can hold the new entry.
-                if inode == 0 {
-                    if rec_len >= new_rec_len {
-                        let mut full_entry = new_entry;
-                        full_entry.rec_len = rec_len as u16;
-                        full_entry.to_disk_bytes(&mut data[offset..offset + 8]);
-                        let nlen = full_entry.name_len as usize;
-                        data[offset + 8..offset + 8 + nlen]
-                            .copy_from_slice(&full_entry.name[..nlen]);
-                        inserted = true;
-                    }
-                    return;
-                }
-
-                // Occupied entry: try to split tail space.
-                let cur_name_len = data[offset + 6] as usize;
-                let mut ideal = 8 + cur_name_len;
-                ideal = (ideal + 3) & !3;
-                if ideal <= rec_len {
-                    let tail = rec_len - ideal;
-                    if tail >= new_rec_len {
-                        let ideal_bytes = (ideal as u16).to_le_bytes();
-                        data[offset + 4] = ideal_bytes[0];
-                        data[offset + 5] = ideal_bytes[1];
-
-                        let new_off = offset + ideal;
-                        let mut full_entry = new_entry;
-                        full_entry.rec_len = tail as u16;
-                        full_entry.to_disk_bytes(&mut data[new_off..new_off + 8]);
-                        let nlen = full_entry.name_len as usize;
-                        data[new_off + 8..new_off + 8 + nlen]
-                            .copy_from_slice(&full_entry.name[..nlen]);
-                        inserted = true;
-                        return;
-                    }
-                }
-
-                if entry_end == block_bytes {
-                    return;
-                }
-                offset = entry_end;
+                tail.update(data);
             }
         });
     }
 
     if inserted {
+        fs.fast_commit.track_link(parent_ino_num, child_ino, name_bytes);
         return Ok(());
     }
 
-    // 所有现有逻辑块都无法容纳新目录项：为目录分配一个新数据块，并扩展 inode 映射
-    let new_block = fs.alloc_block(device)?;
-
-    // 更新 parent_inode 的块映射（extent 或直接块）和大小统计
-    let block_bytes = BLOCK_SIZE;
-    let old_blocks = if total_size == 0 {
-        0
-    } else {
-        total_size.div_ceil(block_bytes)
-    };
-    let new_lbn = old_blocks as u32; // 新块对应的逻辑块号
-
-    if fs.superblock.has_extents() && parent_inode.have_extend_header_and_use_extend() {
-        // extent 目录：通过 ExtentTree 追加一个长度为 1 的 extent
-        let new_ext = Ext4Extent::new(new_lbn, new_block, 1);
-        let mut tree = ExtentTree::new(parent_inode);
-        tree.insert_extent(fs, new_ext, device)?;
-    } else {
-        // 传统直接块模式：仅支持追加到前 12 个直接块
-        if old_blocks >= 12 {
-            return Err(BlockDevError::Unsupported);
+    // 单块目录写满：与内核一致，转换为 htree 索引目录后按哈希插入
+    if total_blocks == 1 && can_index_dir(fs, parent_inode) {
+        make_indexed_dir(fs, device, parent_ino_num, parent_inode)?;
+        if dx_add_entry(fs, device, parent_ino_num, parent_inode, &new_entry)? {
+            fs.fast_commit.track_link(parent_ino_num, child_ino, name_bytes);
+            return Ok(());
         }
-        parent_inode.i_block[old_blocks] = new_block as u32;
+        return Err(BlockDevError::Corrupted);
     }
 
-    // 更新 parent_inode 的 i_size / i_blocks，并写回 inode 表
-    let new_size = total_size + block_bytes;
-    parent_inode.i_size_lo = new_size as u32;
-    parent_inode.i_size_high = ((new_size as u64) >> 32) as u32;
-    //fix:extend元数据也会占block，不能仅仅靠现有blocks_count计算，需要考虑extent树的开销
-    let cur = parent_inode.blocks_count();
-    let add_sectors = (BLOCK_SIZE as u64 / 512) as u64;
-    let newv = cur.saturating_add(add_sectors);
-    parent_inode.i_blocks_lo = (newv & 0xffff_ffff) as u32;
-    parent_inode.l_i_blocks_high = ((newv >> 32) & 0xffff) as u16;
-
-    let (p_group, _pidx) = fs.inode_allocator.global_to_group(parent_ino_num);
-    let inode_table_start = match fs.group_descs.get(p_group as usize) {
-        Some(desc) => desc.inode_table(),
-        None => return Err(BlockDevError::Corrupted),
-    };
-    let (p_block_num, p_offset, _pg) = fs.inodetable_cahce.calc_inode_location(
-        parent_ino_num,
-        fs.superblock.s_inodes_per_group,
-        inode_table_start,
-        BLOCK_SIZE,
-    );
+    // 所有现有逻辑块都无法容纳新目录项：为目录分配一个新数据块，并扩展 inode 映射
+    let (_lbn, new_block) = append_dir_block(fs, device, parent_ino_num, parent_inode)?;
 
-    fs.inodetable_cahce.modify(
-        device,
-        parent_ino_num as u64,
-        p_block_num,
-        p_offset,
-        |inode| {
-            inode.i_size_lo = parent_inode.i_size_lo;
-            inode.i_size_high = parent_inode.i_size_high;
-            inode.i_blocks_lo = parent_inode.i_blocks_lo;
-            inode.l_i_blocks_high = parent_inode.l_i_blocks_high;
-            inode.i_flags = parent_inode.i_flags;
-            inode.i_block = parent_inode.i_block;
-        },
-    )?;
-
-    // 在新分配的数据块中写入唯一的目录项，占满整个块
+    // 在新分配的数据块中写入唯一的目录项，占满尾部之前的整个块
     fs.datablock_cache
         .modify(device, new_block, |data| {
-            for b in data.iter_mut() {
-                *b = 0;
-            }
             let mut full_entry = new_entry;
-            full_entry.rec_len = BLOCK_SIZE as u16;
+            full_entry.rec_len = tail.usable() as u16;
             full_entry.to_disk_bytes(&mut data[0..8]);
             let nlen = full_entry.name_len as usize;
             data[8..8 + nlen].copy_from_slice(&full_entry.name[..nlen]);
+            tail.init(data);
         })?;
 
+    fs.fast_commit.track_link(parent_ino_num, child_ino, name_bytes);
     Ok(())
 }
 
@@ -391,66 +574,30 @@ pub fn mkdir_with_ino<B: BlockDevice>(
     }
 
     // 从头逐一判断父路径是否存在，不存在则递归创建
-    // 只针对中间父目录，最后一个组件留给当前 mkd 创建
-    let mut cur_path = String::from("");
-    for i in 0..(parts.len().saturating_sub(1)) {
-        if cur_path.is_empty() {
-            cur_path.push('/');
-            cur_path.push_str(parts[i]);
-        } else {
-            cur_path.push('/');
-            cur_path.push_str(parts[i]);
-        }
-
-        if let Ok(None) = get_file_inode(fs, device, &cur_path) {
-            if mkdir(device, fs, &cur_path).is_none() {
-                error!("mkdir recursive parent create failed path={} parent={}", path, cur_path);
-                return None;
-            }
+    // 只针对中间父目录，最后一个组件留给当前 mkd 创建；相对路径保持相对
+    let mut cur_path = String::from(if norm_path.starts_with('/') { "/" } else { "" });
+    for part in &parts[..parts.len() - 1] {
+        cur_path.push_str(part);
+        if let Ok(None) = get_file_inode(fs, device, &cur_path)
+            && mkdir(device, fs, &cur_path).is_none()
+        {
+            error!("mkdir recursive parent create failed path={path} parent={cur_path}");
+            return None;
         }
+        cur_path.push('/');
     }
 
-    // 计算 parent 与 child
-    let child = parts.last().unwrap().to_string();
-    let parent = if parts.len() == 1 {
-        "/".to_string()
-    } else {
-        let mut p = String::from("");
-        for i in 0..(parts.len() - 1) {
-            p.push('/');
-            p.push_str(parts[i]);
-        }
-        p
-    };
-
-    // 再次获取父目录 inode 及其 inode 号
-    let (parent_ino_num, mut parent_inode) =
-        match get_inode_with_num(fs, device, &parent).ok().flatten() {
-            Some((n, ino)) => (n, ino),
-            None => {
-                error!("mkdir get parent inode failed path={} parent={} child={}", path, parent, child);
-                return None;
-            }
-        };
-
-    // 特殊情况：根目录本身
-    if (parent.is_empty() || parent == "/") && child.is_empty() {
-        debug!("Creating root directory");
-        if let Err(e) = create_root_directory_entry(fs, device) {
-            error!("mkdir create_root_directory_entry failed path={} err={:?} ({})", path, e, e);
+    // 解析父目录（跟随中间的符号链接），最后一个分量留给本次创建
+    let (parent_ino_num, parent_inode, child) = match lookup_parent(fs, device, &norm_path) {
+        Ok(v) => v,
+        Err(e) => {
+            error!("mkdir get parent inode failed path={path} err={e:?} ({e})");
             return None;
         }
-        return match fs.get_root(device) {
-            Ok(inode) => Some((fs.root_inode, inode)),
-            Err(e) => {
-                error!("mkdir get_root failed path={} err={:?} ({})", path, e, e);
-                None
-            }
-        };
-    }
+    };
 
     // 特殊情况：/lost+found
-    if (parent.is_empty() || parent == "/") && child == "lost+found" {
+    if parent_ino_num == fs.root_inode && child == "lost+found" {
         debug!("Creating /lost+found directory");
         if let Err(e) = create_lost_found_directory(fs, device) {
             error!("mkdir create_lost_found_directory failed path={} err={:?} ({})", path, e, e);
@@ -465,11 +612,28 @@ pub fn mkdir_with_ino<B: BlockDevice>(
         };
     }
 
+    mkdir_in(device, fs, parent_ino_num, parent_inode, &child)
+}
+
+/// 在已解析的父目录 parent_ino 下创建名为 child 的空目录（只有 "." 和 ".."）
+///
+/// 调用方负责确认 child 在父目录中不存在
+pub fn mkdir_in<B: BlockDevice>(
+    device: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    parent_ino_num: u32,
+    mut parent_inode: Ext4Inode,
+    child: &str,
+) -> Option<(u32, Ext4Inode)> {
+    if let Err(e) = check_name(&fs.superblock, &parent_inode, child.as_bytes()) {
+        error!("mkdir rejected name child={child} err={e:?} ({e})");
+        return None;
+    }
     // 为新目录分配 inode（内部自动选择块组）
     let new_dir_ino = match fs.alloc_inode(device) {
         Ok(ino) => ino,
         Err(e) => {
-            error!("mkdir alloc_inode failed path={} parent={} child={} err={:?} ({})", path, parent, child, e, e);
+            error!("mkdir alloc_inode failed parent_ino={parent_ino_num} child={child} err={e:?} ({e})");
             return None;
         }
     };
@@ -478,11 +642,20 @@ pub fn mkdir_with_ino<B: BlockDevice>(
     let data_block = match fs.alloc_block(device) {
         Ok(b) => b,
         Err(e) => {
-            error!("mkdir alloc_block failed path={} ino={} err={:?} ({})", path, new_dir_ino, e, e);
+            error!("mkdir alloc_block failed child={child} ino={new_dir_ino} err={e:?} ({e})");
             return None;
         }
     };
 
+    //仅仅的视图，修改过后的
+    let mut inode_pre = fs
+        .get_inode_by_num(device, new_dir_ino)
+        .expect("Can't getinode");
+    let tail = DirBlockTail::new(fs, new_dir_ino, &inode_pre);
+    // 子目录继承父目录的 casefold 标志
+    let casefold_fl = parent_inode.i_flags & Ext4Inode::EXT4_CASEFOLD_FL;
+    let dir_ft = disk_file_type(fs, Ext4DirEntry2::EXT4_FT_DIR);
+
     // 初始化新目录的数据块：写 '.' 和 '..'
     {
         let cached = fs.datablock_cache.create_new(data_block);
@@ -490,21 +663,11 @@ pub fn mkdir_with_ino<B: BlockDevice>(
 
         let dot_name = b".";
         let dot_rec_len = Ext4DirEntry2::entry_len(dot_name.len() as u8);
-        let dot = Ext4DirEntry2::new(
-            new_dir_ino,
-            dot_rec_len,
-            Ext4DirEntry2::EXT4_FT_DIR,
-            dot_name,
-        );
+        let dot = Ext4DirEntry2::new(new_dir_ino, dot_rec_len, dir_ft, dot_name);
 
         let dotdot_name = b"..";
-        let dotdot_rec_len = (BLOCK_SIZE as u16).saturating_sub(dot_rec_len);
-        let dotdot = Ext4DirEntry2::new(
-            parent_ino_num,
-            dotdot_rec_len,
-            Ext4DirEntry2::EXT4_FT_DIR,
-            dotdot_name,
-        );
+        let dotdot_rec_len = (tail.usable() as u16).saturating_sub(dot_rec_len);
+        let dotdot = Ext4DirEntry2::new(parent_ino_num, dotdot_rec_len, dir_ft, dotdot_name);
 
         {
             dot.to_disk_bytes(&mut data[0..8]);
@@ -518,15 +681,11 @@ pub fn mkdir_with_ino<B: BlockDevice>(
             let name_len = dotdot.name_len as usize;
             data[offset + 8..offset + 8 + name_len].copy_from_slice(&dotdot.name[..name_len]);
         }
+        tail.init(data);
     }
 
     // 写新目录 inode（单块目录，按特性选择 extent 或直接块）
     let (group_idx, _idx) = fs.inode_allocator.global_to_group(new_dir_ino);
-    //仅仅的视图，修改过后的
-
-    let mut inode_pre = fs
-        .get_inode_by_num(device, new_dir_ino)
-        .expect("Can't getinode");
     build_file_block_mapping(fs, &mut inode_pre, &[data_block], device);
     if fs
         .modify_inode(device, new_dir_ino, |inode| {
@@ -538,50 +697,23 @@ pub fn mkdir_with_ino<B: BlockDevice>(
             inode.i_blocks_lo = (BLOCK_SIZE / 512) as u32;
             inode.l_i_blocks_high = 0;
             inode.i_dtime = 0;
-            inode.i_flags |= inode_pre.i_flags
+            inode.i_flags |= inode_pre.i_flags | casefold_fl
 
             //由于借用冲突，暂时先把mapping移步到外面
         })
         .is_err()
     {
-        error!("mkdir modify_inode failed path={} ino={}", path, new_dir_ino);
+        error!("mkdir modify_inode failed child={child} ino={new_dir_ino}");
         return None;
     }
 
     //更新父目录的i_links_count+1
-    {
-        let (p_group, _pidx) = fs.inode_allocator.global_to_group(parent_ino_num);
-        let p_inode_table_start = match fs.group_descs.get(p_group as usize) {
-            Some(desc) => desc.inode_table(),
-            None => {
-                error!("mkdir parent group desc missing path={} parent_ino={} group={}", path, parent_ino_num, p_group);
-                return None;
-            }
-        };
-        let (p_block_num, p_offset, _pg) = fs.inodetable_cahce.calc_inode_location(
-            parent_ino_num,
-            fs.superblock.s_inodes_per_group,
-            p_inode_table_start,
-            BLOCK_SIZE,
-        );
-
-        let _ = fs.inodetable_cahce.modify(
-            device,
-            parent_ino_num as u64,
-            p_block_num,
-            p_offset,
-            |inode| {
-                inode.i_links_count = inode.i_links_count.saturating_add(1);
-            },
-        );
-    }
+    let _ = fs.modify_inode(device, parent_ino_num, |inode| {
+        inode.i_links_count = inode.i_links_count.saturating_add(1);
+    });
 
     // 更新新目录所属块组的目录计数
-    if let Some(desc) = fs.get_group_desc_mut(group_idx) {
-        let newc = desc.used_dirs_count().saturating_add(1);
-        desc.bg_used_dirs_count_lo = (newc & 0xFFFF) as u16;
-        desc.bg_used_dirs_count_hi = ((newc >> 16) & 0xFFFF) as u16;
-    }
+    fs.group_desc_table_mut().increment_used_dirs(group_idx);
 
     // 在父目录的数据块中插入新目录项（线性目录，多块遍历，必要时自动扩展目录块）
     if insert_dir_entry(
@@ -590,14 +722,13 @@ pub fn mkdir_with_ino<B: BlockDevice>(
         parent_ino_num,
         &mut parent_inode,
         new_dir_ino,
-        &child,
+        child,
         Ext4DirEntry2::EXT4_FT_DIR,
     )
     .is_err()
     {
         error!(
-            "mkdir insert_dir_entry failed path={} parent_ino={} child={} ino={}",
-            path,
+            "mkdir insert_dir_entry failed parent_ino={} child={} ino={}",
             parent_ino_num,
             child,
             new_dir_ino
@@ -609,8 +740,8 @@ pub fn mkdir_with_ino<B: BlockDevice>(
         Ok(inode) => Some((new_dir_ino, inode)),
         Err(e) => {
             error!(
-                "mkdir get_inode_by_num failed path={} ino={} err={:?} ({})",
-                path,
+                "mkdir get_inode_by_num failed child={} ino={} err={:?} ({})",
+                child,
                 new_dir_ino,
                 e,
                 e
@@ -632,6 +763,13 @@ pub fn create_root_directory_entry<B: BlockDevice>(
     let root_inode_num = fs.root_inode;
     let data_block = fs.alloc_block(block_dev)?;
 
+    //仅仅的视图，修改过后的
+    let mut inode_pre = fs
+        .get_inode_by_num(block_dev, root_inode_num)
+        .expect("Can't getinode");
+    let tail = DirBlockTail::new(fs, root_inode_num, &inode_pre);
+    let dir_ft = disk_file_type(fs, Ext4DirEntry2::EXT4_FT_DIR);
+
     //  写入目录项 . 和 ..
     {
         let cached = fs.datablock_cache.create_new(data_block);
@@ -640,22 +778,12 @@ pub fn create_root_directory_entry<B: BlockDevice>(
         // . 目录项
         let dot_name = b".";
         let dot_rec_len = Ext4DirEntry2::entry_len(dot_name.len() as u8);
-        let dot = Ext4DirEntry2::new(
-            root_inode_num,
-            dot_rec_len,
-            Ext4DirEntry2::EXT4_FT_DIR,
-            dot_name,
-        );
+        let dot = Ext4DirEntry2::new(root_inode_num, dot_rec_len, dir_ft, dot_name);
 
         // ..目录项（根的父目录仍为自己）
         let dotdot_name = b"..";
-        let dotdot_rec_len = (BLOCK_SIZE as u16).saturating_sub(dot_rec_len);
-        let dotdot = Ext4DirEntry2::new(
-            root_inode_num,
-            dotdot_rec_len,
-            Ext4DirEntry2::EXT4_FT_DIR,
-            dotdot_name,
-        );
+        let dotdot_rec_len = (tail.usable() as u16).saturating_sub(dot_rec_len);
+        let dotdot = Ext4DirEntry2::new(root_inode_num, dotdot_rec_len, dir_ft, dotdot_name);
 
         {
             dot.to_disk_bytes(&mut data[0..8]);
@@ -669,13 +797,9 @@ pub fn create_root_directory_entry<B: BlockDevice>(
             let name_len = dotdot.name_len as usize;
             data[offset + 8..offset + 8 + name_len].copy_from_slice(&dotdot.name[..name_len]);
         }
+        tail.init(data);
     }
 
-    //仅仅的视图，修改过后的
-    let root_inode_num = fs.root_inode;
-    let mut inode_pre = fs
-        .get_inode_by_num(block_dev, root_inode_num)
-        .expect("Can't getinode");
     build_file_block_mapping(fs, &mut inode_pre, &[data_block], block_dev);
 
     fs.modify_inode(block_dev, fs.root_inode, |inode| {
@@ -715,6 +839,7 @@ pub fn create_lost_found_directory<B: BlockDevice>(
     }
 
     let root_inode_num = fs.root_inode;
+    fs.dentry_cache.invalidate(root_inode_num, b"lost+found");
 
     //  分配 inode（内部自动选择块组）
     let lost_ino = fs.alloc_inode(block_dev)?;
@@ -723,6 +848,13 @@ pub fn create_lost_found_directory<B: BlockDevice>(
     //  分配数据块（内部自动选择块组）
     let data_block = fs.alloc_block(block_dev)?;
 
+    //仅仅的视图，修改过后的
+    let mut inode_pre = fs
+        .get_inode_by_num(block_dev, lost_ino)
+        .expect("Can't getinode");
+    let tail = DirBlockTail::new(fs, lost_ino, &inode_pre);
+    let dir_ft = disk_file_type(fs, Ext4DirEntry2::EXT4_FT_DIR);
+
     //  初始化 lost+found 目录块（".", ".."）
     {
         let cached = fs.datablock_cache.create_new(data_block);
@@ -730,16 +862,11 @@ pub fn create_lost_found_directory<B: BlockDevice>(
 
         let dot_name = b".";
         let dot_rec_len = Ext4DirEntry2::entry_len(dot_name.len() as u8);
-        let dot = Ext4DirEntry2::new(lost_ino, dot_rec_len, Ext4DirEntry2::EXT4_FT_DIR, dot_name);
+        let dot = Ext4DirEntry2::new(lost_ino, dot_rec_len, dir_ft, dot_name);
 
         let dotdot_name = b"..";
-        let dotdot_rec_len = (BLOCK_SIZE as u16).saturating_sub(dot_rec_len);
-        let dotdot = Ext4DirEntry2::new(
-            root_inode_num,
-            dotdot_rec_len,
-            Ext4DirEntry2::EXT4_FT_DIR,
-            dotdot_name,
-        );
+        let dotdot_rec_len = (tail.usable() as u16).saturating_sub(dot_rec_len);
+        let dotdot = Ext4DirEntry2::new(root_inode_num, dotdot_rec_len, dir_ft, dotdot_name);
 
         {
             dot.to_disk_bytes(&mut data[0..8]);
@@ -753,15 +880,11 @@ pub fn create_lost_found_directory<B: BlockDevice>(
             let name_len = dotdot.name_len as usize;
             data[offset + 8..offset + 8 + name_len].copy_from_slice(&dotdot.name[..name_len]);
         }
+        tail.init(data);
     }
 
     //  写 lost+found inode
     let (lf_group, _idx) = fs.inode_allocator.global_to_group(lost_ino);
-
-    //仅仅的视图，修改过后的
-    let mut inode_pre = fs
-        .get_inode_by_num(block_dev, lost_ino)
-        .expect("Can't getinode");
     build_file_block_mapping(fs, &mut inode_pre, &[data_block], block_dev);
     debug!(
         "When create lost+found inode iblock,:{:?} ,data_block:{:?}",
@@ -795,30 +918,21 @@ pub fn create_lost_found_directory<B: BlockDevice>(
         return Err(BlockDevError::Corrupted);
     }
 
+    let root_tail = DirBlockTail::new(fs, root_inode_num, &root_inode);
     fs.datablock_cache
         .modify(block_dev, root_block as u64, move |data| {
             let dot_name = b".";
             let dot_rec_len = Ext4DirEntry2::entry_len(dot_name.len() as u8);
-            let dot = Ext4DirEntry2::new(
-                root_inode_num,
-                dot_rec_len,
-                Ext4DirEntry2::EXT4_FT_DIR,
-                dot_name,
-            );
+            let dot = Ext4DirEntry2::new(root_inode_num, dot_rec_len, dir_ft, dot_name);
 
             let dotdot_name = b"..";
             let dotdot_rec_len = Ext4DirEntry2::entry_len(dotdot_name.len() as u8);
-            let dotdot = Ext4DirEntry2::new(
-                root_inode_num,
-                dotdot_rec_len,
-                Ext4DirEntry2::EXT4_FT_DIR,
-                dotdot_name,
-            );
+            let dotdot = Ext4DirEntry2::new(root_inode_num, dotdot_rec_len, dir_ft, dotdot_name);
 
             let lf_name = b"lost+found";
-            let lf_rec_len = (BLOCK_SIZE as u16).saturating_sub(dot_rec_len + dotdot_rec_len);
-            let lost =
-                Ext4DirEntry2::new(lost_ino, lf_rec_len, Ext4DirEntry2::EXT4_FT_DIR, lf_name);
+            let lf_rec_len =
+                (root_tail.usable() as u16).saturating_sub(dot_rec_len + dotdot_rec_len);
+            let lost = Ext4DirEntry2::new(lost_ino, lf_rec_len, dir_ft, lf_name);
 
             // 清零整个块
             for b in data.iter_mut() {
@@ -841,29 +955,14 @@ pub fn create_lost_found_directory<B: BlockDevice>(
             lost.to_disk_bytes(&mut data[offset..offset + 8]);
             let lf_len = lost.name_len as usize;
             data[offset + 8..offset + 8 + lf_len].copy_from_slice(&lost.name[..lf_len]);
+            root_tail.init(data);
         })?;
 
     //  更新根 inode 的链接计数（多了一个子目录）
-    let inode_table_start = match fs.group_descs.first() {
-        Some(desc) => desc.inode_table(),
-        None => return Err(BlockDevError::Corrupted),
-    };
-    let (block_num, offset, _group_idx) = fs.inodetable_cahce.calc_inode_location(
-        fs.root_inode,
-        fs.superblock.s_inodes_per_group,
-        inode_table_start,
-        BLOCK_SIZE,
-    );
-
-    fs.inodetable_cahce.modify(
-        block_dev,
-        fs.root_inode as u64,
-        block_num,
-        offset,
-        |inode| {
-            inode.i_links_count = inode.i_links_count.saturating_add(1);
-        },
-    )?;
+    let root_ino = fs.root_inode;
+    fs.modify_inode(block_dev, root_ino, |inode| {
+        inode.i_links_count = inode.i_links_count.saturating_add(1);
+    })?;
 
     //  记录到超级块
     fs.superblock.s_lpf_ino = lost_ino;
@@ -220,7 +220,9 @@ impl Ext4Inode {
     pub const EXT4_SNAPFILE_SHRUNK_FL: u32 = 0x08000000; // 快照收缩
     pub const EXT4_INLINE_DATA_FL: u32 = 0x10000000; // 内联数据
     pub const EXT4_PROJINHERIT_FL: u32 = 0x20000000; // 创建时继承项目ID
+    pub const EXT4_CASEFOLD_FL: u32 = 0x40000000; // 目录按 casefold 比较名字
     pub const EXT4_RESERVED_FL: u32 = 0x80000000; // 保留
+    pub const EXT4_FL_USER_MODIFIABLE: u32 = 0x604BC0FF; // chattr 可以修改的标志
 }
 
 /// Extent头部结构
@@ -315,6 +317,15 @@ impl Ext4Extent {
     pub fn is_initialized(&self) -> bool {
         self.ee_len <= Self::EXT_INIT_MAX_LEN
     }
+
+    /// 实际覆盖的块数（去掉未初始化标志）
+    pub fn actual_len(&self) -> u32 {
+        if self.is_initialized() {
+            self.ee_len as u32
+        } else {
+            (self.ee_len - Self::EXT_INIT_MAX_LEN) as u32
+        }
+    }
 }
 
 /// 实现 DiskFormat trait 用于字节序转换
@@ -87,6 +87,25 @@ pub struct Ext4DirEntryTail {
 impl Ext4DirEntryTail {
     pub const RESERVED_FT: u8 = 0xDE;
     pub const TAIL_LEN: u16 = 12;
+
+    /// 构造携带指定校验和的尾部
+    pub fn new(checksum: u32) -> Self {
+        Self {
+            det_reserved_zero1: 0,
+            det_rec_len: Self::TAIL_LEN,
+            det_reserved_zero2: 0,
+            det_reserved_ft: Self::RESERVED_FT,
+            det_checksum: checksum,
+        }
+    }
+
+    /// 是否为合法的尾部（伪目录项：inode 0、rec_len 12、name_len 0、file_type 0xDE）
+    pub fn is_valid(&self) -> bool {
+        self.det_reserved_zero1 == 0
+            && self.det_rec_len == Self::TAIL_LEN
+            && self.det_reserved_zero2 == 0
+            && self.det_reserved_ft == Self::RESERVED_FT
+    }
 }
 
 /// HTree根节点信息结构
@@ -153,13 +172,25 @@ pub struct Ext4DxNode {
 
 /// Extent状态树的叶子节点
 #[repr(C)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Ext4ExtentStatus {
     pub es_lblk: u64, // 第一个逻辑块
     pub es_len: u64,  // extent长度
     pub es_pblk: u64, // 第一个物理块
 }
 
+impl Ext4ExtentStatus {
+    /// 覆盖的最后一个逻辑块之后的块号
+    pub fn end(&self) -> u64 {
+        self.es_lblk + self.es_len
+    }
+
+    /// 是否覆盖逻辑块 lblk
+    pub fn contains(&self, lblk: u64) -> bool {
+        lblk >= self.es_lblk && lblk < self.end()
+    }
+}
+
 /// 用于在目录中查找文件名的辅助结构
 #[derive(Debug)]
 pub struct Ext4DirEntryInfo<'a> {
@@ -283,59 +314,205 @@ pub mod classic_dir {
 /// HTree索引目录（Hash Tree Directory）辅助函数
 pub mod htree_dir {
     use super::*;
+    use crate::ext4_backend::superblock::Ext4Superblock;
+
+    /// 种子全零时使用的默认哈希缓冲区（与 MD4 初始值一致）
+    const DEFAULT_SEED: [u32; 4] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476];
+
+    /// 32 位 htree 的 EOF 哈希值，正常名字的哈希不能与之相等
+    const EXT4_HTREE_EOF_32BIT: u32 = 0x7fffffff;
+
+    /// 根据 dx_root 的 hash_version 和超级块 s_flags 得到实际使用的哈希版本
+    ///
+    /// 与内核一致：设置了 EXT2_FLAGS_UNSIGNED_HASH 时，前三种版本换成对应的无符号变体
+    pub fn effective_hash_version(hash_version: u8, s_flags: u32) -> u8 {
+        if hash_version <= Ext4DxRootInfo::DX_HASH_TEA
+            && s_flags & Ext4Superblock::EXT2_FLAGS_UNSIGNED_HASH != 0
+        {
+            hash_version + 3
+        } else {
+            hash_version
+        }
+    }
 
-    /// 计算文件名的哈希值
+    /// 计算文件名的哈希值（主哈希）
     pub fn calculate_hash(name: &[u8], hash_version: u8, hash_seed: &[u32; 4]) -> u32 {
-        match hash_version {
-            Ext4DxRootInfo::DX_HASH_LEGACY => legacy_hash(name),
-            Ext4DxRootInfo::DX_HASH_HALF_MD4 => half_md4_hash(name, hash_seed),
-            Ext4DxRootInfo::DX_HASH_TEA => tea_hash(name, hash_seed),
-            _ => 0,
+        calculate_hash_pair(name, hash_version, hash_seed)
+            .map(|(hash, _)| hash)
+            .unwrap_or(0)
+    }
+
+    /// 计算文件名的 (主哈希, 次哈希)，与 Linux ext4fs_dirhash 逐位一致
+    ///
+    /// 不支持的哈希版本返回 None
+    pub fn calculate_hash_pair(
+        name: &[u8],
+        hash_version: u8,
+        hash_seed: &[u32; 4],
+    ) -> Option<(u32, u32)> {
+        let mut buf = if hash_seed.iter().any(|&s| s != 0) {
+            *hash_seed
+        } else {
+            DEFAULT_SEED
+        };
+        let unsigned = matches!(
+            hash_version,
+            Ext4DxRootInfo::DX_HASH_LEGACY_UNSIGNED
+                | Ext4DxRootInfo::DX_HASH_HALF_MD4_UNSIGNED
+                | Ext4DxRootInfo::DX_HASH_TEA_UNSIGNED
+        );
+
+        let (hash, minor_hash) = match hash_version {
+            Ext4DxRootInfo::DX_HASH_LEGACY | Ext4DxRootInfo::DX_HASH_LEGACY_UNSIGNED => {
+                (dx_hack_hash(name, unsigned), 0)
+            }
+            Ext4DxRootInfo::DX_HASH_HALF_MD4 | Ext4DxRootInfo::DX_HASH_HALF_MD4_UNSIGNED => {
+                let mut input = [0u32; 8];
+                let mut rest = name;
+                while !rest.is_empty() {
+                    str2hashbuf(rest, &mut input, unsigned);
+                    half_md4_transform(&mut buf, &input);
+                    rest = &rest[rest.len().min(32)..];
+                }
+                (buf[1], buf[2])
+            }
+            Ext4DxRootInfo::DX_HASH_TEA | Ext4DxRootInfo::DX_HASH_TEA_UNSIGNED => {
+                let mut input = [0u32; 4];
+                let mut rest = name;
+                while !rest.is_empty() {
+                    str2hashbuf(rest, &mut input, unsigned);
+                    tea_transform(&mut buf, &input);
+                    rest = &rest[rest.len().min(16)..];
+                }
+                (buf[0], buf[1])
+            }
+            _ => return None,
+        };
+
+        let mut hash = hash & !1;
+        if hash == EXT4_HTREE_EOF_32BIT << 1 {
+            hash = (EXT4_HTREE_EOF_32BIT - 1) << 1;
         }
+        Some((hash, minor_hash))
     }
 
-    /// 传统哈希算法（简化实现）
-    fn legacy_hash(name: &[u8]) -> u32 {
-        let mut hash = 0u32;
-        for &byte in name {
-            hash = hash.wrapping_mul(33).wrapping_add(byte as u32);
+    /// 按内核 char 的符号性取字节值
+    fn char_value(byte: u8, unsigned: bool) -> u32 {
+        if unsigned {
+            byte as u32
+        } else {
+            byte as i8 as i32 as u32
         }
-        hash
     }
 
-    /// Half MD4哈希算法（简化实现）
-    fn half_md4_hash(name: &[u8], seed: &[u32; 4]) -> u32 {
-        // 这是一个简化版本，实际实现需要完整的MD4算法
-        let mut hash = seed[0];
+    /// 传统哈希算法（dx_hack_hash）
+    fn dx_hack_hash(name: &[u8], unsigned: bool) -> u32 {
+        let mut hash0: u32 = 0x12a3fe2d;
+        let mut hash1: u32 = 0x37abe8f9;
         for &byte in name {
-            hash = hash.wrapping_mul(1103515245).wrapping_add(byte as u32);
+            let mut hash =
+                hash1.wrapping_add(hash0 ^ char_value(byte, unsigned).wrapping_mul(7152373));
+            if hash & 0x8000_0000 != 0 {
+                hash = hash.wrapping_sub(0x7fff_ffff);
+            }
+            hash1 = hash0;
+            hash0 = hash;
         }
-        hash
+        hash0 << 1
     }
 
-    /// TEA哈希算法（Tiny Encryption Algorithm）
-    fn tea_hash(name: &[u8], seed: &[u32; 4]) -> u32 {
-        let mut hash = seed[0];
-        let mut buf = [0u32; 4];
-
-        for chunk in name.chunks(16) {
-            for (i, bytes) in chunk.chunks(4).enumerate() {
-                if i >= 4 {
-                    break;
-                }
-                let mut val = 0u32;
-                for &b in bytes {
-                    val = (val << 8) | b as u32;
-                }
-                buf[i] = val;
+    /// 把名字的前 out.len()*4 个字节装进哈希输入缓冲区，不足部分用长度填充
+    ///
+    /// 填充值取自剩余名字的总长度（不是本次截取的长度），与内核 str2hashbuf 一致
+    fn str2hashbuf(msg: &[u8], out: &mut [u32], unsigned: bool) {
+        let mut pad = (msg.len() as u32) | ((msg.len() as u32) << 8);
+        pad |= pad << 16;
+
+        let mut val = pad;
+        let mut idx = 0;
+        let len = msg.len().min(out.len() * 4);
+        for (i, &byte) in msg[..len].iter().enumerate() {
+            val = char_value(byte, unsigned).wrapping_add(val << 8);
+            if i % 4 == 3 {
+                out[idx] = val;
+                idx += 1;
+                val = pad;
             }
+        }
+        if idx < out.len() {
+            out[idx] = val;
+            idx += 1;
+        }
+        for slot in &mut out[idx..] {
+            *slot = pad;
+        }
+    }
 
-            // TEA算法的简化版本
-            for _ in 0..4 {
-                hash = hash.wrapping_add(buf[0] ^ buf[1]);
-            }
+    /// 半 MD4 变换（三轮各 8 步），结果累加回 buf
+    fn half_md4_transform(buf: &mut [u32; 4], input: &[u32; 8]) {
+        const K2: u32 = 0x5A82_7999;
+        const K3: u32 = 0x6ED9_EBA1;
+        let f = |x: u32, y: u32, z: u32| z ^ (x & (y ^ z));
+        let g = |x: u32, y: u32, z: u32| (x & y).wrapping_add((x ^ y) & z);
+        let h = |x: u32, y: u32, z: u32| x ^ y ^ z;
+
+        let [mut a, mut b, mut c, mut d] = *buf;
+        macro_rules! round {
+            ($f:ident, $a:ident, $b:ident, $c:ident, $d:ident, $x:expr, $s:expr) => {
+                $a = $a.wrapping_add($f($b, $c, $d)).wrapping_add($x).rotate_left($s)
+            };
+        }
+
+        round!(f, a, b, c, d, input[0], 3);
+        round!(f, d, a, b, c, input[1], 7);
+        round!(f, c, d, a, b, input[2], 11);
+        round!(f, b, c, d, a, input[3], 19);
+        round!(f, a, b, c, d, input[4], 3);
+        round!(f, d, a, b, c, input[5], 7);
+        round!(f, c, d, a, b, input[6], 11);
+        round!(f, b, c, d, a, input[7], 19);
+
+        round!(g, a, b, c, d, input[1].wrapping_add(K2), 3);
+        round!(g, d, a, b, c, input[3].wrapping_add(K2), 5);
+        round!(g, c, d, a, b, input[5].wrapping_add(K2), 9);
+        round!(g, b, c, d, a, input[7].wrapping_add(K2), 13);
+        round!(g, a, b, c, d, input[0].wrapping_add(K2), 3);
+        round!(g, d, a, b, c, input[2].wrapping_add(K2), 5);
+        round!(g, c, d, a, b, input[4].wrapping_add(K2), 9);
+        round!(g, b, c, d, a, input[6].wrapping_add(K2), 13);
+
+        round!(h, a, b, c, d, input[3].wrapping_add(K3), 3);
+        round!(h, d, a, b, c, input[7].wrapping_add(K3), 9);
+        round!(h, c, d, a, b, input[2].wrapping_add(K3), 11);
+        round!(h, b, c, d, a, input[6].wrapping_add(K3), 15);
+        round!(h, a, b, c, d, input[1].wrapping_add(K3), 3);
+        round!(h, d, a, b, c, input[5].wrapping_add(K3), 9);
+        round!(h, c, d, a, b, input[0].wrapping_add(K3), 11);
+        round!(h, b, c, d, a, input[4].wrapping_add(K3), 15);
+
+        buf[0] = buf[0].wrapping_add(a);
+        buf[1] = buf[1].wrapping_add(b);
+        buf[2] = buf[2].wrapping_add(c);
+        buf[3] = buf[3].wrapping_add(d);
+    }
+
+    /// TEA 变换（16 轮），结果累加回 buf[0..2]
+    fn tea_transform(buf: &mut [u32; 4], input: &[u32; 4]) {
+        const DELTA: u32 = 0x9E37_79B9;
+        let [a, b, c, d] = *input;
+        let (mut b0, mut b1) = (buf[0], buf[1]);
+        let mut sum = 0u32;
+        for _ in 0..16 {
+            sum = sum.wrapping_add(DELTA);
+            b0 = b0.wrapping_add(
+                ((b1 << 4).wrapping_add(a)) ^ b1.wrapping_add(sum) ^ ((b1 >> 5).wrapping_add(b)),
+            );
+            b1 = b1.wrapping_add(
+                ((b0 << 4).wrapping_add(c)) ^ b0.wrapping_add(sum) ^ ((b0 >> 5).wrapping_add(d)),
+            );
         }
-        hash
+        buf[0] = buf[0].wrapping_add(b0);
+        buf[1] = buf[1].wrapping_add(b1);
     }
 }
 
@@ -363,3 +540,27 @@ impl DiskFormat for Ext4DirEntry2 {
         8 // 固定头部大小，不包括变长文件名
     }
 }
+
+impl DiskFormat for Ext4DirEntryTail {
+    fn from_disk_bytes(bytes: &[u8]) -> Self {
+        Self {
+            det_reserved_zero1: read_u32_le(&bytes[0..4]),
+            det_rec_len: read_u16_le(&bytes[4..6]),
+            det_reserved_zero2: bytes[6],
+            det_reserved_ft: bytes[7],
+            det_checksum: read_u32_le(&bytes[8..12]),
+        }
+    }
+
+    fn to_disk_bytes(&self, bytes: &mut [u8]) {
+        write_u32_le(self.det_reserved_zero1, &mut bytes[0..4]);
+        write_u16_le(self.det_rec_len, &mut bytes[4..6]);
+        bytes[6] = self.det_reserved_zero2;
+        bytes[7] = self.det_reserved_ft;
+        write_u32_le(self.det_checksum, &mut bytes[8..12]);
+    }
+
+    fn disk_size() -> usize {
+        Self::TAIL_LEN as usize
+    }
+}
@@ -14,7 +14,7 @@ pub enum BlockDevError {
     WriteError,
 
     /// 块号超出范围
-    BlockOutOfRange { block_id: u32, max_blocks: u64 },
+    BlockOutOfRange { block_id: u64, max_blocks: u64 },
 
     /// 无效的块大小
     InvalidBlockSize { size: usize, expected: usize },
@@ -40,6 +40,9 @@ pub enum BlockDevError {
     /// 超时
     Timeout,
 
+    /// 需要的数据还没从异步设备读上来，取回后重试
+    WouldBlock,
+
     /// 不支持的操作
     Unsupported,
 
@@ -58,6 +61,24 @@ pub enum BlockDevError {
     /// 校验和错误
     ChecksumError,
 
+    /// 文件或目录项不存在
+    NotFound,
+
+    /// 目标已存在
+    AlreadyExists,
+
+    /// 目录非空
+    NotEmpty,
+
+    /// 不是目录
+    NotDirectory,
+
+    /// 是目录
+    IsDirectory,
+
+    /// 符号链接层数过多（ELOOP）
+    SymlinkLoop,
+
     /// 未知错误
     Unknown,
 }
@@ -95,12 +116,19 @@ impl core::fmt::Display for BlockDevError {
             }
             BlockDevError::DeviceBusy => write!(f, "device is busy"),
             BlockDevError::Timeout => write!(f, "operation timed out"),
+            BlockDevError::WouldBlock => write!(f, "operation would block"),
             BlockDevError::Unsupported => write!(f, "unsupported operation"),
             BlockDevError::ReadOnly => write!(f, "device is read-only"),
             BlockDevError::NoSpace => write!(f, "no space left on device"),
             BlockDevError::PermissionDenied => write!(f, "permission denied"),
             BlockDevError::Corrupted => write!(f, "device or data is corrupted"),
             BlockDevError::ChecksumError => write!(f, "checksum error"),
+            BlockDevError::NotFound => write!(f, "no such file or directory"),
+            BlockDevError::AlreadyExists => write!(f, "file exists"),
+            BlockDevError::NotEmpty => write!(f, "directory not empty"),
+            BlockDevError::NotDirectory => write!(f, "not a directory"),
+            BlockDevError::IsDirectory => write!(f, "is a directory"),
+            BlockDevError::SymlinkLoop => write!(f, "too many levels of symbolic links"),
             BlockDevError::Unknown => write!(f, "unknown error"),
         }
     }
@@ -125,6 +153,10 @@ pub enum RSEXT4Error {
     UnsupportedFeature,
     /// 已经挂载
     AlreadyMounted,
+    /// 超级块声明外部日志，但没有提供日志设备
+    JournalDeviceMissing,
+    /// 日志设备与文件系统记录的日志UUID不匹配
+    JournalUuidMismatch,
 }
 
 impl core::fmt::Display for RSEXT4Error {
@@ -136,6 +168,8 @@ impl core::fmt::Display for RSEXT4Error {
             RSEXT4Error::FilesystemHasErrors => write!(f, "文件系统有错误"),
             RSEXT4Error::UnsupportedFeature => write!(f, "不支持的特性"),
             RSEXT4Error::AlreadyMounted => write!(f, "文件系统已挂载"),
+            RSEXT4Error::JournalDeviceMissing => write!(f, "缺少外部日志设备"),
+            RSEXT4Error::JournalUuidMismatch => write!(f, "日志设备UUID不匹配"),
         }
     }
 }
\ No newline at end of file
@@ -2,16 +2,22 @@
 //!
 //! 提供文件系统挂载、卸载、文件操作等高层接口
 
-use crate::ext4_backend::bitmap::InodeBitmap;
+use crate::ext4_backend::bitmap::{
+    BitmapError, BlockBitmap, BlockBitmapMut, InodeBitmap, InodeBitmapMut,
+};
 use crate::ext4_backend::bitmap_cache::*;
 use crate::ext4_backend::blockdev::*;
 use crate::ext4_backend::blockgroup_description::*;
 use crate::ext4_backend::bmalloc::*;
+use crate::ext4_backend::buffer_cache::*;
 use crate::ext4_backend::config::*;
 use crate::ext4_backend::datablock_cache::*;
+use crate::ext4_backend::dentry_cache::*;
+use crate::ext4_backend::extent_status::*;
 use crate::ext4_backend::dir::*;
 use crate::ext4_backend::disknode::*;
 use crate::ext4_backend::endian::*;
+use crate::ext4_backend::fast_commit::*;
 use crate::ext4_backend::inodetable_cache::*;
 use crate::ext4_backend::jbd2::jbd2::*;
 use crate::ext4_backend::jbd2::jbdstruct::*;
@@ -43,6 +49,10 @@ pub struct Ext4FileSystem {
     pub inodetable_cahce: InodeCache,
     /// DataBlock缓存
     pub datablock_cache: DataBlockCache,
+    /// 目录项缓存：(父目录, 名字) -> 子 inode，含负缓存
+    pub dentry_cache: DentryCache,
+    /// extent 状态缓存：inode 的逻辑块 -> 物理块
+    pub extent_cache: ExtentStatusCache,
     /// 根目录inode号
     pub root_inode: u32,
     /// 块组数量
@@ -50,7 +60,11 @@ pub struct Ext4FileSystem {
     /// 是否已挂载
     pub mounted: bool,
     /// Journal 超级块 开始块号
-    pub journal_sb_block_start: Option<u32>,
+    pub journal_sb_block_start: Option<u64>,
+    /// 自上次提交以来需要写入 fast commit 的修改
+    pub fast_commit: FastCommitTracker,
+    /// 当前工作目录 inode 号，相对路径从这里开始解析
+    pub cwd: u32,
 }
 
 impl Ext4FileSystem {
@@ -180,6 +194,24 @@ impl Ext4FileSystem {
 
     /// 打开Ext4文件系统
     pub fn mount<B: BlockDevice>(block_dev: &mut Jbd2Dev<B>) -> Result<Self, RSEXT4Error> {
+        Self::mount_with_config(block_dev, &CacheConfig::default())
+    }
+
+    /// 打开Ext4文件系统，缓存大小由 `cache` 指定
+    pub fn mount_with_config<B: BlockDevice>(
+        block_dev: &mut Jbd2Dev<B>,
+        cache: &CacheConfig,
+    ) -> Result<Self, RSEXT4Error> {
+        let fs = Self::load_with_config(block_dev, cache)?;
+        fs.finish_mount(block_dev)
+    }
+
+    /// 只读取超级块和块组描述符构造实例，不检查根目录也不加载日志，过程中不写设备
+    /// 异步挂载先用它找出挂载要读的块
+    pub fn load_with_config<B: BlockDevice>(
+        block_dev: &mut Jbd2Dev<B>,
+        cache: &CacheConfig,
+    ) -> Result<Self, RSEXT4Error> {
         debug!("Start mounting Ext4 filesystem...");
 
         //在mount时应该重放一遍日志
@@ -198,6 +230,11 @@ impl Ext4FileSystem {
         }
         debug!("Superblock magic verified");
 
+        if superblock.has_casefold() && superblock.s_encoding != Ext4Superblock::EXT4_ENC_UTF8_12_1 {
+            error!("Unsupported filename encoding: {}", superblock.s_encoding);
+            return Err(RSEXT4Error::UnsupportedFeature);
+        }
+
         // 3. 检查文件系统状态
         if superblock.s_state == Ext4Superblock::EXT4_ERROR_FS {
             warn!("Filesystem is in error state");
@@ -218,8 +255,8 @@ impl Ext4FileSystem {
         let inode_allocator = InodeAllocator::new(&superblock);
         debug!("Allocators initialized");
 
-        // 7. 初始化位图缓存（最多缓存8个位图）
-        let bitmap_cache = BitmapCache::default();
+        // 7. 初始化位图缓存
+        let bitmap_cache = BitmapCache::with_budget(cache.bitmap_bytes);
         debug!("Bitmap cache initialized (lazy loading)");
 
         // 初始化inode缓存
@@ -230,15 +267,16 @@ impl Ext4FileSystem {
             0 => DEFAULT_INODE_SIZE as usize,
             n => n as usize,
         };
-        let inode_cache = InodeCache::new(INODE_CACHE_MAX, inode_size);
+        let inode_cache = InodeCache::with_budget(cache.inode_bytes, inode_size);
         debug!("Inode cache initialized");
 
         // 初始化数据块缓存
-        let datablock_cache = DataBlockCache::new(DATABLOCK_CACHE_MAX, BLOCK_SIZE);
+        let datablock_cache =
+            DataBlockCache::with_budget(cache.data_bytes, cache.dirty_data_bytes, BLOCK_SIZE);
         debug!("Data block cache initialized");
 
         // 构造文件系统实例
-        let mut fs = Self {
+        let fs = Self {
             superblock,
             group_descs,
             block_allocator,
@@ -250,9 +288,19 @@ impl Ext4FileSystem {
             group_count,
             mounted: true,
             journal_sb_block_start: None,
+            fast_commit: FastCommitTracker::default(),
+            cwd: 2,
+            dentry_cache: DentryCache::new(cache.dentry_entries),
+            extent_cache: ExtentStatusCache::default(),
         };
         //详细debug输出
         debug_super_and_desc(&fs.superblock, &fs);
+        Ok(fs)
+    }
+
+    /// 检查根目录和 lost+found，加载并重放日志，完成挂载
+    fn finish_mount<B: BlockDevice>(self, block_dev: &mut Jbd2Dev<B>) -> Result<Self, RSEXT4Error> {
+        let mut fs = self;
 
         // rootinode check !
         debug!("Checking root directory...");
@@ -294,7 +342,7 @@ impl Ext4FileSystem {
 
         // journal check
         {
-            if fs.superblock.has_journal() {
+            if fs.superblock.has_journal() && !fs.superblock.has_external_journal() {
                 let mut jouranl_exist: bool = true;
                 fs.modify_inode(block_dev, JOURNAL_FILE_INODE as u32, |ji| {
                     jouranl_exist = ji.i_mode != 0;
@@ -307,29 +355,41 @@ impl Ext4FileSystem {
                     && !jouranl_exist
                 {
                     // 不存在但 superblock 声明有 journal，则创建一个新的 journal 文件
-                    create_journal_entry(&mut fs, block_dev).expect("create journal entry failed");
+                    let journal_blocks = default_journal_blocks(fs.superblock.blocks_count())
+                        .unwrap_or(JBD2_MIN_JOURNAL_BLOCKS);
+                    create_journal_entry(&mut fs, block_dev, journal_blocks)
+                        .expect("create journal entry failed");
                     //dump_journal_inode(&mut fs, block_dev);
                 }
             }
             //实际启用Journal
-            if block_dev.is_use_journal() {
+            if block_dev.is_use_journal() && fs.superblock.has_external_journal() {
+                // 外部日志：按 s_journal_uuid 校验注入的日志设备
+                let fs_sb = fs.superblock;
+                let j_sb = match block_dev.journal_device_mut() {
+                    Some(journal_dev) => load_external_journal_superblock(journal_dev, &fs_sb)?,
+                    None => {
+                        error!("Superblock requires an external journal device, but none attached");
+                        return Err(RSEXT4Error::JournalDeviceMissing);
+                    }
+                };
+                fs.journal_sb_block_start = Some(EXTERNAL_JOURNAL_SB_BLOCK);
+                block_dev.set_journal_superblock(j_sb, EXTERNAL_JOURNAL_SB_BLOCK);
+                block_dev.journal_replay();
+            } else if block_dev.is_use_journal() {
                 // 到这里为止：journal inode 一定存在
                 // 初始化 jbd2：读入 journal 超级块并塞进 Jbd2Dev
-                let mut j_inode = fs
-                    .get_inode_by_num(block_dev, JOURNAL_FILE_INODE as u32)
-                    .expect("load journal inode failed");
-
-                // 解析 journal inode 第 0 号逻辑块 -> 物理块
-                let journal_first_block = resolve_inode_block( block_dev, &mut j_inode, 0)
-                    .and_then(|opt| opt.ok_or(BlockDevError::Corrupted))
-                    .expect("resolve journal first block failed");
+                // journal inode 第 0 号逻辑块是 journal 超级块，日志区按 inode 的块映射寻址
+                let (journal_first_block, journal_block_map) =
+                    journal_inode_block_map(&mut fs, block_dev)
+                        .expect("resolve journal block map failed");
 
                 //写入fs
                 fs.journal_sb_block_start = Some(journal_first_block);
                 // 通过数据块缓存读出 journal superblock 内容
                 let journal_data = fs
                     .datablock_cache
-                    .get_or_load(block_dev, journal_first_block as u64)
+                    .get_or_load(block_dev, journal_first_block)
                     .expect("load journal superblock block failed")
                     .data
                     .clone();
@@ -338,10 +398,34 @@ impl Ext4FileSystem {
 
                 // 把 journal superblock 交给 Jbd2Dev，由它内部 lazy-init JBD2DEVSYSTEM
                 block_dev.set_journal_superblock(j_sb, fs.journal_sb_block_start.unwrap());
+                block_dev.set_journal_block_map(journal_block_map);
 
                 // Mount-time journal replay for crash recovery.
                 block_dev.journal_replay(); //这里是在读取超级块之后再进行回放的，目前为了快速开启日志时数据不一致问题已经在写入超级块，块组描述符时直接落盘
             }
+            // fast commit：jbd2 重放之后再重放 fast commit 区域，然后开始跟踪修改
+            if block_dev.is_use_journal() && fs.journal_sb_block_start.is_some() {
+                if block_dev.is_fast_commit() {
+                    replay_fast_commit(&mut fs, block_dev).map_err(|_| RSEXT4Error::IoError)?;
+                } else if fs.superblock.has_fast_commit() {
+                    block_dev
+                        .set_fast_commit(true)
+                        .map_err(|_| RSEXT4Error::IoError)?;
+                }
+                fs.fast_commit.enabled = block_dev.is_fast_commit();
+            }
+            // 64 位文件系统的块号可能超过 u32，journal 描述符必须使用带高位的 tag
+            if block_dev.is_use_journal()
+                && fs.journal_sb_block_start.is_some()
+                && (fs
+                    .superblock
+                    .has_feature_incompat(Ext4Superblock::EXT4_FEATURE_INCOMPAT_64BIT)
+                    || fs.superblock.blocks_count() > u32::MAX as u64)
+            {
+                block_dev
+                    .enable_journal_64bit()
+                    .map_err(|_| RSEXT4Error::IoError)?;
+            }
         }
 
         //详细的Inode/DataBlock占用情况
@@ -357,12 +441,12 @@ impl Ext4FileSystem {
 
             let inode_bitmap_data = fs
                 .bitmap_cache
-                .get_or_load(block_dev, inode_cache_key, inode_bitmap_blk as u64)
+                .get_or_load(block_dev, inode_cache_key, inode_bitmap_blk)
                 .expect("Blcok Read Failed!")
                 .clone();
             let blockbitmap_data = fs
                 .bitmap_cache
-                .get_or_load(block_dev, data_cache_key, data_bitmap_blk as u64)
+                .get_or_load(block_dev, data_cache_key, data_bitmap_blk)
                 .expect("Blcok Read Failed!");
 
             let mut indoe_count: u64 = 0;
@@ -447,7 +531,7 @@ impl Ext4FileSystem {
             // 只在块号变化时重新读取块
             if current_block != Some(block_num) {
                 block_dev
-                    .read_block(block_num as u32)
+                    .read_block(block_num)
                     .map_err(|_| RSEXT4Error::IoError)?;
                 current_block = Some(block_num);
             }
@@ -504,7 +588,8 @@ impl Ext4FileSystem {
 
         //确保缓存已经提交完毕
         block_dev.umount_commit();
-       
+        block_dev.issue_discards()?;
+        self.fast_commit.clear();
 
         self.mounted = false;
         info!("Filesystem unmounted cleanly");
@@ -512,6 +597,61 @@ impl Ext4FileSystem {
         Ok(())
     }
 
+    /// 写回所有缓存的修改并提交日志，返回前设备已经落盘
+    pub fn sync_fs<B: BlockDevice>(&mut self, block_dev: &mut Jbd2Dev<B>) -> BlockDevResult<()> {
+        full_commit(self, block_dev)?;
+        block_dev.cantflush()
+    }
+
+    /// 持久化单个文件，先写回它自己的脏数据块
+    /// 启用日志时 ordered 模式要求事务涉及的数据都先于提交落盘，之后走 fsync；
+    /// 未启用日志时只写回该文件的数据、inode 以及目录块、位图等共享元数据，其他文件的脏数据留在缓存中
+    pub fn sync_file<B: BlockDevice>(
+        &mut self,
+        block_dev: &mut Jbd2Dev<B>,
+        ino: u32,
+    ) -> BlockDevResult<()> {
+        self.datablock_cache.flush_inode(block_dev, ino)?;
+        if block_dev.is_use_journal() && self.journal_sb_block_start.is_some() {
+            return fsync(self, block_dev);
+        }
+        self.datablock_cache.flush_unowned(block_dev)?;
+        self.inodetable_cahce.flush(block_dev, ino as u64)?;
+        self.bitmap_cache.flush_all(block_dev)?;
+        self.sync_superblock(block_dev)?;
+        self.sync_group_descriptors(block_dev)?;
+        block_dev.cantflush()
+    }
+
+    /// 开始记录之后被改脏的数据块、inode 和位图，配合 journal_tracked 让一个日志句柄只提交自己的修改
+    pub fn start_tracking(&mut self) {
+        self.datablock_cache.start_tracking();
+        self.inodetable_cahce.start_tracking();
+        self.bitmap_cache.start_tracking();
+    }
+
+    /// 停止记录，丢弃记录下来的条目（不写回）
+    pub fn stop_tracking(&mut self) {
+        self.datablock_cache.take_tracked();
+        self.inodetable_cahce.take_tracked();
+        self.bitmap_cache.take_tracked();
+    }
+
+    /// 停止记录，把 start_tracking 之后被改脏的块作为元数据写进当前事务，
+    /// 其他文件的脏块留在缓存中
+    pub fn journal_tracked<B: BlockDevice>(&mut self, block_dev: &mut Jbd2Dev<B>) -> BlockDevResult<()> {
+        for block in self.datablock_cache.take_tracked() {
+            self.datablock_cache.flush_journaled(block_dev, block)?;
+        }
+        for ino in self.inodetable_cahce.take_tracked() {
+            self.inodetable_cahce.flush(block_dev, ino)?;
+        }
+        for key in self.bitmap_cache.take_tracked() {
+            self.bitmap_cache.flush(block_dev, &key)?;
+        }
+        self.sync_group_descriptors(block_dev)
+    }
+
     /// 同步块组描述符到磁盘
     /// 按 ext4 标准布局，将所有块组描述符写回：
     /// GDT 字节流紧跟在超级块之后
@@ -545,11 +685,11 @@ impl Ext4FileSystem {
                 if let Some(prev_block) = current_block
                     && Some(prev_block) == buffer_snapshot_block {
                         //由于目前日志回放在fs构建之后（块组描述符读取之后），目前为了快速修复防止读取到旧的超级块。直接落盘写回
-                        block_dev.write_block(prev_block as u32, false)?;
+                        block_dev.write_block(prev_block, false)?;
                     }
 
                 // 读取新块
-                block_dev.read_block(block_num as u32)?;
+                block_dev.read_block(block_num)?;
                 current_block = Some(block_num);
                 buffer_snapshot_block = Some(block_num);
             }
@@ -572,7 +712,7 @@ impl Ext4FileSystem {
         // 写回最后一个块
         if let Some(last_block) = current_block
             && Some(last_block) == buffer_snapshot_block {
-                block_dev.write_block(last_block as u32, true)?;
+                block_dev.write_block(last_block, true)?;
             }
 
         debug!("Group descriptors written back");
@@ -606,6 +746,20 @@ impl Ext4FileSystem {
         self.group_descs.get_mut(group_idx as usize)
     }
 
+    /// 以 BlockGroupDescTableMut 视图修改内存中的块组描述符
+    pub fn group_desc_table_mut(&mut self) -> BlockGroupDescTableMut<'_> {
+        let desc_size = core::mem::size_of::<Ext4GroupDesc>();
+        let count = self.group_descs.len();
+        // Ext4GroupDesc 是只含整数字段的 repr(C) 结构体，可以按字节视图访问
+        let data = unsafe {
+            core::slice::from_raw_parts_mut(
+                self.group_descs.as_mut_ptr() as *mut u8,
+                count * desc_size,
+            )
+        };
+        BlockGroupDescTableMut::new(data, desc_size, count as u32)
+    }
+
     /// 使用闭包修改指定 inode，内部自动计算 inode 在磁盘上的位置
     pub fn modify_inode<B, F>(
         &mut self,
@@ -633,8 +787,19 @@ impl Ext4FileSystem {
             BLOCK_SIZE,
         );
 
+        // fast commit 需要知道修改前文件覆盖的逻辑块范围，以便为截断生成删除记录
+        let mut old_blocks = 0u32;
         self.inodetable_cahce
-            .modify(block_dev, inode_num as u64, block_num, offset, f)
+            .modify(block_dev, inode_num as u64, block_num, offset, |inode| {
+                old_blocks = inode.size().div_ceil(BLOCK_SIZE as u64) as u32;
+                f(inode)
+            })?;
+        // extent 树可能跟着变了
+        self.extent_cache.invalidate(inode_num);
+        if inode_num != JOURNAL_FILE_INODE as u32 {
+            self.fast_commit.track_inode(inode_num, old_blocks);
+        }
+        Ok(())
     }
 
     /// 按 inode 号加载 inode（只读），内部自动计算在磁盘上的位置
@@ -709,6 +874,7 @@ impl Ext4FileSystem {
                 })?;
 
             let alloc = alloc_res?;
+            block_dev.cancel_discard(alloc.global_block, count);
 
             // 更新块组描述符
             if let Some(desc_mut) = self.get_group_desc_mut(group_idx) {
@@ -753,6 +919,78 @@ impl Ext4FileSystem {
         Err(BlockDevError::NoSpace)
     }
 
+    /// 从 goal_group 开始依次在各块组中分配 count 个块，每组取第一段空闲区域，尽量保持连续
+    /// 空间不足时回滚已分配的块
+    pub fn alloc_blocks_from_group<B: BlockDevice>(
+        &mut self,
+        block_dev: &mut Jbd2Dev<B>,
+        goal_group: u32,
+        count: u32,
+    ) -> BlockDevResult<Vec<u64>> {
+        let mut blocks = Vec::with_capacity(count as usize);
+        let group_count = self.group_count;
+        let goal_group = goal_group.min(group_count.saturating_sub(1));
+        let order = (goal_group..group_count).chain(0..goal_group);
+
+        for group_idx in order {
+            while (blocks.len() as u32) < count {
+                let Some(desc) = self.get_group_desc(group_idx) else {
+                    break;
+                };
+                if desc.free_blocks_count() == 0 {
+                    break;
+                }
+                let bitmap_block = desc.block_bitmap();
+                let want = count - blocks.len() as u32;
+                let mut alloc_res: Result<(BlockAlloc, u32), BlockDevError> =
+                    Err(BlockDevError::NoSpace);
+                self.bitmap_cache.modify(
+                    block_dev,
+                    CacheKey::new_block(group_idx),
+                    bitmap_block,
+                    |data| {
+                        alloc_res = self
+                            .block_allocator
+                            .alloc_first_free_run(data, group_idx, want)
+                            .map_err(|_| BlockDevError::NoSpace);
+                    },
+                )?;
+                let Ok((alloc, got)) = alloc_res else {
+                    break;
+                };
+                block_dev.cancel_discard(alloc.global_block, got);
+
+                if let Some(desc_mut) = self.get_group_desc_mut(group_idx) {
+                    let new_count = desc_mut.free_blocks_count().saturating_sub(got);
+                    desc_mut.bg_free_blocks_count_lo = (new_count & 0xFFFF) as u16;
+                    desc_mut.bg_free_blocks_count_hi = (new_count >> 16) as u16;
+                }
+                let sb_free = self.superblock.free_blocks_count().saturating_sub(got as u64);
+                self.superblock.s_free_blocks_count_lo = (sb_free & 0xFFFFFFFF) as u32;
+                self.superblock.s_free_blocks_count_hi = (sb_free >> 32) as u32;
+
+                debug!(
+                    "alloc_blocks_from_group: group={} first_global_block={} count={}",
+                    group_idx, alloc.global_block, got
+                );
+                blocks.extend((0..got as u64).map(|off| alloc.global_block + off));
+            }
+            if blocks.len() as u32 == count {
+                return Ok(blocks);
+            }
+        }
+
+        debug!(
+            "alloc_blocks_from_group: only {} of {} blocks available, rolling back",
+            blocks.len(),
+            count
+        );
+        for blk in blocks {
+            self.free_block(block_dev, blk)?;
+        }
+        Err(BlockDevError::NoSpace)
+    }
+
     /// 在整个文件系统中分配一个数据块（兼容旧接口）
     pub fn alloc_block<B: BlockDevice>(
         &mut self,
@@ -762,6 +1000,52 @@ impl Ext4FileSystem {
         Ok(v.pop().unwrap())
     }
 
+    /// 把接下来逐块分配 count 个数据块会用到的块位图读进缓存，不修改位图
+    pub fn preload_block_bitmaps<B: BlockDevice>(
+        &mut self,
+        block_dev: &mut Jbd2Dev<B>,
+        count: u32,
+    ) -> BlockDevResult<()> {
+        // alloc_block 总是取第一个还有空闲块的组
+        let mut covered = 0u64;
+        for group_idx in 0..self.group_count {
+            if covered >= count as u64 {
+                break;
+            }
+            let Some(desc) = self.get_group_desc(group_idx) else {
+                break;
+            };
+            let free = desc.free_blocks_count();
+            if free == 0 {
+                continue;
+            }
+            let bitmap_block = desc.block_bitmap();
+            self.bitmap_cache
+                .get_or_load(block_dev, CacheKey::new_block(group_idx), bitmap_block)?;
+            covered += free as u64;
+        }
+        Ok(())
+    }
+
+    /// 把下一次 alloc_inode 会用到的 inode 位图和 inode 表块读进缓存，不修改位图
+    pub fn preload_next_inode<B: BlockDevice>(
+        &mut self,
+        block_dev: &mut Jbd2Dev<B>,
+    ) -> BlockDevResult<()> {
+        let Some(group_idx) = self.group_descs.iter().position(|d| d.free_inodes_count() >= 1) else {
+            return Ok(());
+        };
+        let group_idx = group_idx as u32;
+        let bitmap_block = self.group_descs[group_idx as usize].inode_bitmap();
+        let cached =
+            self.bitmap_cache
+                .get_or_load(block_dev, CacheKey::new_inode(group_idx), bitmap_block)?;
+        if let Some(ino) = self.inode_allocator.peek_free_inode(&cached.data, group_idx) {
+            self.get_inode_by_num(block_dev, ino)?;
+        }
+        Ok(())
+    }
+
     /// 在整个文件系统中分配指定数量的 inode
     pub fn alloc_inodes<B: BlockDevice>(
         &mut self,
@@ -822,6 +1106,9 @@ impl Ext4FileSystem {
                 "Allocated inodes: group={}, first_global_inode={}, count={} [delayed write]",
                 group_idx, inodes[0], count
             );
+            for &ino in &inodes {
+                self.fast_commit.track_create(ino);
+            }
 
             return Ok(inodes);
         }
@@ -880,21 +1167,208 @@ impl Ext4FileSystem {
         if !did_free {
             return Ok(());
         }
+        self.account_freed_blocks(block_dev, group_idx, global_block, 1)
+    }
+
+    /// 释放 [start, start+count) 这段连续的数据块，可以跨块组
+    /// 每个块组内整段都已分配时一次清除位图，否则逐块释放（已空闲的块跳过）
+    pub fn free_blocks<B: BlockDevice>(
+        &mut self,
+        block_dev: &mut Jbd2Dev<B>,
+        start: u64,
+        count: u32,
+    ) -> BlockDevResult<()> {
+        let blocks_per_group = self.superblock.s_blocks_per_group;
+        let mut global = start;
+        let end = start + count as u64;
+        while global < end {
+            let (group_idx, block_in_group) = self.block_allocator.global_to_group(global);
+            let run = ((blocks_per_group - block_in_group) as u64).min(end - global) as u32;
+            let bitmap_block = self
+                .get_group_desc(group_idx)
+                .ok_or(BlockDevError::Corrupted)?
+                .block_bitmap();
+
+            let mut freed = false;
+            self.bitmap_cache.modify(
+                block_dev,
+                CacheKey::new_block(group_idx),
+                bitmap_block,
+                |data| {
+                    let bitmap = BlockBitmap::new(data, blocks_per_group);
+                    let all_used = (block_in_group..block_in_group + run)
+                        .all(|idx| bitmap.is_allocated(idx) == Some(true));
+                    freed = all_used
+                        && self
+                            .block_allocator
+                            .free_blocks(data, block_in_group, run)
+                            .is_ok();
+                },
+            )?;
+
+            if freed {
+                self.account_freed_blocks(block_dev, group_idx, global, run)?;
+            } else {
+                for blk in global..global + run as u64 {
+                    self.free_block(block_dev, blk)?;
+                }
+            }
+            global += run as u64;
+        }
+        Ok(())
+    }
+
+    /// 位图清除之后更新计数，清掉块组的已trim状态并把释放的块排进discard队列
+    fn account_freed_blocks<B: BlockDevice>(
+        &mut self,
+        block_dev: &mut Jbd2Dev<B>,
+        group_idx: u32,
+        global_block: u64,
+        count: u32,
+    ) -> BlockDevResult<()> {
         let desc = self
             .get_group_desc_mut(group_idx)
             .ok_or(BlockDevError::Corrupted)?;
         // 更新块组 free_blocks_count
         let before = desc.free_blocks_count();
-        let new_count = before.saturating_add(1);
+        let new_count = before.saturating_add(count);
         desc.bg_free_blocks_count_lo = (new_count & 0xFFFF) as u16;
         desc.bg_free_blocks_count_hi = (new_count >> 16) as u16;
+        desc.bg_flags &= !Ext4GroupDesc::EXT4_BG_TRIMMED;
 
         // 更新超级块 free_blocks_count
-        self.superblock.s_free_blocks_count_lo =
-            self.superblock.s_free_blocks_count_lo.saturating_add(1);
+        let sb_free = self.superblock.free_blocks_count().saturating_add(count as u64);
+        self.superblock.s_free_blocks_count_lo = (sb_free & 0xFFFFFFFF) as u32;
+        self.superblock.s_free_blocks_count_hi = (sb_free >> 32) as u32;
+
+        block_dev.queue_discard(global_block, count);
         Ok(())
     }
 
+    /// 对所有块组里不短于 min_len 个块的空闲区域下发discard，返回discard的块数
+    /// 先做一次完整提交，保证位图上空闲的块在磁盘上也已经释放；
+    /// 处理过的块组在描述符里置上 EXT4_BG_TRIMMED 并落盘，之后没有释放过块的组直接跳过
+    pub fn fstrim<B: BlockDevice>(
+        &mut self,
+        block_dev: &mut Jbd2Dev<B>,
+        min_len: u32,
+    ) -> BlockDevResult<u64> {
+        self.sync_fs(block_dev)?;
+
+        let min_len = min_len.max(1);
+        let blocks_per_group = self.superblock.s_blocks_per_group;
+        let first_data_block = self.superblock.s_first_data_block as u64;
+        let blocks_count = self.superblock.blocks_count();
+        let mut trimmed = 0u64;
+        let mut marked = false;
+
+        for group_idx in 0..self.group_count {
+            let Some(desc) = self.get_group_desc(group_idx) else {
+                break;
+            };
+            if desc.is_trimmed() || desc.free_blocks_count() < min_len {
+                continue;
+            }
+            let bitmap_block = desc.block_bitmap();
+            let uninit = desc.is_block_bitmap_uninit();
+            let group_start = first_data_block + group_idx as u64 * blocks_per_group as u64;
+            let group_len = blocks_count
+                .saturating_sub(group_start)
+                .min(blocks_per_group as u64) as u32;
+
+            // BLOCK_UNINIT 的块组磁盘上没有位图，按元数据布局算出来
+            let data = if uninit {
+                match self.uninit_block_bitmap(group_idx, group_start, group_len) {
+                    Some(data) => data,
+                    None => continue,
+                }
+            } else {
+                self.bitmap_cache
+                    .get_or_load(block_dev, CacheKey::new_block(group_idx), bitmap_block)?
+                    .data
+                    .clone()
+            };
+            let bitmap = BlockBitmap::new(&data, blocks_per_group);
+            let mut runs = Vec::new();
+            let mut idx = 0u32;
+            while idx < group_len {
+                if bitmap.is_free(idx) != Some(true) {
+                    idx += 1;
+                    continue;
+                }
+                let run_start = idx;
+                while idx < group_len && bitmap.is_free(idx) == Some(true) {
+                    idx += 1;
+                }
+                if idx - run_start >= min_len {
+                    runs.push((group_start + run_start as u64, idx - run_start));
+                }
+            }
+
+            for (start, len) in runs {
+                block_dev.discard(start, len)?;
+                trimmed += len as u64;
+            }
+            if let Some(desc) = self.get_group_desc_mut(group_idx) {
+                desc.bg_flags |= Ext4GroupDesc::EXT4_BG_TRIMMED;
+                marked = true;
+            }
+        }
+        // 已trim标志随块组描述符一起提交
+        if marked {
+            self.sync_fs(block_dev)?;
+        }
+
+        debug!("fstrim: discarded {trimmed} blocks (min_len={min_len})");
+        Ok(trimmed)
+    }
+
+    /// 为 BLOCK_UNINIT 的块组构造块位图：超级块/GDT 备份和落在组内的位图、inode 表标为已用
+    /// 算出的空闲块数和描述符对不上时返回 None，调用者跳过这个组
+    fn uninit_block_bitmap(&self, group_idx: u32, group_start: u64, group_len: u32) -> Option<Vec<u8>> {
+        let sb = &self.superblock;
+        let mut data = alloc::vec![0u8; BLOCK_SIZE];
+        let mut bitmap = BlockBitmapMut::new(&mut data, sb.s_blocks_per_group);
+        let group_end = group_start + group_len as u64;
+
+        let has_super = !sb.has_feature_ro_compat(Ext4Superblock::EXT4_FEATURE_RO_COMPAT_SPARSE_SUPER)
+            || need_redundant_backup(group_idx);
+        let descs_per_block = (BLOCK_SIZE as u32 / sb.get_desc_size() as u32).max(1);
+        let meta_bg = sb.has_feature_incompat(Ext4Superblock::EXT4_FEATURE_INCOMPAT_META_BG);
+        let base_blocks = if meta_bg && group_idx / descs_per_block >= sb.s_first_meta_bg {
+            let idx = group_idx % descs_per_block;
+            let gdt = (idx == 0 || idx == 1 || idx == descs_per_block - 1) as u64;
+            has_super as u64 + gdt
+        } else if has_super {
+            1 + self.group_count.div_ceil(descs_per_block) as u64 + sb.s_reserved_gdt_blocks as u64
+        } else {
+            0
+        };
+        for blk in group_start..(group_start + base_blocks).min(group_end) {
+            let _ = bitmap.allocate((blk - group_start) as u32);
+        }
+
+        // flex_bg 下别的组的位图和 inode 表也可能放在这个组里
+        let table_blocks = sb.inode_table_blocks() as u64;
+        for desc in &self.group_descs {
+            let table = desc.inode_table();
+            let used = [(desc.block_bitmap(), 1), (desc.inode_bitmap(), 1), (table, table_blocks)];
+            for (start, len) in used {
+                for blk in start.max(group_start)..(start + len).min(group_end) {
+                    let _ = bitmap.allocate((blk - group_start) as u32);
+                }
+            }
+        }
+
+        let free = (0..group_len).filter(|&i| bitmap.is_allocated(i) == Some(false)).count() as u32;
+        let expected = self.get_group_desc(group_idx)?.free_blocks_count();
+        if free != expected {
+            warn!("fstrim: group {group_idx} uninit layout has {free} free blocks, descriptor says {expected}, skipping");
+            return None;
+        }
+        Some(data)
+    }
+
     /// 根据 inode 号释放一个 inode
     /// 内部自动计算所属块组和位图位置，并更新块组/超级块计数
     pub fn free_inode<B: BlockDevice>(
@@ -902,6 +1376,8 @@ impl Ext4FileSystem {
         block_dev: &mut Jbd2Dev<B>,
         inode_num: u32,
     ) -> BlockDevResult<()> {
+        // 释放的 inode 可能是目录，它的 inode 号复用前不能再命中旧的目录项
+        self.dentry_cache.invalidate_dir(inode_num);
         // 通过 InodeAllocator 反推 (group_idx, inode_in_group)
         let (group_idx, inode_in_group) = self.inode_allocator.global_to_group(inode_num);
         let bitmap_block;
@@ -952,6 +1428,117 @@ impl Ext4FileSystem {
         Ok(())
     }
 
+    /// 在位图中把指定数据块标记为已使用（日志重放用），已使用时不做任何修改
+    /// 内部自动计算所属块组和位图位置，并更新块组/超级块计数
+    pub fn mark_block_used<B: BlockDevice>(
+        &mut self,
+        block_dev: &mut Jbd2Dev<B>,
+        global_block: u64,
+    ) -> BlockDevResult<()> {
+        let (group_idx, block_in_group) = self.block_allocator.global_to_group(global_block);
+        let bitmap_block = self
+            .get_group_desc(group_idx)
+            .ok_or(BlockDevError::Corrupted)?
+            .block_bitmap();
+        let cache_key = CacheKey::new_block(group_idx);
+        let blocks_per_group = self.superblock.s_blocks_per_group;
+
+        let mut mark_ok = Ok(());
+        let mut did_mark = true;
+        self.bitmap_cache
+            .modify(block_dev, cache_key, bitmap_block, |data| {
+                mark_ok = match BlockBitmapMut::new(data, blocks_per_group).allocate(block_in_group) {
+                    Ok(()) => Ok(()),
+                    Err(BitmapError::AlreadyAllocated) => {
+                        did_mark = false;
+                        Ok(())
+                    }
+                    Err(_) => Err(BlockDevError::Corrupted),
+                };
+            })?;
+        mark_ok?;
+
+        if !did_mark {
+            return Ok(());
+        }
+        block_dev.cancel_discard(global_block, 1);
+        let desc = self
+            .get_group_desc_mut(group_idx)
+            .ok_or(BlockDevError::Corrupted)?;
+        let new_count = desc.free_blocks_count().saturating_sub(1);
+        desc.bg_free_blocks_count_lo = (new_count & 0xFFFF) as u16;
+        desc.bg_free_blocks_count_hi = (new_count >> 16) as u16;
+
+        let sb_free = self.superblock.free_blocks_count().saturating_sub(1);
+        self.superblock.s_free_blocks_count_lo = (sb_free & 0xFFFFFFFF) as u32;
+        self.superblock.s_free_blocks_count_hi = (sb_free >> 32) as u32;
+        Ok(())
+    }
+
+    /// 在位图中把指定 inode 标记为已使用（日志重放用），已使用时不做任何修改
+    pub fn mark_inode_used<B: BlockDevice>(
+        &mut self,
+        block_dev: &mut Jbd2Dev<B>,
+        inode_num: u32,
+        is_dir: bool,
+    ) -> BlockDevResult<()> {
+        let (group_idx, inode_in_group) = self.inode_allocator.global_to_group(inode_num);
+        let bitmap_block = self
+            .get_group_desc(group_idx)
+            .ok_or(BlockDevError::Corrupted)?
+            .inode_bitmap();
+        let cache_key = CacheKey::new_inode(group_idx);
+        let inodes_per_group = self.superblock.s_inodes_per_group;
+
+        let mut mark_ok = Ok(());
+        let mut did_mark = true;
+        self.bitmap_cache
+            .modify(block_dev, cache_key, bitmap_block, |data| {
+                mark_ok = match InodeBitmapMut::new(data, inodes_per_group).allocate(inode_in_group) {
+                    Ok(()) => Ok(()),
+                    Err(BitmapError::AlreadyAllocated) => {
+                        did_mark = false;
+                        Ok(())
+                    }
+                    Err(_) => Err(BlockDevError::Corrupted),
+                };
+            })?;
+        mark_ok?;
+
+        if !did_mark {
+            return Ok(());
+        }
+        let desc = self
+            .get_group_desc_mut(group_idx)
+            .ok_or(BlockDevError::Corrupted)?;
+        let new_count = desc.free_inodes_count().saturating_sub(1);
+        desc.bg_free_inodes_count_lo = (new_count & 0xFFFF) as u16;
+        desc.bg_free_inodes_count_hi = (new_count >> 16) as u16;
+        if is_dir {
+            let dirs = desc.used_dirs_count().saturating_add(1);
+            desc.bg_used_dirs_count_lo = (dirs & 0xFFFF) as u16;
+            desc.bg_used_dirs_count_hi = ((dirs >> 16) & 0xFFFF) as u16;
+        }
+
+        self.superblock.s_free_inodes_count = self.superblock.s_free_inodes_count.saturating_sub(1);
+        Ok(())
+    }
+
+    /// 丢弃所有缓存并重新读取超级块和块组描述符
+    /// 日志重放直接写底层设备，之后内存中的元数据可能已经过期
+    pub fn reload_metadata<B: BlockDevice>(
+        &mut self,
+        block_dev: &mut Jbd2Dev<B>,
+    ) -> BlockDevResult<()> {
+        self.bitmap_cache.clear();
+        self.inodetable_cahce.clear();
+        self.datablock_cache.clear();
+        self.superblock = read_superblock(block_dev)?;
+        self.group_descs = Self::load_group_descriptors(block_dev, self.group_count)
+            .map_err(|_| BlockDevError::Corrupted)?;
+        Ok(())
+    }
+
     /// 查找有空闲块的块组
     pub fn find_group_with_free_blocks(&self) -> Option<u32> {
         for (idx, desc) in self.group_descs.iter().enumerate() {
@@ -1025,7 +1612,15 @@ pub fn find_file<B: BlockDevice>(
 
 /// 简化的挂载函数（用于兼容旧代码）
 pub fn mount<B: BlockDevice>(block_dev: &mut Jbd2Dev<B>) -> BlockDevResult<Ext4FileSystem> {
-    match Ext4FileSystem::mount(block_dev) {
+    mount_with_config(block_dev, &CacheConfig::default())
+}
+
+/// 挂载并指定缓存大小
+pub fn mount_with_config<B: BlockDevice>(
+    block_dev: &mut Jbd2Dev<B>,
+    cache: &CacheConfig,
+) -> BlockDevResult<Ext4FileSystem> {
+    match Ext4FileSystem::mount_with_config(block_dev, cache) {
         Ok(_fs) => {
             info!("Ext4 filesystem mounted");
             Ok(_fs)
@@ -1145,11 +1740,11 @@ pub fn compute_fs_layout(inode_size:u16,total_blocks: u64) -> FsLayoutInfo {
     let reserved_gdt_blocks: u32 = RESERVED_GDT_BLOCKS;
 
     // 组0布局：
-    // - 对于 4K：Primary superblock at 0, GDT at 1, Reserved GDT blocks at 2..(2+reserved_gdt_blocks-1)
+    // - 对于 4K：Primary superblock at 0, GDT at 1..(1+gdt_blocks-1), Reserved GDT blocks 紧随其后
     // - 我们在预留 GDT 区域之后顺序放置 block_bitmap、inode_bitmap、inode_table
     let group0_start: u32 = first_data_block;
-    let reserved_gdt_start: u32 = group0_start + 2; // 块0=引导/超级块，块1=GDT，块2.. 预留GDT
-    let group0_block_bitmap: u32 = reserved_gdt_start + reserved_gdt_blocks; // 2 + reserved
+    let reserved_gdt_start: u32 = group0_start + 1 + gdt_blocks; // 块0=引导/超级块，块1..=GDT，之后预留GDT
+    let group0_block_bitmap: u32 = reserved_gdt_start + reserved_gdt_blocks; // 1 + gdt + reserved
     let group0_inode_bitmap: u32 = group0_block_bitmap + 1;
     let group0_inode_table: u32 = group0_inode_bitmap + 1;
     let group0_metadata_blocks: u32 = (group0_inode_table + inode_table_blocks) - group0_start;
@@ -1177,7 +1772,47 @@ pub fn compute_fs_layout(inode_size:u16,total_blocks: u64) -> FsLayoutInfo {
     }
 }
 
+/// mkfs 可选参数
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MkfsOptions {
+    /// 内部日志块数，None 时按设备大小自动选择（与 e2fsprogs 相同，设备太小则不建日志）
+    pub journal_blocks: Option<u32>,
+}
+
 pub fn mkfs<B: BlockDevice>(block_dev: &mut Jbd2Dev<B>) -> BlockDevResult<()> {
+    mkfs_inner(block_dev, None, &MkfsOptions::default())
+}
+
+/// 按给定参数格式化文件系统
+pub fn mkfs_with_options<B: BlockDevice>(
+    block_dev: &mut Jbd2Dev<B>,
+    options: &MkfsOptions,
+) -> BlockDevResult<()> {
+    mkfs_inner(block_dev, None, options)
+}
+
+/// 格式化文件系统并使用外部日志设备
+/// 日志设备需先用 mkfs_journal_device 格式化，文件系统UUID会登记到日志设备的用户列表
+pub fn mkfs_with_journal_device<B: BlockDevice, J: BlockDevice + ?Sized>(
+    block_dev: &mut Jbd2Dev<B>,
+    journal_dev: &mut J,
+) -> BlockDevResult<()> {
+    let jbd2_sb = read_external_journal_superblock(journal_dev)?;
+    if jbd2_sb.s_header.h_magic != JBD2_MAGIC {
+        error!("Journal device is not formatted");
+        return Err(BlockDevError::Corrupted);
+    }
+    mkfs_inner(block_dev, Some(jbd2_sb.s_uuid), &MkfsOptions::default())?;
+    let sb = read_superblock(block_dev)?;
+    register_journal_user(journal_dev, &sb.s_uuid)
+}
+
+/// journal_uuid 为 Some 时日志放在外部设备上，不创建 journal inode
+fn mkfs_inner<B: BlockDevice>(
+    block_dev: &mut Jbd2Dev<B>,
+    journal_uuid: Option<[u8; 16]>,
+    options: &MkfsOptions,
+) -> BlockDevResult<()> {
     debug!("Start initializing Ext4 filesystem...");
     // mkfs 阶段先强制关闭日志，避免还未初始化 journal superblock 时触发 JBD2 逻辑
     block_dev.set_journal_use(false);
@@ -1194,8 +1829,29 @@ pub fn mkfs<B: BlockDevice>(block_dev: &mut Jbd2Dev<B>) -> BlockDevResult<()> {
     debug!("  Blocks per group: {}", layout.blocks_per_group);
     debug!("  Inodes per group: {}", layout.inodes_per_group);
 
+    // 内部日志的块数：显式指定时至少 JBD2_MIN_JOURNAL_BLOCKS 且不超过设备一半
+    let journal_blocks = match (journal_uuid, options.journal_blocks) {
+        (Some(_), _) => None,
+        (None, Some(blocks)) => {
+            if blocks < JBD2_MIN_JOURNAL_BLOCKS || blocks as u64 > total_blocks / 2 {
+                error!("Invalid journal size: {blocks} blocks (device {total_blocks} blocks)");
+                return Err(BlockDevError::InvalidInput);
+            }
+            Some(blocks)
+        }
+        (None, None) => default_journal_blocks(total_blocks),
+    };
+    debug!("  Journal blocks: {journal_blocks:?}");
+
     //构建并根据fearure写入到所有group超级块
-    let superblock = build_superblock(total_blocks, &layout);
+    let mut superblock = build_superblock(total_blocks, &layout);
+    if let Some(uuid) = journal_uuid {
+        superblock.s_journal_inum = 0;
+        superblock.s_journal_uuid = uuid;
+    } else {
+        // 内部日志在下面的挂载流程中按指定大小创建，先去掉特性避免挂载时自动创建
+        superblock.s_feature_compat &= !Ext4Superblock::EXT4_FEATURE_COMPAT_HAS_JOURNAL;
+    }
     write_superblock(block_dev, &superblock)?;
     debug!("Superblock written");
 
@@ -1204,12 +1860,11 @@ pub fn mkfs<B: BlockDevice>(block_dev: &mut Jbd2Dev<B>) -> BlockDevResult<()> {
 
     //注意顺序
     let mut descs: VecDeque<Ext4GroupDesc> = VecDeque::new();
-    //为superblock写入gdt（全部标记为UNINIT）
+    //为superblock写入gdt（全部标记为UNINIT），按块打包写入
     for group_id in 0..total_groups {
-        let desc = build_uninit_group_desc(&superblock, group_id, &layout);
-        write_group_desc(block_dev, group_id, &desc)?;
-        descs.push_back(desc);
+        descs.push_back(build_uninit_group_desc(&superblock, group_id, &layout));
     }
+    write_gdt_blocks(block_dev, 1, &descs, &superblock, &layout)?;
     //为其它块组选择性的写入冗余备份desc
     write_gdt_redundant_backup(block_dev, &descs, &superblock, total_groups, &layout)?;
     debug!("{total_groups} block group descriptors written");
@@ -1225,11 +1880,19 @@ pub fn mkfs<B: BlockDevice>(block_dev: &mut Jbd2Dev<B>) -> BlockDevResult<()> {
     // 注意：此时日志仍然关闭，等真正挂载时再开启 JBD2
     {
         let mut fs = Ext4FileSystem::mount(block_dev).expect("Mount Failed!");
+        if let Some(journal_blocks) = journal_blocks {
+            fs.superblock.s_feature_compat |= Ext4Superblock::EXT4_FEATURE_COMPAT_HAS_JOURNAL;
+            create_journal_entry(&mut fs, block_dev, journal_blocks)?;
+        }
         fs.umount(block_dev)?;
     }
 
     //  验证：读回超级块检查魔数
     let verify_sb = read_superblock(block_dev)?;
+    if journal_blocks.is_some() {
+        // 日志特性和 s_jnl_blocks 在挂载流程中才写入，同步到备份超级块
+        write_superblock_redundant_backup(block_dev, &verify_sb, total_groups, &layout)?;
+    }
 
     // mkfs 结束前恢复日志开关（为后续真实挂载做准备）
     block_dev.set_journal_use(old_jouranl_use);
@@ -1285,6 +1948,8 @@ fn build_superblock(total_blocks: u64, layout: &FsLayoutInfo) -> Ext4Superblock
     //需要生成UUID
     let uuid = generate_uuid();
     sb.s_hash_seed = uuid.0;
+    // 与 x86 上的 mke2fs 一致，明确记录目录哈希按有符号 char 计算
+    sb.s_flags |= Ext4Superblock::EXT2_FLAGS_SIGNED_HASH;
 
     //设置文件系统UUID
     let filesys_uuid = generate_uuid_8();
@@ -1349,9 +2014,9 @@ fn build_uninit_group_desc(
     );
 
     // 位图和 inode 表块号
-    desc.bg_block_bitmap_lo = gl.group_blcok_bitmap_startblocks as u32;
-    desc.bg_inode_bitmap_lo = gl.group_inode_bitmap_startblocks as u32;
-    desc.bg_inode_table_lo = gl.group_inode_table_startblocks as u32;
+    desc.set_block_bitmap(gl.group_blcok_bitmap_startblocks);
+    desc.set_inode_bitmap(gl.group_inode_bitmap_startblocks);
+    desc.set_inode_table(gl.group_inode_table_startblocks);
 
     // 理论空闲块数：整组减去元数据块
     let used_meta = gl.metadata_blocks_in_group as u32;
@@ -1403,10 +2068,10 @@ fn write_superblock_redundant_backup<B: BlockDevice>(
             //需要超级块备份
             if need_redundant_backup(gid) {
                 let super_blocks = group_layout.group_start_block;
-                block_dev.read_block(super_blocks as u32).expect("Superblock read failed!");
+                block_dev.read_block(super_blocks).expect("Superblock read failed!");
                 let buffer = block_dev.buffer_mut();
                 sb.to_disk_bytes(&mut buffer[0..SUPERBLOCK_SIZE]);
-                block_dev.write_block(super_blocks as u32, true)?;
+                block_dev.write_block(super_blocks, true)?;
             }
         }
     }
@@ -1491,25 +2156,7 @@ fn write_gdt_redundant_backup<B: BlockDevice>(
                     fs_layout.gdt_blocks,
                 );
                 let gdt_start = group_layout.group_start_block + 1; //跳过超级块
-
-                let mut desc_iter = descs.iter();
-                //循环写入desc
-                for gdt_block_id in gdt_start..group_layout.group_blcok_bitmap_startblocks {
-                    block_dev.read_block(gdt_block_id as u32)?;
-                    let buffer = block_dev.buffer_mut();
-                    let mut current_offset = 0_usize; //descoffset循环记录
-                    for _ in 0..fs_layout.descs_per_block {
-                        if let Some(desc) = desc_iter.next() {
-                            desc.to_disk_bytes(
-                                &mut buffer
-                                    [current_offset..current_offset + desc_size as usize],
-                            );
-                            current_offset += desc_size as usize;
-                        }
-                    }
-                    //写回磁盘
-                    block_dev.write_block(gdt_block_id as u32, true)?;
-                }
+                write_gdt_blocks(block_dev, gdt_start, descs, sb, fs_layout)?;
             }
         }
     }
@@ -1517,6 +2164,33 @@ fn write_gdt_redundant_backup<B: BlockDevice>(
     Ok(())
 }
 
+/// 从 gdt_start 开始按块写入全部组描述符（主 GDT 或备份 GDT）
+fn write_gdt_blocks<B: BlockDevice>(
+    block_dev: &mut Jbd2Dev<B>,
+    gdt_start: u64,
+    descs: &VecDeque<Ext4GroupDesc>,
+    sb: &Ext4Superblock,
+    fs_layout: &FsLayoutInfo,
+) -> BlockDevResult<()> {
+    let desc_size = sb.get_desc_size() as usize;
+    let mut desc_iter = descs.iter();
+    //循环写入desc
+    for gdt_block_id in gdt_start..gdt_start + fs_layout.gdt_blocks as u64 {
+        block_dev.read_block(gdt_block_id)?;
+        let buffer = block_dev.buffer_mut();
+        let mut current_offset = 0_usize; //descoffset循环记录
+        for _ in 0..fs_layout.descs_per_block {
+            if let Some(desc) = desc_iter.next() {
+                desc.to_disk_bytes(&mut buffer[current_offset..current_offset + desc_size]);
+                current_offset += desc_size;
+            }
+        }
+        //写回磁盘
+        block_dev.write_block(gdt_block_id, true)?;
+    }
+    Ok(())
+}
+
 /// 写入块组0的描述符 管字节序
 fn write_group_desc<B: BlockDevice>(
     block_dev: &mut Jbd2Dev<B>,
@@ -1536,17 +2210,26 @@ fn write_group_desc<B: BlockDevice>(
     let end = in_block + desc_size;
 
     // 读取目标块，修改对应 slice，再写回
-    block_dev.read_block(block_num as u32)?;
+    block_dev.read_block(block_num)?;
     let buffer = block_dev.buffer_mut();
     if end > buffer.len() {
         return Err(BlockDevError::Corrupted);
     }
     desc.to_disk_bytes(&mut buffer[in_block..end]);
-    block_dev.write_block(block_num as u32, true)?;
+    block_dev.write_block(block_num, true)?;
 
     Ok(())
 }
 
+/// inode 位图中超出 inodes_per_group 的填充位置 1
+fn mark_inode_bitmap_padding(buffer: &mut [u8], inodes_per_group: u32) {
+    let full_from = inodes_per_group.div_ceil(8) as usize;
+    for i in inodes_per_group..full_from as u32 * 8 {
+        buffer[(i / 8) as usize] |= 1 << (i % 8);
+    }
+    buffer[full_from..BLOCK_SIZE].fill(0xFF);
+}
+
 /// 初始化块组0
 fn initialize_group_0<B: BlockDevice>(
     block_dev: &mut Jbd2Dev<B>,
@@ -1568,7 +2251,7 @@ fn initialize_group_0<B: BlockDevice>(
             buffer[byte_idx] |= 1 << bit_idx;
         }
     }
-    block_dev.write_block(block_bitmap_blk, true)?;
+    block_dev.write_block(block_bitmap_blk as u64, true)?;
 
     {
         let buffer = block_dev.buffer_mut();
@@ -1581,14 +2264,9 @@ fn initialize_group_0<B: BlockDevice>(
         }
 
         // 2.5padding无效inode为1
-        let bits_per_group = BLOCK_SIZE_U32 * 8;
-        for i in layout.inodes_per_group..bits_per_group {
-            let byte_idx: usize = (i / 8) as usize;
-            let bit_idx = i % 8;
-            buffer[byte_idx] |= 1 << bit_idx;
-        }
+        mark_inode_bitmap_padding(buffer, layout.inodes_per_group);
     }
-    block_dev.write_block(inode_bitmap_blk, true)?;
+    block_dev.write_block(inode_bitmap_blk as u64, true)?;
 
     //  清零inode表
     {
@@ -1596,7 +2274,7 @@ fn initialize_group_0<B: BlockDevice>(
         buffer.fill(0);
     }
     for i in 0..layout.inode_table_blocks {
-        block_dev.write_block(inode_table_blk + i, true)?;
+        block_dev.write_block(inode_table_blk as u64 + i as u64, true)?;
     }
 
     //  更新块组0的描述符（清除UNINIT标志）
@@ -1636,8 +2314,8 @@ fn initialize_other_groups_bitmaps<B: BlockDevice>(
             layout.gdt_blocks,
         );
 
-        let block_bitmap_blk = gl.group_blcok_bitmap_startblocks as u32;
-        let inode_bitmap_blk = gl.group_inode_bitmap_startblocks as u32;
+        let block_bitmap_blk = gl.group_blcok_bitmap_startblocks;
+        let inode_bitmap_blk = gl.group_inode_bitmap_startblocks;
 
         //  初始化块位图：全0 → 所有块空闲
         {
@@ -1659,15 +2337,149 @@ fn initialize_other_groups_bitmaps<B: BlockDevice>(
             buffer.fill(0);
 
             // padding无效inode
-            let bits_per_group = BLOCK_SIZE_U32 * 8;
-            for i in layout.inodes_per_group..bits_per_group {
-                let byte_idx: usize = (i / 8) as usize;
-                let bit_idx = i % 8;
-                buffer[byte_idx] |= 1 << bit_idx;
-            }
+            mark_inode_bitmap_padding(buffer, layout.inodes_per_group);
         }
         block_dev.write_block(inode_bitmap_blk, true)?;
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::ext4_backend::file::{mkfile, read_file};
+    use crate::ext4_backend::jbd2::logdump::{JournalScanMode, inspect_journal};
+    use crate::ext4_backend::namei::lookup_path;
+    use alloc::collections::BTreeMap;
+
+    const ZERO: [u8; 256] = [0; 256];
+
+    struct SparseDev {
+        blocks: BTreeMap<u64, Vec<u8>>,
+        total_blocks: u64,
+    }
+
+    impl BlockDevice for SparseDev {
+        fn write(&mut self, buffer: &[u8], block_id: u64, count: u32) -> BlockDevResult<()> {
+            for i in 0..count as usize {
+                let data = &buffer[i * BLOCK_SIZE..(i + 1) * BLOCK_SIZE];
+                let len = data
+                    .chunks(256)
+                    .rposition(|c| c != &ZERO[..c.len()])
+                    .map_or(0, |p| (p + 1) * 256);
+                if len == 0 {
+                    self.blocks.remove(&(block_id + i as u64));
+                } else {
+                    self.blocks.insert(block_id + i as u64, data[..len].to_vec());
+                }
+            }
+            Ok(())
+        }
+
+        fn read(&mut self, buffer: &mut [u8], block_id: u64, count: u32) -> BlockDevResult<()> {
+            for i in 0..count as usize {
+                let out = &mut buffer[i * BLOCK_SIZE..(i + 1) * BLOCK_SIZE];
+                out.fill(0);
+                if let Some(data) = self.blocks.get(&(block_id + i as u64)) {
+                    out[..data.len()].copy_from_slice(data);
+                }
+            }
+            Ok(())
+        }
+
+        fn open(&mut self) -> BlockDevResult<()> {
+            Ok(())
+        }
+
+        fn close(&mut self) -> BlockDevResult<()> {
+            Ok(())
+        }
+
+        fn total_blocks(&self) -> u64 {
+            self.total_blocks
+        }
+    }
+
+    #[test]
+    fn test_block_numbers_above_16tib() {
+        const HIGH: u64 = 1 << 32;
+        const BLOCKS_PER_GROUP: u64 = 8 * BLOCK_SIZE as u64;
+        let high_group = (HIGH / BLOCKS_PER_GROUP) as usize;
+        let storage = SparseDev {
+            blocks: BTreeMap::new(),
+            total_blocks: HIGH + 2 * BLOCKS_PER_GROUP,
+        };
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, storage, false);
+        mkfs_with_options(&mut dev, &MkfsOptions { journal_blocks: Some(1024) }).unwrap();
+        let mut fs = mount(&mut dev).unwrap();
+        assert!(fs.superblock.has_feature_incompat(Ext4Superblock::EXT4_FEATURE_INCOMPAT_64BIT));
+        // mkfs 直接创建 64 位日志，不依赖挂载时再打开
+        let dump = inspect_journal(&mut fs, &mut dev, JournalScanMode::Full).unwrap();
+        assert_ne!(dump.superblock.s_feature_incompat & JBD2_FEATURE_INCOMPAT_64BIT, 0);
+        // 16TiB 之后的块组，元数据位置写在描述符的 _hi 字段里
+        let desc = fs.group_descs[high_group];
+        assert_eq!(desc.bg_block_bitmap_hi, 1);
+        assert!(desc.block_bitmap() >= HIGH && desc.inode_table() >= HIGH);
+        umount(fs, &mut dev).unwrap();
+
+        dev.set_journal_use(true);
+        let mut fs = mount(&mut dev).unwrap();
+        // 把 16TiB 以下的块组伪装成已满，让分配落到高位块组
+        for desc in fs.group_descs.iter_mut().take(high_group) {
+            desc.bg_free_blocks_count_lo = 0;
+            desc.bg_free_blocks_count_hi = 0;
+        }
+        let data: Vec<u8> = (0..3 * BLOCK_SIZE).map(|i| (i % 251) as u8).collect();
+        mkfile(&mut dev, &mut fs, "/big", Some(&data), None).unwrap();
+        let (_, mut inode) = lookup_path(&mut fs, &mut dev, "/big", true).unwrap();
+        let first = resolve_inode_block(&mut dev, &mut inode, 0).unwrap().unwrap();
+        assert!(first >= HIGH);
+
+        umount(fs, &mut dev).unwrap();
+
+        // 高位块组的位图经过日志，descriptor tag 带上块号高 32 位
+        let mut fs = mount(&mut dev).unwrap();
+        let dump = inspect_journal(&mut fs, &mut dev, JournalScanMode::Full).unwrap();
+        assert_ne!(dump.superblock.s_feature_incompat & JBD2_FEATURE_INCOMPAT_64BIT, 0);
+        assert!(
+            dump.transactions
+                .iter()
+                .flat_map(|t| &t.tags)
+                .any(|tag| tag.target_block == desc.block_bitmap())
+        );
+        assert_eq!(read_file(&mut dev, &mut fs, "/big").unwrap().unwrap(), data);
+        let (_, mut inode) = lookup_path(&mut fs, &mut dev, "/big", true).unwrap();
+        assert_eq!(resolve_inode_block(&mut dev, &mut inode, 2).unwrap(), Some(first + 2));
+
+        // 空闲块数的低 32 位为 0 时，重放标记已用块要从高 32 位借位
+        fs.superblock.s_free_blocks_count_lo = 0;
+        fs.superblock.s_free_blocks_count_hi = 1;
+        fs.mark_block_used(&mut dev, first + 3).unwrap();
+        assert_eq!(fs.superblock.free_blocks_count(), HIGH - 1);
+
+        // 没有 64 位 tag 的日志不能记录 16TiB 之后的块，提交失败且日志区不被改动
+        let mut journal = SparseDev {
+            blocks: BTreeMap::new(),
+            total_blocks: 64,
+        };
+        let mut sys = JBD2DEVSYSTEM {
+            start_block: 0,
+            block_map: Vec::new(),
+            max_len: 16,
+            head: 0,
+            sequence: 1,
+            jbd2_super_block: JournalSuperBllockS {
+                s_maxlen: 16,
+                ..Default::default()
+            },
+            commit_queue: Vec::new(),
+            fc_off: 0,
+        };
+        sys.commit_queue.push(Jbd2Update(first + 3, [0x77; BLOCK_SIZE]));
+        assert_eq!(sys.commit_transaction(&mut journal), Err(()));
+        assert!(journal.blocks.is_empty());
+    }
+}
@@ -0,0 +1,234 @@
+//! Extent 状态缓存
+//!
+//! 类似 Linux 的 extent status tree：按 inode 记录最近查到的 extent，
+//! 读写时先在这里查逻辑块对应的物理块，未命中再从 extent 树按范围向后读取。
+//! inode 被修改（包括 extent 树变化）时整个 inode 的条目失效
+
+use alloc::collections::BTreeMap;
+
+use crate::ext4_backend::blockdev::*;
+use crate::ext4_backend::buffer_cache::*;
+use crate::ext4_backend::config::*;
+use crate::ext4_backend::disknode::*;
+use crate::ext4_backend::entries::*;
+use crate::ext4_backend::error::*;
+use crate::ext4_backend::ext4::*;
+use crate::ext4_backend::extents_tree::*;
+
+/// 未命中时一次从 extent 树读取的 extent 数
+const EXTENT_STATUS_FILL: usize = 16;
+
+/// extent 状态缓存
+pub struct ExtentStatusCache {
+    /// inode 号 -> (起始逻辑块 -> extent)
+    inodes: LruCache<u32, BTreeMap<u64, Ext4ExtentStatus>>,
+    /// 最多记录的 inode 数
+    max_inodes: usize,
+    /// 每个 inode 最多记录的 extent 数
+    max_extents: usize,
+}
+
+impl ExtentStatusCache {
+    pub fn new(max_inodes: usize, max_extents: usize) -> Self {
+        Self {
+            inodes: LruCache::new(),
+            max_inodes: max_inodes.max(1),
+            max_extents: max_extents.max(1),
+        }
+    }
+
+    /// 查找覆盖逻辑块 lblk 的 extent
+    pub fn lookup(&mut self, ino: u32, lblk: u64) -> Option<Ext4ExtentStatus> {
+        let extents = self.inodes.get_mut(&ino)?;
+        let (_, es) = extents.range(..=lblk).next_back()?;
+        es.contains(lblk).then_some(*es)
+    }
+
+    /// 记录一个 extent，该 inode 的条目满了时丢掉起始逻辑块离它最远的一个
+    pub fn insert(&mut self, ino: u32, es: Ext4ExtentStatus) {
+        if es.es_len == 0 {
+            return;
+        }
+        if self.inodes.get_mut(&ino).is_none() {
+            if self.inodes.len() >= self.max_inodes
+                && let Some(lru) = self.inodes.lru_key()
+            {
+                self.inodes.remove(&lru);
+            }
+            self.inodes.insert(ino, BTreeMap::new());
+        }
+        let Some(extents) = self.inodes.get_mut(&ino) else {
+            return;
+        };
+        if !extents.contains_key(&es.es_lblk) && extents.len() >= self.max_extents {
+            let first = extents.keys().next().copied();
+            let last = extents.keys().next_back().copied();
+            let victim = match (first, last) {
+                (Some(f), Some(l)) if es.es_lblk.abs_diff(f) >= es.es_lblk.abs_diff(l) => f,
+                (_, Some(l)) => l,
+                _ => return,
+            };
+            extents.remove(&victim);
+        }
+        extents.insert(es.es_lblk, es);
+    }
+
+    /// 丢弃一个 inode 的所有条目
+    pub fn invalidate(&mut self, ino: u32) {
+        self.inodes.remove(&ino);
+    }
+
+    pub fn clear(&mut self) {
+        self.inodes.clear();
+    }
+
+    /// 记录的 extent 总数
+    pub fn len(&self) -> usize {
+        self.inodes.values().map(|extents| extents.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inodes.is_empty()
+    }
+}
+
+impl Default for ExtentStatusCache {
+    fn default() -> Self {
+        Self::new(EXTENT_STATUS_INODES, EXTENT_STATUS_PER_INODE)
+    }
+}
+
+impl From<&Ext4Extent> for Ext4ExtentStatus {
+    fn from(ext: &Ext4Extent) -> Self {
+        Self {
+            es_lblk: ext.ee_block as u64,
+            es_len: ext.actual_len() as u64,
+            es_pblk: ext.start_block(),
+        }
+    }
+}
+
+/// 把逻辑块 lblk 映射到物理块，返回 (物理块, 从 lblk 开始连续的块数)；
+/// lblk 落在空洞中时物理块为 None，块数为到下一个 extent 为止的空洞长度
+pub fn map_blocks<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    dev: &mut Jbd2Dev<B>,
+    ino: u32,
+    inode: &Ext4Inode,
+    lblk: u32,
+) -> BlockDevResult<(Option<u64>, u32)> {
+    let lblk64 = lblk as u64;
+    if let Some(es) = fs.extent_cache.lookup(ino, lblk64) {
+        return Ok((Some(es.es_pblk + (lblk64 - es.es_lblk)), (es.end() - lblk64) as u32));
+    }
+    if !inode.have_extend_header_and_use_extend() {
+        return Err(BlockDevError::Unsupported);
+    }
+
+    let mut inode = *inode;
+    let tree = ExtentTree::new(&mut inode);
+    let extents = tree.extents_from(dev, lblk, EXTENT_STATUS_FILL)?;
+    for ext in &extents {
+        fs.extent_cache.insert(ino, ext.into());
+    }
+
+    Ok(match extents.first().map(Ext4ExtentStatus::from) {
+        Some(es) if es.contains(lblk64) => {
+            (Some(es.es_pblk + (lblk64 - es.es_lblk)), (es.end() - lblk64) as u32)
+        }
+        Some(es) => (None, (es.es_lblk - lblk64) as u32),
+        None => (None, u32::MAX - lblk),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ext4_backend::file::*;
+    use crate::ext4_backend::loopfile::*;
+    use crate::ext4_backend::memdev::MemDev;
+
+    fn es(lblk: u64, len: u64, pblk: u64) -> Ext4ExtentStatus {
+        Ext4ExtentStatus {
+            es_lblk: lblk,
+            es_len: len,
+            es_pblk: pblk,
+        }
+    }
+
+    #[test]
+    fn test_extent_status_cache() {
+        let mut cache = ExtentStatusCache::new(2, 3);
+        cache.insert(10, es(0, 4, 100));
+        cache.insert(10, es(8, 2, 200));
+        assert_eq!(cache.lookup(10, 3), Some(es(0, 4, 100)));
+        assert_eq!(cache.lookup(10, 4), None);
+        assert_eq!(cache.lookup(10, 9), Some(es(8, 2, 200)));
+
+        // 条目满了丢掉离新 extent 最远的
+        cache.insert(10, es(20, 1, 300));
+        cache.insert(10, es(30, 1, 400));
+        assert_eq!(cache.lookup(10, 0), None);
+        assert_eq!(cache.len(), 3);
+
+        // inode 数量超出时淘汰最久未访问的 inode
+        cache.insert(11, es(0, 1, 500));
+        cache.lookup(10, 8);
+        cache.insert(12, es(0, 1, 600));
+        assert_eq!(cache.lookup(11, 0), None);
+        assert!(cache.lookup(10, 8).is_some());
+
+        cache.invalidate(10);
+        assert_eq!(cache.lookup(10, 8), None);
+        cache.clear();
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_map_blocks_on_fragmented_file() {
+        let mut dev = Jbd2Dev::initial_jbd2dev(
+            0,
+            MemDev::new(16 * 1024),
+            false,
+        );
+        mkfs(&mut dev).unwrap();
+        let mut fs = mount(&mut dev).unwrap();
+
+        // 每隔一块写一块：200 个单块 extent，extent 树需要一层索引
+        mkfile(&mut dev, &mut fs, "/frag", None, None);
+        for i in 0..200u64 {
+            let block = alloc::vec![i as u8 + 1; BLOCK_SIZE];
+            write_file(&mut dev, &mut fs, "/frag", 2 * i * BLOCK_SIZE as u64, &block).unwrap();
+        }
+        let (ino, mut inode) = get_file_inode(&mut fs, &mut dev, "/frag").unwrap().unwrap();
+        assert!(inode.i_block[1] & 0xffff_0000 != 0, "extent tree depth should be > 0");
+        let expected = resolve_inode_block_allextend(&mut fs, &mut dev, &mut inode).unwrap();
+        assert_eq!(expected.len(), 200);
+
+        fs.extent_cache.clear();
+        for lbn in 0..399u32 {
+            let (phys, len) = map_blocks(&mut fs, &mut dev, ino, &inode, lbn).unwrap();
+            assert_eq!(phys, expected.get(&lbn).copied(), "lbn {lbn}");
+            assert_eq!(len, 1);
+        }
+        // 最后一个 extent 之后的空洞一直延伸到逻辑块上限
+        let (phys, len) = map_blocks(&mut fs, &mut dev, ino, &inode, 399).unwrap();
+        assert_eq!(phys, None);
+        assert_eq!(len, u32::MAX - 399);
+        assert!(fs.extent_cache.len() <= EXTENT_STATUS_PER_INODE);
+
+        let content = read_file(&mut dev, &mut fs, "/frag").unwrap().unwrap();
+        assert_eq!(content.len(), 399 * BLOCK_SIZE);
+        for (lbn, block) in content.chunks(BLOCK_SIZE).enumerate() {
+            let want = if lbn % 2 == 0 { (lbn / 2) as u8 + 1 } else { 0 };
+            assert!(block.iter().all(|&b| b == want), "lbn {lbn}");
+        }
+
+        // 修改 inode 之后缓存失效
+        map_blocks(&mut fs, &mut dev, ino, &inode, 0).unwrap();
+        assert!(fs.extent_cache.lookup(ino, 0).is_some());
+        fs.modify_inode(&mut dev, ino, |_| {}).unwrap();
+        assert!(fs.extent_cache.lookup(ino, 0).is_none());
+        umount(fs, &mut dev).unwrap();
+    }
+}
@@ -288,7 +288,7 @@ impl<'a> ExtentTree<'a> {
                 );
 
                 // 读取子节点所在的物理块，并从块开头解析 extent 节点
-                dev.read_block(child_block as u32)?;
+                dev.read_block(child_block)?;
                 let buf = dev.buffer();
                 let child = match Self::parse_node_from_bytes(buf) {
                     Some(n) => n,
@@ -300,6 +300,94 @@ impl<'a> ExtentTree<'a> {
         }
     }
 
+    /// 从覆盖 lblock 的 extent（lblock 落在空洞中时为其后的第一个）开始向后收集最多 max 个 extent，
+    /// 只读取这段范围经过的节点，代价与 extent 数量而不是文件大小相关
+    pub fn extents_from<B: BlockDevice>(
+        &self,
+        dev: &mut Jbd2Dev<B>,
+        lblock: u32,
+        max: usize,
+    ) -> BlockDevResult<Vec<Ext4Extent>> {
+        fn walk<B: BlockDevice>(
+            dev: &mut Jbd2Dev<B>,
+            node: &ExtentNode,
+            lblock: u32,
+            max: usize,
+            out: &mut Vec<Ext4Extent>,
+        ) -> BlockDevResult<()> {
+            match node {
+                ExtentNode::Leaf { entries, .. } => {
+                    for ext in entries {
+                        if out.len() >= max {
+                            break;
+                        }
+                        let len = ext.actual_len();
+                        if len != 0 && ext.ee_block.saturating_add(len) > lblock {
+                            out.push(*ext);
+                        }
+                    }
+                }
+                ExtentNode::Index { entries, .. } => {
+                    // 从最后一个 ei_block <= lblock 的索引开始向后
+                    let first = entries.iter().rposition(|idx| idx.ei_block <= lblock).unwrap_or(0);
+                    for idx in &entries[first..] {
+                        if out.len() >= max {
+                            break;
+                        }
+                        let child_block = ((idx.ei_leaf_hi as u64) << 32) | (idx.ei_leaf_lo as u64);
+                        dev.read_block(child_block)?;
+                        let child = ExtentTree::parse_node_from_bytes(dev.buffer())
+                            .ok_or(BlockDevError::Corrupted)?;
+                        walk(dev, &child, lblock, max, out)?;
+                    }
+                }
+            }
+            Ok(())
+        }
+
+        let mut out = Vec::new();
+        if let Some(root) = self.load_root_from_inode() {
+            walk(dev, &root, lblock, max, &mut out)?;
+        }
+        Ok(out)
+    }
+
+    /// 收集整棵树的叶子 extent（按逻辑块排序）以及非根节点占用的物理块
+    pub fn collect_extents<B: BlockDevice>(
+        &self,
+        dev: &mut Jbd2Dev<B>,
+    ) -> BlockDevResult<(Vec<Ext4Extent>, Vec<u64>)> {
+        fn walk<B: BlockDevice>(
+            dev: &mut Jbd2Dev<B>,
+            node: &ExtentNode,
+            exts: &mut Vec<Ext4Extent>,
+            nodes: &mut Vec<u64>,
+        ) -> BlockDevResult<()> {
+            match node {
+                ExtentNode::Leaf { entries, .. } => exts.extend_from_slice(entries),
+                ExtentNode::Index { entries, .. } => {
+                    for idx in entries {
+                        let child_block = ((idx.ei_leaf_hi as u64) << 32) | (idx.ei_leaf_lo as u64);
+                        nodes.push(child_block);
+                        dev.read_block(child_block)?;
+                        let child = ExtentTree::parse_node_from_bytes(dev.buffer())
+                            .ok_or(BlockDevError::Corrupted)?;
+                        walk(dev, &child, exts, nodes)?;
+                    }
+                }
+            }
+            Ok(())
+        }
+
+        let mut exts = Vec::new();
+        let mut nodes = Vec::new();
+        if let Some(root) = self.load_root_from_inode() {
+            walk(dev, &root, &mut exts, &mut nodes)?;
+        }
+        exts.sort_unstable_by_key(|e| e.ee_block);
+        Ok((exts, nodes))
+    }
+
     pub fn remove_extend<B: BlockDevice>(
         &mut self,
         fs: &mut Ext4FileSystem,
@@ -421,7 +509,7 @@ impl<'a> ExtentTree<'a> {
                         while idx_pos < entries.len() {
                             let child_phy = ((entries[idx_pos].ei_leaf_hi as u64) << 32)
                                 | (entries[idx_pos].ei_leaf_lo as u64);
-                            dev.read_block(child_phy as u32)?;
+                            dev.read_block(child_phy)?;
                             let child = ExtentTree::parse_node_from_bytes(dev.buffer())
                                 .ok_or(BlockDevError::Corrupted)?;
 
@@ -535,7 +623,7 @@ impl<'a> ExtentTree<'a> {
             entries: &mut Vec<Ext4Extent>,
             cur_lbn: u32,
             remaining: u32,
-            phy_block: Option<u32>,
+            phy_block: Option<u64>,
         ) -> BlockDevResult<StepRes> {
             if entries.is_empty() {
                 return Ok(StepRes {
@@ -617,8 +705,8 @@ impl<'a> ExtentTree<'a> {
             {
                 let base = extent_start_phys(&e);
                 let off = within_off as u64;
-                for j in 0..(cut_len as u64) {
-                    fs.free_block(dev, base + off + j)?;
+                fs.free_blocks(dev, base + off, cut_len)?;
+                for _ in 0..cut_len {
                     tree.sub_inode_sectors_for_block();
                 }
             }
@@ -685,7 +773,7 @@ impl<'a> ExtentTree<'a> {
             node: &mut ExtentNode,
             cur_lbn: u32,
             remaining: u32,
-            phy_block: Option<u32>,
+            phy_block: Option<u64>,
         ) -> BlockDevResult<StepRes> {
             match node {
                 ExtentNode::Leaf { header, entries } =>
@@ -710,7 +798,7 @@ impl<'a> ExtentTree<'a> {
                     while idx_pos < entries.len() {
                         let child_phy = ((entries[idx_pos].ei_leaf_hi as u64) << 32)
                             | (entries[idx_pos].ei_leaf_lo as u64);
-                        dev.read_block(child_phy as u32)?;
+                        dev.read_block(child_phy)?;
                         let child_bytes = dev.buffer();
                         let mut child_node =
                             ExtentTree::parse_node_from_bytes(child_bytes).ok_or(BlockDevError::Corrupted)?;
@@ -722,7 +810,7 @@ impl<'a> ExtentTree<'a> {
                             &mut child_node,
                             search_lbn,
                             remaining,
-                            Some(child_phy as u32),
+                            Some(child_phy),
                         )?;
 
                         match child_res.kind {
@@ -843,7 +931,7 @@ impl<'a> ExtentTree<'a> {
 
                 if entries.len() == 1 {
                     let child_phy = ((entries[0].ei_leaf_hi as u64) << 32) | (entries[0].ei_leaf_lo as u64);
-                    block_dev.read_block(child_phy as u32)?;
+                    block_dev.read_block(child_phy)?;
                     let child_bytes = block_dev.buffer();
                     let mut child_node =
                         ExtentTree::parse_node_from_bytes(child_bytes).ok_or(BlockDevError::Corrupted)?;
@@ -958,7 +1046,7 @@ impl<'a> ExtentTree<'a> {
 
                 // 将当前的 root (左半部分) 写入新分配的物理块
                 // 注意：写入磁盘时要更新 eh_max，因为从 inode (max~4) 移到了 block (max~340)
-                Self::write_node_to_block(block_dev, new_left_block as u32, &root, block_eh_max)?;
+                Self::write_node_to_block(block_dev, new_left_block, &root, block_eh_max)?;
 
                 // 在 Inode 中构建新的 Root Index
                 let inline_bytes = self.inode.i_block.len() * 4;
@@ -1011,7 +1099,7 @@ impl<'a> ExtentTree<'a> {
         block_dev: &mut Jbd2Dev<B>,
         node: &mut ExtentNode,
         new_ext: Ext4Extent,
-        phy_block: Option<u32>,
+        phy_block: Option<u64>,
     ) -> BlockDevResult<Option<SplitInfo>> {
         match node {
             ExtentNode::Leaf { header, entries } => {
@@ -1048,7 +1136,10 @@ impl<'a> ExtentTree<'a> {
                             let new_phys_start =
                                 ((new_ext.ee_start_hi as u64) << 32) | new_ext.ee_start_lo as u64;
 
-                            if new_phys_start == prev_phys_start + prev_len as u64 {
+                            // 已初始化和未初始化的 extent 不能合并
+                            if new_phys_start == prev_phys_start + prev_len as u64
+                                && prev.is_initialized() == new_ext.is_initialized()
+                            {
                                 let total = prev_len + new_len;
                                 let hi_flag = prev.ee_len & 0x8000; // 保留原高位标志
 
@@ -1186,7 +1277,7 @@ impl<'a> ExtentTree<'a> {
                 // 写右节点（新块）
                 Self::write_node_to_block(
                     block_dev,
-                    new_phy_block as u32,
+                    new_phy_block,
                     &right_node,
                     right_header.eh_max,
                 )?;
@@ -1239,7 +1330,7 @@ impl<'a> ExtentTree<'a> {
                 let child_phy_block = ((entries[idx_pos].ei_leaf_hi as u64) << 32)
                     | (entries[idx_pos].ei_leaf_lo as u64);
                 // 读取子节点
-                block_dev.read_block(child_phy_block as u32)?;
+                block_dev.read_block(child_phy_block)?;
                 let child_bytes = block_dev.buffer();
                 let mut child_node =
                     Self::parse_node_from_bytes(child_bytes).expect("Can't parse node from bytes!");
@@ -1250,7 +1341,7 @@ impl<'a> ExtentTree<'a> {
                     block_dev,
                     &mut child_node,
                     new_ext,
-                    Some(child_phy_block as u32),
+                    Some(child_phy_block),
                 )?;
 
                 //  处理子节点返回的结果
@@ -1325,7 +1416,7 @@ impl<'a> ExtentTree<'a> {
                     // 写回
                     Self::write_node_to_block(
                         block_dev,
-                        new_phy_block as u32,
+                        new_phy_block,
                         &right_node,
                         right_header.eh_max,
                     )?;
@@ -1359,14 +1450,14 @@ impl<'a> ExtentTree<'a> {
     /// 通用的写节点到物理块函数
     fn write_node_to_block<B: BlockDevice>(
         dev: &mut Jbd2Dev<B>,
-        block_id: u32,
+        block_id: u64,
         node: &ExtentNode,
         eh_max: u16,
     ) -> BlockDevResult<()> {
         let hdr_size = Ext4ExtentHeader::disk_size();
-        // 读取块
-        dev.read_block(block_id)?;
-        let buf = dev.buffer_mut();
+        // 节点内容完全由 header 和条目决定，不用先读出旧块（新分配的块读了也没用）
+        let mut block = alloc::vec![0u8; BLOCK_SIZE];
+        let buf = block.as_mut_slice();
 
         match node {
             ExtentNode::Leaf { header, entries } => {
@@ -1402,9 +1493,7 @@ impl<'a> ExtentTree<'a> {
                 }
             }
         }
-        // 标记脏并写回
-        dev.write_block(block_id, true)?;
-        Ok(())
+        dev.write_blocks(&block, block_id, 1, true)
     }
 
     /// 计算标准数据块能容纳的条目数
@@ -1463,7 +1552,7 @@ mod tests {
     }
 
     impl BlockDevice for MemBlockDev {
-        fn write(&mut self, buffer: &[u8], block_id: u32, count: u32) -> BlockDevResult<()> {
+        fn write(&mut self, buffer: &[u8], block_id: u64, count: u32) -> BlockDevResult<()> {
             let block_size = BLOCK_SIZE;
             let required = block_size * count as usize;
             if buffer.len() < required {
@@ -1484,7 +1573,7 @@ mod tests {
             Ok(())
         }
 
-        fn read(&mut self, buffer: &mut [u8], block_id: u32, count: u32) -> BlockDevResult<()> {
+        fn read(&mut self, buffer: &mut [u8], block_id: u64, count: u32) -> BlockDevResult<()> {
             let block_size = BLOCK_SIZE;
             let required = block_size * count as usize;
             if buffer.len() < required {
@@ -1612,7 +1701,7 @@ mod tests {
                 ExtentNode::Index { entries, .. } => {
                     for idx in entries {
                         let child_phy = ((idx.ei_leaf_hi as u64) << 32) | (idx.ei_leaf_lo as u64);
-                        dev.read_block(child_phy as u32).unwrap();
+                        dev.read_block(child_phy).unwrap();
                         let child =
                             ExtentTree::parse_node_from_bytes(dev.buffer()).expect("parse child");
                         walk(dev, &child, out);
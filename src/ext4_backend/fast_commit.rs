@@ -0,0 +1,1102 @@
+//! Ext4 fast commit
+//!
+//! fsync 时不提交完整事务，只把自上次提交以来的逻辑修改（extent 增删、目录项增删、inode 内容）
+//! 编码成紧凑的 tag 记录写入日志末尾的 fast commit 区域，磁盘格式与 Linux ext4 fast commit 一致。
+//! 挂载时在 jbd2 重放之后重放这些记录；无法用记录描述的修改回退到完整提交。
+
+use crate::ext4_backend::blockdev::*;
+use crate::ext4_backend::config::*;
+use crate::ext4_backend::dir::*;
+use crate::ext4_backend::disknode::*;
+use crate::ext4_backend::endian::*;
+use crate::ext4_backend::entries::*;
+use crate::ext4_backend::error::*;
+use crate::ext4_backend::ext4::*;
+use crate::ext4_backend::extents_tree::*;
+use crate::ext4_backend::file::*;
+use crate::ext4_backend::loopfile::*;
+use crate::ext4_backend::superblock::*;
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::vec;
+use alloc::vec::Vec;
+use log::{debug, info, warn};
+
+/// 新增逻辑块范围（ino + ext4_extent）
+pub const EXT4_FC_TAG_ADD_RANGE: u16 = 0x0001;
+/// 删除逻辑块范围（ino, lblk, len）
+pub const EXT4_FC_TAG_DEL_RANGE: u16 = 0x0002;
+/// 新建 inode 的目录项（parent_ino, ino, name）
+pub const EXT4_FC_TAG_CREAT: u16 = 0x0003;
+/// 增加目录项
+pub const EXT4_FC_TAG_LINK: u16 = 0x0004;
+/// 删除目录项
+pub const EXT4_FC_TAG_UNLINK: u16 = 0x0005;
+/// inode 原始内容（ino + raw inode）
+pub const EXT4_FC_TAG_INODE: u16 = 0x0006;
+/// 块尾填充
+pub const EXT4_FC_TAG_PAD: u16 = 0x0007;
+/// 一次 fast commit 的结束标记（tid, crc）
+pub const EXT4_FC_TAG_TAIL: u16 = 0x0008;
+/// 事务中第一次 fast commit 的头（features, tid）
+pub const EXT4_FC_TAG_HEAD: u16 = 0x0009;
+
+/// tag + len 头部长度
+const EXT4_FC_TAG_BASE_LEN: usize = 4;
+/// i_block 在 raw inode 中的偏移，重放时保留磁盘上的 i_block
+const INODE_I_BLOCK_OFFSET: usize = 0x28;
+/// i_generation 在 raw inode 中的偏移
+const INODE_I_GENERATION_OFFSET: usize = 0x64;
+/// 内存 inode 序列化的最大长度
+const RAW_INODE_MAX: usize = Ext4Inode::LARGE_INODE_SIZE as usize;
+
+/// fast commit 使用的 crc32c（反射多项式 0x82F63B78，不做初始/最终取反，与内核 ext4_chksum 一致）
+pub fn crc32c(seed: u32, data: &[u8]) -> u32 {
+    let mut crc = seed;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0x82F6_3B78
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    crc
+}
+
+/// 一条 fast commit 逻辑记录（HEAD/TAIL/PAD 只在编码时出现）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FcRecord {
+    /// 逻辑块 [lblk, lblk+len) 映射到物理块 pblk 起始的连续区域，unwritten 表示预分配未写入
+    AddRange {
+        ino: u32,
+        lblk: u32,
+        len: u32,
+        pblk: u64,
+        unwritten: bool,
+    },
+    /// 删除逻辑块 [lblk, lblk+len) 的映射
+    DelRange { ino: u32, lblk: u32, len: u32 },
+    /// 新建 inode 并链接到父目录
+    Creat { parent: u32, ino: u32, name: Vec<u8> },
+    /// 为已有 inode 增加目录项
+    Link { parent: u32, ino: u32, name: Vec<u8> },
+    /// 删除目录项
+    Unlink { parent: u32, ino: u32, name: Vec<u8> },
+    /// inode 原始内容（128 + i_extra_isize 字节）
+    Inode { ino: u32, raw: Vec<u8> },
+}
+
+impl FcRecord {
+    /// 记录对应的 tag
+    pub fn tag(&self) -> u16 {
+        match self {
+            FcRecord::AddRange { .. } => EXT4_FC_TAG_ADD_RANGE,
+            FcRecord::DelRange { .. } => EXT4_FC_TAG_DEL_RANGE,
+            FcRecord::Creat { .. } => EXT4_FC_TAG_CREAT,
+            FcRecord::Link { .. } => EXT4_FC_TAG_LINK,
+            FcRecord::Unlink { .. } => EXT4_FC_TAG_UNLINK,
+            FcRecord::Inode { .. } => EXT4_FC_TAG_INODE,
+        }
+    }
+
+    /// 编码记录的 value 部分（不含 tag/len 头）
+    pub fn encode_value(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        match self {
+            FcRecord::AddRange {
+                ino,
+                lblk,
+                len,
+                pblk,
+                unwritten,
+            } => {
+                // 未初始化 extent 的长度加上 32768
+                let ee_len = if *unwritten {
+                    *len as u16 + Ext4Extent::EXT_INIT_MAX_LEN
+                } else {
+                    *len as u16
+                };
+                out.extend_from_slice(&ino.to_le_bytes());
+                out.extend_from_slice(&lblk.to_le_bytes());
+                out.extend_from_slice(&ee_len.to_le_bytes());
+                out.extend_from_slice(&((*pblk >> 32) as u16).to_le_bytes());
+                out.extend_from_slice(&(*pblk as u32).to_le_bytes());
+            }
+            FcRecord::DelRange { ino, lblk, len } => {
+                out.extend_from_slice(&ino.to_le_bytes());
+                out.extend_from_slice(&lblk.to_le_bytes());
+                out.extend_from_slice(&len.to_le_bytes());
+            }
+            FcRecord::Creat { parent, ino, name }
+            | FcRecord::Link { parent, ino, name }
+            | FcRecord::Unlink { parent, ino, name } => {
+                out.extend_from_slice(&parent.to_le_bytes());
+                out.extend_from_slice(&ino.to_le_bytes());
+                out.extend_from_slice(name);
+            }
+            FcRecord::Inode { ino, raw } => {
+                out.extend_from_slice(&ino.to_le_bytes());
+                out.extend_from_slice(raw);
+            }
+        }
+        out
+    }
+
+    /// 从 tag 和 value 解码记录，未知 tag 或长度不足返回 None
+    pub fn decode(tag: u16, value: &[u8]) -> Option<Self> {
+        match tag {
+            EXT4_FC_TAG_ADD_RANGE if value.len() >= 16 => {
+                let ee_len = read_u16_le(&value[8..10]) as u32;
+                // 长度超过 32768 表示未初始化 extent
+                let unwritten = ee_len > Ext4Extent::EXT_INIT_MAX_LEN as u32;
+                let len = if unwritten {
+                    ee_len - Ext4Extent::EXT_INIT_MAX_LEN as u32
+                } else {
+                    ee_len
+                };
+                Some(FcRecord::AddRange {
+                    ino: read_u32_le(&value[0..4]),
+                    lblk: read_u32_le(&value[4..8]),
+                    len,
+                    pblk: (read_u16_le(&value[10..12]) as u64) << 32
+                        | read_u32_le(&value[12..16]) as u64,
+                    unwritten,
+                })
+            }
+            EXT4_FC_TAG_DEL_RANGE if value.len() >= 12 => Some(FcRecord::DelRange {
+                ino: read_u32_le(&value[0..4]),
+                lblk: read_u32_le(&value[4..8]),
+                len: read_u32_le(&value[8..12]),
+            }),
+            EXT4_FC_TAG_CREAT | EXT4_FC_TAG_LINK | EXT4_FC_TAG_UNLINK if value.len() > 8 => {
+                let parent = read_u32_le(&value[0..4]);
+                let ino = read_u32_le(&value[4..8]);
+                let name = value[8..].to_vec();
+                Some(match tag {
+                    EXT4_FC_TAG_CREAT => FcRecord::Creat { parent, ino, name },
+                    EXT4_FC_TAG_LINK => FcRecord::Link { parent, ino, name },
+                    _ => FcRecord::Unlink { parent, ino, name },
+                })
+            }
+            EXT4_FC_TAG_INODE
+                if value.len() >= 4 + Ext4Inode::GOOD_OLD_INODE_SIZE as usize =>
+            {
+                Some(FcRecord::Inode {
+                    ino: read_u32_le(&value[0..4]),
+                    raw: value[4..].to_vec(),
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// 按 Linux 的规则把记录打包成整块：
+/// 当前块放不下时用 PAD 填满（保留块末尾一个 0 字节），TAIL 总是填满最后一块
+struct FcWriter {
+    buf: Vec<u8>,
+    crc: u32,
+}
+
+impl FcWriter {
+    fn new() -> Self {
+        Self {
+            buf: Vec::new(),
+            crc: 0,
+        }
+    }
+
+    fn push_tl(&mut self, tag: u16, len: usize) {
+        self.buf.extend_from_slice(&tag.to_le_bytes());
+        self.buf.extend_from_slice(&(len as u16).to_le_bytes());
+    }
+
+    /// 为 len 字节（含头部）预留空间，放下之后至少还要能容纳一个 PAD 头
+    fn reserve(&mut self, len: usize) {
+        let off = self.buf.len() % BLOCK_SIZE;
+        if BLOCK_SIZE - off - 1 > len + EXT4_FC_TAG_BASE_LEN {
+            return;
+        }
+        let pad_len = BLOCK_SIZE - off - 1 - EXT4_FC_TAG_BASE_LEN;
+        let start = self.buf.len();
+        self.push_tl(EXT4_FC_TAG_PAD, pad_len);
+        self.buf.resize(start + EXT4_FC_TAG_BASE_LEN + pad_len, 0);
+        self.crc = crc32c(self.crc, &self.buf[start..]);
+        // 块末尾的最后一个字节不计入校验
+        self.buf.push(0);
+    }
+
+    fn push(&mut self, tag: u16, value: &[u8]) {
+        self.reserve(EXT4_FC_TAG_BASE_LEN + value.len());
+        let start = self.buf.len();
+        self.push_tl(tag, value.len());
+        self.buf.extend_from_slice(value);
+        self.crc = crc32c(self.crc, &self.buf[start..]);
+    }
+
+    fn push_head(&mut self, tid: u32) {
+        let mut value = [0u8; 8];
+        value[4..8].copy_from_slice(&tid.to_le_bytes());
+        self.push(EXT4_FC_TAG_HEAD, &value);
+    }
+
+    fn push_record(&mut self, record: &FcRecord) {
+        self.push(record.tag(), &record.encode_value());
+    }
+
+    /// 写入 TAIL（tid + crc），返回按块对齐的数据
+    fn finish(mut self, tid: u32) -> Vec<u8> {
+        self.reserve(EXT4_FC_TAG_BASE_LEN + 8);
+        let off = self.buf.len() % BLOCK_SIZE + EXT4_FC_TAG_BASE_LEN + 8;
+        let start = self.buf.len();
+        self.push_tl(EXT4_FC_TAG_TAIL, BLOCK_SIZE - off + 8);
+        self.buf.extend_from_slice(&tid.to_le_bytes());
+        let crc = crc32c(self.crc, &self.buf[start..]);
+        self.buf.extend_from_slice(&crc.to_le_bytes());
+        self.buf.resize(self.buf.len().div_ceil(BLOCK_SIZE) * BLOCK_SIZE, 0);
+        self.buf
+    }
+}
+
+/// 扫描 fast commit 区域，返回属于 expected_tid 且被有效 TAIL 覆盖的记录
+/// 第一块必须以 HEAD 开头；遇到 tid 或 crc 不匹配的 TAIL、无法解析的 tag 即停止
+pub fn scan_fast_commit(area: &[u8], expected_tid: u32) -> Vec<FcRecord> {
+    let mut valid = Vec::new();
+    let mut pending = Vec::new();
+    let mut crc = 0u32;
+
+    'blocks: for (idx, blk) in area.chunks_exact(BLOCK_SIZE).enumerate() {
+        if idx == 0 && read_u16_le(&blk[0..2]) != EXT4_FC_TAG_HEAD {
+            break;
+        }
+        let mut cur = 0usize;
+        while cur + EXT4_FC_TAG_BASE_LEN <= BLOCK_SIZE {
+            let tag = read_u16_le(&blk[cur..cur + 2]);
+            let len = read_u16_le(&blk[cur + 2..cur + 4]) as usize;
+            let end = cur + EXT4_FC_TAG_BASE_LEN + len;
+            if end > BLOCK_SIZE {
+                break 'blocks;
+            }
+            let value = &blk[cur + EXT4_FC_TAG_BASE_LEN..end];
+            match tag {
+                EXT4_FC_TAG_HEAD => {
+                    if len < 8
+                        || read_u32_le(&value[0..4]) != 0
+                        || read_u32_le(&value[4..8]) != expected_tid
+                    {
+                        break 'blocks;
+                    }
+                    crc = crc32c(crc, &blk[cur..end]);
+                }
+                EXT4_FC_TAG_TAIL => {
+                    if len < 8 {
+                        break 'blocks;
+                    }
+                    crc = crc32c(crc, &blk[cur..cur + EXT4_FC_TAG_BASE_LEN + 4]);
+                    let tid = read_u32_le(&value[0..4]);
+                    let stored = read_u32_le(&value[4..8]);
+                    if tid != expected_tid || stored != crc {
+                        break 'blocks;
+                    }
+                    valid.append(&mut pending);
+                    crc = 0;
+                }
+                EXT4_FC_TAG_PAD => {
+                    crc = crc32c(crc, &blk[cur..end]);
+                }
+                _ => match FcRecord::decode(tag, value) {
+                    Some(record) => {
+                        crc = crc32c(crc, &blk[cur..end]);
+                        pending.push(record);
+                    }
+                    None => break 'blocks,
+                },
+            }
+            cur = end;
+        }
+    }
+    valid
+}
+
+/// 记录自上次提交以来被修改的 inode 和目录项，fsync 时据此生成 fast commit 记录
+#[derive(Default)]
+pub struct FastCommitTracker {
+    /// 是否记录修改（日志启用 fast commit 后打开）
+    pub enabled: bool,
+    /// inode 号 -> 修改前覆盖的逻辑块数（取最大值）
+    inodes: BTreeMap<u32, u32>,
+    /// 新分配、尚未链接进目录的 inode
+    created: BTreeSet<u32>,
+    /// 按发生顺序排列的目录项修改
+    dentries: Vec<FcRecord>,
+    /// 出现了 fast commit 无法描述的修改（例如目录 ".." 改写），下次只能完整提交
+    ineligible: bool,
+}
+
+impl FastCommitTracker {
+    /// 记录一次 inode 修改
+    pub fn track_inode(&mut self, ino: u32, old_blocks: u32) {
+        if !self.enabled {
+            return;
+        }
+        let entry = self.inodes.entry(ino).or_insert(old_blocks);
+        *entry = (*entry).max(old_blocks);
+    }
+
+    /// 记录新分配的 inode，之后第一次链接生成 CREAT
+    pub fn track_create(&mut self, ino: u32) {
+        if self.enabled {
+            self.created.insert(ino);
+        }
+    }
+
+    /// 记录新增目录项
+    pub fn track_link(&mut self, parent: u32, ino: u32, name: &[u8]) {
+        if !self.enabled {
+            return;
+        }
+        let name = name.to_vec();
+        let record = if self.created.remove(&ino) {
+            FcRecord::Creat { parent, ino, name }
+        } else {
+            FcRecord::Link { parent, ino, name }
+        };
+        self.dentries.push(record);
+    }
+
+    /// 记录删除目录项
+    pub fn track_unlink(&mut self, parent: u32, ino: u32, name: &[u8]) {
+        if self.enabled {
+            self.dentries.push(FcRecord::Unlink {
+                parent,
+                ino,
+                name: name.to_vec(),
+            });
+        }
+    }
+
+    /// 标记存在 fast commit 无法重放的修改
+    pub fn mark_ineligible(&mut self) {
+        if self.enabled {
+            self.ineligible = true;
+        }
+    }
+
+    /// 没有需要提交的修改
+    pub fn is_empty(&self) -> bool {
+        self.inodes.is_empty() && self.dentries.is_empty()
+    }
+
+    /// 完整提交或 fast commit 之后清空
+    pub fn clear(&mut self) {
+        self.ineligible = false;
+        self.inodes.clear();
+        self.created.clear();
+        self.dentries.clear();
+    }
+}
+
+/// extent 实际覆盖的块数（去掉未初始化标志）
+fn extent_len(ext: &Ext4Extent) -> u32 {
+    let len = ext.ee_len as u32;
+    if len > Ext4Extent::EXT_INIT_MAX_LEN as u32 {
+        len - Ext4Extent::EXT_INIT_MAX_LEN as u32
+    } else {
+        len
+    }
+}
+
+/// 由 inode 类型得到目录项文件类型
+pub fn dir_entry_file_type(inode: &Ext4Inode) -> u8 {
+    match inode.i_mode & Ext4Inode::S_IFMT {
+        Ext4Inode::S_IFREG => Ext4DirEntry2::EXT4_FT_REG_FILE,
+        Ext4Inode::S_IFDIR => Ext4DirEntry2::EXT4_FT_DIR,
+        Ext4Inode::S_IFCHR => Ext4DirEntry2::EXT4_FT_CHRDEV,
+        Ext4Inode::S_IFBLK => Ext4DirEntry2::EXT4_FT_BLKDEV,
+        Ext4Inode::S_IFIFO => Ext4DirEntry2::EXT4_FT_FIFO,
+        Ext4Inode::S_IFSOCK => Ext4DirEntry2::EXT4_FT_SOCK,
+        Ext4Inode::S_IFLNK => Ext4DirEntry2::EXT4_FT_SYMLINK,
+        _ => Ext4DirEntry2::EXT4_FT_UNKNOWN,
+    }
+}
+
+/// 生成 INODE 记录，长度为 128 + i_extra_isize（不超过 inode 大小）
+fn inode_record<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+    ino: u32,
+) -> BlockDevResult<FcRecord> {
+    let inode = fs.get_inode_by_num(block_dev, ino)?;
+    let mut raw = vec![0u8; RAW_INODE_MAX];
+    inode.to_disk_bytes(&mut raw);
+    let good_old = Ext4Inode::GOOD_OLD_INODE_SIZE as usize;
+    let inode_size = (fs.superblock.s_inode_size as usize).min(RAW_INODE_MAX);
+    let len = if inode_size > good_old {
+        (good_old + inode.i_extra_isize as usize).min(inode_size)
+    } else {
+        good_old
+    };
+    raw.truncate(len);
+    Ok(FcRecord::Inode { ino, raw })
+}
+
+/// 为 inode 生成覆盖 [0, max(修改前, 修改后)) 的范围记录：已映射部分 ADD_RANGE，空洞 DEL_RANGE
+/// 非 extent inode（例如快速符号链接）的 i_block 不会被重放，返回 None 由调用方回退到完整提交
+fn range_records<B: BlockDevice>(
+    block_dev: &mut Jbd2Dev<B>,
+    ino: u32,
+    inode: &mut Ext4Inode,
+    old_blocks: u32,
+) -> BlockDevResult<Option<Vec<FcRecord>>> {
+    let mut out = Vec::new();
+    if !inode.have_extend_header_and_use_extend() {
+        if inode.i_block.iter().any(|&w| w != 0) {
+            return Ok(None);
+        }
+        if old_blocks > 0 {
+            out.push(FcRecord::DelRange {
+                ino,
+                lblk: 0,
+                len: old_blocks,
+            });
+        }
+        return Ok(Some(out));
+    }
+
+    let new_blocks = inode.size().div_ceil(BLOCK_SIZE as u64) as u32;
+    let (exts, _nodes) = ExtentTree::new(inode).collect_extents(block_dev)?;
+    let mut cur = 0u32;
+    for ext in &exts {
+        let len = extent_len(ext);
+        if len == 0 {
+            continue;
+        }
+        if ext.ee_block > cur {
+            out.push(FcRecord::DelRange {
+                ino,
+                lblk: cur,
+                len: ext.ee_block - cur,
+            });
+        }
+        out.push(FcRecord::AddRange {
+            ino,
+            lblk: ext.ee_block,
+            len,
+            pblk: ext.start_block(),
+            unwritten: !ext.is_initialized(),
+        });
+        cur = ext.ee_block.saturating_add(len);
+    }
+    let end = old_blocks.max(new_blocks);
+    if end > cur {
+        out.push(FcRecord::DelRange {
+            ino,
+            lblk: cur,
+            len: end - cur,
+        });
+    }
+    Ok(Some(out))
+}
+
+/// 写入 inode 的范围记录和 INODE 记录，无法表示时返回 false
+fn push_inode_updates<B: BlockDevice>(
+    writer: &mut FcWriter,
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+    ino: u32,
+    old_blocks: u32,
+) -> BlockDevResult<bool> {
+    let mut inode = fs.get_inode_by_num(block_dev, ino)?;
+    let Some(ranges) = range_records(block_dev, ino, &mut inode, old_blocks)? else {
+        debug!("fast commit: inode {ino} is not extent based, fall back to full commit");
+        return Ok(false);
+    };
+    for record in &ranges {
+        writer.push_record(record);
+    }
+    writer.push_record(&inode_record(fs, block_dev, ino)?);
+    Ok(true)
+}
+
+/// 把跟踪到的修改编码为一次 fast commit，无法表示时返回 None
+fn build_fast_commit<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+    tid: u32,
+    with_head: bool,
+) -> BlockDevResult<Option<Vec<u8>>> {
+    let mut writer = FcWriter::new();
+    if with_head {
+        writer.push_head(tid);
+    }
+
+    let dentries = fs.fast_commit.dentries.clone();
+    for record in &dentries {
+        // 新建的 inode 先写入内容和数据块映射，重放 CREAT 及其子目录项时 inode 已经可用
+        if let FcRecord::Creat { ino, .. } = record {
+            let old_blocks = fs.fast_commit.inodes.get(ino).copied().unwrap_or(0);
+            if !push_inode_updates(&mut writer, fs, block_dev, *ino, old_blocks)? {
+                return Ok(None);
+            }
+        }
+        writer.push_record(record);
+    }
+
+    let inodes: Vec<(u32, u32)> = fs.fast_commit.inodes.iter().map(|(&k, &v)| (k, v)).collect();
+    for (ino, old_blocks) in inodes {
+        if !push_inode_updates(&mut writer, fs, block_dev, ino, old_blocks)? {
+            return Ok(None);
+        }
+    }
+
+    Ok(Some(writer.finish(tid)))
+}
+
+/// 完整提交：写回所有缓存、超级块和块组描述符并提交 jbd2 事务
+pub fn full_commit<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+) -> BlockDevResult<()> {
+    fs.datablock_cache.flush_all(block_dev)?;
+    fs.bitmap_cache.flush_all(block_dev)?;
+    fs.inodetable_cahce.flush_all(block_dev)?;
+    fs.sync_superblock(block_dev)?;
+    fs.sync_group_descriptors(block_dev)?;
+    block_dev.commit_journal()?;
+    // 释放这些块的位图已经提交，可以通知设备了
+    block_dev.issue_discards()?;
+    fs.fast_commit.clear();
+    Ok(())
+}
+
+/// 持久化自上次提交以来的修改
+/// 启用 fast commit 时只写 fast commit 记录；未启用、区域已满或存在无法表示的修改时回退到完整提交
+pub fn fsync<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+) -> BlockDevResult<()> {
+    if block_dev.fast_commit_state().is_none() {
+        return full_commit(fs, block_dev);
+    }
+
+    // 数据块先写回原位置，元数据留在事务缓存中等待下一次完整提交
+    fs.datablock_cache.flush_all(block_dev)?;
+    fs.bitmap_cache.flush_all(block_dev)?;
+    fs.inodetable_cahce.flush_all(block_dev)?;
+    // 写回时事务缓存满了会触发完整提交，tid 和 fast commit 区的位置要在写回之后读取
+    let Some((tid, fc_off)) = block_dev.fast_commit_state() else {
+        return full_commit(fs, block_dev);
+    };
+    if fs.fast_commit.is_empty() {
+        return Ok(());
+    }
+    if fs.fast_commit.ineligible {
+        return full_commit(fs, block_dev);
+    }
+
+    if let Some(blocks) = build_fast_commit(fs, block_dev, tid, fc_off == 0)?
+        && block_dev.write_fast_commit(&blocks)?
+    {
+        debug!(
+            "fast commit: tid={} wrote {} blocks",
+            tid,
+            blocks.len() / BLOCK_SIZE
+        );
+        fs.fast_commit.clear();
+        return Ok(());
+    }
+    full_commit(fs, block_dev)
+}
+
+/// 打开/关闭 fast commit，需要文件系统带有日志
+pub fn set_fast_commit<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+    enable: bool,
+) -> BlockDevResult<()> {
+    if !fs.superblock.has_journal() || !block_dev.is_use_journal() {
+        return Err(BlockDevError::Unsupported);
+    }
+    if enable {
+        fs.superblock.s_feature_compat |= Ext4Superblock::EXT4_FEATURE_COMPAT_FAST_COMMIT;
+    } else {
+        fs.superblock.s_feature_compat &= !Ext4Superblock::EXT4_FEATURE_COMPAT_FAST_COMMIT;
+    }
+    full_commit(fs, block_dev)?;
+    block_dev.set_fast_commit(enable)?;
+    fs.fast_commit.enabled = block_dev.is_fast_commit();
+    Ok(())
+}
+
+/// 在目录中按名字查找目录项，返回 inode 号
+fn lookup_dir_entry<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+    dir: &mut Ext4Inode,
+    name: &[u8],
+) -> BlockDevResult<Option<u32>> {
+    let total_blocks = dir.size().div_ceil(BLOCK_SIZE as u64) as u32;
+    for lbn in 0..total_blocks {
+        let Some(phys) = resolve_inode_block(block_dev, dir, lbn)? else {
+            continue;
+        };
+        let cached = fs.datablock_cache.get_or_load(block_dev, phys)?;
+        if let Some(entry) = classic_dir::find_entry(&cached.data[..BLOCK_SIZE], name) {
+            return Ok(Some(entry.inode));
+        }
+    }
+    Ok(None)
+}
+
+/// 重放 ADD_RANGE：删除映射不一致的部分，再补上缺失的部分
+fn replay_add_range<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+    ino: u32,
+    lblk: u32,
+    len: u32,
+    pblk: u64,
+    unwritten: bool,
+) -> BlockDevResult<()> {
+    let mut inode = fs.get_inode_by_num(block_dev, ino)?;
+    if !inode.have_extend_header_and_use_extend() {
+        inode.i_flags |= Ext4Inode::EXT4_EXTENTS_FL;
+        inode.write_extend_header();
+    }
+    let end = lblk.saturating_add(len);
+    {
+        let mut tree = ExtentTree::new(&mut inode);
+        let (exts, _nodes) = tree.collect_extents(block_dev)?;
+        let mut kept: Vec<(u32, u32)> = Vec::new();
+        for ext in &exts {
+            let ext_end = ext.ee_block.saturating_add(extent_len(ext));
+            let start = ext.ee_block.max(lblk);
+            let stop = ext_end.min(end);
+            if start >= stop {
+                continue;
+            }
+            let have = ext.start_block() + (start - ext.ee_block) as u64;
+            if have == pblk + (start - lblk) as u64 && ext.is_initialized() != unwritten {
+                kept.push((start, stop));
+            } else {
+                tree.remove_extend(
+                    fs,
+                    Ext4Extent::new(start, have, (stop - start) as u16),
+                    block_dev,
+                )?;
+            }
+        }
+        let mut cur = lblk;
+        kept.push((end, end));
+        for (start, stop) in kept {
+            if cur < start {
+                let mut ext = Ext4Extent::new(cur, pblk + (cur - lblk) as u64, (start - cur) as u16);
+                if unwritten {
+                    ext.ee_len += Ext4Extent::EXT_INIT_MAX_LEN;
+                }
+                tree.insert_extent(fs, ext, block_dev)?;
+            }
+            cur = cur.max(stop);
+        }
+    }
+    fs.modify_inode(block_dev, ino, |td| {
+        td.i_flags = inode.i_flags;
+        td.i_block = inode.i_block;
+    })
+}
+
+/// 重放 DEL_RANGE：删除范围内所有已映射的块
+fn replay_del_range<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+    ino: u32,
+    lblk: u32,
+    len: u32,
+) -> BlockDevResult<()> {
+    let mut inode = fs.get_inode_by_num(block_dev, ino)?;
+    if !inode.have_extend_header_and_use_extend() {
+        return Ok(());
+    }
+    let end = lblk.saturating_add(len);
+    {
+        let mut tree = ExtentTree::new(&mut inode);
+        let (exts, _nodes) = tree.collect_extents(block_dev)?;
+        for ext in &exts {
+            let ext_end = ext.ee_block.saturating_add(extent_len(ext));
+            let start = ext.ee_block.max(lblk);
+            let stop = ext_end.min(end);
+            if start >= stop {
+                continue;
+            }
+            let phys = ext.start_block() + (start - ext.ee_block) as u64;
+            tree.remove_extend(
+                fs,
+                Ext4Extent::new(start, phys, (stop - start) as u16),
+                block_dev,
+            )?;
+        }
+    }
+    fs.modify_inode(block_dev, ino, |td| {
+        td.i_flags = inode.i_flags;
+        td.i_block = inode.i_block;
+    })
+}
+
+/// 重放 INODE：覆盖除 i_block 以外的内容，i_block 由范围记录重建
+fn replay_inode<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+    ino: u32,
+    raw: &[u8],
+) -> BlockDevResult<()> {
+    let cur = fs.get_inode_by_num(block_dev, ino)?;
+    let mut bytes = [0u8; RAW_INODE_MAX];
+    cur.to_disk_bytes(&mut bytes);
+    let n = raw.len().min(RAW_INODE_MAX);
+    bytes[..INODE_I_BLOCK_OFFSET].copy_from_slice(&raw[..INODE_I_BLOCK_OFFSET]);
+    bytes[INODE_I_GENERATION_OFFSET..n].copy_from_slice(&raw[INODE_I_GENERATION_OFFSET..n]);
+    let mut inode = Ext4Inode::from_disk_bytes(&bytes);
+    if inode.i_flags & Ext4Inode::EXT4_EXTENTS_FL != 0 && !inode.have_extend_header_and_use_extend()
+    {
+        inode.write_extend_header();
+    }
+    fs.modify_inode(block_dev, ino, |td| *td = inode)
+}
+
+/// 重放 CREAT/LINK：目录项已经指向该 inode 时跳过，指向其它 inode 时替换
+fn replay_link<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+    parent: u32,
+    ino: u32,
+    name: &[u8],
+) -> BlockDevResult<()> {
+    let Ok(name_str) = core::str::from_utf8(name) else {
+        warn!("fast commit replay: skip non utf8 dentry under parent {parent}");
+        return Ok(());
+    };
+    let mut parent_inode = fs.get_inode_by_num(block_dev, parent)?;
+    if !parent_inode.is_dir() {
+        return Ok(());
+    }
+    match lookup_dir_entry(fs, block_dev, &mut parent_inode, name)? {
+        Some(cur) if cur == ino => return Ok(()),
+        Some(_) => {
+            remove_dir_entry(fs, block_dev, parent, &mut parent_inode, name_str);
+        }
+        None => {}
+    }
+    let child = fs.get_inode_by_num(block_dev, ino)?;
+    insert_dir_entry(
+        fs,
+        block_dev,
+        parent,
+        &mut parent_inode,
+        ino,
+        name_str,
+        dir_entry_file_type(&child),
+    )
+}
+
+/// 重放 UNLINK：只删除仍然指向该 inode 的目录项
+fn replay_unlink<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+    parent: u32,
+    ino: u32,
+    name: &[u8],
+) -> BlockDevResult<()> {
+    let Ok(name_str) = core::str::from_utf8(name) else {
+        return Ok(());
+    };
+    let mut parent_inode = fs.get_inode_by_num(block_dev, parent)?;
+    if !parent_inode.is_dir() {
+        return Ok(());
+    }
+    if lookup_dir_entry(fs, block_dev, &mut parent_inode, name)? == Some(ino) {
+        remove_dir_entry(fs, block_dev, parent, &mut parent_inode, name_str);
+    }
+    Ok(())
+}
+
+/// 重放结束后修正 inode：重新计算 i_blocks 并在位图中标记 inode 及其所有块
+/// 链接数为 0 的 inode 直接释放
+fn finish_replayed_inode<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+    ino: u32,
+) -> BlockDevResult<()> {
+    let mut inode = fs.get_inode_by_num(block_dev, ino)?;
+    if inode.i_mode == 0 || inode.i_links_count == 0 {
+        return fs.free_inode(block_dev, ino);
+    }
+    if inode.have_extend_header_and_use_extend() {
+        let (exts, nodes) = ExtentTree::new(&mut inode).collect_extents(block_dev)?;
+        let mut used = nodes.len() as u64;
+        for blk in nodes {
+            fs.mark_block_used(block_dev, blk)?;
+        }
+        for ext in &exts {
+            let len = extent_len(ext) as u64;
+            for off in 0..len {
+                fs.mark_block_used(block_dev, ext.start_block() + off)?;
+            }
+            used += len;
+        }
+        let sectors = used * (BLOCK_SIZE as u64 / 512);
+        fs.modify_inode(block_dev, ino, |td| {
+            td.i_blocks_lo = (sectors & 0xffff_ffff) as u32;
+            td.l_i_blocks_high = ((sectors >> 32) & 0xffff) as u16;
+        })?;
+    }
+    fs.mark_inode_used(block_dev, ino, inode.is_dir())
+}
+
+/// 挂载时在 jbd2 重放之后重放 fast commit 区域，成功后立即完整提交
+/// 返回是否重放了记录
+pub fn replay_fast_commit<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+) -> BlockDevResult<bool> {
+    let Some((tid, _)) = block_dev.fast_commit_state() else {
+        return Ok(false);
+    };
+    let area = block_dev.read_fast_commit_area()?;
+    let records = scan_fast_commit(&area, tid);
+    if records.is_empty() {
+        return Ok(false);
+    }
+    info!("fast commit replay: tid={} records={}", tid, records.len());
+
+    let enabled = fs.fast_commit.enabled;
+    fs.fast_commit.enabled = false;
+    fs.datablock_cache.flush_all(block_dev)?;
+    fs.bitmap_cache.flush_all(block_dev)?;
+    fs.inodetable_cahce.flush_all(block_dev)?;
+    fs.reload_metadata(block_dev)?;
+
+    // 先占住所有新增范围，避免重放中为 extent 节点分配块时用到它们
+    for record in &records {
+        if let FcRecord::AddRange { len, pblk, .. } = record {
+            for off in 0..*len as u64 {
+                fs.mark_block_used(block_dev, pblk + off)?;
+            }
+        }
+    }
+
+    let mut touched = BTreeSet::new();
+    for record in &records {
+        match record {
+            FcRecord::AddRange {
+                ino,
+                lblk,
+                len,
+                pblk,
+                unwritten,
+            } => {
+                replay_add_range(fs, block_dev, *ino, *lblk, *len, *pblk, *unwritten)?;
+                touched.insert(*ino);
+            }
+            FcRecord::DelRange { ino, lblk, len } => {
+                replay_del_range(fs, block_dev, *ino, *lblk, *len)?;
+                touched.insert(*ino);
+            }
+            FcRecord::Creat { parent, ino, name } | FcRecord::Link { parent, ino, name } => {
+                replay_link(fs, block_dev, *parent, *ino, name)?;
+                touched.insert(*parent);
+            }
+            FcRecord::Unlink { parent, ino, name } => {
+                replay_unlink(fs, block_dev, *parent, *ino, name)?;
+            }
+            FcRecord::Inode { ino, raw } => {
+                replay_inode(fs, block_dev, *ino, raw)?;
+                touched.insert(*ino);
+            }
+        }
+    }
+    for ino in touched {
+        finish_replayed_inode(fs, block_dev, ino)?;
+    }
+
+    full_commit(fs, block_dev)?;
+    fs.fast_commit.enabled = enabled;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ext4_backend::ext4::{mkfs, mount, umount};
+    use crate::ext4_backend::memdev::MemDev;
+
+    /// mkfs 后以日志模式挂载并打开 fast commit
+    fn setup_fast_commit_fs(storage: &MemDev) -> (Jbd2Dev<MemDev>, Ext4FileSystem) {
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, storage.clone(), false);
+        mkfs(&mut dev).unwrap();
+        let fs = mount(&mut dev).unwrap();
+        umount(fs, &mut dev).unwrap();
+        dev.set_journal_use(true);
+        let mut fs = mount(&mut dev).unwrap();
+        set_fast_commit(&mut fs, &mut dev, true).unwrap();
+        (dev, fs)
+    }
+
+    #[test]
+    fn crc32c_check_value() {
+        assert_eq!(crc32c(!0, b"123456789") ^ !0, 0xE306_9283);
+    }
+
+    #[test]
+    fn scan_stops_at_torn_fast_commit() {
+        let name = vec![b'x'; 200];
+        let mut first = FcWriter::new();
+        first.push_head(7);
+        let mut expected = Vec::new();
+        // 足够多的记录，迫使写入 PAD 并跨块
+        for i in 0..40u32 {
+            let record = FcRecord::Link {
+                parent: 2,
+                ino: 100 + i,
+                name: name.clone(),
+            };
+            first.push_record(&record);
+            expected.push(record);
+        }
+        let add = FcRecord::AddRange {
+            ino: 12,
+            lblk: 3,
+            len: 5,
+            pblk: 0x1_0000_0400,
+            unwritten: false,
+        };
+        first.push_record(&add);
+        expected.push(add);
+        let mut area = first.finish(7);
+        assert!(area.len() > BLOCK_SIZE);
+
+        let mut second = FcWriter::new();
+        second.push_record(&FcRecord::DelRange {
+            ino: 12,
+            lblk: 0,
+            len: 3,
+        });
+        let mut torn = second.finish(7);
+        torn[10] ^= 0xFF;
+        area.extend_from_slice(&torn);
+
+        assert_eq!(scan_fast_commit(&area, 7), expected);
+        assert!(scan_fast_commit(&area, 8).is_empty());
+    }
+
+    #[test]
+    fn add_range_keeps_unwritten_flag() {
+        let add = FcRecord::AddRange {
+            ino: 12,
+            lblk: 8,
+            len: 4,
+            pblk: 0x2000,
+            unwritten: true,
+        };
+        let value = add.encode_value();
+        assert_eq!(read_u16_le(&value[8..10]), 4 + Ext4Extent::EXT_INIT_MAX_LEN);
+        assert_eq!(FcRecord::decode(EXT4_FC_TAG_ADD_RANGE, &value), Some(add));
+
+        // 重放后预分配范围仍是未初始化 extent，读不到磁盘上的旧数据
+        let storage = MemDev::new(16 * 1024);
+        let (mut dev, mut fs) = setup_fast_commit_fs(&storage);
+        mkfile(&mut dev, &mut fs, "/prealloc", Some(b"head"), None);
+        let (ino, _) = get_file_inode(&mut fs, &mut dev, "/prealloc").unwrap().unwrap();
+        let pblk = fs.alloc_blocks(&mut dev, 4).unwrap()[0];
+        replay_add_range(&mut fs, &mut dev, ino, 8, 4, pblk, true).unwrap();
+        let mut inode = fs.get_inode_by_num(&mut dev, ino).unwrap();
+        let (exts, _) = ExtentTree::new(&mut inode).collect_extents(&mut dev).unwrap();
+        let ext = exts.iter().find(|e| e.ee_block == 8).unwrap();
+        assert!(!ext.is_initialized());
+        assert_eq!((ext.actual_len(), ext.start_block()), (4, pblk));
+        let first = exts.iter().find(|e| e.ee_block == 0).unwrap();
+        assert!(first.is_initialized());
+        umount(fs, &mut dev).unwrap();
+    }
+
+    #[test]
+    fn fsync_survives_crash_via_fast_commit() {
+        let storage = MemDev::new(16 * 1024);
+        let (mut dev, mut fs) = setup_fast_commit_fs(&storage);
+        assert!(fs.superblock.has_fast_commit());
+
+        mkdir(&mut dev, &mut fs, "/fc");
+        mkfile(&mut dev, &mut fs, "/fc/keep", None, None);
+        mkfile(&mut dev, &mut fs, "/fc/gone", Some(b"bye"), None);
+        let payload: Vec<u8> = (0..3 * BLOCK_SIZE + 17).map(|i| (i % 253) as u8).collect();
+        write_file(&mut dev, &mut fs, "/fc/keep", 0, &payload).unwrap();
+        delete_file(&mut fs, &mut dev, "/fc/gone");
+        fsync(&mut fs, &mut dev).unwrap();
+        let (tid, fc_off) = dev.fast_commit_state().unwrap();
+        assert!(fc_off > 0);
+        let free_blocks = fs.superblock.free_blocks_count();
+        let free_inodes = fs.superblock.s_free_inodes_count;
+
+        // 不卸载直接丢弃，模拟掉电
+        drop(fs);
+        drop(dev);
+
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, storage.clone(), true);
+        let mut fs = mount(&mut dev).unwrap();
+        assert!(dev.fast_commit_state().unwrap().0 > tid);
+        let got = read_file(&mut dev, &mut fs, "/fc/keep").unwrap().unwrap();
+        assert_eq!(got, payload);
+        assert!(read_file(&mut dev, &mut fs, "/fc/gone").unwrap().is_none());
+        assert_eq!(fs.superblock.free_blocks_count(), free_blocks);
+        assert_eq!(fs.superblock.s_free_inodes_count, free_inodes);
+        umount(fs, &mut dev).unwrap();
+    }
+
+    #[test]
+    fn fsync_after_commit_during_flush() {
+        let storage = MemDev::new(16 * 1024);
+        let (mut dev, mut fs) = setup_fast_commit_fs(&storage);
+        mkdir(&mut dev, &mut fs, "/fc");
+        fsync(&mut fs, &mut dev).unwrap();
+        let (tid, _) = dev.fast_commit_state().unwrap();
+
+        let payload: Vec<u8> = (0..2 * BLOCK_SIZE + 5).map(|i| (i % 241) as u8).collect();
+        mkfile(&mut dev, &mut fs, "/fc/data", Some(&payload), None);
+        // 缓存上限为 1 块，fsync 写回缓存时会触发完整提交
+        dev.set_journal_buffer_max(1);
+        fsync(&mut fs, &mut dev).unwrap();
+        assert!(dev.fast_commit_state().unwrap().0 > tid);
+
+        drop(fs);
+        drop(dev);
+
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, storage.clone(), true);
+        let mut fs = mount(&mut dev).unwrap();
+        let got = read_file(&mut dev, &mut fs, "/fc/data").unwrap().unwrap();
+        assert_eq!(got, payload);
+        umount(fs, &mut dev).unwrap();
+    }
+
+    #[test]
+    fn fsync_without_fast_commit_falls_back_to_full_commit() {
+        let storage = MemDev::new(16 * 1024);
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, storage.clone(), false);
+        mkfs(&mut dev).unwrap();
+        let fs = mount(&mut dev).unwrap();
+        umount(fs, &mut dev).unwrap();
+        dev.set_journal_use(true);
+        let mut fs = mount(&mut dev).unwrap();
+        assert!(dev.fast_commit_state().is_none());
+
+        mkfile(&mut dev, &mut fs, "/full", Some(b"full commit"), None);
+        fsync(&mut fs, &mut dev).unwrap();
+        drop(fs);
+        drop(dev);
+
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, storage.clone(), true);
+        let mut fs = mount(&mut dev).unwrap();
+        let got = read_file(&mut dev, &mut fs, "/full").unwrap().unwrap();
+        assert_eq!(got, b"full commit");
+    }
+}
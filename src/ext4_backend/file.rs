@@ -1,71 +1,553 @@
 use core::u32;
 
-use alloc::string::ToString;
+use alloc::string::{String, ToString};
 use alloc::vec::Vec;
 use log::{error, info};
 use log::{debug, warn};
 
 use crate::ext4_backend::blockdev::*;
+use crate::ext4_backend::casefold::*;
 use crate::ext4_backend::config::*;
 use crate::ext4_backend::dir::*;
 use crate::ext4_backend::disknode::*;
 use crate::ext4_backend::entries::*;
 use crate::ext4_backend::ext4::*;
+use crate::ext4_backend::extent_status::*;
 use crate::ext4_backend::extents_tree::*;
+use crate::ext4_backend::fast_commit::*;
+use crate::ext4_backend::hashtree::*;
 use crate::ext4_backend::loopfile::*;
+use crate::ext4_backend::namei::*;
+use crate::ext4_backend::readahead::*;
 use crate::ext4_backend::error::*;
-use alloc::string::String;
 
 
 
 
+/// rename 标志：目标已存在时返回 AlreadyExists
+pub const RENAME_NOREPLACE: u32 = 1 << 0;
+/// rename 标志：原子交换两个已存在的目录项
+pub const RENAME_EXCHANGE: u32 = 1 << 1;
+/// rename 标志：在源位置留下 whiteout（设备号 0/0 的字符设备）
+pub const RENAME_WHITEOUT: u32 = 1 << 2;
+
+/// 重命名，目标已存在时原子地替换目标
 pub fn rename<B: BlockDevice>(
     device: &mut Jbd2Dev<B>,
     fs: &mut Ext4FileSystem,
     old_path: &str,
     new_path: &str,
 ) -> BlockDevResult<()> {
-    let old_norm = split_paren_child_and_tranlatevalid(old_path);
-    let new_norm = split_paren_child_and_tranlatevalid(new_path);
+    rename2(device, fs, old_path, new_path, 0)
+}
 
-    // 新文件是否存在：存在则先删除
-    if let Some((_ino, inod)) = get_inode_with_num(fs, device, &new_norm).ok().flatten() {
-        if inod.is_dir() {
-            delete_dir(fs, device, new_path);
-        } else {
-            delete_file(fs, device, new_path);
+/// 按 renameat2 语义重命名，flags 为 RENAME_* 的组合
+pub fn rename2<B: BlockDevice>(
+    device: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    old_path: &str,
+    new_path: &str,
+    flags: u32,
+) -> BlockDevResult<()> {
+    let (old_dir_ino, _, old_name) = lookup_parent(fs, device, old_path)?;
+    let (new_dir_ino, _, new_name) = lookup_parent(fs, device, new_path)?;
+    rename_with_ino(device, fs, old_dir_ino, &old_name, new_dir_ino, &new_name, flags)
+}
+
+/// 在一个日志事务内把 (old_dir_ino, old_name) 重命名为 (new_dir_ino, new_name)
+///
+/// 覆盖时先原地改写目标目录项再删除源目录项，任何时刻两个名字至少有一个可见；
+/// 目录跨父目录移动时同时改写 ".." 并调整两个父目录的链接数
+pub fn rename_with_ino<B: BlockDevice>(
+    device: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    old_dir_ino: u32,
+    old_name: &str,
+    new_dir_ino: u32,
+    new_name: &str,
+    flags: u32,
+) -> BlockDevResult<()> {
+    let exchange = flags & RENAME_EXCHANGE != 0;
+    if flags & !(RENAME_NOREPLACE | RENAME_EXCHANGE | RENAME_WHITEOUT) != 0
+        || (exchange && flags & (RENAME_NOREPLACE | RENAME_WHITEOUT) != 0)
+        || !valid_entry_name(old_name)
+        || !valid_entry_name(new_name)
+    {
+        return Err(BlockDevError::InvalidInput);
+    }
+
+    let old_dir = fs.get_inode_by_num(device, old_dir_ino)?;
+    let new_dir = fs.get_inode_by_num(device, new_dir_ino)?;
+    if !old_dir.is_dir() || !new_dir.is_dir() {
+        return Err(BlockDevError::NotDirectory);
+    }
+
+    let (src_ino, src_ft, _) = find_dir_entry(fs, device, &old_dir, old_name.as_bytes())?
+        .ok_or(BlockDevError::NotFound)?;
+    let src = fs.get_inode_by_num(device, src_ino)?;
+    let target = match find_dir_entry(fs, device, &new_dir, new_name.as_bytes())? {
+        Some((ino, ft, _)) => Some((ino, ft, fs.get_inode_by_num(device, ino)?)),
+        None => None,
+    };
+
+    match &target {
+        None if exchange => return Err(BlockDevError::NotFound),
+        Some(_) if flags & RENAME_NOREPLACE != 0 => return Err(BlockDevError::AlreadyExists),
+        // 两个名字指向同一个 inode（同一目录项或硬链接）时什么也不做
+        Some((tgt_ino, _, _)) if *tgt_ino == src_ino => return Ok(()),
+        Some((_, _, tgt)) if !exchange => {
+            if src.is_dir() && !tgt.is_dir() {
+                return Err(BlockDevError::NotDirectory);
+            }
+            if !src.is_dir() && tgt.is_dir() {
+                return Err(BlockDevError::IsDirectory);
+            }
+            if tgt.is_dir() && !dir_is_empty(fs, device, tgt)? {
+                return Err(BlockDevError::NotEmpty);
+            }
+        }
+        _ => {}
+    }
+
+    // 目录不能移动到自己的子树里
+    if old_dir_ino != new_dir_ino {
+        if src.is_dir() && is_ancestor_of(fs, device, src_ino, new_dir_ino)? {
+            return Err(BlockDevError::InvalidInput);
+        }
+        if exchange
+            && let Some((tgt_ino, _, tgt)) = &target
+            && tgt.is_dir()
+            && is_ancestor_of(fs, device, *tgt_ino, old_dir_ino)?
+        {
+            return Err(BlockDevError::InvalidInput);
+        }
+    }
+
+    let from = RenameEnd {
+        dir_ino: old_dir_ino,
+        name: old_name,
+        entry: Some((src_ino, src_ft, src)),
+    };
+    let to = RenameEnd {
+        dir_ino: new_dir_ino,
+        name: new_name,
+        entry: target,
+    };
+
+    // 句柄关闭之前不会自动提交，rename 的全部修改落在同一个事务里
+    journaled_op(device, fs, |device, fs| apply_rename(device, fs, &from, &to, flags))
+}
+
+/// rename 的一端：父目录、名字以及名字当前指向的 (inode 号, file_type, inode)
+struct RenameEnd<'a> {
+    dir_ino: u32,
+    name: &'a str,
+    entry: Option<(u32, u8, Ext4Inode)>,
+}
+
+/// 按已经检查过的参数修改目录项、".." 和链接数
+fn apply_rename<B: BlockDevice>(
+    device: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    from: &RenameEnd,
+    to: &RenameEnd,
+    flags: u32,
+) -> BlockDevResult<()> {
+    let Some((src_ino, src_ft, src)) = &from.entry else {
+        return Err(BlockDevError::NotFound);
+    };
+    let cross_dir = from.dir_ino != to.dir_ino;
+
+    if flags & RENAME_EXCHANGE != 0 {
+        let Some((tgt_ino, tgt_ft, tgt)) = &to.entry else {
+            return Err(BlockDevError::NotFound);
+        };
+        set_dir_entry(device, fs, from.dir_ino, from.name, *tgt_ino, *tgt_ft)?;
+        set_dir_entry(device, fs, to.dir_ino, to.name, *src_ino, *src_ft)?;
+        if cross_dir {
+            if src.is_dir() {
+                set_dir_parent(device, fs, *src_ino, to.dir_ino)?;
+            }
+            if tgt.is_dir() {
+                set_dir_parent(device, fs, *tgt_ino, from.dir_ino)?;
+            }
+            match (src.is_dir(), tgt.is_dir()) {
+                (true, false) => move_dir_link(device, fs, from.dir_ino, to.dir_ino)?,
+                (false, true) => move_dir_link(device, fs, to.dir_ino, from.dir_ino)?,
+                _ => {}
+            }
+        }
+        return Ok(());
+    }
+
+    match &to.entry {
+        // 原地改写目标目录项，再释放被替换的 inode
+        Some((tgt_ino, _, tgt)) => {
+            set_dir_entry(device, fs, to.dir_ino, to.name, *src_ino, *src_ft)?;
+            drop_replaced_inode(device, fs, to.dir_ino, *tgt_ino, tgt)?;
+        }
+        None => {
+            let mut new_dir = fs.get_inode_by_num(device, to.dir_ino)?;
+            insert_dir_entry(fs, device, to.dir_ino, &mut new_dir, *src_ino, to.name, *src_ft)?;
+        }
+    }
+
+    if flags & RENAME_WHITEOUT != 0 {
+        let whiteout = create_whiteout(device, fs)?;
+        set_dir_entry(
+            device,
+            fs,
+            from.dir_ino,
+            from.name,
+            whiteout,
+            Ext4DirEntry2::EXT4_FT_CHRDEV,
+        )?;
+    } else {
+        let mut old_dir = fs.get_inode_by_num(device, from.dir_ino)?;
+        if !remove_dir_entry(fs, device, from.dir_ino, &mut old_dir, from.name) {
+            return Err(BlockDevError::Corrupted);
         }
     }
-    //删除了还存在？错误!
-    if get_inode_with_num(fs, device, &new_norm).ok().flatten().is_some() {
-        return Err(BlockDevError::WriteError);
+
+    if src.is_dir() && cross_dir {
+        set_dir_parent(device, fs, *src_ino, to.dir_ino)?;
+        move_dir_link(device, fs, from.dir_ino, to.dir_ino)?;
+    }
+    Ok(())
+}
+
+/// 在一个日志句柄里执行 op，只把 op 改脏的目录块、inode、位图连同块组描述符写进这个事务
+fn journaled_op<B, F>(device: &mut Jbd2Dev<B>, fs: &mut Ext4FileSystem, op: F) -> BlockDevResult<()>
+where
+    B: BlockDevice,
+    F: FnOnce(&mut Jbd2Dev<B>, &mut Ext4FileSystem) -> BlockDevResult<()>,
+{
+    device.start_handle(JBD2_HANDLE_CREDITS)?;
+    fs.start_tracking();
+    let result = match op(device, fs) {
+        Ok(()) => fs.journal_tracked(device),
+        Err(e) => {
+            fs.stop_tracking();
+            Err(e)
+        }
+    };
+    device.stop_handle()?;
+    result
+}
+
+/// 可以作为目录项名字：非空、不超过 255 字节、不含 '/'，也不是 "." 或 ".."
+fn valid_entry_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= Ext4DirEntry2::MAX_NAME_LEN as usize
+        && name != "."
+        && name != ".."
+        && !name.contains('/')
+}
+
+/// ancestor 是否是 dir 本身或者它沿 ".." 向上的某个祖先
+fn is_ancestor_of<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    device: &mut Jbd2Dev<B>,
+    ancestor: u32,
+    dir: u32,
+) -> BlockDevResult<bool> {
+    let mut cur = dir;
+    // 目录层数远小于这个值，走不到根说明 ".." 成环
+    for _ in 0..4096 {
+        if cur == ancestor {
+            return Ok(true);
+        }
+        if cur == fs.root_inode {
+            return Ok(false);
+        }
+        cur = dir_parent(device, fs, cur)?;
+    }
+    Err(BlockDevError::Corrupted)
+}
+
+/// 原地把目录项 name 改指向 ino，rec_len 不变
+fn set_dir_entry<B: BlockDevice>(
+    device: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    dir_ino: u32,
+    name: &str,
+    ino: u32,
+    file_type: u8,
+) -> BlockDevResult<()> {
+    let dir = fs.get_inode_by_num(device, dir_ino)?;
+    let key = DirNameKey::new(&fs.superblock, &dir, name.as_bytes());
+    fs.dentry_cache.invalidate(dir_ino, key.hash_name());
+    let (old_ino, _, phys) =
+        find_dir_entry(fs, device, &dir, name.as_bytes())?.ok_or(BlockDevError::NotFound)?;
+    let tail = DirBlockTail::new(fs, dir_ino, &dir);
+    let file_type = disk_file_type(fs, file_type);
+    let mut replaced = false;
+    fs.datablock_cache.modify(device, phys, |data| {
+        replaced = replace_entry_in_block(&mut data[..tail.usable()], &key, ino, file_type);
+        tail.update(data);
+    })?;
+    if !replaced {
+        return Err(BlockDevError::Corrupted);
+    }
+    fs.fast_commit.track_unlink(dir_ino, old_ino, name.as_bytes());
+    fs.fast_commit.track_link(dir_ino, ino, name.as_bytes());
+    Ok(())
+}
+
+/// 把目录的 ".." 改指向新的父目录
+fn set_dir_parent<B: BlockDevice>(
+    device: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    dir_ino: u32,
+    parent_ino: u32,
+) -> BlockDevResult<()> {
+    let mut dir = fs.get_inode_by_num(device, dir_ino)?;
+    let phys = resolve_inode_block(device, &mut dir, 0)?.ok_or(BlockDevError::Corrupted)?;
+    let tail = DirBlockTail::new(fs, dir_ino, &dir);
+    let dir_ft = disk_file_type(fs, Ext4DirEntry2::EXT4_FT_DIR);
+    let mut replaced = false;
+    fs.datablock_cache.modify(device, phys as u64, |data| {
+        replaced =
+            replace_entry_in_block(&mut data[..BLOCK_SIZE], &DirNameKey::exact(b".."), parent_ino, dir_ft);
+        tail.update(data);
+    })?;
+    if !replaced {
+        return Err(BlockDevError::Corrupted);
     }
+    // fast commit 记录不了 ".." 的改写
+    fs.fast_commit.mark_ineligible();
+    Ok(())
+}
 
-    mv(fs, device, &old_norm, &new_norm)?;
+/// 子目录从 from 移到 to：它的 ".." 对 from 的链接转给 to
+fn move_dir_link<B: BlockDevice>(
+    device: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    from: u32,
+    to: u32,
+) -> BlockDevResult<()> {
+    fs.modify_inode(device, from, |td| {
+        td.i_links_count = td.i_links_count.saturating_sub(1);
+    })?;
+    fs.modify_inode(device, to, |td| {
+        td.i_links_count = td.i_links_count.saturating_add(1);
+    })
+}
 
-    // 校验
-    if get_inode_with_num(fs, device, &old_norm).ok().flatten().is_some() {
-        return Err(BlockDevError::WriteError);
+/// 释放被 rename 覆盖的 inode
+///
+/// 空目录直接释放并扣掉它的 ".." 对父目录的链接；其余类型链接数减一，减到 0 才释放
+fn drop_replaced_inode<B: BlockDevice>(
+    device: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    parent_ino: u32,
+    ino: u32,
+    inode: &Ext4Inode,
+) -> BlockDevResult<()> {
+    let links = if inode.is_dir() {
+        0
+    } else {
+        inode.i_links_count.saturating_sub(1)
+    };
+    if links > 0 {
+        return fs.modify_inode(device, ino, |td| td.i_links_count = links);
     }
-    if get_inode_with_num(fs, device, &new_norm).ok().flatten().is_none() {
-        return Err(BlockDevError::WriteError);
+
+    // 数据块连同 extent 树的索引块、叶子块一起释放
+    if inode.have_extend_header_and_use_extend() {
+        let mut copy = *inode;
+        let (exts, nodes) = ExtentTree::new(&mut copy).collect_extents(device)?;
+        for ext in exts {
+            let (start, len) = (ext.start_block(), ext.actual_len());
+            for blk in start..start + len as u64 {
+                fs.datablock_cache.invalidate(blk);
+            }
+            fs.free_blocks(device, start, len)?;
+        }
+        for blk in nodes {
+            fs.datablock_cache.invalidate(blk);
+            fs.free_block(device, blk)?;
+        }
     }
+    fs.free_inode(device, ino)?;
 
+    if inode.is_dir() {
+        fs.modify_inode(device, parent_ino, |td| {
+            td.i_links_count = td.i_links_count.saturating_sub(1);
+        })?;
+        let (group_idx, _idx_in_group) = fs.inode_allocator.global_to_group(ino);
+        fs.group_desc_table_mut().decrement_used_dirs(group_idx);
+    }
     Ok(())
 }
+
+/// 分配一个 whiteout inode：设备号为 0/0 的字符设备
+fn create_whiteout<B: BlockDevice>(
+    device: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+) -> BlockDevResult<u32> {
+    let ino = fs.alloc_inode(device)?;
+    fs.modify_inode(device, ino, |td| {
+        *td = Ext4Inode::default();
+        td.i_mode = Ext4Inode::S_IFCHR;
+        td.i_links_count = 1;
+    })?;
+    Ok(ino)
+}
+
+/// 从目录 dir_ino 中删除非目录项 name，链接数减到 0 时释放 inode 和数据块
+pub fn unlink_with_ino<B: BlockDevice>(
+    device: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    dir_ino: u32,
+    name: &str,
+) -> BlockDevResult<()> {
+    remove_with_ino(device, fs, dir_ino, name, false)
+}
+
+/// 删除空目录：非空返回 NotEmpty，根目录和 /lost+found 返回 DeviceBusy
+pub fn rmdir<B: BlockDevice>(
+    device: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    path: &str,
+) -> BlockDevResult<()> {
+    let (ino, _) = lookup_path(fs, device, path, false)?;
+    if ino == fs.root_inode {
+        return Err(BlockDevError::DeviceBusy);
+    }
+    let (parent_ino, _, name) = lookup_parent(fs, device, path)?;
+    match name.as_str() {
+        "." => Err(BlockDevError::InvalidInput),
+        ".." => Err(BlockDevError::NotEmpty),
+        _ => rmdir_with_ino(device, fs, parent_ino, &name),
+    }
+}
+
+/// 从目录 dir_ino 中删除空目录 name，非空时返回 NotEmpty
+pub fn rmdir_with_ino<B: BlockDevice>(
+    device: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    dir_ino: u32,
+    name: &str,
+) -> BlockDevResult<()> {
+    remove_with_ino(device, fs, dir_ino, name, true)
+}
+
+/// unlink 和 rmdir 的公共部分：检查类型后在一个事务内删除目录项并释放 inode
+fn remove_with_ino<B: BlockDevice>(
+    device: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    dir_ino: u32,
+    name: &str,
+    want_dir: bool,
+) -> BlockDevResult<()> {
+    if !valid_entry_name(name) {
+        return Err(BlockDevError::InvalidInput);
+    }
+    let mut dir = fs.get_inode_by_num(device, dir_ino)?;
+    if !dir.is_dir() {
+        return Err(BlockDevError::NotDirectory);
+    }
+    let (ino, _, _) =
+        find_dir_entry(fs, device, &dir, name.as_bytes())?.ok_or(BlockDevError::NotFound)?;
+    let inode = fs.get_inode_by_num(device, ino)?;
+    // lost+found 留给 e2fsck 使用，不允许删除
+    if want_dir && dir_ino == fs.root_inode && name == "lost+found" {
+        return Err(BlockDevError::DeviceBusy);
+    }
+    match (want_dir, inode.is_dir()) {
+        (true, false) => return Err(BlockDevError::NotDirectory),
+        (false, true) => return Err(BlockDevError::IsDirectory),
+        (true, true) if !dir_is_empty(fs, device, &inode)? => return Err(BlockDevError::NotEmpty),
+        _ => {}
+    }
+
+    journaled_op(device, fs, |device, fs| {
+        if !remove_dir_entry(fs, device, dir_ino, &mut dir, name) {
+            return Err(BlockDevError::Corrupted);
+        }
+        drop_replaced_inode(device, fs, dir_ino, ino, &inode)
+    })
+}
+
+/// 为 inode ino 在目录 dir_ino 下新增硬链接 name，目录不能被硬链接
+pub fn link_with_ino<B: BlockDevice>(
+    device: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    ino: u32,
+    dir_ino: u32,
+    name: &str,
+) -> BlockDevResult<()> {
+    if !valid_entry_name(name) {
+        return Err(BlockDevError::InvalidInput);
+    }
+    let inode = fs.get_inode_by_num(device, ino)?;
+    if inode.is_dir() {
+        return Err(BlockDevError::IsDirectory);
+    }
+    let mut dir = fs.get_inode_by_num(device, dir_ino)?;
+    if !dir.is_dir() {
+        return Err(BlockDevError::NotDirectory);
+    }
+    if find_dir_entry(fs, device, &dir, name.as_bytes())?.is_some() {
+        return Err(BlockDevError::AlreadyExists);
+    }
+
+    journaled_op(device, fs, |device, fs| {
+        insert_dir_entry(fs, device, dir_ino, &mut dir, ino, name, dir_entry_file_type(&inode))?;
+        fs.modify_inode(device, ino, |td| {
+            td.i_links_count = td.i_links_count.saturating_add(1);
+        })
+    })
+}
+
+/// chattr：把 inode 中用户可修改的标志设置为 flags，其余标志保持不变
+///
+/// 修改 EXT4_CASEFOLD_FL 需要文件系统开启 casefold 特性，并且只能在空目录上进行
+pub fn set_flags_with_ino<B: BlockDevice>(
+    device: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    ino: u32,
+    flags: u32,
+) -> BlockDevResult<()> {
+    let inode = fs.get_inode_by_num(device, ino)?;
+    let new_flags = (inode.i_flags & !Ext4Inode::EXT4_FL_USER_MODIFIABLE)
+        | (flags & Ext4Inode::EXT4_FL_USER_MODIFIABLE);
+    let changed = inode.i_flags ^ new_flags;
+    if changed == 0 {
+        return Ok(());
+    }
+    // extent 和块映射之间的转换不在这里做
+    if changed & Ext4Inode::EXT4_EXTENTS_FL != 0 {
+        return Err(BlockDevError::Unsupported);
+    }
+    if changed & Ext4Inode::EXT4_CASEFOLD_FL != 0 {
+        if !fs.superblock.has_casefold() {
+            return Err(BlockDevError::Unsupported);
+        }
+        if !inode.is_dir() {
+            return Err(BlockDevError::NotDirectory);
+        }
+        if !dir_is_empty(fs, device, &inode)? {
+            return Err(BlockDevError::NotEmpty);
+        }
+        // 缓存里的键按旧的比较方式生成
+        fs.dentry_cache.invalidate_dir(ino);
+    }
+
+    journaled_op(device, fs, |device, fs| {
+        fs.modify_inode(device, ino, |td| td.i_flags = new_flags)
+    })
+}
+
 pub fn truncate<B: BlockDevice>(
     device: &mut Jbd2Dev<B>,
     fs: &mut Ext4FileSystem,
     path: &str,
     truncate_size: u64,
 ) -> BlockDevResult<()> {
-    let norm_path = split_paren_child_and_tranlatevalid(path);
-
-    // 首先找到目标文件。
-    let (inode_num, _inode) = match get_inode_with_num(fs, device, &norm_path).ok().flatten() {
-        Some(v) => v,
-        None => return Err(BlockDevError::InvalidInput),
-    };
+    // 首先找到目标文件，路径末尾的符号链接跟随到目标
+    let (inode_num, _inode) = lookup_path(fs, device, path, true)?;
 
     truncate_with_ino(device, fs, inode_num, truncate_size)
 }
@@ -146,7 +628,7 @@ pub fn truncate_with_ino<B: BlockDevice>(
             let mut new_blocks_map: Vec<(u32, u64)> = Vec::new();
             for lbn in old_blocks as u32..new_blocks as u32 {
                 let phys = fs.alloc_block(device)?;
-                fs.datablock_cache.modify_new(phys, |data| {
+                fs.datablock_cache.modify_new_file(inode_num, phys, |data| {
                     for b in data.iter_mut() {
                         *b = 0;
                     }
@@ -204,7 +686,7 @@ pub fn truncate_with_ino<B: BlockDevice>(
     if new_blocks > old_blocks {
         for lbn in old_blocks as u32..new_blocks as u32 {
             let phys = fs.alloc_block(device)?;
-            fs.datablock_cache.modify_new(phys, |data| {
+            fs.datablock_cache.modify_new_file(inode_num, phys, |data| {
                 for b in data.iter_mut() {
                     *b = 0;
                 }
@@ -243,42 +725,38 @@ pub fn create_symbol_link<B: BlockDevice>(
     dst_path: &str,
 ) -> BlockDevResult<()> {
     // 首先判断两个目标文件是否存在，被链接不存在报错，链接文件存在报错。
-    let src_norm = split_paren_child_and_tranlatevalid(src_path);
     let dst_norm = split_paren_child_and_tranlatevalid(dst_path);
-
-    if get_file_inode(fs, device, &src_norm)?.is_none() {
-        return Err(BlockDevError::InvalidInput);
-    }
     if get_file_inode(fs, device, &dst_norm)?.is_some() {
         return Err(BlockDevError::InvalidInput);
     }
 
     // 拆 parent / child（父目录必须存在）
-    let (parent, child) = if let Some(pos) = dst_norm.rfind('/') {
-        let p = if pos == 0 {
-            "/".to_string()
-        } else {
-            dst_norm[..pos].to_string()
-        };
-        let c = dst_norm[pos + 1..].to_string();
-        (p, c)
-    } else {
-        ("/".to_string(), dst_norm)
-    };
+    let (parent_ino_num, parent_inode, child) = lookup_parent(fs, device, &dst_norm)?;
 
-    let (parent_ino_num, parent_inode) = match get_inode_with_num(fs, device, &parent).ok().flatten()
-    {
-        Some(v) => v,
-        None => return Err(BlockDevError::InvalidInput),
-    };
-    if !parent_inode.is_dir() {
+    // 相对目标和跟随时一样从链接所在的目录开始解析
+    if found(namei(fs, device, parent_ino_num, src_path, false))?.is_none() {
         return Err(BlockDevError::InvalidInput);
     }
 
+    symlink_in(device, fs, parent_ino_num, parent_inode, &child, src_path).map(|_| ())
+}
+
+/// 在已解析的父目录 parent_ino 下创建指向 target 的符号链接 child，返回新 inode 号
+///
+/// 目标不超过 60 字节时存成快速符号链接；调用方负责确认 child 在父目录中不存在
+pub fn symlink_in<B: BlockDevice>(
+    device: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    parent_ino_num: u32,
+    parent_inode: Ext4Inode,
+    child: &str,
+    target: &str,
+) -> BlockDevResult<u32> {
+    check_name(&fs.superblock, &parent_inode, child.as_bytes())?;
     // 为新链接分配 inode
     let new_ino = fs.alloc_inode(device)?;
 
-    let target_bytes = src_path.as_bytes();
+    let target_bytes = target.as_bytes();
     let target_len = target_bytes.len();
     let size_lo = (target_len as u64 & 0xffffffff) as u32;
     let size_hi = ((target_len as u64) >> 32) as u32;
@@ -354,116 +832,27 @@ pub fn create_symbol_link<B: BlockDevice>(
         parent_ino_num,
         &mut parent_inode_copy,
         new_ino,
-        &child,
+        child,
         Ext4DirEntry2::EXT4_FT_SYMLINK,
     )?;
 
-    Ok(())
+    Ok(new_ino)
 }
 
 
 
 
-fn read_symlink_target<B: BlockDevice>(
-    device: &mut Jbd2Dev<B>,
-    fs: &mut Ext4FileSystem,
-    inode: &mut Ext4Inode,
-) -> BlockDevResult<Vec<u8>> {
-
-
-    let size = inode.size() as usize;
-    if size == 0 {
-        return Ok(Vec::new());
-    }
-
-    if size <= 60 {
-        let mut raw = [0u8; 60];
-        for (i, word) in inode.i_block.iter().take(15).enumerate() {
-            raw[i * 4..i * 4 + 4].copy_from_slice(&word.to_le_bytes());
-        }
-        return Ok(raw[..size].to_vec());
-    }
-
-    let block_bytes = BLOCK_SIZE;
-    let total_blocks = size.div_ceil(block_bytes);
-    let mut buf = Vec::with_capacity(size);
-
-    if inode.have_extend_header_and_use_extend() {
-        let blocks = resolve_inode_block_allextend(fs, device, inode)?;
-        for &phys in blocks.values() {
-            let cached = fs.datablock_cache.get_or_load(device, phys)?;
-            let data = &cached.data[..block_bytes];
-            buf.extend_from_slice(data);
-            if buf.len() >= size {
-                break;
-            }
-        }
-    } else {
-        for lbn in 0..total_blocks {
-            let phys = match resolve_inode_block( device, inode, lbn as u32)? {
-                Some(b) => b,
-                None => break,
-            };
-            let cached = fs.datablock_cache.get_or_load(device, phys as u64)?;
-            let data = &cached.data[..block_bytes];
-            buf.extend_from_slice(data);
-        }
-    }
-
-    buf.truncate(size);
-
-  
-
-    Ok(buf)
-}
-
-fn resolve_symlink_path(current_path: &str, target: &str) -> String {
-    if target.starts_with('/') {
-        return split_paren_child_and_tranlatevalid(target);
-    }
-    let parent = match current_path.rfind('/') {
-        Some(0) | None => "/",
-        Some(pos) => &current_path[..pos],
-    };
-    let mut combined = String::new();
-    if parent == "/" {
-        combined.push('/');
-        combined.push_str(target);
-    } else {
-        combined.push_str(parent);
-        combined.push('/');
-        combined.push_str(target);
-    }
-    split_paren_child_and_tranlatevalid(&combined)
-}
-
 fn read_file_follow<B: BlockDevice>(
     device: &mut Jbd2Dev<B>,
     fs: &mut Ext4FileSystem,
     path: &str,
-    depth: usize,
 ) -> BlockDevResult<Option<Vec<u8>>> {
-  
-    if depth > 8 {
-        return Err(BlockDevError::InvalidInput);
-    }
-
-    let mut inode = match get_file_inode(fs, device, path) {
-        Ok(Some((_ino_num, ino))) => ino,
-        Ok(None) => return Ok(None),
-        Err(e) => return Err(e),
+    // 符号链接（包括链接成环的检测）交给 namei 处理
+    let (ino_num, mut inode) = match found(lookup_path(fs, device, path, true))? {
+        Some(v) => v,
+        None => return Ok(None),
     };
 
-    if inode.is_symlink() {
-        let target_bytes = read_symlink_target(device, fs, &mut inode)?;
-        let target = match core::str::from_utf8(&target_bytes) {
-            Ok(s) => s,
-            Err(_) => return Err(BlockDevError::Corrupted),
-        };
-        let resolved = resolve_symlink_path(path, target);
-        return read_file_follow(device, fs, &resolved, depth + 1);
-    }
-
     if !inode.is_file() {
         error!("Entry:{path} not aa file");
         return BlockDevResult::Err(BlockDevError::ReadError);
@@ -477,298 +866,37 @@ fn read_file_follow<B: BlockDevice>(
     let block_bytes = BLOCK_SIZE;
     let total_blocks = size.div_ceil(block_bytes);
 
-    let mut buf = Vec::with_capacity(size);
-
     if inode.have_extend_header_and_use_extend() {
-        let blocks = resolve_inode_block_allextend(fs, device, &mut inode)?;
-        for &phys in blocks.values() {
-            let cached = fs.datablock_cache.get_or_load(device, phys)?;
-            let data = &cached.data[..block_bytes];
-            buf.extend_from_slice(data);
-            if buf.len() >= size {
-                break;
-            }
-        }
-    } else {
-        for lbn in 0..total_blocks {
-            let phys = match resolve_inode_block( device, &mut inode, lbn as u32)? {
-                Some(b) => b,
-                None => break,
-            };
+        // 连续的物理块合并读取，空洞补零
+        let buf = read_mapped(device, fs, ino_num, &inode, 0, size as u64, None)?;
+        return Ok(Some(buf));
+    }
 
-            let cached = fs.datablock_cache.get_or_load(device, phys as u64)?;
-            let data = &cached.data[..block_bytes];
-            buf.extend_from_slice(data);
-        }
+    let mut buf = Vec::with_capacity(size);
+    for lbn in 0..total_blocks {
+        let phys = match resolve_inode_block( device, &mut inode, lbn as u32)? {
+            Some(b) => b,
+            None => break,
+        };
+
+        let cached = fs.datablock_cache.get_or_load(device, phys as u64)?;
+        let data = &cached.data[..block_bytes];
+        buf.extend_from_slice(data);
     }
 
     buf.truncate(size);
 
-   
-
     Ok(Some(buf))
 }
 
-//mv
+///mv：目标已存在时失败，其余语义同 rename
 pub fn mv<B: BlockDevice>(
     fs: &mut Ext4FileSystem,
     block_dev: &mut Jbd2Dev<B>,
     old_path: &str,
     new_path: &str,
 ) -> BlockDevResult<()> {
-    //找到对应entry，找不到就返回。
-    //判断new_path的父目录是否已经存在不存在就返回，存在继续判断new_path是否有对应的entry，存在就返回
-    //判断被移动的entry类型，如果是目录
-    //对entry的父目录的link-1.
-    //将旧entry使用insertnewentry插入到新目录修改文件名称，更新长度信息，使用removeentry...删除旧entry
-    //对新父目录的link+1.
-    //如果是文件或者链接
-    //将旧entry使用insertnewentry插入到新目录修改文件名称，更新长度信息，使用removeentry...删除旧entry
-
-    let old_norm = split_paren_child_and_tranlatevalid(old_path);
-    let new_norm = split_paren_child_and_tranlatevalid(new_path);
-
-    let (old_parent, old_name) = match old_norm.rfind('/') {
-        Some(pos) => {
-            let parent = if pos == 0 {
-                "/".to_string()
-            } else {
-                old_norm[..pos].to_string()
-            };
-            let name = old_norm[pos + 1..].to_string();
-            (parent, name)
-        }
-        None => {
-            error!("mv invalid old_path(no '/'): old_path={}", old_path);
-            return Err(BlockDevError::InvalidInput);
-        }
-    };
-    let (new_parent, new_name) = match new_norm.rfind('/') {
-        Some(pos) => {
-            let parent = if pos == 0 {
-                "/".to_string()
-            } else {
-                new_norm[..pos].to_string()
-            };
-            let name = new_norm[pos + 1..].to_string();
-            (parent, name)
-        }
-        None => {
-            error!("mv invalid new_path(no '/'): new_path={}", new_path);
-            return Err(BlockDevError::InvalidInput);
-        }
-    };
-
-    // 找到 old entry（inode + file_type），找不到就返回
-    let (_old_pino, mut old_parent_inode) = match get_inode_with_num(fs, block_dev, &old_parent)
-        .ok()
-        .flatten()
-    {
-        Some(v) => v,
-        None => {
-            error!("mv old parent not found: old_path={} old_parent={}", old_path, old_parent);
-            return Err(BlockDevError::InvalidInput);
-        }
-    };
-
-    let mut src_ino: Option<u32> = None;
-    let mut src_ft: Option<u8> = None;
-    if let Ok(blocks) = resolve_inode_block_allextend(fs, block_dev, &mut old_parent_inode) {
-        for phys in blocks {
-            let cached = match fs.datablock_cache.get_or_load(block_dev, phys.1) {
-                Ok(v) => v,
-                Err(_) => continue,
-            };
-            let data = &cached.data[..BLOCK_SIZE];
-            let iter = DirEntryIterator::new(data);
-            for (entry, _) in iter {
-                if entry.inode == 0 {
-                    continue;
-                }
-                if entry.name == old_name.as_bytes() {
-                    src_ino = Some(entry.inode);
-                    src_ft = Some(entry.file_type);
-                    break;
-                }
-            }
-            if src_ino.is_some() {
-                break;
-            }
-        }
-    }
-    if src_ino.is_none() {
-        // Non-extent directory: scan blocks using resolve_inode_block
-        let total_size = old_parent_inode.size() as usize;
-        let total_blocks = if total_size == 0 {
-            0
-        } else {
-            total_size.div_ceil(BLOCK_SIZE)
-        };
-        for lbn in 0..total_blocks {
-            let phys = match resolve_inode_block( block_dev, &mut old_parent_inode, lbn as u32) {
-                Ok(Some(b)) => b,
-                _ => continue,
-            };
-            let cached = match fs.datablock_cache.get_or_load(block_dev, phys as u64) {
-                Ok(v) => v,
-                Err(_) => continue,
-            };
-            let data = &cached.data[..BLOCK_SIZE];
-            let iter = DirEntryIterator::new(data);
-            for (entry, _) in iter {
-                if entry.inode == 0 {
-                    continue;
-                }
-                if entry.name == old_name.as_bytes() {
-                    src_ino = Some(entry.inode);
-                    src_ft = Some(entry.file_type);
-                    break;
-                }
-            }
-            if src_ino.is_some() {
-                break;
-            }
-        }
-    }
-    let src_ino = match src_ino {
-        Some(v) => v,
-        None => {
-            error!(
-                "mv source entry not found in old parent: old_path={} old_parent={} old_name={}",
-                old_path, old_parent, old_name
-            );
-            return Err(BlockDevError::InvalidInput);
-        }
-    };
-    let src_ft = src_ft.unwrap_or(Ext4DirEntry2::EXT4_FT_UNKNOWN);
-
-    // new_parent 必须存在且是目录
-    let (new_pino, new_parent_inode) = match get_inode_with_num(fs, block_dev, &new_parent)
-        .ok()
-        .flatten()
-    {
-        Some(v) => v,
-        None => {
-            error!("mv new parent not found: new_path={} new_parent={}", new_path, new_parent);
-            return Err(BlockDevError::InvalidInput);
-        }
-    };
-    if !new_parent_inode.is_dir() {
-        error!("mv new parent is not dir: new_path={} new_parent={}", new_path, new_parent);
-        return Err(BlockDevError::InvalidInput);
-    }
-
-    // new_path 已存在则返回
-    if get_inode_with_num(fs, block_dev, &new_norm).ok().flatten().is_some() {
-        error!("mv destination already exists: new_path={} new_norm={}", new_path, new_norm);
-        return Err(BlockDevError::InvalidInput);
-    }
-
-    // old_path 不允许为根目录
-    if old_norm == "/" {
-        error!("mv refuses to move root: old_path={}", old_path);
-        return Err(BlockDevError::InvalidInput);
-    }
-
-    // 插入新 entry 到 new_parent
-    let mut new_parent_inode_copy = new_parent_inode;
-    if insert_dir_entry(
-        fs,
-        block_dev,
-        new_pino,
-        &mut new_parent_inode_copy,
-        src_ino,
-        &new_name,
-        src_ft,
-    )
-    .is_err()
-    {
-        error!(
-            "mv insert_dir_entry failed: old_path={} new_path={} new_parent={} new_name={} src_ino={}",
-            old_path,
-            new_path,
-            new_parent,
-            new_name,
-            src_ino
-        );
-        return Err(BlockDevError::WriteError);
-    }
-
-    // 删除旧 entry
-    if !remove_inodeentry_from_parentdir(fs, block_dev, &old_parent, &old_name) {
-        let _ = remove_inodeentry_from_parentdir(fs, block_dev, &new_parent, &new_name);
-        error!(
-            "mv remove old entry failed: old_parent={} old_name={} (rollback new_parent={} new_name={})",
-            old_parent,
-            old_name,
-            new_parent,
-            new_name
-        );
-        return Err(BlockDevError::WriteError);
-    }
-
-    // 目录跨父目录移动：更新 link 以及 '..'
-    let mut moved_inode = match fs.get_inode_by_num(block_dev, src_ino) {
-        Ok(v) => v,
-        Err(e) => {
-            error!("mv get_inode_by_num failed ino={} err={:?} ({})", src_ino, e, e);
-            return Err(e);
-        }
-    };
-    if moved_inode.is_dir() {
-        // 父目录不同才需要改
-        let old_pino = match get_inode_with_num(fs, block_dev, &old_parent)
-            .ok()
-            .flatten()
-        {
-            Some((n, _)) => n,
-            None => {
-                error!("mv old parent vanished while moving dir: old_parent={}", old_parent);
-                return Err(BlockDevError::InvalidInput);
-            }
-        };
-        if old_pino != new_pino {
-            let _ = fs.modify_inode(block_dev, old_pino, |td| {
-                td.i_links_count = td.i_links_count.saturating_sub(1);
-            });
-            let _ = fs.modify_inode(block_dev, new_pino, |td| {
-                td.i_links_count = td.i_links_count.saturating_add(1);
-            });
-
-            // 更新被移动目录的 ".." 指向新父目录 inode
-            let first_blk = match resolve_inode_block( block_dev, &mut moved_inode, 0) {
-                Ok(Some(b)) => b,
-                _ => {
-                    error!("mv resolve_inode_block failed for moved dir ino={}", src_ino);
-                    return Err(BlockDevError::Corrupted);
-                }
-            };
-            let _ = fs
-                .datablock_cache
-                .modify(block_dev, first_blk as u64, |data| {
-                    let block_bytes = BLOCK_SIZE;
-                    if block_bytes < 24 {
-                        return;
-                    }
-                    // '.' entry at offset 0
-                    let rec_len0 = u16::from_le_bytes([data[4], data[5]]) as usize;
-                    if rec_len0 == 0 || rec_len0 + 8 > block_bytes {
-                        return;
-                    }
-                    let off1 = rec_len0;
-                    if off1 + 4 > block_bytes {
-                        return;
-                    }
-                    let bytes = new_pino.to_le_bytes();
-                    data[off1] = bytes[0];
-                    data[off1 + 1] = bytes[1];
-                    data[off1 + 2] = bytes[2];
-                    data[off1 + 3] = bytes[3];
-                });
-        }
-    }
-
-    Ok(())
+    rename2(block_dev, fs, old_path, new_path, RENAME_NOREPLACE)
 }
 
 ///UnLink
@@ -777,66 +905,22 @@ pub fn unlink<B: BlockDevice>(
     block_dev: &mut Jbd2Dev<B>,
     link_path: &str,
 ) {
-    //首先逐级扫描entry找到对应linkentry。
-    let norm_path = split_paren_child_and_tranlatevalid(link_path);
-    let (parent_path, child_name) = if let Some(pos) = norm_path.rfind('/') {
-        let parent = if pos == 0 {
-            "/".to_string()
-        } else {
-            norm_path[..pos].to_string()
-        };
-        let child = norm_path[pos + 1..].to_string();
-        (parent, child)
-    } else {
-        ("/".to_string(), norm_path)
-    };
-
-    let (_pino, mut parent_inode) = match get_inode_with_num(fs, block_dev, &parent_path)
-        .ok()
-        .flatten()
-    {
-        Some(v) => v,
-        None => {
-            warn!("Parent directory not found, unlink failed: {parent_path}");
-            return;
-        }
-    };
-
-    let mut target_ino: Option<u32> = None;
-    let blocks = match resolve_inode_block_allextend(fs, block_dev, &mut parent_inode) {
+    //首先解析父目录，再在父目录里找到对应linkentry（最后一个分量不跟随符号链接）
+    let (parent_ino, parent_inode, child_name) = match lookup_parent(fs, block_dev, link_path) {
         Ok(v) => v,
         Err(e) => {
-            warn!(
-                "Parse parent dir blocks failed, unlink failed: {e:?} parent={parent_path}"
-            );
+            warn!("Parent directory not found, unlink failed: {link_path} {e:?}");
             return;
         }
     };
-
-    for &phys in blocks.values() {
-        let cached = match fs.datablock_cache.get_or_load(block_dev, phys) {
-            Ok(v) => v,
-            Err(_) => continue,
-        };
-        let data = &cached.data[..BLOCK_SIZE];
-        let iter = DirEntryIterator::new(data);
-        for (entry, _) in iter {
-            if entry.inode == 0 {
-                continue;
-            }
-            if entry.name == child_name.as_bytes() {
-                target_ino = Some(entry.inode);
-                break;
-            }
-        }
-        if target_ino.is_some() {
-            break;
-        }
+    if child_name == "." || child_name == ".." {
+        warn!("Can't unlink dot entry: {link_path}");
+        return;
     }
 
-    let target_ino = match target_ino {
-        Some(v) => v,
-        None => {
+    let target_ino = match find_dir_entry(fs, block_dev, &parent_inode, child_name.as_bytes()) {
+        Ok(Some((ino, _, _))) => ino,
+        _ => {
             warn!("Link entry not found, unlink failed: {link_path}");
             return;
         }
@@ -890,11 +974,14 @@ pub fn unlink<B: BlockDevice>(
     }
 
     //最后调用removeentryfromparent移除entry
-    let removed = remove_inodeentry_from_parentdir(fs, block_dev, &parent_path, &child_name);
+    let removed = match fs.get_inode_by_num(block_dev, parent_ino) {
+        Ok(mut parent_inode) => {
+            remove_dir_entry(fs, block_dev, parent_ino, &mut parent_inode, &child_name)
+        }
+        Err(_) => false,
+    };
     if !removed {
-        warn!(
-            "Dir entry '{child_name}' not found under parent {parent_path} in unlink"
-        );
+        warn!("Dir entry '{child_name}' not found under parent {parent_ino} in unlink");
     }
 }
 
@@ -905,13 +992,20 @@ pub fn link<B: BlockDevice>(
     link_path: &str,
     linked_path: &str,
 ) {
-    let link_norm = split_paren_child_and_tranlatevalid(link_path);
-    let linked_norm = split_paren_child_and_tranlatevalid(linked_path);
-
-    // 1.检查 被链接文件本身是否存在，不存在返回。
-    let (target_ino, target_inode) = match get_file_inode(fs, block_dev, &linked_norm) {
-        Ok(Some(v)) => v,
-        _ => return,
+    // 1.检查 被链接文件本身是否存在，不存在返回（和 linkat 默认行为一样不跟随符号链接）
+    let (_, linked_parent, linked_child_name) =
+        match lookup_parent(fs, block_dev, linked_path) {
+            Ok(v) => v,
+            Err(_) => return,
+        };
+    let (target_ino, copied_ft) =
+        match find_dir_entry(fs, block_dev, &linked_parent, linked_child_name.as_bytes()) {
+            Ok(Some((ino, ft, _))) => (ino, ft),
+            _ => return,
+        };
+    let target_inode = match fs.get_inode_by_num(block_dev, target_ino) {
+        Ok(v) => v,
+        Err(_) => return,
     };
 
     // 1.5 不允许链接目录
@@ -919,88 +1013,30 @@ pub fn link<B: BlockDevice>(
         return;
     }
 
-    // 2.检查链接文件本身是否已经存在同名entry，存在返回
-    if get_file_inode(fs, block_dev, &link_norm)
-        .ok()
-        .flatten()
-        .is_some()
-    {
-        return;
-    }
-
-    // link_path 的父目录必须存在且是目录
-    let (parent_path, child_name) = if let Some(pos) = link_norm.rfind('/') {
-        let parent = if pos == 0 {
-            "/".to_string()
-        } else {
-            link_norm[..pos].to_string()
-        };
-        let child = link_norm[pos + 1..].to_string();
-        (parent, child)
-    } else {
-        ("/".to_string(), link_norm)
-    };
-    let (parent_ino, mut parent_inode) = match get_inode_with_num(fs, block_dev, &parent_path)
-        .ok()
-        .flatten()
-    {
-        Some(v) => v,
-        None => return,
+    // 2.link_path 的父目录必须存在且是目录，且不能已经存在同名entry
+    let (parent_ino, mut parent_inode, child_name) = match lookup_parent(fs, block_dev, link_path) {
+        Ok(v) => v,
+        Err(_) => return,
     };
-    if !parent_inode.is_dir() {
+    if !valid_entry_name(&child_name) {
         return;
     }
+    match find_dir_entry(fs, block_dev, &parent_inode, child_name.as_bytes()) {
+        Ok(None) => {}
+        _ => return,
+    }
 
-    // 3.复制目标entry（主要复制 file_type），插入到当前父目录（新名字）
-    let (linked_parent_path, linked_child_name) = if let Some(pos) = linked_norm.rfind('/') {
-        let parent = if pos == 0 {
-            "/".to_string()
-        } else {
-            linked_norm[..pos].to_string()
-        };
-        let child = linked_norm[pos + 1..].to_string();
-        (parent, child)
+    // 3.复制目标entry的 file_type，插入到当前父目录（新名字）
+    let file_type = if copied_ft != Ext4DirEntry2::EXT4_FT_UNKNOWN {
+        copied_ft
+    } else if target_inode.is_file() {
+        Ext4DirEntry2::EXT4_FT_REG_FILE
+    } else if target_inode.is_symlink() {
+        Ext4DirEntry2::EXT4_FT_SYMLINK
     } else {
-        ("/".to_string(), linked_norm.clone())
+        Ext4DirEntry2::EXT4_FT_UNKNOWN
     };
 
-    let mut copied_ft: Option<u8> = None;
-    if let Some((_lpino, mut lp_inode)) = get_inode_with_num(fs, block_dev, &linked_parent_path)
-        .ok()
-        .flatten()
-        && let Ok(blocks) = resolve_inode_block_allextend(fs, block_dev, &mut lp_inode) {
-            for &phys in blocks.values() {
-                let cached = match fs.datablock_cache.get_or_load(block_dev, phys) {
-                    Ok(v) => v,
-                    Err(_) => continue,
-                };
-                let data = &cached.data[..BLOCK_SIZE];
-                let iter = DirEntryIterator::new(data);
-                for (entry, _) in iter {
-                    if entry.inode == 0 {
-                        continue;
-                    }
-                    if entry.name == linked_child_name.as_bytes() {
-                        copied_ft = Some(entry.file_type);
-                        break;
-                    }
-                }
-                if copied_ft.is_some() {
-                    break;
-                }
-            }
-        }
-
-    let file_type = copied_ft.unwrap_or_else(|| {
-        if target_inode.is_file() {
-            Ext4DirEntry2::EXT4_FT_REG_FILE
-        } else if target_inode.is_symlink() {
-            Ext4DirEntry2::EXT4_FT_SYMLINK
-        } else {
-            Ext4DirEntry2::EXT4_FT_UNKNOWN
-        }
-    });
-
     // insert_dir_entry 会根据 child_name 重新计算 name_len/rec_len（满足“更新名字和长度信息”）
     if insert_dir_entry(
         fs,
@@ -1023,29 +1059,143 @@ pub fn link<B: BlockDevice>(
         })
         .is_err()
     {
-        let _ = remove_inodeentry_from_parentdir(fs, block_dev, &parent_path, &child_name);
+        if let Ok(mut parent_inode) = fs.get_inode_by_num(block_dev, parent_ino) {
+            let _ = remove_dir_entry(fs, block_dev, parent_ino, &mut parent_inode, &child_name);
+        }
+    }
+}
+
+pub fn remove_inodeentry_from_parentdir<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+    parent_path: &str,
+    child_name: &str,
+) -> bool {
+    let parent_info = match get_inode_with_num(fs, block_dev, parent_path)
+        .ok()
+        .flatten()
+    {
+        Some(v) => v,
+        None => {
+            warn!(
+                "Parent directory not found for path {parent_path}, remove entry failed"
+            );
+            return false;
+        }
+    };
+    let (parent_ino_num, mut parent_inode) = parent_info;
+    remove_dir_entry(fs, block_dev, parent_ino_num, &mut parent_inode, child_name)
+}
+
+/// 在单个目录块中删除与 key 匹配的目录项，返回被删除条目的 inode 号
+///
+/// 有前驱条目时把空间并入前驱，否则只把 inode 清零
+pub fn remove_entry_from_block(data: &mut [u8], key: &DirNameKey) -> Option<u32> {
+    let block_bytes = data.len();
+    let mut offset: usize = 0;
+    let mut prev_off: Option<usize> = None;
+    let mut prev_rec_len: u16 = 0;
+    while offset + 8 <= block_bytes {
+        let inode = u32::from_le_bytes([
+            data[offset],
+            data[offset + 1],
+            data[offset + 2],
+            data[offset + 3],
+        ]);
+        let rec_len = u16::from_le_bytes([data[offset + 4], data[offset + 5]]);
+        if rec_len < 8 {
+            break;
+        }
+        let name_len = data[offset + 6] as usize;
+        let entry_end = offset + rec_len as usize;
+        if entry_end > block_bytes {
+            break;
+        }
+
+        // Only compare name bytes within the current entry's rec_len.
+        if name_len > 0 && offset + 8 + name_len <= entry_end {
+            let name = &data[offset + 8..offset + 8 + name_len];
+            if inode != 0 && key.matches(name) {
+                if let Some(poff) = prev_off {
+                    // Merge current entry's space into previous entry.
+                    let new_len = prev_rec_len.saturating_add(rec_len);
+                    let bytes = new_len.to_le_bytes();
+                    data[poff + 4] = bytes[0];
+                    data[poff + 5] = bytes[1];
+                }
+                // Clear current entry inode so it will be treated as free.
+                data[offset..offset + 4].copy_from_slice(&0u32.to_le_bytes());
+                return Some(inode);
+            }
+        }
+        if entry_end >= block_bytes {
+            break;
+        }
+        prev_off = Some(offset);
+        prev_rec_len = rec_len;
+        offset = entry_end;
     }
+    None
 }
 
-pub fn remove_inodeentry_from_parentdir<B: BlockDevice>(
+/// 在单个目录块中把与 key 匹配的目录项改指向 ino，名字和 rec_len 保持不变
+pub fn replace_entry_in_block(data: &mut [u8], key: &DirNameKey, ino: u32, file_type: u8) -> bool {
+    let block_bytes = data.len();
+    let mut offset: usize = 0;
+    while offset + 8 <= block_bytes {
+        let inode = u32::from_le_bytes([
+            data[offset],
+            data[offset + 1],
+            data[offset + 2],
+            data[offset + 3],
+        ]);
+        let rec_len = u16::from_le_bytes([data[offset + 4], data[offset + 5]]) as usize;
+        let name_len = data[offset + 6] as usize;
+        if rec_len < 8 || offset + rec_len > block_bytes {
+            break;
+        }
+        if inode != 0
+            && 8 + name_len <= rec_len
+            && key.matches(&data[offset + 8..offset + 8 + name_len])
+        {
+            data[offset..offset + 4].copy_from_slice(&ino.to_le_bytes());
+            data[offset + 7] = file_type;
+            return true;
+        }
+        offset += rec_len;
+    }
+    false
+}
+
+/// 从指定父目录 inode 的数据块中删除名为 child_name 的目录项
+///
+/// htree 索引目录沿哈希路径查找并维护索引；删除后变空的块会被回收，目录随之缩小
+pub fn remove_dir_entry<B: BlockDevice>(
     fs: &mut Ext4FileSystem,
     block_dev: &mut Jbd2Dev<B>,
-    parent_path: &str,
+    parent_ino_num: u32,
+    parent_inode: &mut Ext4Inode,
     child_name: &str,
 ) -> bool {
-    let parent_info = match get_inode_with_num(fs, block_dev, parent_path)
-        .ok()
-        .flatten()
-    {
-        Some(v) => v,
-        None => {
-            warn!(
-                "Parent directory not found for path {parent_path}, remove entry failed"
-            );
-            return false;
+    let name_bytes = child_name.as_bytes();
+    let key = DirNameKey::new(&fs.superblock, parent_inode, name_bytes);
+    fs.dentry_cache.invalidate(parent_ino_num, key.hash_name());
+
+    if parent_inode.is_htree_indexed() {
+        match dx_remove_entry(fs, block_dev, parent_ino_num, parent_inode, name_bytes) {
+            Ok(DxRemoveResult::Removed(removed_ino)) => {
+                fs.fast_commit
+                    .track_unlink(parent_ino_num, removed_ino, name_bytes);
+                return true;
+            }
+            Ok(DxRemoveResult::NotFound) => return false,
+            Ok(DxRemoveResult::IndexUnusable) => {}
+            Err(e) => {
+                warn!("htree remove of {child_name} in dir {parent_ino_num} failed: {e}");
+                return false;
+            }
         }
-    };
-    let (_parent_ino_num, mut parent_inode) = parent_info;
+    }
 
     let total_size = parent_inode.size() as usize;
     let block_bytes = BLOCK_SIZE;
@@ -1055,330 +1205,159 @@ pub fn remove_inodeentry_from_parentdir<B: BlockDevice>(
         total_size.div_ceil(block_bytes)
     };
 
-    let mut removed = false;
-    let name_bytes = child_name.as_bytes();
+    let mut removed: Option<(u32, u32, bool)> = None; // (lbn, ino, 块是否已空)
+    let tail = DirBlockTail::new(fs, parent_ino_num, parent_inode);
 
     for lbn in 0..total_blocks {
-        if removed {
-            break;
-        }
-        let phys = match resolve_inode_block( block_dev, &mut parent_inode, lbn as u32) {
+        let phys = match resolve_inode_block( block_dev, parent_inode, lbn as u32) {
             Ok(Some(b)) => b,
             _ => continue,
         };
         let _ = fs.datablock_cache.modify(block_dev, phys as u64, |data| {
-            if removed {
-                return;
-            }
-            let mut offset: usize = 0;
-            let mut prev_off: Option<usize> = None;
-            let mut prev_rec_len: u16 = 0;
-            while offset + 8 <= block_bytes {
-                let inode = u32::from_le_bytes([
-                    data[offset],
-                    data[offset + 1],
-                    data[offset + 2],
-                    data[offset + 3],
-                ]);
-                let rec_len = u16::from_le_bytes([data[offset + 4], data[offset + 5]]);
-                if rec_len < 8 {
-                    break;
-                }
-                let name_len = data[offset + 6] as usize;
-                let entry_end = offset + rec_len as usize;
-                if entry_end > block_bytes {
-                    break;
-                }
-
-                // Only compare name bytes within the current entry's rec_len.
-                if name_len > 0 && offset + 8 + name_len <= entry_end {
-                    let name = &data[offset + 8..offset + 8 + name_len];
-                    if inode != 0 && name == name_bytes {
-                        if let Some(poff) = prev_off {
-                            // Merge current entry's space into previous entry.
-                            let new_len = prev_rec_len.saturating_add(rec_len);
-                            let bytes = new_len.to_le_bytes();
-                            data[poff + 4] = bytes[0];
-                            data[poff + 5] = bytes[1];
-
-                            // Clear current entry inode so it will be treated as free.
-                            let zero = 0u32.to_le_bytes();
-                            data[offset] = zero[0];
-                            data[offset + 1] = zero[1];
-                            data[offset + 2] = zero[2];
-                            data[offset + 3] = zero[3];
-                        } else {
-                            // No previous entry in this block: mark this entry free.
-                            let zero = 0u32.to_le_bytes();
-                            data[offset] = zero[0];
-                            data[offset + 1] = zero[1];
-                            data[offset + 2] = zero[2];
-                            data[offset + 3] = zero[3];
-                        }
-                        removed = true;
-                        break;
-                    }
-                }
-                if entry_end >= block_bytes {
-                    break;
-                }
-                prev_off = Some(offset);
-                prev_rec_len = rec_len;
-                offset = entry_end;
+            if let Some(ino) = remove_entry_from_block(&mut data[..tail.usable()], &key) {
+                let empty = DirEntryIterator::new(&data[..block_bytes]).next().is_none();
+                tail.update(data);
+                removed = Some((lbn as u32, ino, empty));
             }
         });
+        if removed.is_some() {
+            break;
+        }
     }
 
-    removed
+    let Some((lbn, removed_ino, empty)) = removed else {
+        return false;
+    };
+    fs.fast_commit
+        .track_unlink(parent_ino_num, removed_ino, name_bytes);
+
+    // 线性目录块的顺序无关紧要，空块直接用最后一个块填补后截断
+    if empty
+        && lbn != 0
+        && !parent_inode.is_htree_indexed()
+        && let Err(e) = reclaim_dir_block(fs, block_dev, parent_ino_num, parent_inode, lbn)
+    {
+        warn!("reclaim empty block {lbn} of dir {parent_ino_num} failed: {e}");
+    }
+    true
 }
 
-///删除目录
+///删除目录（递归删除其中所有内容），失败只记录日志；需要知道结果时用 remove_tree
 pub fn delete_dir<B: BlockDevice>(fs: &mut Ext4FileSystem, block_dev: &mut Jbd2Dev<B>, path: &str) {
-    #[derive(Clone)]
-    struct DirFrame {
-        path: alloc::string::String,
-        ino_num: u32,
-        inode: Ext4Inode,
-        parent_path: Option<alloc::string::String>,
-        name_in_parent: Option<alloc::string::String>,
-        stage: u8, // 0=scan, 1=cleanup
-    }
-
-    let norm_path = split_paren_child_and_tranlatevalid(path);
-    let (root_ino_num, root_inode) = match get_file_inode(fs, block_dev, &norm_path) {
-        Ok(Some(v)) => v,
-        Ok(None) => {
-            warn!("Dir not exist, delete failed!");
+    match lookup_path(fs, block_dev, path, false) {
+        Ok((_, inode)) if inode.is_dir() => {}
+        Ok(_) => {
+            error!("path:{path} is not a dir!");
             return;
         }
         Err(e) => {
             warn!("Dir lookup error, delete failed: {e:?}");
             return;
         }
-    };
-    if !root_inode.is_dir() {
-        error!("path:{path} is not a dir!");
-        return;
     }
-
-    let (parent_path, child_name) = if norm_path == "/" {
-        (None, None)
-    } else if let Some(pos) = norm_path.rfind('/') {
-        let parent = if pos == 0 {
-            "/".to_string()
-        } else {
-            norm_path[..pos].to_string()
-        };
-        let child = norm_path[pos + 1..].to_string();
-        (Some(parent), Some(child))
-    } else {
-        (Some("/".to_string()), Some(norm_path.clone()))
-    };
-
-    let mut stack: Vec<DirFrame> = Vec::new();
-    stack.push(DirFrame {
-        path: norm_path,
-        ino_num: root_ino_num,
-        inode: root_inode,
-        parent_path,
-        name_in_parent: child_name,
-        stage: 0,
+    let removed = remove_tree(block_dev, fs, path, |removed, _| {
+        debug!("delete entry path={removed}");
     });
+    if let Err(e) = removed {
+        warn!("delete_dir failed: {e:?} path={path}");
+    }
+}
 
-    // 算法采用while显式栈实现。
-    while let Some(mut frame) = stack.pop() {
-        // 1.首先遍历对应目录块。DirEntryIterator遍历所有entry（跳过. ..）。
-        if frame.stage == 0 {
-            let block_bytes = BLOCK_SIZE;
-
-            let dir_blocks =
-                match resolve_inode_block_allextend(fs, block_dev, &mut frame.inode) {
-                    Ok(v) => v,
-                    Err(e) => {
-                        warn!("Parse dir blocks failed: {:?} path={}", e, frame.path);
-                        return;
-                    }
-                };
-
-            let mut to_descend: Vec<(
-                alloc::string::String,
-                u32,
-                Ext4Inode,
-                alloc::string::String,
-            )> = Vec::new();
-
-            for &phys in dir_blocks.values() {
-                // 先收集 entry，避免在持有 datablock_cache 借用时再次可变借用 fs
-                let mut child_entries: Vec<(u32, alloc::string::String)> = Vec::new();
-                {
-                    let cached = match fs.datablock_cache.get_or_load(block_dev, phys) {
-                        Ok(v) => v,
-                        Err(e) => {
-                            warn!(
-                                "load dir block {} failed: {:?} path={}",
-                                phys, e, frame.path
-                            );
-                            return;
-                        }
-                    };
-                    let data = &cached.data[..block_bytes];
-                    let iter = DirEntryIterator::new(data);
-                    for (entry, _) in iter {
-                        if entry.is_dot() || entry.is_dotdot() {
-                            continue;
-                        }
-                        let child_name_bytes = entry.name.to_vec();
-                        let child_name_str = match core::str::from_utf8(&child_name_bytes) {
-                            Ok(s) => s,
-                            Err(_) => {
-                                warn!("invalid child name utf8 under dir {}", frame.path);
-                                continue;
-                            }
-                        };
-                        child_entries.push((entry.inode, child_name_str.to_string()));
-                    }
-                }
-
-                for (child_ino, child_name) in child_entries {
-                    let child_path = if frame.path == "/" {
-                        alloc::format!("/{child_name}")
-                    } else {
-                        alloc::format!("{}/{}", frame.path, child_name)
-                    };
-
-                    // 每次扫描到的entry把entry的path 用error输出。
-                    debug!("scan entry path={child_path}");
-
-                    // 2.判断entry类型。
-                    let child_inode = match fs.get_inode_by_num(block_dev, child_ino) {
-                        Ok(v) => v,
-                        Err(e) => {
-                            warn!(
-                                "get child inode {child_ino} failed: {e:?} path={child_path}"
-                            );
-                            continue;
-                        }
-                    };
-
-                    // 是普通文件或者是链接，调用deletefile删除对应文件。
-                    if !child_inode.is_dir() {
-                        delete_file(fs, block_dev, &child_path);
-                        continue;
-                    }
-
-                    // 是dir类型就更新父目录的inode链接数-1 然后继续深入这个目录（跳过. ..）。
-                    let _ = fs.modify_inode(block_dev, frame.ino_num, |td| {
-                        td.i_links_count = td.i_links_count.saturating_sub(1);
-                    });
+/// remove_tree 的进度：到目前为止删除的非目录文件数和目录数
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RemoveTreeProgress {
+    pub files: u32,
+    pub dirs: u32,
+}
 
-                    to_descend.push((child_path, child_ino, child_inode, child_name));
-                }
-            }
+/// 递归删除 path 及其下面的所有内容，每删除一项就用它的路径和累计进度回调一次
+///
+/// path 末尾的符号链接不跟随，删除的是链接本身；遇到错误立即停止，已删除的部分不会恢复
+pub fn remove_tree<B, F>(
+    device: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    path: &str,
+    mut on_removed: F,
+) -> BlockDevResult<RemoveTreeProgress>
+where
+    B: BlockDevice,
+    F: FnMut(&str, &RemoveTreeProgress),
+{
+    struct Frame {
+        parent_ino: u32,
+        name: String,
+        path: String,
+        ino: u32,
+        expanded: bool,
+    }
 
-            // 深度优先：反向压栈
-            let parent_path_for_children = frame.path.clone();
+    let norm_path = split_paren_child_and_tranlatevalid(path);
+    let (ino, inode) = lookup_path(fs, device, &norm_path, false)?;
+    if ino == fs.root_inode {
+        return Err(BlockDevError::DeviceBusy);
+    }
+    let (parent_ino, _, name) = lookup_parent(fs, device, &norm_path)?;
+    if name == "." || name == ".." {
+        return Err(BlockDevError::InvalidInput);
+    }
+    if parent_ino == fs.root_inode && name == "lost+found" {
+        return Err(BlockDevError::DeviceBusy);
+    }
 
-            frame.stage = 1;
-            stack.push(frame);
+    let mut progress = RemoveTreeProgress::default();
+    if !inode.is_dir() {
+        unlink_with_ino(device, fs, parent_ino, &name)?;
+        progress.files += 1;
+        on_removed(&norm_path, &progress);
+        return Ok(progress);
+    }
 
-            for (child_path, child_ino, child_inode, child_name) in to_descend.into_iter().rev() {
-                stack.push(DirFrame {
-                    path: child_path,
-                    ino_num: child_ino,
-                    inode: child_inode,
-                    parent_path: Some(parent_path_for_children.clone()),
-                    name_in_parent: Some(child_name),
-                    stage: 0,
-                });
-            }
+    let mut stack = alloc::vec![Frame {
+        parent_ino,
+        name,
+        path: norm_path,
+        ino,
+        expanded: false,
+    }];
+    // 后序遍历：目录第一次出栈时删掉其中的文件并压入子目录，第二次出栈时已经为空
+    while let Some(mut frame) = stack.pop() {
+        if frame.expanded {
+            rmdir_with_ino(device, fs, frame.parent_ino, &frame.name)?;
+            progress.dirs += 1;
+            on_removed(&frame.path, &progress);
             continue;
         }
 
-        // 当深入的目录为空时（只剩下.和..）返回上一级
-        let mut cur_inode = match fs.get_inode_by_num(block_dev, frame.ino_num) {
-            Ok(v) => v,
-            Err(e) => {
-                warn!(
-                    "get inode {} failed in cleanup: {:?} path={}",
-                    frame.ino_num, e, frame.path
-                );
-                return;
-            }
-        };
-
-        // 如果此时的dir类型的entrylinks数不是2就warn发出警告然后继续
-        if cur_inode.i_links_count != 2 {
-            warn!(
-                "dir inode links_count != 2 (links={}) path={} ino={}",
-                cur_inode.i_links_count, frame.path, frame.ino_num
-            );
-        }
-
-        // 调用函数从父目录删除这条entry。
-        if let (Some(pp), Some(name)) = (&frame.parent_path, &frame.name_in_parent) {
-            let removed_path = if pp == "/" {
-                alloc::format!("/{name}")
+        let dir = fs.get_inode_by_num(device, frame.ino)?;
+        let entries = dir_entries(fs, device, &dir)?;
+        frame.expanded = true;
+        let dir_ino = frame.ino;
+        let dir_path = frame.path.clone();
+        stack.push(frame);
+
+        for (child_ino, _, child_name) in entries {
+            let child_name = String::from_utf8(child_name).map_err(|_| BlockDevError::Unsupported)?;
+            let child_path = if dir_path.ends_with('/') {
+                alloc::format!("{dir_path}{child_name}")
             } else {
-                alloc::format!("{pp}/{name}")
+                alloc::format!("{dir_path}/{child_name}")
             };
-            // 删除entry时一样。
-            debug!("delete entry path={removed_path}");
-
-            let removed = remove_inodeentry_from_parentdir(fs, block_dev, pp, name);
-            if !removed {
-                warn!(
-                    "Dir entry '{}' not found under parent {} (path={})",
-                    name, pp, frame.path
-                );
-                return;
-            }
-
-            if let Some((pino, _)) = get_inode_with_num(fs, block_dev, pp).ok().flatten() {
-                let _ = fs.modify_inode(block_dev, pino, |td| {
-                    td.i_links_count = td.i_links_count.saturating_sub(1);
+            if fs.get_inode_by_num(device, child_ino)?.is_dir() {
+                stack.push(Frame {
+                    parent_ino: dir_ino,
+                    name: child_name,
+                    path: child_path,
+                    ino: child_ino,
+                    expanded: false,
                 });
+            } else {
+                unlink_with_ino(device, fs, dir_ino, &child_name)?;
+                progress.files += 1;
+                on_removed(&child_path, &progress);
             }
         }
-
-        // 然后仿照deletefile的逻辑释放entry对应的inode的blocks和inode。
-        let used_blocks: Vec<u64> =
-            match resolve_inode_block_allextend(fs, block_dev, &mut cur_inode) {
-                Ok(v) => v.into_values().collect(),
-                Err(e) => {
-                    warn!(
-                        "Parse dir blocks failed (freeing): {:?} path={}",
-                        e, frame.path
-                    );
-                    return;
-                }
-            };
-
-        for blk in used_blocks {
-            if let Err(e) = fs.free_block(block_dev, blk) {
-                warn!(
-                    "free_block failed for blk {}: {:?} path={}",
-                    blk, e, frame.path
-                );
-                return;
-            }
-        }
-        if let Err(e) = fs.free_inode(block_dev, frame.ino_num) {
-            warn!(
-                "free_inode failed for inode {}: {:?} path={}",
-                frame.ino_num, e, frame.path
-            );
-            return;
-        }
-
-        // 最后更新块组的dir计数-1。
-        let (group_idx, _idx_in_group) = fs.inode_allocator.global_to_group(frame.ino_num);
-        if let Some(desc) = fs.get_group_desc_mut(group_idx) {
-            let before = desc.used_dirs_count();
-            let new_count = before.saturating_sub(1);
-            desc.bg_used_dirs_count_lo = (new_count & 0xFFFF) as u16;
-            desc.bg_used_dirs_count_hi = (new_count >> 16) as u16;
-        }
     }
+    Ok(progress)
 }
 
 ///删除文件/删除链接文件
@@ -1458,7 +1437,7 @@ pub fn delete_file<B: BlockDevice>(
         let child = norm_path[pos + 1..].to_string();
         (parent, child)
     } else {
-        ("/".to_string(), norm_path)
+        (".".to_string(), norm_path)
     };
 
     // 查找父目录 inode
@@ -1507,7 +1486,9 @@ pub fn build_file_block_mapping<B: BlockDevice>(
             let prev_lbn = lbn - 1;
             let prev_pblk = data_blocks[prev_lbn as usize];
 
-            let is_contiguous = pblk == prev_pblk.saturating_add(1);
+            // 单个 extent 最多 EXT_INIT_MAX_LEN 块
+            let is_contiguous = pblk == prev_pblk.saturating_add(1)
+                && run_len < Ext4Extent::EXT_INIT_MAX_LEN as u32;
 
             if is_contiguous {
                 run_len = run_len.saturating_add(1);
@@ -1559,50 +1540,56 @@ pub fn mkfile_with_ino<B: BlockDevice>(
     let norm_path = split_paren_child_and_tranlatevalid(path);
 
     // 如果目标已存在，直接返回
-    if let Ok(Some((_ino_num, inode))) = get_file_inode(fs, device, &norm_path) {
-        let ino = match get_inode_with_num(fs, device, &norm_path).ok().flatten() {
-            Some((ino, _)) => ino,
-            None => {
-                error!("mkfile_with_ino existing file but failed to get ino path={}", path);
-                return None;
-            }
-        };
-        return Some((ino, inode));
+    if let Ok(Some(existing)) = get_file_inode(fs, device, &norm_path) {
+        return Some(existing);
     }
 
-    // 拆 parent / child
-    let mut valid_path = norm_path;
-    let split_point = match valid_path.rfind('/') {
-        Some(v) => v,
-        None => {
-            error!("mkfile invalid path(no '/'): path={}", path);
+    // 确保父目录存在；没有 '/' 的相对路径父目录就是当前工作目录
+    if let Some(pos) = norm_path.rfind('/')
+        && pos > 0
+        && mkdir(device, fs, &norm_path[..pos]).is_none()
+    {
+        error!("mkfile mkdir parent failed path={} parent={}", path, &norm_path[..pos]);
+        return None;
+    }
+
+    // 解析父目录 inode 及其 inode 号，最后一个分量留给本次创建
+    let (parent_ino_num, parent_inode, child) = match lookup_parent(fs, device, &norm_path) {
+        Ok(v) => v,
+        Err(e) => {
+            error!("mkfile get parent inode failed path={path} err={e:?} ({e})");
             return None;
         }
     };
-    let child = valid_path.split_off(split_point)[1..].to_string();
-    let parent = valid_path;
-
-    // 确保父目录存在
-    if mkdir(device, fs, &parent).is_none() {
-        error!("mkfile mkdir parent failed path={} parent={}", path, parent);
+    if child == "." || child == ".." {
+        error!("mkfile invalid file name path={path}");
         return None;
     }
 
-    // 重新获取父目录 inode 及其 inode 号
-    let (parent_ino_num, parent_inode) =
-        match get_inode_with_num(fs, device, &parent).ok().flatten() {
-            Some((n, ino)) => (n, ino),
-            None => {
-                error!("mkfile get parent inode failed path={} parent={}", path, parent);
-                return None;
-            }
-        };
+    mkfile_in(device, fs, parent_ino_num, parent_inode, &child, initial_data, file_type)
+}
 
+/// 在已解析的父目录 parent_ino 下创建名为 child 的文件，file_type 决定 inode 类型
+///
+/// 调用方负责确认 child 在父目录中不存在
+pub fn mkfile_in<B: BlockDevice>(
+    device: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    parent_ino_num: u32,
+    parent_inode: Ext4Inode,
+    child: &str,
+    initial_data: Option<&[u8]>,
+    file_type: Option<u8>,
+) -> Option<(u32, Ext4Inode)> {
+    if let Err(e) = check_name(&fs.superblock, &parent_inode, child.as_bytes()) {
+        error!("mkfile rejected name child={child} err={e:?} ({e})");
+        return None;
+    }
     //为新文件分配 inode（内部自动选择块组）
     let new_file_ino = match fs.alloc_inode(device) {
         Ok(ino) => ino,
         Err(e) => {
-            error!("mkfile alloc_inode failed path={} err={:?} ({})", path, e, e);
+            error!("mkfile alloc_inode failed child={child} err={e:?} ({e})");
             return None;
         }
     };
@@ -1625,7 +1612,7 @@ pub fn mkfile_with_ino<B: BlockDevice>(
             let blk = match fs.alloc_block(device) {
                 Ok(b) => b,
                 Err(e) => {
-                    error!("mkfile alloc_block failed path={} err={:?} ({})", path, e, e);
+                    error!("mkfile alloc_block failed child={child} err={e:?} ({e})");
                     break;
                 }
             };
@@ -1633,7 +1620,7 @@ pub fn mkfile_with_ino<B: BlockDevice>(
             let write_len = core::cmp::min(remaining, BLOCK_SIZE);
 
             // 将数据写入新分配的数据块，其余部分填零
-            fs.datablock_cache.modify_new(blk, |data| {
+            fs.datablock_cache.modify_new_file(new_file_ino, blk, |data| {
                 for b in data.iter_mut() {
                     *b = 0;
                 }
@@ -1646,6 +1633,10 @@ pub fn mkfile_with_ino<B: BlockDevice>(
             remaining -= write_len;
             src_off += write_len;
         }
+        if let Err(e) = fs.datablock_cache.balance_dirty(device) {
+            error!("mkfile writeback failed child={child} err={e:?} ({e})");
+            return None;
+        }
     }
 
     // 构造新文件 inode 的内存版本，然后通过 modify_inode 一次性写回
@@ -1710,7 +1701,7 @@ pub fn mkfile_with_ino<B: BlockDevice>(
         })
         .is_err()
     {
-        error!("mkfile modify_inode failed path={} ino={}", path, new_file_ino);
+        error!("mkfile modify_inode failed child={child} ino={new_file_ino}");
         return None;
     }
 
@@ -1728,14 +1719,13 @@ pub fn mkfile_with_ino<B: BlockDevice>(
         parent_ino_num,
         &mut parent_inode_copy,
         new_file_ino,
-        &child,
+        child,
         file_type,
     )
     .is_err()
     {
         error!(
-            "mkfile insert_dir_entry failed path={} parent_ino={} child={} ino={}",
-            path,
+            "mkfile insert_dir_entry failed parent_ino={} child={} ino={}",
             parent_ino_num,
             child,
             new_file_ino
@@ -1748,8 +1738,8 @@ pub fn mkfile_with_ino<B: BlockDevice>(
         Ok(inode) => Some((new_file_ino, inode)),
         Err(e) => {
             error!(
-                "mkfile get_inode_by_num failed path={} ino={} err={:?} ({})",
-                path,
+                "mkfile get_inode_by_num failed child={} ino={} err={:?} ({})",
+                child,
                 new_file_ino,
                 e,
                 e
@@ -1765,7 +1755,7 @@ pub fn read_file<B: BlockDevice>(
     fs: &mut Ext4FileSystem,
     path: &str,
 ) -> BlockDevResult<Option<Vec<u8>>> {
-    read_file_follow(device, fs, path, 0)
+    read_file_follow(device, fs, path)
 }
 
 pub fn write_file<B: BlockDevice>(
@@ -1780,11 +1770,7 @@ pub fn write_file<B: BlockDevice>(
     }
 
     // 获取 inode 及其 inode 号
-    let info = match get_inode_with_num(fs, device, path).ok().flatten() {
-        Some(v) => v,
-        None => return Err(BlockDevError::WriteError),
-    };
-    let (inode_num, _inode) = info;
+    let (inode_num, _inode) = lookup_path(fs, device, path, true)?;
 
     write_file_with_ino(device, fs, inode_num, offset, data)
 }
@@ -1795,6 +1781,47 @@ pub fn write_file_with_ino<B: BlockDevice>(
     inode_num: u32,
     offset: u64,
     data: &[u8],
+) -> BlockDevResult<()> {
+    write_file_blocks(device, fs, inode_num, offset, data, false)
+}
+
+/// 同 write_file_with_ino，但完整覆盖的块不经过数据块缓存直接写到设备（类似 O_DIRECT），
+/// 不足一块的首尾部分仍走缓存
+pub fn write_file_direct_with_ino<B: BlockDevice>(
+    device: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    inode_num: u32,
+    offset: u64,
+    data: &[u8],
+) -> BlockDevResult<()> {
+    write_file_blocks(device, fs, inode_num, offset, data, true)
+}
+
+/// 直接写回一段物理连续、完整覆盖的块，并丢弃缓存里这些块的旧内容
+fn write_direct_run<B: BlockDevice>(
+    device: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    data: &[u8],
+    phys: u64,
+    count: u64,
+) -> BlockDevResult<()> {
+    if count == 0 {
+        return Ok(());
+    }
+    device.write_blocks(&data[..count as usize * BLOCK_SIZE], phys, count as u32, false)?;
+    for blk in phys..phys + count {
+        fs.datablock_cache.invalidate(blk);
+    }
+    Ok(())
+}
+
+fn write_file_blocks<B: BlockDevice>(
+    device: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    inode_num: u32,
+    offset: u64,
+    data: &[u8],
+    direct: bool,
 ) -> BlockDevResult<()> {
     if data.is_empty() {
         return Ok(());
@@ -1833,31 +1860,40 @@ pub fn write_file_with_ino<B: BlockDevice>(
         }
     }
 
-    let mut blocks_map = if inode.have_extend_header_and_use_extend() {
-        Some(resolve_inode_block_allextend(fs, device, &mut inode)?)
-    } else {
-        None
-    };
+    // 当前映射窗口：从逻辑块 .0 开始的 .2 个块映射到 .1 起的连续物理块，.1 为 None 表示空洞
+    let mut window: (u64, Option<u64>, u64) = (0, None, 0);
+
+    // Direct 模式下攒起来的一段物理连续、完整覆盖的块：(起始逻辑块, 起始物理块, 块数)
+    let mut direct_run: (u64, u64, u64) = (0, 0, 0);
 
     for lbn in start_lbn..=end_lbn {
+        let block_start = lbn * block_bytes;
+        let full_block = direct && block_start >= offset && block_start + block_bytes <= end;
+
         let phys = if inode.have_extend_header_and_use_extend() {
-            let map = blocks_map.as_mut().ok_or(BlockDevError::Corrupted)?;
-            if let Some(&b) = map.get(&(lbn as u32)) {
-                b
+            if lbn < window.0 || lbn >= window.0 + window.2 {
+                let (phys, len) = map_blocks(fs, device, inode_num, &inode, lbn as u32)?;
+                window = (lbn, phys, len as u64);
+            }
+            if let Some(b) = window.1 {
+                b + (lbn - window.0)
             } else {
                 // Hole: allocate a new block and insert an extent for this single LBN.
                 let new_phys = fs.alloc_block(device)?;
-                fs.datablock_cache.modify_new(new_phys, |blk| {
-                    for b in blk.iter_mut() {
-                        *b = 0;
-                    }
-                });
+                if !full_block {
+                    fs.datablock_cache.modify_new_file(inode_num, new_phys, |blk| {
+                        for b in blk.iter_mut() {
+                            *b = 0;
+                        }
+                    });
+                }
                 {
                     let mut tree = ExtentTree::new(&mut inode);
                     let ext = Ext4Extent::new(lbn as u32, new_phys, 1);
                     tree.insert_extent(fs, ext, device)?;
                 }
-                map.insert(lbn as u32, new_phys);
+                // extent 树变了；窗口里剩下的部分仍是空洞
+                fs.extent_cache.invalidate(inode_num);
 
                 let add_iblocks = (BLOCK_SIZE / 512) as u32;
                 inode.i_blocks_lo = inode.i_blocks_lo.saturating_add(add_iblocks);
@@ -1869,13 +1905,24 @@ pub fn write_file_with_ino<B: BlockDevice>(
             }
         } else {
             match resolve_inode_block(device, &mut inode, lbn as u32)? {
-                Some(b) => b as u64,
+                Some(b) => b,
                 None => return Err(BlockDevError::Unsupported),
             }
         };
 
-        fs.datablock_cache.modify(device, phys as u64, |blk| {
-            let block_start = lbn * block_bytes;
+        if full_block {
+            let (run_lbn, run_phys, run_len) = direct_run;
+            if run_len > 0 && run_phys + run_len == phys && run_len < MAX_DIRECT_RUN {
+                direct_run.2 += 1;
+            } else {
+                let src = (run_lbn * block_bytes).saturating_sub(offset) as usize;
+                write_direct_run(device, fs, &data[src..], run_phys, run_len)?;
+                direct_run = (lbn, phys, 1);
+            }
+            continue;
+        }
+
+        fs.datablock_cache.modify_file(device, inode_num, phys, |blk| {
             let block_end = block_start + block_bytes;
 
             let write_start = core::cmp::max(offset, block_start);
@@ -1892,6 +1939,10 @@ pub fn write_file_with_ino<B: BlockDevice>(
         })?;
     }
 
+    let (run_lbn, run_phys, run_len) = direct_run;
+    let src = (run_lbn * block_bytes).saturating_sub(offset) as usize;
+    write_direct_run(device, fs, &data[src..], run_phys, run_len)?;
+
     if end > old_size {
         inode.i_size_lo = (end as u64 & 0xffff_ffff) as u32;
         inode.i_size_high = ((end as u64) >> 32) as u32;
@@ -1901,5 +1952,503 @@ pub fn write_file_with_ino<B: BlockDevice>(
         *td = inode;
     })?;
 
-    Ok(())
+    fs.datablock_cache.balance_dirty(device)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ext4_backend::bitmap_cache::CacheKey;
+    use crate::ext4_backend::buffer_cache::CacheConfig;
+    use crate::ext4_backend::superblock::Ext4Superblock;
+    use crate::ext4_backend::memdev::MemDev;
+
+    fn lookup<B: BlockDevice>(dev: &mut Jbd2Dev<B>, fs: &mut Ext4FileSystem, path: &str) -> Option<(u32, Ext4Inode)> {
+        get_file_inode(fs, dev, path).unwrap()
+    }
+
+    #[test]
+    fn test_rename_flags_and_errors() {
+        let storage = MemDev::new(16 * 1024);
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, storage, false);
+        mkfs(&mut dev).unwrap();
+        let mut fs = mount(&mut dev).unwrap();
+
+        mkdir(&mut dev, &mut fs, "/a/sub");
+        mkdir(&mut dev, &mut fs, "/b/empty");
+        mkfile(&mut dev, &mut fs, "/a/f", Some(b"file f"), None);
+        mkfile(&mut dev, &mut fs, "/b/g", Some(b"file g"), None);
+        mkfile(&mut dev, &mut fs, "/a/sub/x", None, None);
+
+        let err = |r: BlockDevResult<()>| r.unwrap_err();
+        assert_eq!(
+            err(rename2(&mut dev, &mut fs, "/a/f", "/b/g", RENAME_NOREPLACE)),
+            BlockDevError::AlreadyExists
+        );
+        assert_eq!(
+            err(rename(&mut dev, &mut fs, "/a", "/a/sub/a")),
+            BlockDevError::InvalidInput
+        );
+        assert_eq!(err(rename(&mut dev, &mut fs, "/b", "/a")), BlockDevError::NotEmpty);
+        assert_eq!(err(rename(&mut dev, &mut fs, "/a/f", "/b")), BlockDevError::IsDirectory);
+        assert_eq!(err(rename(&mut dev, &mut fs, "/b", "/a/f")), BlockDevError::NotDirectory);
+        assert_eq!(
+            err(rename2(&mut dev, &mut fs, "/a/f", "/b/none", RENAME_EXCHANGE)),
+            BlockDevError::NotFound
+        );
+        assert_eq!(
+            err(rename2(&mut dev, &mut fs, "/a/f", "/b/g", RENAME_EXCHANGE | RENAME_NOREPLACE)),
+            BlockDevError::InvalidInput
+        );
+
+        // EXCHANGE 交换两个文件
+        rename2(&mut dev, &mut fs, "/a/f", "/b/g", RENAME_EXCHANGE).unwrap();
+        assert_eq!(read_file(&mut dev, &mut fs, "/a/f").unwrap().unwrap(), b"file g");
+        assert_eq!(read_file(&mut dev, &mut fs, "/b/g").unwrap().unwrap(), b"file f");
+
+        // 目录跨父目录移动：".." 和两边父目录的链接数
+        let (a_ino, a) = lookup(&mut dev, &mut fs, "/a").unwrap();
+        let (b_ino, b) = lookup(&mut dev, &mut fs, "/b").unwrap();
+        let (sub_ino, _) = lookup(&mut dev, &mut fs, "/a/sub").unwrap();
+        rename(&mut dev, &mut fs, "/a/sub", "/b/sub").unwrap();
+        assert!(lookup(&mut dev, &mut fs, "/a/sub").is_none());
+        assert_eq!(lookup(&mut dev, &mut fs, "/b/sub").unwrap().0, sub_ino);
+        assert!(lookup(&mut dev, &mut fs, "/b/sub/x").is_some());
+        assert_eq!(dir_parent(&mut dev, &mut fs, sub_ino).unwrap(), b_ino);
+        assert_eq!(fs.get_inode_by_num(&mut dev, a_ino).unwrap().i_links_count, a.i_links_count - 1);
+        assert_eq!(fs.get_inode_by_num(&mut dev, b_ino).unwrap().i_links_count, b.i_links_count + 1);
+
+        // 覆盖空目录：被覆盖的目录释放，它的 ".." 对父目录的链接随之消失
+        let (empty_ino, _) = lookup(&mut dev, &mut fs, "/b/empty").unwrap();
+        let dirs_before = fs.get_group_desc(0).unwrap().used_dirs_count();
+        rename(&mut dev, &mut fs, "/b/sub", "/b/empty").unwrap();
+        assert_eq!(lookup(&mut dev, &mut fs, "/b/empty").unwrap().0, sub_ino);
+        assert!(fs.get_inode_by_num(&mut dev, empty_ino).unwrap().i_links_count == 0);
+        assert_eq!(fs.get_inode_by_num(&mut dev, b_ino).unwrap().i_links_count, b.i_links_count);
+        assert_eq!(fs.get_group_desc(0).unwrap().used_dirs_count(), dirs_before - 1);
+
+        // WHITEOUT 在源位置留下 0/0 字符设备
+        rename2(&mut dev, &mut fs, "/b/g", "/b/h", RENAME_WHITEOUT).unwrap();
+        assert_eq!(read_file(&mut dev, &mut fs, "/b/h").unwrap().unwrap(), b"file f");
+        let (_, whiteout) = lookup(&mut dev, &mut fs, "/b/g").unwrap();
+        assert_eq!(whiteout.i_mode & 0xF000, Ext4Inode::S_IFCHR);
+        assert_eq!(whiteout.i_block[0], 0);
+
+        // 同一个 inode 上的 rename 什么也不做
+        rename(&mut dev, &mut fs, "/b/h", "/b/h").unwrap();
+        assert!(lookup(&mut dev, &mut fs, "/b/h").is_some());
+        umount(fs, &mut dev).unwrap();
+    }
+
+    #[test]
+    fn test_rename_commits_atomically() {
+        let storage = MemDev::new(16 * 1024);
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, storage.clone(), false);
+        mkfs(&mut dev).unwrap();
+        let mut fs = mount(&mut dev).unwrap();
+        mkdir(&mut dev, &mut fs, "/d");
+        mkfile(&mut dev, &mut fs, "/d/a", Some(b"new"), None);
+        mkfile(&mut dev, &mut fs, "/d/c", Some(b"old"), None);
+        umount(fs, &mut dev).unwrap();
+
+        let remount = |storage: &MemDev| {
+            let mut dev = Jbd2Dev::initial_jbd2dev(0, storage.clone(), true);
+            dev.set_journal_buffer_max(256);
+            let fs = mount(&mut dev).unwrap();
+            (dev, fs)
+        };
+
+        // 事务未提交就掉电：rename 整体不可见
+        let (mut dev, mut fs) = remount(&storage);
+        rename(&mut dev, &mut fs, "/d/a", "/d/c").unwrap();
+        drop(fs);
+        drop(dev);
+        let (mut dev, mut fs) = remount(&storage);
+        assert_eq!(read_file(&mut dev, &mut fs, "/d/a").unwrap().unwrap(), b"new");
+        assert_eq!(read_file(&mut dev, &mut fs, "/d/c").unwrap().unwrap(), b"old");
+
+        // 提交之后掉电：rename 整体可见
+        rename(&mut dev, &mut fs, "/d/a", "/d/c").unwrap();
+        dev.commit_journal().unwrap();
+        drop(fs);
+        drop(dev);
+        let (mut dev, mut fs) = remount(&storage);
+        assert!(read_file(&mut dev, &mut fs, "/d/a").unwrap().is_none());
+        assert_eq!(read_file(&mut dev, &mut fs, "/d/c").unwrap().unwrap(), b"new");
+        umount(fs, &mut dev).unwrap();
+    }
+
+    #[test]
+    fn test_sync_file_and_dirty_throttling() {
+        let storage = MemDev::new(16 * 1024);
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, storage.clone(), false);
+        mkfs(&mut dev).unwrap();
+        let mut cfg = CacheConfig::with_budget(256 * BLOCK_SIZE);
+        cfg.dirty_data_bytes = 16 * BLOCK_SIZE;
+        let mut fs = mount_with_config(&mut dev, &cfg).unwrap();
+        mkfile(&mut dev, &mut fs, "/a", Some(b"old a"), None);
+        mkfile(&mut dev, &mut fs, "/b", Some(b"old b"), None);
+        fs.sync_fs(&mut dev).unwrap();
+
+        write_file(&mut dev, &mut fs, "/a", 0, b"new a").unwrap();
+        write_file(&mut dev, &mut fs, "/b", 0, b"new b").unwrap();
+        let (ino_a, _) = lookup(&mut dev, &mut fs, "/a").unwrap();
+        let (ino_b, _) = lookup(&mut dev, &mut fs, "/b").unwrap();
+        fs.sync_file(&mut dev, ino_a).unwrap();
+        let dirty: Vec<u32> = fs.datablock_cache.dirty_inodes().collect();
+        assert_eq!(dirty, [ino_b]);
+
+        // 掉电：只有 sync_file 过的 /a 是新内容
+        drop(fs);
+        drop(dev);
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, storage, false);
+        let mut fs = mount_with_config(&mut dev, &cfg).unwrap();
+        assert_eq!(read_file(&mut dev, &mut fs, "/a").unwrap().unwrap(), b"new a");
+        assert_eq!(read_file(&mut dev, &mut fs, "/b").unwrap().unwrap(), b"old b");
+
+        // 大文件写入过程中脏块不会超过上限
+        let big = alloc::vec![0x5au8; 64 * BLOCK_SIZE];
+        mkfile(&mut dev, &mut fs, "/big", Some(&big), None);
+        assert!(fs.datablock_cache.stats().dirty_entries <= 16);
+        umount(fs, &mut dev).unwrap();
+    }
+
+    #[test]
+    fn test_at_apis_by_dir_inode() {
+        use crate::ext4_backend::api::*;
+
+        let storage = MemDev::new(16 * 1024);
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, storage, false);
+        mkfs(&mut dev).unwrap();
+        let mut fs = mount(&mut dev).unwrap();
+        let root = fs.root_inode;
+
+        let d = mkdirat(&mut dev, &mut fs, root, "d").unwrap();
+        let sub = mkdirat(&mut dev, &mut fs, d, "sub").unwrap();
+        assert_eq!(
+            mkdirat(&mut dev, &mut fs, d, "sub").unwrap_err(),
+            BlockDevError::AlreadyExists
+        );
+        assert_eq!(
+            mkdirat(&mut dev, &mut fs, d, "missing/x").unwrap_err(),
+            BlockDevError::NotFound
+        );
+
+        // openat 只按 dir_ino 解析，不受 cwd 影响
+        let mut f = openat(&mut dev, &mut fs, d, "f", true).unwrap();
+        write_at(&mut dev, &mut fs, &mut f, b"data").unwrap();
+        assert_eq!(f.inode.size(), 4);
+        assert_eq!(read_file(&mut dev, &mut fs, "/d/f").unwrap().unwrap(), b"data");
+        assert!(openat(&mut dev, &mut fs, sub, "f", false).is_err());
+
+        // symlinkat 不要求目标存在；fstatat 按标志决定是否跟随
+        let l = symlinkat(&mut dev, &mut fs, "../f", sub, "l").unwrap();
+        assert_eq!(fstatat(&mut dev, &mut fs, sub, "l", AT_SYMLINK_NOFOLLOW).unwrap().0, l);
+        assert_eq!(fstatat(&mut dev, &mut fs, sub, "l", 0).unwrap().0, f.ino);
+        symlinkat(&mut dev, &mut fs, "nowhere", sub, "dangling").unwrap();
+        assert_eq!(
+            fstatat(&mut dev, &mut fs, sub, "dangling", 0).unwrap_err(),
+            BlockDevError::NotFound
+        );
+
+        // 以创建方式打开悬空的符号链接时创建它指向的文件，不会多出同名目录项
+        let created = openat(&mut dev, &mut fs, sub, "dangling", true).unwrap();
+        assert_eq!(fstatat(&mut dev, &mut fs, sub, "nowhere", 0).unwrap().0, created.ino);
+        let sub_inode = fs.get_inode_by_num(&mut dev, sub).unwrap();
+        let entries = dir_entries(&mut fs, &mut dev, &sub_inode).unwrap();
+        assert_eq!(entries.iter().filter(|e| e.2 == b"dangling").count(), 1);
+        symlinkat(&mut dev, &mut fs, "loop", sub, "loop").unwrap();
+        assert_eq!(
+            openat(&mut dev, &mut fs, sub, "loop", true).err(),
+            Some(BlockDevError::SymlinkLoop)
+        );
+        unlinkat(&mut dev, &mut fs, sub, "loop", 0).unwrap();
+        unlinkat(&mut dev, &mut fs, sub, "nowhere", 0).unwrap();
+
+        // linkat 默认链接符号链接本身，AT_SYMLINK_FOLLOW 时链接目标
+        linkat(&mut dev, &mut fs, sub, "l", d, "l2", 0).unwrap();
+        linkat(&mut dev, &mut fs, sub, "l", d, "f2", AT_SYMLINK_FOLLOW).unwrap();
+        assert_eq!(fstatat(&mut dev, &mut fs, d, "l2", AT_SYMLINK_NOFOLLOW).unwrap().0, l);
+        let (_, target) = fstatat(&mut dev, &mut fs, d, "f2", 0).unwrap();
+        assert_eq!(target.i_links_count, 2);
+        assert_eq!(
+            linkat(&mut dev, &mut fs, root, "d", d, "dl", 0).unwrap_err(),
+            BlockDevError::IsDirectory
+        );
+
+        renameat2(&mut dev, &mut fs, d, "f2", sub, "moved", RENAME_NOREPLACE).unwrap();
+        assert_eq!(fstatat(&mut dev, &mut fs, sub, "moved", 0).unwrap().0, f.ino);
+
+        // unlinkat 区分文件和目录
+        assert_eq!(
+            unlinkat(&mut dev, &mut fs, d, "sub", 0).unwrap_err(),
+            BlockDevError::IsDirectory
+        );
+        assert_eq!(
+            unlinkat(&mut dev, &mut fs, d, "sub", AT_REMOVEDIR).unwrap_err(),
+            BlockDevError::NotEmpty
+        );
+        assert_eq!(
+            unlinkat(&mut dev, &mut fs, d, "f", AT_REMOVEDIR).unwrap_err(),
+            BlockDevError::NotDirectory
+        );
+        for name in ["l", "dangling", "moved"] {
+            unlinkat(&mut dev, &mut fs, sub, name, 0).unwrap();
+        }
+        unlinkat(&mut dev, &mut fs, d, "sub", AT_REMOVEDIR).unwrap();
+        assert_eq!(fs.get_inode_by_num(&mut dev, d).unwrap().i_links_count, 2);
+        assert_eq!(fstatat(&mut dev, &mut fs, d, "f", 0).unwrap().1.i_links_count, 1);
+        unlinkat(&mut dev, &mut fs, d, "f", 0).unwrap();
+        unlinkat(&mut dev, &mut fs, d, "l2", 0).unwrap();
+        let d_inode = fs.get_inode_by_num(&mut dev, d).unwrap();
+        assert!(dir_is_empty(&mut fs, &mut dev, &d_inode).unwrap());
+        umount(fs, &mut dev).unwrap();
+    }
+
+    #[test]
+    fn test_rmdir_and_remove_tree() {
+        let storage = MemDev::new(16 * 1024);
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, storage, false);
+        mkfs(&mut dev).unwrap();
+        let mut fs = mount(&mut dev).unwrap();
+        let used_dirs = |fs: &Ext4FileSystem| fs.group_descs.iter().map(|d| d.used_dirs_count()).sum::<u32>();
+
+        mkdir(&mut dev, &mut fs, "/t/a/b");
+        mkdir(&mut dev, &mut fs, "/t/c");
+        mkfile(&mut dev, &mut fs, "/t/a/f1", Some(b"1"), None);
+        mkfile(&mut dev, &mut fs, "/t/a/b/f2", Some(b"2"), None);
+        create_symbol_link(&mut dev, &mut fs, "/t/a", "/t/c/l").unwrap();
+        let dirs_before = used_dirs(&fs);
+
+        assert_eq!(rmdir(&mut dev, &mut fs, "/").unwrap_err(), BlockDevError::DeviceBusy);
+        assert_eq!(rmdir(&mut dev, &mut fs, "/lost+found").unwrap_err(), BlockDevError::DeviceBusy);
+        assert_eq!(rmdir(&mut dev, &mut fs, "/t/a").unwrap_err(), BlockDevError::NotEmpty);
+        assert_eq!(rmdir(&mut dev, &mut fs, "/t/a/f1").unwrap_err(), BlockDevError::NotDirectory);
+        assert_eq!(rmdir(&mut dev, &mut fs, "/t/nope").unwrap_err(), BlockDevError::NotFound);
+        assert_eq!(
+            remove_tree(&mut dev, &mut fs, "/lost+found", |_, _| {}).unwrap_err(),
+            BlockDevError::DeviceBusy
+        );
+
+        // 空目录：父目录链接数和块组目录计数各减一
+        let t_links = lookup(&mut dev, &mut fs, "/t").unwrap().1.i_links_count;
+        unlink(&mut fs, &mut dev, "/t/c/l");
+        rmdir(&mut dev, &mut fs, "/t/c").unwrap();
+        assert!(lookup(&mut dev, &mut fs, "/t/c").is_none());
+        assert_eq!(lookup(&mut dev, &mut fs, "/t").unwrap().1.i_links_count, t_links - 1);
+        assert_eq!(used_dirs(&fs), dirs_before - 1);
+
+        let mut seen = Vec::new();
+        let progress = remove_tree(&mut dev, &mut fs, "/t", |path, p| {
+            seen.push((alloc::string::String::from(path), *p));
+        })
+        .unwrap();
+        assert_eq!(progress, RemoveTreeProgress { files: 2, dirs: 3 });
+        assert_eq!(seen.len(), 5);
+        assert_eq!(seen.last().unwrap().0, "/t");
+        // 后序：目录总是在它里面的内容之后删除
+        let pos = |p: &str| seen.iter().position(|(s, _)| s == p).unwrap();
+        assert!(pos("/t/a/b/f2") < pos("/t/a/b"));
+        assert!(pos("/t/a/b") < pos("/t/a"));
+        assert!(lookup(&mut dev, &mut fs, "/t").is_none());
+        assert_eq!(used_dirs(&fs), dirs_before - 4);
+        assert_eq!(fs.get_root(&mut dev).unwrap().i_links_count, 3);
+        umount(fs, &mut dev).unwrap();
+    }
+
+    #[test]
+    fn test_unlink_frees_extent_tree_and_journals_own_blocks() {
+        let storage = MemDev::new(16 * 1024);
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, storage, false);
+        mkfs(&mut dev).unwrap();
+        let mut fs = mount(&mut dev).unwrap();
+        mkdir(&mut dev, &mut fs, "/d");
+        mkfile(&mut dev, &mut fs, "/d/other", Some(b"other"), None);
+        let free_before = fs.superblock.free_blocks_count();
+
+        // 隔块写入，extent 超过 i_block 能放下的 4 个，树长出一层叶子块
+        mkfile(&mut dev, &mut fs, "/d/sparse", None, None);
+        for i in 0..8u64 {
+            write_file(&mut dev, &mut fs, "/d/sparse", i * 2 * BLOCK_SIZE as u64, b"x").unwrap();
+        }
+        let (_, mut sparse) = lookup(&mut dev, &mut fs, "/d/sparse").unwrap();
+        let (exts, nodes) = ExtentTree::new(&mut sparse).collect_extents(&mut dev).unwrap();
+        assert_eq!(exts.len(), 8);
+        assert!(!nodes.is_empty());
+
+        // 别的文件有未提交的 inode 修改，不应被带进 unlink 的事务
+        let (other_ino, _) = lookup(&mut dev, &mut fs, "/d/other").unwrap();
+        fs.modify_inode(&mut dev, other_ino, |td| td.i_mtime = 12345).unwrap();
+
+        let (dir_ino, _) = lookup(&mut dev, &mut fs, "/d").unwrap();
+        unlink_with_ino(&mut dev, &mut fs, dir_ino, "sparse").unwrap();
+        assert_eq!(fs.superblock.free_blocks_count(), free_before);
+        for blk in nodes {
+            let (group, idx) = fs.block_allocator.global_to_group(blk);
+            let desc = *fs.get_group_desc(group).unwrap();
+            let bitmap = fs
+                .bitmap_cache
+                .get_or_load(&mut dev, CacheKey::new_block(group), desc.block_bitmap())
+                .unwrap();
+            assert_eq!(bitmap.data[idx as usize / 8] & (1 << (idx % 8)), 0);
+        }
+        assert!(fs.inodetable_cahce.get(other_ino as u64).unwrap().dirty);
+        umount(fs, &mut dev).unwrap();
+    }
+
+    #[test]
+    fn test_dir_tail_and_filetype_less() {
+        use crate::ext4_backend::endian::{read_u16_le, read_u32_le};
+
+        let storage = MemDev::new(16 * 1024);
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, storage, false);
+        mkfs(&mut dev).unwrap();
+        let mut fs = mount(&mut dev).unwrap();
+        fs.superblock.s_feature_ro_compat |= Ext4Superblock::EXT4_FEATURE_RO_COMPAT_METADATA_CSUM;
+        fs.superblock.s_feature_incompat &= !Ext4Superblock::EXT4_FEATURE_INCOMPAT_FILETYPE;
+
+        // 每个目录叶子块末尾都有尾部且校验和正确；htree 目录的 0 号块是 dx_root，
+        // 索引块在 dx_entry 之后保留 dx_tail，limit 相应减小
+        let check_dir = |dev: &mut Jbd2Dev<MemDev>, fs: &mut Ext4FileSystem, path: &str| {
+            let (ino, mut inode) = lookup(dev, fs, path).unwrap();
+            let tail = DirBlockTail::new(fs, ino, &inode);
+            let indexed = inode.is_htree_indexed();
+            let blocks = resolve_inode_block_allextend(fs, dev, &mut inode).unwrap();
+            for (&lbn, &phys) in blocks.iter() {
+                let data = &fs.datablock_cache.get_or_load(dev, phys).unwrap().data;
+                let is_node = read_u32_le(&data[0..4]) == 0
+                    && read_u16_le(&data[4..6]) as usize == BLOCK_SIZE;
+                if indexed && lbn == 0 {
+                    assert_eq!(read_u16_le(&data[32..34]) as usize, (BLOCK_SIZE - 32 - 8) / 8);
+                    assert!(tail.verify_dx(data, 32), "{path} dx_root");
+                } else if indexed && is_node {
+                    assert_eq!(read_u16_le(&data[8..10]) as usize, (BLOCK_SIZE - 8 - 8) / 8);
+                    assert!(tail.verify_dx(data, 8), "{path} dx_node {lbn}");
+                } else {
+                    assert!(tail.verify(data), "{path} block {lbn}");
+                    assert!(DirEntryIterator::new(&data[..BLOCK_SIZE]).all(|(e, _)| e.file_type == 0));
+                }
+            }
+        };
+
+        mkdir(&mut dev, &mut fs, "/s/sub");
+        mkfile(&mut dev, &mut fs, "/s/f", Some(b"f"), None);
+        rename(&mut dev, &mut fs, "/s/f", "/s/g").unwrap();
+        create_symbol_link(&mut dev, &mut fs, "/s/g", "/s/l").unwrap();
+        check_dir(&mut dev, &mut fs, "/s");
+        check_dir(&mut dev, &mut fs, "/s/sub");
+
+        // 写满单块目录转换为 htree，再删掉大半触发叶子合并
+        mkdir(&mut dev, &mut fs, "/big");
+        for i in 0..400 {
+            mkfile(&mut dev, &mut fs, &alloc::format!("/big/file_{i:04}"), None, None);
+        }
+        assert!(lookup(&mut dev, &mut fs, "/big").unwrap().1.is_htree_indexed());
+        check_dir(&mut dev, &mut fs, "/big");
+        for i in (0..400).filter(|i| i % 4 != 0) {
+            unlink(&mut fs, &mut dev, &alloc::format!("/big/file_{i:04}"));
+        }
+        check_dir(&mut dev, &mut fs, "/big");
+
+        // 没有 filetype 时类型由 inode 的 i_mode 得到，重新挂载后仍然成立
+        umount(fs, &mut dev).unwrap();
+        let mut fs = mount(&mut dev).unwrap();
+        assert!(!fs.superblock.has_filetype());
+        let (s_ino, s) = lookup(&mut dev, &mut fs, "/s").unwrap();
+        let mut types = dir_entries(&mut fs, &mut dev, &s).unwrap();
+        types.sort_by(|a, b| a.2.cmp(&b.2));
+        let types: Vec<(u8, Vec<u8>)> = types.into_iter().map(|(_, ft, name)| (ft, name)).collect();
+        assert_eq!(
+            types,
+            [
+                (Ext4DirEntry2::EXT4_FT_REG_FILE, b"g".to_vec()),
+                (Ext4DirEntry2::EXT4_FT_SYMLINK, b"l".to_vec()),
+                (Ext4DirEntry2::EXT4_FT_DIR, b"sub".to_vec()),
+            ]
+        );
+        assert_eq!(
+            find_dir_entry(&mut fs, &mut dev, &s, b"sub").unwrap().unwrap().1,
+            Ext4DirEntry2::EXT4_FT_DIR
+        );
+        assert_eq!(read_file_follow(&mut dev, &mut fs, "/s/l").unwrap().unwrap(), b"f");
+        rmdir_with_ino(&mut dev, &mut fs, s_ino, "sub").unwrap();
+        check_dir(&mut dev, &mut fs, "/s");
+        assert_eq!(lookup(&mut dev, &mut fs, "/big/file_0396").map(|_| ()), Some(()));
+        assert!(lookup(&mut dev, &mut fs, "/big/file_0397").is_none());
+        umount(fs, &mut dev).unwrap();
+    }
+
+    #[test]
+    fn test_casefold_directory() {
+        use crate::ext4_backend::api::*;
+
+        let storage = MemDev::new(16 * 1024);
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, storage, false);
+        mkfs(&mut dev).unwrap();
+        let mut fs = mount(&mut dev).unwrap();
+        mkdir(&mut dev, &mut fs, "/ci");
+        mkdir(&mut dev, &mut fs, "/cs");
+        mkfile(&mut dev, &mut fs, "/cs/Name", None, None);
+        // chattr 设置的是完整的标志，在现有标志上加 casefold
+        let set_casefold = |dev: &mut Jbd2Dev<MemDev>, fs: &mut Ext4FileSystem, path: &str| {
+            let (_, inode) = lookup(dev, fs, path).unwrap();
+            chattr(dev, fs, path, inode.i_flags | Ext4Inode::EXT4_CASEFOLD_FL)
+        };
+
+        // 没有 casefold 特性时不能设置标志
+        assert_eq!(set_casefold(&mut dev, &mut fs, "/ci").unwrap_err(), BlockDevError::Unsupported);
+        fs.superblock.s_feature_incompat |= Ext4Superblock::EXT4_FEATURE_INCOMPAT_CASEFOLD;
+        fs.superblock.s_encoding = Ext4Superblock::EXT4_ENC_UTF8_12_1;
+        assert_eq!(set_casefold(&mut dev, &mut fs, "/cs").unwrap_err(), BlockDevError::NotEmpty);
+        assert_eq!(set_casefold(&mut dev, &mut fs, "/cs/Name").unwrap_err(), BlockDevError::NotDirectory);
+        set_casefold(&mut dev, &mut fs, "/ci").unwrap();
+        let (ci_ino, ci) = lookup(&mut dev, &mut fs, "/ci").unwrap();
+
+        // 普通目录仍然区分大小写
+        assert!(lookup(&mut dev, &mut fs, "/cs/NAME").is_none());
+
+        mkfile(&mut dev, &mut fs, "/ci/Été.TXT", Some(b"summer"), None);
+        assert_eq!(read_file(&mut dev, &mut fs, "/ci/e\u{301}TE\u{301}.txt").unwrap().unwrap(), b"summer");
+        assert_eq!(
+            mkdirat(&mut dev, &mut fs, ci_ino, "ÉTÉ.txt").unwrap_err(),
+            BlockDevError::AlreadyExists
+        );
+        let sub = mkdirat(&mut dev, &mut fs, ci_ino, "Sub").unwrap();
+        // 折叠表未覆盖的字符（亚美尼亚字母）不能出现在 casefold 目录的新名字里
+        assert_eq!(mkdirat(&mut dev, &mut fs, ci_ino, "Աբ").unwrap_err(), BlockDevError::Unsupported);
+        mkfile(&mut dev, &mut fs, "/ci/Աբ", None, None);
+        assert!(lookup(&mut dev, &mut fs, "/ci/Աբ").is_none());
+        mkfile(&mut dev, &mut fs, "/cs/Աբ", None, None);
+        assert_eq!(
+            rename(&mut dev, &mut fs, "/cs/Աբ", "/ci/Աբ").unwrap_err(),
+            BlockDevError::Unsupported
+        );
+        assert!(lookup(&mut dev, &mut fs, "/cs/Աբ").is_some());
+        mkfile(&mut dev, &mut fs, "/ci/文件", Some(b"cjk"), None);
+        assert_eq!(read_file(&mut dev, &mut fs, "/ci/文件").unwrap().unwrap(), b"cjk");
+        assert_ne!(fs.get_inode_by_num(&mut dev, sub).unwrap().i_flags & Ext4Inode::EXT4_CASEFOLD_FL, 0);
+
+        // 写满一个块转换为 htree 后按折叠名字的哈希查找
+        for i in 0..300 {
+            mkfile(&mut dev, &mut fs, &alloc::format!("/ci/Straße_{i}"), None, None);
+        }
+        assert!(lookup(&mut dev, &mut fs, "/ci").unwrap().1.is_htree_indexed());
+        for i in 0..300 {
+            assert!(lookup(&mut dev, &mut fs, &alloc::format!("/ci/STRASSE_{i}")).is_some());
+        }
+        rename(&mut dev, &mut fs, "/ci/strasse_7", "/ci/SUB/moved").unwrap();
+        assert!(lookup(&mut dev, &mut fs, "/ci/Straße_7").is_none());
+        assert!(lookup(&mut dev, &mut fs, "/ci/sub/MOVED").is_some());
+        unlink(&mut fs, &mut dev, "/ci/STRASSE_8");
+        assert!(lookup(&mut dev, &mut fs, "/ci/Straße_8").is_none());
+        assert_eq!(
+            chattr(&mut dev, &mut fs, "/ci", ci.i_flags & !Ext4Inode::EXT4_CASEFOLD_FL).unwrap_err(),
+            BlockDevError::NotEmpty
+        );
+        umount(fs, &mut dev).unwrap();
+
+        let mut fs = mount(&mut dev).unwrap();
+        assert!(lookup(&mut dev, &mut fs, "/ci/straSSE_299").is_some());
+        umount(fs, &mut dev).unwrap();
+    }
 }
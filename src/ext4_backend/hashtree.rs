@@ -4,12 +4,17 @@
 //! Supports Ext4 HTree index format, including multiple hash algorithms
 
 use crate::ext4_backend::blockdev::*;
+use crate::ext4_backend::casefold::*;
 use crate::ext4_backend::config::*;
 use crate::ext4_backend::disknode::*;
 use crate::ext4_backend::endian::*;
 use crate::ext4_backend::entries::*;
 use crate::ext4_backend::ext4::*;
 use crate::ext4_backend::loopfile::*;
+use crate::ext4_backend::dir::*;
+use crate::ext4_backend::file::*;
+use crate::ext4_backend::error::*;
+use crate::ext4_backend::superblock::Ext4Superblock;
 
 use alloc::vec::Vec;
 use log::error;
@@ -51,17 +56,99 @@ pub struct HashTreeSearchResult {
     /// Found directory entry
     pub entry: Ext4DirEntryInfo<'static>,
     /// Block number where entry is located
-    pub block_num: u32,
+    pub block_num: u64,
     /// Offset within the block
     pub offset: usize,
 }
 
+/// Offset of dx_root_info inside the root block ("." and ".." fake entries come first)
+const DX_ROOT_INFO_OFFSET: usize = 24;
+/// Offset of count/limit inside an index node (after the fake empty entry)
+const DX_NODE_ENTRIES_OFFSET: usize = 8;
+/// Index depth limit without LARGEDIR (root plus one level of nodes)
+const DX_MAX_LEVELS: u8 = 2;
+/// Index depth limit with LARGEDIR
+const DX_MAX_LEVELS_LARGEDIR: u8 = 3;
+
+/// One index block on the path from dx_root to a leaf
+#[derive(Debug, Clone)]
+struct DxFrame {
+    /// Logical block of the index block inside the directory
+    lblk: u32,
+    /// Byte offset of the count/limit header
+    entries_off: usize,
+    /// Maximum number of entries the block can hold
+    limit: u16,
+    /// Index entries; entries[0].hash is implicitly 0
+    entries: Vec<Ext4DxEntry>,
+    /// Entry followed for the current hash
+    at: usize,
+}
+
+impl DxFrame {
+    /// Last entry whose hash is <= `hash`
+    fn select(&self, hash: u32) -> usize {
+        self.entries[1..].partition_point(|e| e.hash <= hash)
+    }
+
+    /// Write count/limit and entries back into the index block and refresh its dx_tail
+    fn write(&self, data: &mut [u8], tail: &DirBlockTail) {
+        let off = self.entries_off;
+        write_u16_le(self.limit, &mut data[off..off + 2]);
+        write_u16_le(self.entries.len() as u16, &mut data[off + 2..off + 4]);
+        write_u32_le(self.entries[0].block, &mut data[off + 4..off + 8]);
+        for (i, entry) in self.entries.iter().enumerate().skip(1) {
+            let pos = off + i * 8;
+            write_u32_le(entry.hash, &mut data[pos..pos + 4]);
+            write_u32_le(entry.block, &mut data[pos + 4..pos + 8]);
+        }
+        let used = off + self.entries.len() * 8;
+        let end = off + self.limit as usize * 8;
+        data[used..end].fill(0);
+        tail.update_dx(data, off);
+    }
+}
+
+/// Index path for one name: the hash and the frames from root to leaf
+#[derive(Debug, Clone)]
+struct DxPath {
+    hash: u32,
+    /// Effective hash version (unsigned variants already applied)
+    hash_version: u8,
+    /// indirect_levels from dx_root_info
+    levels: u8,
+    frames: Vec<DxFrame>,
+}
+
+impl DxPath {
+    /// Logical block the deepest frame points at
+    fn leaf(&self) -> u32 {
+        let frame = self.frames.last().expect("dx path always has a root frame");
+        frame.entries[frame.at].block
+    }
+}
+
+/// Map a directory logical block to its physical block
+fn dir_block<B: BlockDevice>(
+    block_dev: &mut Jbd2Dev<B>,
+    dir_inode: &Ext4Inode,
+    lblk: u32,
+) -> Result<u64, HashTreeError> {
+    match resolve_inode_block(block_dev, &mut dir_inode.clone(), lblk) {
+        Ok(Some(block)) => Ok(block),
+        Ok(None) => Err(HashTreeError::InvalidHashTree),
+        Err(_) => Err(HashTreeError::BlockOutOfRange),
+    }
+}
+
 /// Hash tree manager
 pub struct HashTreeManager {
     /// Hash seed (from superblock)
     hash_seed: [u32; 4],
-    /// Hash version
+    /// Default hash version (from superblock), used for new indexes
     hash_version: u8,
+    /// Superblock s_flags, selects signed/unsigned hash variants
+    hash_flags: u32,
     /// Number of indirect levels
     indirect_levels: u8,
 }
@@ -72,10 +159,27 @@ impl HashTreeManager {
         Self {
             hash_seed,
             hash_version,
+            hash_flags: 0,
             indirect_levels,
         }
     }
 
+    /// Set superblock s_flags so lookups honour EXT2_FLAGS_UNSIGNED_HASH
+    pub fn with_hash_flags(mut self, hash_flags: u32) -> Self {
+        self.hash_flags = hash_flags;
+        self
+    }
+
+    /// Default hash version recorded in the superblock
+    pub fn hash_version(&self) -> u8 {
+        self.hash_version
+    }
+
+    /// Number of indirect levels this manager was created with
+    pub fn indirect_levels(&self) -> u8 {
+        self.indirect_levels
+    }
+
     /// Search for filename in directory (using hash tree)
     pub fn lookup<B: BlockDevice>(
         &self,
@@ -88,49 +192,118 @@ impl HashTreeManager {
             "Starting hash tree lookup: {:?}",
             core::str::from_utf8(target_name)
         );
+        let key = DirNameKey::new(&fs.superblock, dir_inode, target_name);
 
         // 1. Check if directory has hash tree index enabled
         if !dir_inode.is_htree_indexed() {
            //warn!("Directory does not have hash tree index enabled, falling back to linear search");
-            return self.fallback_to_linear_search(fs, block_dev, dir_inode, target_name);
+            return self.fallback_to_linear_search(fs, block_dev, dir_inode, &key);
         }
 
-        // 2. Calculate hash value of target filename
-        let target_hash =
-            htree_dir::calculate_hash(target_name, self.hash_version, &self.hash_seed);
-        debug!("Target hash value: 0x{target_hash:08x}");
-
-        // 3. Read root node
-        let root_block = self.get_root_block( block_dev, dir_inode)?;
-        let root_data = self.read_block_data(fs, block_dev, root_block)?;
-
-        // 4. Parse root node
-        let root_info = self.parse_root_node(&root_data)?;
-
-        // 5. Search in hash tree
-        match self.search_in_hash_tree(fs, block_dev, &root_info, target_hash, target_name) {
-            Ok(result) => Ok(result),
+        // 2. Walk the index from the root down to the leaf covering the name's hash
+        let mut path = match self.dx_probe(fs, block_dev, dir_inode, &key) {
+            Ok(path) => path,
             Err(e) => {
                 warn!(
                     "Hash tree lookup failed: {e}, falling back to linear search"
                 );
-                self.fallback_to_linear_search(fs, block_dev, dir_inode, target_name)
+                return self.fallback_to_linear_search(fs, block_dev, dir_inode, &key);
+            }
+        };
+        debug!("Target hash value: 0x{:08x}", path.hash);
+
+        // 3. Search the leaf, continuing into the next leaf on hash collisions
+        loop {
+            let leaf = dir_block(block_dev, dir_inode, path.leaf())?;
+            let leaf_data = self.read_block_data(fs, block_dev, leaf)?;
+            if let Ok(result) = self.search_in_leaf_data(&leaf_data, &key, leaf) {
+                return Ok(result);
+            }
+            if !self.dx_next_leaf(fs, block_dev, dir_inode, &mut path)? {
+                return Err(HashTreeError::EntryNotFound);
             }
         }
     }
 
-    /// Get hash tree root block number
-    fn get_root_block<B: BlockDevice>(
+    /// Hash a name the way the directory's dx_root says to
+    fn name_hash(&self, name: &[u8], hash_version: u8) -> Option<u32> {
+        htree_dir::calculate_hash_pair(name, hash_version, &self.hash_seed).map(|(hash, _)| hash)
+    }
+
+    /// Walk the index from dx_root to the leaf that covers the hash of `key`
+    ///
+    /// Casefolded directories hash the folded name.
+    fn dx_probe<B: BlockDevice>(
+        &self,
+        fs: &mut Ext4FileSystem,
+        block_dev: &mut Jbd2Dev<B>,
+        dir_inode: &Ext4Inode,
+        key: &DirNameKey,
+    ) -> Result<DxPath, HashTreeError> {
+        let root = dir_block(block_dev, dir_inode, 0)?;
+        let root_data = self.read_block_data(fs, block_dev, root)?;
+        let (root_version, levels, frame) = self.parse_root_node(&root_data)?;
+
+        let hash_version = htree_dir::effective_hash_version(root_version, self.hash_flags);
+        let hash = self
+            .name_hash(key.hash_name(), hash_version)
+            .ok_or(HashTreeError::UnsupportedHashVersion)?;
+
+        let mut path = DxPath {
+            hash,
+            hash_version,
+            levels,
+            frames: Vec::new(),
+        };
+        let mut frame = frame;
+        frame.at = frame.select(hash);
+        path.frames.push(frame);
+
+        for _ in 0..levels {
+            let lblk = path.leaf();
+            let node = dir_block(block_dev, dir_inode, lblk)?;
+            let node_data = self.read_block_data(fs, block_dev, node)?;
+            let mut frame = self.parse_internal_node(&node_data, lblk)?;
+            frame.at = frame.select(hash);
+            path.frames.push(frame);
+        }
+        Ok(path)
+    }
+
+    /// Advance `path` to the next leaf if it may hold more names with the same hash
+    ///
+    /// The low bit of an index hash marks a leaf that continues a hash run from the previous one.
+    fn dx_next_leaf<B: BlockDevice>(
         &self,
+        fs: &mut Ext4FileSystem,
         block_dev: &mut Jbd2Dev<B>,
         dir_inode: &Ext4Inode,
-    ) -> Result<u32, HashTreeError> {
-        // Root block is usually the first data block of the directory
-        match resolve_inode_block(block_dev, &mut dir_inode.clone(), 0) {
-            Ok(Some(block)) => Ok(block),
-            Ok(None) => Err(HashTreeError::InvalidHashTree),
-            Err(_) => Err(HashTreeError::BlockOutOfRange),
+        path: &mut DxPath,
+    ) -> Result<bool, HashTreeError> {
+        let Some(level) = path
+            .frames
+            .iter()
+            .rposition(|f| f.at + 1 < f.entries.len())
+        else {
+            return Ok(false);
+        };
+
+        let frame = &mut path.frames[level];
+        frame.at += 1;
+        let bumped = frame.entries[frame.at].hash;
+        if bumped & 1 == 0 || bumped & !1 != path.hash {
+            return Ok(false);
         }
+
+        for next in level + 1..path.frames.len() {
+            let lblk = path.frames[next - 1].entries[path.frames[next - 1].at].block;
+            let node = dir_block(block_dev, dir_inode, lblk)?;
+            let node_data = self.read_block_data(fs, block_dev, node)?;
+            let mut frame = self.parse_internal_node(&node_data, lblk)?;
+            frame.at = 0;
+            path.frames[next] = frame;
+        }
+        Ok(true)
     }
 
     /// Read block data
@@ -138,46 +311,42 @@ impl HashTreeManager {
         &self,
         fs: &mut Ext4FileSystem,
         block_dev: &mut Jbd2Dev<B>,
-        block_num: u32,
+        block_num: u64,
     ) -> Result<Vec<u8>, HashTreeError> {
-        match fs.datablock_cache.get_or_load(block_dev, block_num as u64) {
+        match fs.datablock_cache.get_or_load(block_dev, block_num) {
             Ok(cached_block) => Ok(cached_block.data.clone()),
             Err(_) => Err(HashTreeError::BlockOutOfRange),
         }
     }
 
-    /// Parse root node
-    fn parse_root_node(&self, data: &[u8]) -> Result<HashTreeNode, HashTreeError> {
-        if data.len() < core::mem::size_of::<Ext4DxRoot>() {
+    /// Parse root node, returning (hash_version, indirect_levels, root frame)
+    fn parse_root_node(&self, data: &[u8]) -> Result<(u8, u8, DxFrame), HashTreeError> {
+        if data.len() < DX_ROOT_INFO_OFFSET + core::mem::size_of::<Ext4DxRootInfo>() {
             return Err(HashTreeError::BufferTooSmall);
         }
 
-        // Parse root node info
-        let dot = Ext4DirEntryInfo::parse_from_bytes(&data[0..8])
-            .ok_or(HashTreeError::CorruptedHashTree)?;
-
-        let dotdot = Ext4DirEntryInfo::parse_from_bytes(&data[dot.inode as usize..])
-            .ok_or(HashTreeError::CorruptedHashTree)?;
+        // "." must be a 12 byte record followed by ".." covering the rest of the block
+        let dot_rec_len = read_u16_le(&data[4..6]) as usize;
+        let dotdot_rec_len = read_u16_le(&data[16..18]) as usize;
+        if dot_rec_len != 12 || data[6] != 1 || data[18] != 2 || 12 + dotdot_rec_len != BLOCK_SIZE
+        {
+            return Err(HashTreeError::InvalidHashTree);
+        }
 
-        // Extract root info
-        let info_offset = dot.inode as usize + dotdot.inode as usize;
-        if info_offset + core::mem::size_of::<Ext4DxRootInfo>() > data.len() {
+        let info = &data[DX_ROOT_INFO_OFFSET..];
+        let reserved_zero = read_u32_le(&info[0..4]);
+        let hash_version = info[4];
+        let info_length = info[5];
+        let indirect_levels = info[6];
+        if reserved_zero != 0 || info_length != Ext4DxRootInfo::INFO_LENGTH {
             return Err(HashTreeError::CorruptedHashTree);
         }
+        if indirect_levels >= DX_MAX_LEVELS_LARGEDIR {
+            return Err(HashTreeError::UnsupportedHashVersion);
+        }
 
-        let info_bytes = &data[info_offset..info_offset + core::mem::size_of::<Ext4DxRootInfo>()];
-        let hash_version = info_bytes[5]; // hash_version field is at offset 5
-        let indirect_levels = info_bytes[6]; // indirect_levels field is at offset 6
-
-        // Parse hash entries
-        let entries_offset = info_offset + core::mem::size_of::<Ext4DxRootInfo>();
-        let entries = self.parse_dx_entries(&data[entries_offset..])?;
-
-        Ok(HashTreeNode::Root {
-            hash_version,
-            indirect_levels,
-            entries,
-        })
+        let frame = self.parse_dx_frame(data, 0, DX_ROOT_INFO_OFFSET + info_length as usize)?;
+        Ok((hash_version, indirect_levels, frame))
     }
 
     /// Parse DX entry array
@@ -200,87 +369,17 @@ impl HashTreeManager {
         Ok(entries)
     }
 
-    /// Search in hash tree
-    fn search_in_hash_tree<B: BlockDevice>(
-        &self,
-        fs: &mut Ext4FileSystem,
-        block_dev: &mut Jbd2Dev<B>,
-        node: &HashTreeNode,
-        target_hash: u32,
-        target_name: &[u8],
-    ) -> Result<HashTreeSearchResult, HashTreeError> {
-        match node {
-            HashTreeNode::Root { entries, .. } => {
-                self.search_in_entries(fs, block_dev, entries, target_hash, target_name, 0)
-            }
-            HashTreeNode::Internal { entries, .. } => {
-                self.search_in_entries(fs, block_dev, entries, target_hash, target_name, 0)
-            }
-            HashTreeNode::Leaf { block_num, .. } => {
-                self.search_in_leaf_block(fs, block_dev, *block_num, target_name)
-            }
-        }
-    }
-
-    /// Search in entry list
-    fn search_in_entries<B: BlockDevice>(
-        &self,
-        fs: &mut Ext4FileSystem,
-        block_dev: &mut Jbd2Dev<B>,
-        entries: &[Ext4DxEntry],
-        target_hash: u32,
-        target_name: &[u8],
-        level: u32,
-    ) -> Result<HashTreeSearchResult, HashTreeError> {
-        // Find appropriate entry (largest entry with hash <= target hash)
-        let mut selected_entry = None;
-        for entry in entries {
-            if entry.hash <= target_hash {
-                selected_entry = Some(entry);
-            } else {
-                break;
-            }
-        }
-
-        let entry = selected_entry.ok_or(HashTreeError::EntryNotFound)?;
-
-        // Read target block
-        let block_data = self.read_block_data(fs, block_dev, entry.block)?;
-
-        // Check if this is a leaf node
-        if level >= self.indirect_levels as u32 {
-            // Leaf node, search for specific directory entries within it
-            self.search_in_leaf_data(&block_data, target_name, entry.block)
-        } else {
-            // Internal node, recursive search
-            let internal_node = self.parse_internal_node(&block_data)?;
-            self.search_in_hash_tree(fs, block_dev, &internal_node, target_hash, target_name)
-        }
-    }
-
-    /// Search in leaf block
-    fn search_in_leaf_block<B: BlockDevice>(
-        &self,
-        fs: &mut Ext4FileSystem,
-        block_dev: &mut Jbd2Dev<B>,
-        block_num: u32,
-        target_name: &[u8],
-    ) -> Result<HashTreeSearchResult, HashTreeError> {
-        let block_data = self.read_block_data(fs, block_dev, block_num)?;
-        self.search_in_leaf_data(&block_data, target_name, block_num)
-    }
-
     /// Search in leaf data
     fn search_in_leaf_data(
         &self,
         data: &[u8],
-        target_name: &[u8],
-        block_num: u32,
+        key: &DirNameKey,
+        block_num: u64,
     ) -> Result<HashTreeSearchResult, HashTreeError> {
         let iter = DirEntryIterator::new(data);
 
         for (entry, offset) in iter {
-            if entry.name == target_name {
+            if key.matches(entry.name) {
                 return Ok(HashTreeSearchResult {
                     entry: unsafe { core::mem::transmute(entry) },
                     block_num,
@@ -293,30 +392,49 @@ impl HashTreeManager {
     }
 
     /// Parse internal node
-    fn parse_internal_node(&self, data: &[u8]) -> Result<HashTreeNode, HashTreeError> {
+    fn parse_internal_node(&self, data: &[u8], lblk: u32) -> Result<DxFrame, HashTreeError> {
         if data.len() < core::mem::size_of::<Ext4DxNode>() {
             return Err(HashTreeError::BufferTooSmall);
         }
 
-        // Skip fake directory entries
-        let fake_entry_size = core::mem::size_of::<Ext4DirEntry2>();
-        let countlimit_offset = fake_entry_size;
-
-        if countlimit_offset + core::mem::size_of::<Ext4DxCountlimit>() > data.len() {
+        // Index nodes start with a fake empty entry spanning the whole block
+        let fake_inode = read_u32_le(&data[0..4]);
+        let fake_rec_len = read_u16_le(&data[4..6]) as usize;
+        if fake_inode != 0 || fake_rec_len != BLOCK_SIZE {
             return Err(HashTreeError::CorruptedHashTree);
         }
 
-        let countlimit_bytes =
-            &data[countlimit_offset..countlimit_offset + core::mem::size_of::<Ext4DxCountlimit>()];
-        let _count = read_u16_le(&countlimit_bytes[2..4]) as usize; // count field is at offset 2
+        self.parse_dx_frame(data, lblk, DX_NODE_ENTRIES_OFFSET)
+    }
 
-        // Parse entries
-        let entries_offset = countlimit_offset + core::mem::size_of::<Ext4DxCountlimit>();
-        let entries = self.parse_dx_entries(&data[entries_offset..])?;
+    /// Parse the count/limit header and entries of an index block
+    fn parse_dx_frame(&self, data: &[u8], lblk: u32, off: usize) -> Result<DxFrame, HashTreeError> {
+        if off + 8 > data.len() {
+            return Err(HashTreeError::BufferTooSmall);
+        }
+        let limit = read_u16_le(&data[off..off + 2]);
+        let count = read_u16_le(&data[off + 2..off + 4]) as usize;
+        if count == 0 || count > limit as usize || off + limit as usize * 8 > data.len() {
+            return Err(HashTreeError::CorruptedHashTree);
+        }
 
-        Ok(HashTreeNode::Internal {
+        // The first entry's hash slot holds count/limit; its hash is implicitly 0
+        let mut entries = Vec::with_capacity(count);
+        entries.push(Ext4DxEntry {
+            hash: 0,
+            block: read_u32_le(&data[off + 4..off + 8]),
+        });
+        entries.extend(self.parse_dx_entries(&data[off + 8..off + count * 8])?);
+        if entries.len() != count {
+            return Err(HashTreeError::CorruptedHashTree);
+        }
+
+        Ok(DxFrame {
+            lblk,
+            entries_off: off,
+            limit,
             entries,
-            level: 0, // Should actually read from node
+            at: 0,
         })
     }
 
@@ -326,11 +444,11 @@ impl HashTreeManager {
         fs: &mut Ext4FileSystem,
         block_dev: &mut Jbd2Dev<B>,
         dir_inode: &Ext4Inode,
-        target_name: &[u8],
+        key: &DirNameKey,
     ) -> Result<HashTreeSearchResult, HashTreeError> {
         debug!(
             "Using linear search: {:?}",
-            core::str::from_utf8(target_name)
+            core::str::from_utf8(key.name())
         );
 
         let total_size = dir_inode.size() as usize;
@@ -361,10 +479,12 @@ impl HashTreeManager {
                 };
 
                 let block_data = &cached_block.data[..block_bytes];
-                if let Some(entry) = classic_dir::find_entry(block_data, target_name) {
+                if let Some((entry, _)) =
+                    DirEntryIterator::new(block_data).find(|(entry, _)| key.matches(entry.name))
+                {
                     return Ok(HashTreeSearchResult {
                         entry: unsafe { core::mem::transmute(entry) },
-                        block_num: phys as u32,
+                        block_num: phys,
                         offset: 0,
                     });
                 }
@@ -394,7 +514,7 @@ pub enum HashTreeNode {
     },
     /// Leaf node
     Leaf {
-        block_num: u32,
+        block_num: u64,
         entries: Vec<Ext4DirEntryInfo<'static>>,
     },
 }
@@ -419,7 +539,8 @@ impl Ext4InodeHashTreeExt for Ext4Inode {
             return None;
         }
 
-        Some((htree_dir::calculate_hash(b"", 0, &[0; 4]) as u8, 0))
+        // The real values live in the dx_root block; report the defaults here
+        Some((Ext4DxRootInfo::DX_HASH_LEGACY, 0))
     }
 }
 
@@ -430,6 +551,7 @@ pub fn create_hash_tree_manager(fs: &Ext4FileSystem) -> HashTreeManager {
         fs.superblock.s_def_hash_version,
         0, // indirect_levels, needs to be read from directory inode
     )
+    .with_hash_flags(fs.superblock.s_flags)
 }
 
 /// Convenient directory lookup function
@@ -443,12 +565,593 @@ pub fn lookup_directory_entry<B: BlockDevice>(
     manager.lookup(fs, block_dev, dir_inode, target_name)
 }
 
+/// Maximum number of dx entries in the root block, leaving room for the dx_tail
+fn dx_root_limit(tail: &DirBlockTail) -> u16 {
+    ((BLOCK_SIZE - DX_ROOT_INFO_OFFSET - Ext4DxRootInfo::INFO_LENGTH as usize - tail.dx_tail_len())
+        / core::mem::size_of::<Ext4DxEntry>()) as u16
+}
+
+/// Maximum number of dx entries in an index node, leaving room for the dx_tail
+fn dx_node_limit(tail: &DirBlockTail) -> u16 {
+    ((BLOCK_SIZE - DX_NODE_ENTRIES_OFFSET - tail.dx_tail_len()) / core::mem::size_of::<Ext4DxEntry>())
+        as u16
+}
+
+/// Index depth allowed by the filesystem features
+fn dx_max_levels(fs: &Ext4FileSystem) -> u8 {
+    if fs
+        .superblock
+        .has_feature_incompat(Ext4Superblock::EXT4_FEATURE_INCOMPAT_LARGEDIR)
+    {
+        DX_MAX_LEVELS_LARGEDIR
+    } else {
+        DX_MAX_LEVELS
+    }
+}
+
+/// Whether a directory may be converted to an htree index
+pub fn can_index_dir(fs: &Ext4FileSystem, dir_inode: &Ext4Inode) -> bool {
+    let version =
+        htree_dir::effective_hash_version(fs.superblock.s_def_hash_version, fs.superblock.s_flags);
+    fs.superblock
+        .has_feature_compat(Ext4Superblock::EXT4_FEATURE_COMPAT_DIR_INDEX)
+        && dir_inode.have_extend_header_and_use_extend()
+        && htree_dir::calculate_hash_pair(b"", version, &fs.superblock.s_hash_seed).is_some()
+}
+
+/// Map a directory logical block to its physical block for the update paths
+fn dir_block_for_update<B: BlockDevice>(
+    block_dev: &mut Jbd2Dev<B>,
+    dir_inode: &Ext4Inode,
+    lblk: u32,
+) -> BlockDevResult<u64> {
+    dir_block(block_dev, dir_inode, lblk).map_err(|_| BlockDevError::Corrupted)
+}
+
+/// Live entries of a directory block as raw records (header + name)
+fn collect_block_entries(data: &[u8]) -> Vec<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut offset = 0usize;
+    while offset + 8 <= data.len() {
+        let inode = read_u32_le(&data[offset..offset + 4]);
+        let rec_len = read_u16_le(&data[offset + 4..offset + 6]) as usize;
+        let name_len = data[offset + 6] as usize;
+        if rec_len < 8 || offset + rec_len > data.len() {
+            break;
+        }
+        if inode != 0 && 8 + name_len <= rec_len {
+            out.push(data[offset..offset + 8 + name_len].to_vec());
+        }
+        offset += rec_len;
+    }
+    out
+}
+
+/// Rewrite a leaf block with the given records packed from the start
+fn pack_block_entries(data: &mut [u8], records: &[Vec<u8>]) {
+    data.fill(0);
+    let mut offset = 0usize;
+    for (i, record) in records.iter().enumerate() {
+        let name_len = record[6];
+        let rec_len = if i + 1 == records.len() {
+            data.len() - offset
+        } else {
+            Ext4DirEntry2::entry_len(name_len) as usize
+        };
+        data[offset..offset + record.len()].copy_from_slice(record);
+        write_u16_le(rec_len as u16, &mut data[offset + 4..offset + 6]);
+        offset += rec_len;
+    }
+    if records.is_empty() {
+        write_u16_le(data.len() as u16, &mut data[4..6]);
+    }
+}
+
+/// Write a frame back into its index block
+fn store_dx_frame<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+    dir_ino: u32,
+    dir_inode: &Ext4Inode,
+    frame: &DxFrame,
+) -> BlockDevResult<()> {
+    let phys = dir_block_for_update(block_dev, dir_inode, frame.lblk)?;
+    let tail = DirBlockTail::new(fs, dir_ino, dir_inode);
+    fs.datablock_cache
+        .modify(block_dev, phys, |data| frame.write(data, &tail))?;
+    Ok(())
+}
+
+/// Allocate a new index node holding `entries`
+fn new_dx_node<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+    dir_ino: u32,
+    dir_inode: &mut Ext4Inode,
+    entries: Vec<Ext4DxEntry>,
+) -> BlockDevResult<DxFrame> {
+    let (lblk, phys) = append_dir_block(fs, block_dev, dir_ino, dir_inode)?;
+    let tail = DirBlockTail::new(fs, dir_ino, dir_inode);
+    let frame = DxFrame {
+        lblk,
+        entries_off: DX_NODE_ENTRIES_OFFSET,
+        limit: dx_node_limit(&tail),
+        entries,
+        at: 0,
+    };
+    fs.datablock_cache.modify(block_dev, phys, |data| {
+        data.fill(0);
+        // Fake empty entry covering the whole block keeps linear readers happy
+        write_u32_le(0, &mut data[0..4]);
+        write_u16_le(BLOCK_SIZE as u16, &mut data[4..6]);
+        frame.write(data, &tail);
+    })?;
+    Ok(frame)
+}
+
+/// Convert a full single-block directory into an htree indexed directory
+///
+/// Block 0 becomes dx_root and all entries after ".." move into a new leaf block.
+pub fn make_indexed_dir<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+    dir_ino: u32,
+    dir_inode: &mut Ext4Inode,
+) -> BlockDevResult<()> {
+    let root_phys = dir_block_for_update(block_dev, dir_inode, 0)?;
+    let root_data = fs.datablock_cache.get_or_load(block_dev, root_phys)?.data.clone();
+
+    let dot_rec_len = read_u16_le(&root_data[4..6]) as usize;
+    if root_data[6] != 1 || dot_rec_len < 12 || dot_rec_len + 12 > BLOCK_SIZE {
+        return Err(BlockDevError::Corrupted);
+    }
+    let dotdot = &root_data[dot_rec_len..];
+    let dotdot_rec_len = read_u16_le(&dotdot[4..6]) as usize;
+    if dotdot[6] != 2 || dot_rec_len + dotdot_rec_len > BLOCK_SIZE {
+        return Err(BlockDevError::Corrupted);
+    }
+    let dot_header = [root_data[0], root_data[1], root_data[2], root_data[3], root_data[7]];
+    let dotdot_header = [dotdot[0], dotdot[1], dotdot[2], dotdot[3], dotdot[7]];
+    let records = collect_block_entries(&root_data[dot_rec_len + dotdot_rec_len..BLOCK_SIZE]);
+
+    let (leaf_lblk, leaf_phys) = append_dir_block(fs, block_dev, dir_ino, dir_inode)?;
+    let tail = DirBlockTail::new(fs, dir_ino, dir_inode);
+    fs.datablock_cache.modify(block_dev, leaf_phys, |data| {
+        pack_block_entries(&mut data[..tail.usable()], &records);
+        tail.init(data);
+    })?;
+
+    let root = DxFrame {
+        lblk: 0,
+        entries_off: DX_ROOT_INFO_OFFSET + Ext4DxRootInfo::INFO_LENGTH as usize,
+        limit: dx_root_limit(&tail),
+        entries: alloc::vec![Ext4DxEntry {
+            hash: 0,
+            block: leaf_lblk,
+        }],
+        at: 0,
+    };
+    let hash_version = fs.superblock.s_def_hash_version;
+    fs.datablock_cache.modify(block_dev, root_phys, |data| {
+        data.fill(0);
+        // "." then ".." spanning the rest of the block, as the kernel lays out dx_root
+        data[0..4].copy_from_slice(&dot_header[..4]);
+        write_u16_le(12, &mut data[4..6]);
+        data[6] = 1;
+        data[7] = dot_header[4];
+        data[8] = b'.';
+        data[12..16].copy_from_slice(&dotdot_header[..4]);
+        write_u16_le((BLOCK_SIZE - 12) as u16, &mut data[16..18]);
+        data[18] = 2;
+        data[19] = dotdot_header[4];
+        data[20] = b'.';
+        data[21] = b'.';
+
+        let info = &mut data[DX_ROOT_INFO_OFFSET..];
+        info[4] = hash_version;
+        info[5] = Ext4DxRootInfo::INFO_LENGTH;
+        info[6] = 0;
+        root.write(data, &tail);
+    })?;
+
+    dir_inode.i_flags |= Ext4Inode::EXT4_INDEX_FL;
+    fs.modify_inode(block_dev, dir_ino, |inode| {
+        inode.i_flags |= Ext4Inode::EXT4_INDEX_FL;
+    })?;
+    Ok(())
+}
+
+/// Insert a directory entry into an htree indexed directory
+///
+/// The entry goes to the leaf covering its hash. A full leaf is split in two by hash,
+/// and full index blocks are split or the tree grows a level, up to the depth the
+/// filesystem allows. Returns `Ok(false)` if the index is unusable and the caller
+/// should fall back to a linear directory.
+pub fn dx_add_entry<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+    dir_ino: u32,
+    dir_inode: &mut Ext4Inode,
+    entry: &Ext4DirEntry2,
+) -> BlockDevResult<bool> {
+    let manager = create_hash_tree_manager(fs);
+    let name = &entry.name[..entry.name_len as usize];
+    let key = DirNameKey::new(&fs.superblock, dir_inode, name);
+    let mut path = match manager.dx_probe(fs, block_dev, dir_inode, &key) {
+        Ok(path) => path,
+        Err(e) => {
+            warn!("htree index of dir {dir_ino} unusable ({e}), falling back to linear directory");
+            return Ok(false);
+        }
+    };
+
+    let leaf_phys = dir_block_for_update(block_dev, dir_inode, path.leaf())?;
+    let tail = DirBlockTail::new(fs, dir_ino, dir_inode);
+    let mut inserted = false;
+    fs.datablock_cache.modify(block_dev, leaf_phys, |data| {
+        inserted = insert_entry_into_block(&mut data[..tail.usable()], entry);
+        if inserted {
+            tail.update(data);
+        }
+    })?;
+    if inserted {
+        return Ok(true);
+    }
+
+    // Make sure the index can take one more entry before touching the leaf
+    let index_full = path
+        .frames
+        .iter()
+        .all(|f| f.entries.len() >= f.limit as usize);
+    if index_full && path.levels + 1 >= dx_max_levels(fs) {
+        warn!("htree index of dir {dir_ino} is full");
+        return Err(BlockDevError::NoSpace);
+    }
+
+    let (split_hash, new_leaf) =
+        dx_split_leaf(fs, block_dev, dir_ino, dir_inode, &manager, &path, entry)?;
+    dx_insert_index(fs, block_dev, dir_ino, dir_inode, &mut path, split_hash, new_leaf)?;
+    Ok(true)
+}
+
+/// Split a full leaf by hash and insert `entry` into the half it belongs to
+///
+/// Returns the index entry (hash, logical block) for the new upper leaf.
+fn dx_split_leaf<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+    dir_ino: u32,
+    dir_inode: &mut Ext4Inode,
+    manager: &HashTreeManager,
+    path: &DxPath,
+    entry: &Ext4DirEntry2,
+) -> BlockDevResult<(u32, u32)> {
+    let leaf_phys = dir_block_for_update(block_dev, dir_inode, path.leaf())?;
+    let leaf_data = fs.datablock_cache.get_or_load(block_dev, leaf_phys)?.data.clone();
+    let mut map: Vec<(u32, Vec<u8>)> = Vec::new();
+    for record in collect_block_entries(&leaf_data[..BLOCK_SIZE]) {
+        let key = DirNameKey::new(&fs.superblock, dir_inode, &record[8..]);
+        let hash = manager
+            .name_hash(key.hash_name(), path.hash_version)
+            .ok_or(BlockDevError::Corrupted)?;
+        map.push((hash, record));
+    }
+    if map.len() < 2 {
+        return Err(BlockDevError::Corrupted);
+    }
+    map.sort_by_key(|(hash, _)| *hash);
+
+    // Move entries from the top until about half a block has moved, as the kernel does
+    let mut size = 0usize;
+    let mut moved = 0usize;
+    for (_, record) in map.iter().rev() {
+        let len = Ext4DirEntry2::entry_len(record[6]) as usize;
+        if size + len / 2 > BLOCK_SIZE / 2 {
+            break;
+        }
+        size += len;
+        moved += 1;
+    }
+    let split = (map.len() - moved).clamp(1, map.len() - 1);
+    let hash2 = map[split].0;
+    let continued = hash2 == map[split - 1].0;
+
+    let upper: Vec<Vec<u8>> = map[split..].iter().map(|(_, r)| r.clone()).collect();
+    let lower: Vec<Vec<u8>> = map[..split].iter().map(|(_, r)| r.clone()).collect();
+
+    let (new_lblk, new_phys) = append_dir_block(fs, block_dev, dir_ino, dir_inode)?;
+    let tail = DirBlockTail::new(fs, dir_ino, dir_inode);
+    fs.datablock_cache.modify(block_dev, new_phys, |data| {
+        pack_block_entries(&mut data[..tail.usable()], &upper);
+        tail.init(data);
+    })?;
+    fs.datablock_cache.modify(block_dev, leaf_phys, |data| {
+        pack_block_entries(&mut data[..tail.usable()], &lower);
+        tail.init(data);
+    })?;
+
+    let target = if path.hash >= hash2 { new_phys } else { leaf_phys };
+    let mut inserted = false;
+    fs.datablock_cache.modify(block_dev, target, |data| {
+        inserted = insert_entry_into_block(&mut data[..tail.usable()], entry);
+        tail.update(data);
+    })?;
+    if !inserted {
+        return Err(BlockDevError::NoSpace);
+    }
+
+    Ok((hash2 | continued as u32, new_lblk))
+}
+
+/// Add an index entry after the path's current position, splitting full index
+/// blocks upwards and growing the tree when the root is full
+fn dx_insert_index<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+    dir_ino: u32,
+    dir_inode: &mut Ext4Inode,
+    path: &mut DxPath,
+    hash: u32,
+    lblk: u32,
+) -> BlockDevResult<()> {
+    let mut new_entry = Ext4DxEntry { hash, block: lblk };
+    let mut level = path.frames.len() - 1;
+    loop {
+        let frame = &mut path.frames[level];
+        if frame.entries.len() < frame.limit as usize {
+            frame.entries.insert(frame.at + 1, new_entry);
+            let frame = frame.clone();
+            return store_dx_frame(fs, block_dev, dir_ino, dir_inode, &frame);
+        }
+
+        if level == 0 {
+            // Root is full: move its entries into a new node and point the root at it
+            if path.levels + 1 >= dx_max_levels(fs) {
+                return Err(BlockDevError::NoSpace);
+            }
+            let mut entries = frame.entries.clone();
+            entries.insert(frame.at + 1, new_entry);
+            let node = new_dx_node(fs, block_dev, dir_ino, dir_inode, entries)?;
+
+            let root = &mut path.frames[0];
+            root.entries = alloc::vec![Ext4DxEntry {
+                hash: 0,
+                block: node.lblk,
+            }];
+            root.at = 0;
+            path.levels += 1;
+            let root = root.clone();
+            let levels = path.levels;
+            let root_phys = dir_block_for_update(block_dev, dir_inode, 0)?;
+            let tail = DirBlockTail::new(fs, dir_ino, dir_inode);
+            fs.datablock_cache.modify(block_dev, root_phys, |data| {
+                data[DX_ROOT_INFO_OFFSET + 6] = levels;
+                root.write(data, &tail);
+            })?;
+            return Ok(());
+        }
+
+        // Full interior node: move the upper half into a new node
+        let mid = frame.entries.len() / 2;
+        let mut upper = frame.entries.split_off(mid);
+        let pos = frame.at + 1;
+        if pos <= mid {
+            frame.entries.insert(pos, new_entry);
+        } else {
+            upper.insert(pos - mid, new_entry);
+        }
+        let lower = frame.clone();
+        store_dx_frame(fs, block_dev, dir_ino, dir_inode, &lower)?;
+
+        let split_hash = upper[0].hash;
+        let node = new_dx_node(fs, block_dev, dir_ino, dir_inode, upper)?;
+        new_entry = Ext4DxEntry {
+            hash: split_hash,
+            block: node.lblk,
+        };
+        level -= 1;
+    }
+}
+
+/// Outcome of removing a name through the htree index
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DxRemoveResult {
+    /// Entry removed; carries the inode it pointed to
+    Removed(u32),
+    /// No entry with that name
+    NotFound,
+    /// Index could not be read; caller should scan linearly
+    IndexUnusable,
+}
+
+/// Remove a directory entry from an htree indexed directory
+///
+/// The entry is found through the hash path. A leaf left without live entries is
+/// unlinked from the index (together with index nodes that become empty) and its
+/// block is reclaimed, so the directory shrinks.
+pub fn dx_remove_entry<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+    dir_ino: u32,
+    dir_inode: &mut Ext4Inode,
+    name: &[u8],
+) -> BlockDevResult<DxRemoveResult> {
+    let manager = create_hash_tree_manager(fs);
+    let key = DirNameKey::new(&fs.superblock, dir_inode, name);
+    let mut path = match manager.dx_probe(fs, block_dev, dir_inode, &key) {
+        Ok(path) => path,
+        Err(e) => {
+            warn!("htree index of dir {dir_ino} unusable ({e}), removing linearly");
+            return Ok(DxRemoveResult::IndexUnusable);
+        }
+    };
+
+    let tail = DirBlockTail::new(fs, dir_ino, dir_inode);
+    loop {
+        let leaf_phys = dir_block_for_update(block_dev, dir_inode, path.leaf())?;
+        let mut removed = None;
+        let mut empty = false;
+        fs.datablock_cache.modify(block_dev, leaf_phys, |data| {
+            removed = remove_entry_from_block(&mut data[..tail.usable()], &key);
+            empty = DirEntryIterator::new(&data[..BLOCK_SIZE]).next().is_none();
+            tail.update(data);
+        })?;
+        if let Some(ino) = removed {
+            if empty {
+                dx_drop_leaf(fs, block_dev, dir_ino, dir_inode, &path)?;
+            } else {
+                dx_merge_leaf(fs, block_dev, dir_ino, dir_inode, &path)?;
+            }
+            return Ok(DxRemoveResult::Removed(ino));
+        }
+        match manager.dx_next_leaf(fs, block_dev, dir_inode, &mut path) {
+            Ok(true) => {}
+            Ok(false) => return Ok(DxRemoveResult::NotFound),
+            Err(_) => return Err(BlockDevError::Corrupted),
+        }
+    }
+}
+
+/// Bytes the records need when packed into a leaf
+fn records_len(records: &[Vec<u8>]) -> usize {
+    records
+        .iter()
+        .map(|r| Ext4DirEntry2::entry_len(r[6]) as usize)
+        .sum()
+}
+
+/// Merge a sparse leaf with its neighbour under the same index block
+///
+/// Only merges when both leaves together fill at most half a block, so a following
+/// insert does not immediately split them again. The lower leaf takes all entries
+/// (it already covers the lower hash range) and the upper one is dropped.
+fn dx_merge_leaf<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+    dir_ino: u32,
+    dir_inode: &mut Ext4Inode,
+    path: &DxPath,
+) -> BlockDevResult<()> {
+    let frame = path.frames.last().expect("dx path always has a root frame");
+    let sibling_at = if frame.at > 0 {
+        frame.at - 1
+    } else if frame.at + 1 < frame.entries.len() {
+        frame.at + 1
+    } else {
+        return Ok(());
+    };
+
+    let leaf_phys = dir_block_for_update(block_dev, dir_inode, path.leaf())?;
+    let sibling_phys = dir_block_for_update(block_dev, dir_inode, frame.entries[sibling_at].block)?;
+    let mut records =
+        collect_block_entries(&fs.datablock_cache.get_or_load(block_dev, leaf_phys)?.data[..BLOCK_SIZE]);
+    let sibling_records = collect_block_entries(
+        &fs.datablock_cache.get_or_load(block_dev, sibling_phys)?.data[..BLOCK_SIZE],
+    );
+    if records_len(&records) + records_len(&sibling_records) > BLOCK_SIZE / 2 {
+        return Ok(());
+    }
+
+    records.extend(sibling_records);
+    let (lower_at, lower_phys) = if sibling_at < frame.at {
+        (sibling_at, sibling_phys)
+    } else {
+        (frame.at, leaf_phys)
+    };
+    let tail = DirBlockTail::new(fs, dir_ino, dir_inode);
+    fs.datablock_cache.modify(block_dev, lower_phys, |data| {
+        pack_block_entries(&mut data[..tail.usable()], &records);
+        tail.init(data);
+    })?;
+
+    let mut upper = path.clone();
+    if let Some(last) = upper.frames.last_mut() {
+        last.at = lower_at + 1;
+    }
+    dx_drop_leaf(fs, block_dev, dir_ino, dir_inode, &upper)
+}
+
+/// Unlink an empty leaf from the index and reclaim it
+///
+/// Index nodes left with no entries go as well. The root always keeps at least one leaf.
+fn dx_drop_leaf<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+    dir_ino: u32,
+    dir_inode: &mut Ext4Inode,
+    path: &DxPath,
+) -> BlockDevResult<()> {
+    let Some(level) = path.frames.iter().rposition(|f| f.entries.len() > 1) else {
+        return Ok(());
+    };
+
+    let mut frame = path.frames[level].clone();
+    frame.entries.remove(frame.at);
+    store_dx_frame(fs, block_dev, dir_ino, dir_inode, &frame)?;
+
+    let mut freed: Vec<u32> = path.frames[level + 1..].iter().map(|f| f.lblk).collect();
+    freed.push(path.leaf());
+    // Highest first, so moving the last block never lands on a block still to be freed
+    freed.sort_unstable_by(|a, b| b.cmp(a));
+    for lblk in freed {
+        let last = (dir_inode.size().div_ceil(BLOCK_SIZE as u64) as u32).saturating_sub(1);
+        if lblk != last {
+            dx_repoint(fs, block_dev, dir_ino, dir_inode, last, lblk)?;
+        }
+        reclaim_dir_block(fs, block_dev, dir_ino, dir_inode, lblk)?;
+    }
+    Ok(())
+}
+
+/// Point the index entry that references logical block `from` at `to`
+fn dx_repoint<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+    dir_ino: u32,
+    dir_inode: &Ext4Inode,
+    from: u32,
+    to: u32,
+) -> BlockDevResult<()> {
+    let manager = create_hash_tree_manager(fs);
+    let corrupted = |_| BlockDevError::Corrupted;
+
+    let root_phys = dir_block_for_update(block_dev, dir_inode, 0)?;
+    let root_data = manager
+        .read_block_data(fs, block_dev, root_phys)
+        .map_err(corrupted)?;
+    let (_, levels, root) = manager.parse_root_node(&root_data).map_err(corrupted)?;
+
+    let mut pending = alloc::vec![(root, 0u8)];
+    while let Some((mut frame, depth)) = pending.pop() {
+        if let Some(entry) = frame.entries.iter_mut().find(|e| e.block == from) {
+            entry.block = to;
+            return store_dx_frame(fs, block_dev, dir_ino, dir_inode, &frame);
+        }
+        if depth < levels {
+            for entry in &frame.entries {
+                let phys = dir_block_for_update(block_dev, dir_inode, entry.block)?;
+                let data = manager
+                    .read_block_data(fs, block_dev, phys)
+                    .map_err(corrupted)?;
+                let child = manager
+                    .parse_internal_node(&data, entry.block)
+                    .map_err(corrupted)?;
+                pending.push((child, depth + 1));
+            }
+        }
+    }
+    // Not referenced by the index: nothing to fix up
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     use alloc::vec::Vec;
 use crate::ext4_backend::error::BlockDevError;
+    use crate::ext4_backend::superblock::Ext4Superblock;
+    use crate::ext4_backend::memdev::MemDev;
     // Mock block device
     struct MockBlockDevice {
         data: Vec<u8>,
@@ -468,7 +1171,7 @@ use crate::ext4_backend::error::BlockDevError;
 
     impl BlockDevice for MockBlockDevice {
 
-        fn write(&mut self, buffer: &[u8], block_id: u32, count: u32) -> Result<(), BlockDevError> {
+        fn write(&mut self, buffer: &[u8], block_id: u64, count: u32) -> Result<(), BlockDevError> {
             if !self.is_open {
                 return Err(BlockDevError::DeviceNotOpen);
             }
@@ -487,7 +1190,7 @@ use crate::ext4_backend::error::BlockDevError;
             Ok(())
         }
 
-        fn read(&mut self, buffer: &mut [u8], block_id: u32, count: u32) -> Result<(), BlockDevError> {
+        fn read(&mut self, buffer: &mut [u8], block_id: u64, count: u32) -> Result<(), BlockDevError> {
             if !self.is_open {
                 return Err(BlockDevError::DeviceNotOpen);
             }
@@ -549,6 +1252,10 @@ use crate::ext4_backend::error::BlockDevError;
             group_count: 1,
             mounted: true,
             journal_sb_block_start: None,
+            fast_commit: crate::ext4_backend::fast_commit::FastCommitTracker::default(),
+            cwd: 2,
+            dentry_cache: crate::ext4_backend::dentry_cache::DentryCache::default(),
+            extent_cache: crate::ext4_backend::extent_status::ExtentStatusCache::default(),
         }
     }
 
@@ -630,6 +1337,81 @@ use crate::ext4_backend::error::BlockDevError;
         }
     }
 
+    #[test]
+    fn test_htree_hash_kernel_vectors() {
+        // Expected values produced by e2fsprogs `debugfs dx_hash`, which shares
+        // its hash code with the kernel's fs/ext4/hash.c
+        let zero = [0u32; 4];
+        let cases: [(&[u8], u8, (u32, u32)); 14] = [
+            (b"a", 0, (0xe74b53e2, 0)),
+            (b"hello", 0, (0x32252546, 0)),
+            (b"lost+found", 0, (0x5e2aba24, 0)),
+            (b"abcdefghijklmnopqrstuvwxyz0123456789ABCDEFGH", 0, (0xac36d6e0, 0)),
+            (b"a", 1, (0xd5fa7d7a, 0xacb48187)),
+            (b"hello", 1, (0x1746da32, 0x420013b5)),
+            (b"lost+found", 1, (0x591de422, 0x6ffc56e0)),
+            (b"abcdefghijklmnopqrstuvwxyz0123456789ABCDEFGH", 1, (0xbb144a76, 0x630d8be9)),
+            (b"a", 2, (0x6d0ea4c0, 0xc18922df)),
+            (b"hello", 2, (0x6f5bb1a8, 0x231917c2)),
+            (b"lost+found", 2, (0x2dbf9e80, 0xbfebee4f)),
+            (b"abcdefghijklmnopqrstuvwxyz0123456789ABCDEFGH", 2, (0x8ada2f52, 0xdb4c1bb9)),
+            // Bytes >= 0x80 differ between the signed and unsigned variants
+            (b"caf\xe9\xff", 0, (0xfcebb51c, 0)),
+            (b"caf\xe9\xff", 3, (0x0768210a, 0)),
+        ];
+        for (name, version, expected) in cases {
+            assert_eq!(
+                htree_dir::calculate_hash_pair(name, version, &zero),
+                Some(expected),
+                "name {:?} version {}",
+                name,
+                version
+            );
+        }
+
+        let high = b"caf\xe9\xff";
+        assert_eq!(
+            htree_dir::calculate_hash_pair(high, 1, &zero),
+            Some((0x23ebaa7a, 0xa205e71e))
+        );
+        assert_eq!(
+            htree_dir::calculate_hash_pair(high, 4, &zero),
+            Some((0xfebde14c, 0x4f35d4c8))
+        );
+        assert_eq!(
+            htree_dir::calculate_hash_pair(high, 2, &zero),
+            Some((0x86077172, 0x8ac4b587))
+        );
+        assert_eq!(
+            htree_dir::calculate_hash_pair(high, 5, &zero),
+            Some((0x2cdad78e, 0x9c325521))
+        );
+
+        // Seed 12345678-8765-4321-abcd-ef0000fedcba, as stored in s_hash_seed
+        let seed = [0x78563412, 0x21436587, 0x00efcdab, 0xbadcfe00];
+        assert_eq!(
+            htree_dir::calculate_hash_pair(b"hello", 1, &seed),
+            Some((0xf74ac9f6, 0x5481e326))
+        );
+        let long = [b'x'; 70];
+        assert_eq!(
+            htree_dir::calculate_hash_pair(&long, 2, &seed),
+            Some((0x2c1cf87a, 0x66866afd))
+        );
+
+        assert_eq!(htree_dir::calculate_hash_pair(b"hello", 0x8, &zero), None);
+    }
+
+    #[test]
+    fn test_effective_hash_version() {
+        let unsigned = Ext4Superblock::EXT2_FLAGS_UNSIGNED_HASH;
+        let signed = Ext4Superblock::EXT2_FLAGS_SIGNED_HASH;
+        assert_eq!(htree_dir::effective_hash_version(1, signed), 1);
+        assert_eq!(htree_dir::effective_hash_version(0, unsigned), 3);
+        assert_eq!(htree_dir::effective_hash_version(2, unsigned), 5);
+        assert_eq!(htree_dir::effective_hash_version(4, unsigned), 4);
+    }
+
     #[test]
     fn test_inode_htree_check() {
         let mut inode = create_test_dir_inode();
@@ -720,9 +1502,117 @@ use crate::ext4_backend::error::BlockDevError;
             &mut fs,
             &mut mock_dev,
             &dir_inode,
-            b"nonexistent.txt",
+            &DirNameKey::exact(b"nonexistent.txt"),
         );
 
         assert!(matches!(result, Err(HashTreeError::EntryNotFound)));
     }
+
+    #[test]
+    fn test_htree_insert_splits_and_grows() {
+        use crate::ext4_backend::dir::mkdir;
+        use crate::ext4_backend::ext4::{mkfs, mount, umount};
+        use crate::ext4_backend::file::mkfile;
+        use alloc::format;
+
+        let mut dev = Jbd2Dev::initial_jbd2dev(
+            0,
+            MemDev::new(32 * 1024),
+            false,
+        );
+        mkfs(&mut dev).unwrap();
+        let mut fs = mount(&mut dev).unwrap();
+        fs.superblock.s_feature_ro_compat |= Ext4Superblock::EXT4_FEATURE_RO_COMPAT_METADATA_CSUM;
+        mkdir(&mut dev, &mut fs, "/big").unwrap();
+
+        // Long names keep leaves small, so the root fills up and the tree grows a level
+        let name = |i: usize| format!("{:0>250}", i);
+        let count = 7000;
+        for i in 0..count {
+            mkfile(&mut dev, &mut fs, &format!("/big/{}", name(i)), None, None).unwrap();
+        }
+
+        umount(fs, &mut dev).unwrap();
+        let mut fs = mount(&mut dev).unwrap();
+        let (_, dir) = get_file_inode(&mut fs, &mut dev, "/big").unwrap().unwrap();
+        assert!(dir.is_htree_indexed());
+        let manager = create_hash_tree_manager(&fs);
+        let path = manager
+            .dx_probe(&mut fs, &mut dev, &dir, &DirNameKey::exact(name(0).as_bytes()))
+            .unwrap();
+        assert_eq!(path.levels, 1);
+
+        // Index blocks written while the tree grew carry a valid dx_tail checksum
+        let (ino, _) = get_file_inode(&mut fs, &mut dev, "/big").unwrap().unwrap();
+        let tail = DirBlockTail::new(&fs, ino, &dir);
+        for (lblk, count_offset) in [(0, 32), (path.frames[1].lblk, DX_NODE_ENTRIES_OFFSET)] {
+            let phys = dir_block(&mut dev, &dir, lblk).unwrap();
+            let data = manager.read_block_data(&mut fs, &mut dev, phys).unwrap();
+            assert!(tail.verify_dx(&data, count_offset), "index block {lblk}");
+        }
+        assert_eq!(path.frames[0].limit, dx_root_limit(&tail));
+        assert_eq!(path.frames[1].limit, dx_node_limit(&tail));
+        assert!(dx_node_limit(&tail) < dx_node_limit(&DirBlockTail::new(&create_test_fs(), ino, &dir)));
+
+        for i in (0..count).step_by(7) {
+            let found = manager
+                .lookup(&mut fs, &mut dev, &dir, name(i).as_bytes())
+                .unwrap();
+            assert_ne!(found.entry.inode, 0);
+        }
+        assert_eq!(
+            manager
+                .lookup(&mut fs, &mut dev, &dir, b"missing")
+                .unwrap_err(),
+            HashTreeError::EntryNotFound
+        );
+        assert!(get_file_inode(&mut fs, &mut dev, &format!("/big/{}", name(count - 1)))
+            .unwrap()
+            .is_some());
+    }
+
+    #[test]
+    fn test_htree_remove_keeps_index_and_shrinks() {
+        use crate::ext4_backend::dir::mkdir;
+        use crate::ext4_backend::ext4::{mkfs, mount};
+        use crate::ext4_backend::file::{delete_file, mkfile};
+        use alloc::format;
+
+        let mut dev = Jbd2Dev::initial_jbd2dev(
+            0,
+            MemDev::new(16 * 1024),
+            false,
+        );
+        mkfs(&mut dev).unwrap();
+        let mut fs = mount(&mut dev).unwrap();
+        mkdir(&mut dev, &mut fs, "/d").unwrap();
+
+        let name = |i: usize| format!("{:0>250}", i);
+        let count = 1500;
+        for i in 0..count {
+            mkfile(&mut dev, &mut fs, &format!("/d/{}", name(i)), None, None).unwrap();
+        }
+        let (_, dir) = get_file_inode(&mut fs, &mut dev, "/d").unwrap().unwrap();
+        let full_size = dir.size();
+
+        for i in (0..count).filter(|i| i % 10 != 0) {
+            delete_file(&mut fs, &mut dev, &format!("/d/{}", name(i)));
+        }
+
+        let (_, dir) = get_file_inode(&mut fs, &mut dev, "/d").unwrap().unwrap();
+        assert!(dir.is_htree_indexed());
+        assert!(dir.size() < full_size / 2);
+        let manager = create_hash_tree_manager(&fs);
+        for i in 0..count {
+            let found = manager.lookup(&mut fs, &mut dev, &dir, name(i).as_bytes());
+            assert_eq!(found.is_ok(), i % 10 == 0, "entry {i}");
+        }
+
+        // Emptying the directory leaves the root with a single leaf
+        for i in (0..count).step_by(10) {
+            delete_file(&mut fs, &mut dev, &format!("/d/{}", name(i)));
+        }
+        let (_, dir) = get_file_inode(&mut fs, &mut dev, "/d").unwrap().unwrap();
+        assert_eq!(dir.size(), 2 * BLOCK_SIZE as u64);
+    }
 }
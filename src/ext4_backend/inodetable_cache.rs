@@ -6,7 +6,8 @@ use crate::ext4_backend::blockdev::*;
 use crate::ext4_backend::config::*;
 use crate::ext4_backend::disknode::*;
 use crate::ext4_backend::endian::*;
-use alloc::collections::BTreeMap;
+use crate::ext4_backend::buffer_cache::*;
+use alloc::collections::BTreeSet;
 use alloc::vec::Vec;
 use crate::ext4_backend::error::*;
 /// Inode缓存键（全局inode号）
@@ -25,8 +26,6 @@ pub struct CachedInode {
     pub offset_in_block: usize,
     /// Inode号
     pub inode_num: u64,
-    /// 最后访问时间戳
-    pub last_access: u64,
 }
 
 impl CachedInode {
@@ -37,7 +36,6 @@ impl CachedInode {
             block_num,
             offset_in_block: offset,
             inode_num,
-            last_access: 0,
         }
     }
 
@@ -63,13 +61,13 @@ pub struct InodeHandle {
 /// Inode缓存管理器
 pub struct InodeCache {
     /// 缓存的inode
-    cache: BTreeMap<InodeCacheKey, CachedInode>,
+    cache: LruCache<InodeCacheKey, CachedInode>,
     /// 最大缓存条目数
     max_entries: usize,
-    /// 访问计数器
-    access_counter: u64,
     /// 每个inode的大小=
     inode_size: usize,
+    /// start_tracking 之后被改脏的inode
+    tracked: Option<BTreeSet<u64>>,
 }
 
 impl InodeCache {
@@ -78,13 +76,18 @@ impl InodeCache {
     /// * `inode_size` - inode大小（通常是256字节）
     pub fn new(max_entries: usize, inode_size: usize) -> Self {
         Self {
-            cache: BTreeMap::new(),
-            max_entries,
-            access_counter: 0,
+            cache: LruCache::new(),
+            max_entries: max_entries.max(1),
             inode_size,
+            tracked: None,
         }
     }
 
+    /// 按字节预算创建inode缓存，条目数按 inode 大小折算
+    pub fn with_budget(budget_bytes: usize, inode_size: usize) -> Self {
+        Self::new(entries_for_budget(budget_bytes, inode_size), inode_size)
+    }
+
     /// 创建默认配置的缓存
     pub fn default(inode_size:u16) -> Self {
         Self::new(INODE_CACHE_MAX, inode_size as usize)
@@ -127,7 +130,7 @@ impl InodeCache {
         block_num: u64,
         offset: usize,
     ) -> BlockDevResult<Ext4Inode> {
-        block_dev.read_block(block_num as u32)?;
+        block_dev.read_block(block_num)?;
         let buffer = block_dev.buffer();
 
         if offset + self.inode_size > buffer.len() {
@@ -151,26 +154,8 @@ impl InodeCache {
         block_num: u64,
         offset: usize,
     ) -> BlockDevResult<&CachedInode> {
-        // 如果缓存中不存在，则加载
-        if !self.cache.contains_key(&inode_num) {
-            // 检查是否需要淘汰
-            if self.cache.len() >= self.max_entries {
-                self.evict_lru(block_dev)?;
-            }
-
-            // 从磁盘加载
-            let inode = self.load_inode(block_dev,  block_num, offset)?;
-            let cached = CachedInode::new(inode, inode_num, block_num, offset);
-            self.cache.insert(inode_num, cached);
-        }
-
-        // 更新访问时间
-        self.access_counter += 1;
-        if let Some(cached) = self.cache.get_mut(&inode_num) {
-            cached.last_access = self.access_counter;
-        }
-
-        self.cache.get(&inode_num).ok_or(BlockDevError::Corrupted)
+        self.get_or_load_mut(block_dev, inode_num, block_num, offset)
+            .map(|cached| &*cached)
     }
 
     /// 获取可变引用（如果不存在则从磁盘加载）
@@ -183,23 +168,21 @@ impl InodeCache {
     ) -> BlockDevResult<&mut CachedInode> {
         // 如果缓存中不存在，则加载
         if !self.cache.contains_key(&inode_num) {
-            if self.cache.len() >= self.max_entries {
-                self.evict_lru(block_dev)?;
+            // 检查是否需要淘汰
+            while self.cache.len() >= self.max_entries {
+                let Some(lru) = self.cache.lru_key() else {
+                    break;
+                };
+                self.evict(block_dev, lru)?;
             }
 
-            let inode = self.load_inode(block_dev,  block_num, offset)?;
+            // 从磁盘加载
+            let inode = self.load_inode(block_dev, block_num, offset)?;
             let cached = CachedInode::new(inode, inode_num, block_num, offset);
             self.cache.insert(inode_num, cached);
         }
 
-        // 更新访问时间并返回可变引用
-        self.access_counter += 1;
-        if let Some(cached) = self.cache.get_mut(&inode_num) {
-            cached.last_access = self.access_counter;
-            Ok(cached)
-        } else {
-            Err(BlockDevError::Corrupted)
-        }
+        self.cache.get_mut(&inode_num).ok_or(BlockDevError::Corrupted)
     }
 
     /// 获取已缓存的inode（不加载）
@@ -209,22 +192,29 @@ impl InodeCache {
 
     /// 获取可变引用
     pub fn get_mut(&mut self, inode_num: u64) -> Option<&mut CachedInode> {
-        if let Some(cached) = self.cache.get_mut(&inode_num) {
-            self.access_counter += 1;
-            cached.last_access = self.access_counter;
-            Some(cached)
-        } else {
-            None
-        }
+        self.cache.get_mut(&inode_num)
     }
 
     /// 标记inode为脏
     pub fn mark_dirty(&mut self, inode_num: u64) {
-        if let Some(cached) = self.cache.get_mut(&inode_num) {
+        if let Some(cached) = self.cache.peek_mut(&inode_num) {
             cached.mark_dirty();
+            if let Some(tracked) = self.tracked.as_mut() {
+                tracked.insert(inode_num);
+            }
         }
     }
 
+    /// 开始记录之后被改脏的inode
+    pub fn start_tracking(&mut self) {
+        self.tracked = Some(BTreeSet::new());
+    }
+
+    /// 停止记录，返回 start_tracking 之后被改脏的inode号
+    pub fn take_tracked(&mut self) -> BTreeSet<u64> {
+        self.tracked.take().unwrap_or_default()
+    }
+
     /// 使用闭包修改指定inode，并自动标记为脏
     pub fn modify<B, F>(
         &mut self,
@@ -240,7 +230,7 @@ impl InodeCache {
     {
         let cached = self.get_or_load_mut(block_dev, inode_num, block_num, offset)?;
         f(&mut cached.inode);
-        cached.mark_dirty();
+        self.mark_dirty(inode_num);
         Ok(())
     }
 
@@ -260,37 +250,14 @@ impl InodeCache {
         self.modify(block_dev, handle.inode_num, block_num, offset, f)
     }
 
-    /// LRU淘汰
-    fn evict_lru<B: BlockDevice>(&mut self, block_dev: &mut Jbd2Dev<B>) -> BlockDevResult<()> {
-        let lru_key = self
-            .cache
-            .iter()
-            .min_by_key(|(_, cached)| cached.last_access)
-            .map(|(key, _)| *key);
-
-        if let Some(key) = lru_key {
-            self.evict(block_dev, key)?;
-        }
-
-        Ok(())
-    }
-
     /// 淘汰指定的inode
     pub fn evict<B: BlockDevice>(
         &mut self,
         block_dev: &mut Jbd2Dev<B>,
         inode_num: u64,
     ) -> BlockDevResult<()> {
-        if let Some(cached) = self.cache.remove(&inode_num)
-            && cached.dirty {
-                Self::write_inode_static(
-                    block_dev,
-                    &cached.inode,
-                    cached.block_num,
-                    cached.offset_in_block,
-                    self.inode_size,
-                )?;
-            }
+        self.flush(block_dev, inode_num)?;
+        self.cache.remove(&inode_num);
         Ok(())
     }
 
@@ -315,31 +282,35 @@ impl InodeCache {
         // 先按 (block_num, offset) 排序，方便按块聚合写回
         dirty_inodes.sort_by_key(|(block_num, offset, _)| (*block_num, *offset));
 
+        let mut blocks: Vec<(u64, Vec<u8>)> = Vec::new();
         let mut idx = 0usize;
         while idx < dirty_inodes.len() {
             let (block_num, _, _) = dirty_inodes[idx];
 
-            // 读出当前 inode 表块到 Jbd2Dev 的 buffer
-            block_dev.read_block(block_num as u32)?;
-            {
-                let buffer = block_dev.buffer_mut();
-
-                // 将该块上所有脏 inode 的字节写入同一个 buffer 中
-                while idx < dirty_inodes.len() && dirty_inodes[idx].0 == block_num {
-                    let (_b, offset, ref data) = dirty_inodes[idx];
-                    let end = offset + data.len();
-                    if end > buffer.len() {
-                        return Err(BlockDevError::Corrupted);
-                    }
-                    buffer[offset..end].copy_from_slice(data);
-                    idx += 1;
+            // 读出当前 inode 表块
+            block_dev.read_block(block_num)?;
+            let mut buffer = block_dev.buffer().to_vec();
+
+            // 将该块上所有脏 inode 的字节写入同一个 buffer 中
+            while idx < dirty_inodes.len() && dirty_inodes[idx].0 == block_num {
+                let (_b, offset, ref data) = dirty_inodes[idx];
+                let end = offset + data.len();
+                if end > buffer.len() {
+                    return Err(BlockDevError::Corrupted);
                 }
+                buffer[offset..end].copy_from_slice(data);
+                idx += 1;
             }
-
-            // 该 inode 表块只调用一次 write_block，作为 metadata 走 JBD2
-            block_dev.write_block(block_num as u32, true)?;
+            blocks.push((block_num, buffer));
         }
 
+        // 所有 inode 表块一次 write_vectored，作为 metadata 走 JBD2
+        let reqs: Vec<(u64, &[u8])> = blocks
+            .iter()
+            .map(|(block_num, buffer)| (*block_num, buffer.as_slice()))
+            .collect();
+        block_dev.write_vectored(&reqs, true)?;
+
         // 清除所有脏标记
         for cached in self.cache.values_mut() {
             cached.dirty = false;
@@ -354,35 +325,17 @@ impl InodeCache {
         block_dev: &mut Jbd2Dev<B>,
         inode_num: u64,
     ) -> BlockDevResult<()> {
-        if let Some(cached) = self.cache.get(&inode_num)
-            && cached.dirty {
-                let block_num = cached.block_num;
-                let offset = cached.offset_in_block;
-                let mut buffer = alloc::vec![0u8; self.inode_size];
-                cached.inode.to_disk_bytes(&mut buffer);
-
-                Self::write_inode_bytes_static(block_dev, block_num, offset, &buffer)?;
-
-                if let Some(cached) = self.cache.get_mut(&inode_num) {
-                    cached.dirty = false;
-                }
-            }
+        if let Some(cached) = self.cache.peek_mut(&inode_num)
+            && cached.dirty
+        {
+            let mut buffer = alloc::vec![0u8; self.inode_size];
+            cached.inode.to_disk_bytes(&mut buffer);
+            Self::write_inode_bytes_static(block_dev, cached.block_num, cached.offset_in_block, &buffer)?;
+            cached.dirty = false;
+        }
         Ok(())
     }
 
-    /// 写inode到磁盘
-    fn write_inode_static<B: BlockDevice>(
-        block_dev: &mut Jbd2Dev<B>,
-        inode: &Ext4Inode,
-        block_num: u64,
-        offset: usize,
-        inode_size: usize,
-    ) -> BlockDevResult<()> {
-        let mut buffer = alloc::vec![0u8; inode_size];
-        inode.to_disk_bytes(&mut buffer);
-        Self::write_inode_bytes_static(block_dev, block_num, offset, &buffer)
-    }
-
     /// 写inode字节到磁盘
     fn write_inode_bytes_static<B: BlockDevice>(
         block_dev: &mut Jbd2Dev<B>,
@@ -390,12 +343,12 @@ impl InodeCache {
         offset: usize,
         data: &[u8],
     ) -> BlockDevResult<()> {
-        block_dev.read_block(block_num as u32)?;
+        block_dev.read_block(block_num)?;
         let buffer = block_dev.buffer_mut();
 
         buffer[offset..offset + data.len()].copy_from_slice(data);
 
-        block_dev.write_block(block_num as u32, true)?; //只供崩溃恢复用
+        block_dev.write_block(block_num, true)?; //只供崩溃恢复用
         Ok(())
     }
 
@@ -3,12 +3,18 @@ use crate::ext4_backend::config::*;
 use crate::ext4_backend::disknode::*;
 use crate::ext4_backend::endian::*;
 use crate::ext4_backend::ext4::*;
+use crate::ext4_backend::extent_status::*;
 use crate::ext4_backend::file::*;
 use crate::ext4_backend::jbd2::jbdstruct::*;
+use crate::ext4_backend::jbd2::logdump::*;
 use crate::ext4_backend::loopfile::*;
+use crate::ext4_backend::superblock::*;
+use crate::ext4_backend::tool::*;
 use crate::ext4_backend::error::*;
+use alloc::collections::BTreeMap;
 use alloc::vec;
 use log::debug;
+use log::error;
 use log::info;
 use log::warn;
 
@@ -17,7 +23,7 @@ use alloc::vec::Vec;
 
 impl JBD2DEVSYSTEM {
     ///计算下一个日志块的位置(处理回绕),返回当前的（可以直接用，直接写，已经处理过偏移）!
-    pub fn set_next_log_block<B:BlockDevice>(&mut self,block_dev: &mut B) -> u32 {
+    pub fn set_next_log_block<B: BlockDevice + ?Sized>(&mut self,block_dev: &mut B) -> u64 {
        //处理第一次使用journal提交
        if self.jbd2_super_block.s_start==0 {
            //更新内存的s_start 
@@ -28,30 +34,62 @@ impl JBD2DEVSYSTEM {
            self.jbd2_super_block.to_disk_bytes(&mut sb_data);
            block_dev.write(&sb_data, self.start_block, 1).expect("Write superblock failed");
            self.head+=1;
-           let mut target_use = self.start_block + self.jbd2_super_block.s_start+self.head-1;
+           let mut target_rel = self.jbd2_super_block.s_start + self.head - 1;
            //处理环绕
-           if target_use - self.start_block > self.max_len {
+           if target_rel > self.max_len {
                self.head = 0;
-               target_use = self.start_block + self.jbd2_super_block.s_start;
+               target_rel = self.jbd2_super_block.s_start;
            }
-           return target_use;
+           return self.log_block(target_rel);
        }else {
         //不是第一次提交
            self.head+=1;
            //处理环绕
-           let mut target_use = self.start_block + self.jbd2_super_block.s_start+self.head-1;
-           if target_use - self.start_block > self.max_len {
+           let mut target_rel = self.jbd2_super_block.s_start + self.head - 1;
+           if target_rel > self.max_len {
                self.head = 0;
-               target_use = self.start_block + self.jbd2_super_block.s_start;
+               target_rel = self.jbd2_super_block.s_start;
            }
-           return target_use;
+           return self.log_block(target_rel);
        }
        
     }
+
+    /// 日志区相对块号对应的物理块号
+    pub fn log_block(&self, rel: u32) -> u64 {
+        journal_block(self.start_block, &self.block_map, rel)
+    }
+
     ///提交事务
     /// 允许使用原始块设备!
     /// update:Vec<JBD2_UPDATE>
-    pub fn commit_transaction<B: BlockDevice>(&mut self, block_dev: &mut B) -> Result<bool, ()> {
+    pub fn commit_transaction<B: BlockDevice + ?Sized>(&mut self, block_dev: &mut B) -> Result<bool, ()> {
+        if !self.write_log(block_dev)? {
+            return Ok(false);
+        }
+        self.checkpoint(block_dev)?;
+        Ok(true)
+    }
+
+    ///提交事务到外部日志设备，checkpoint写回文件系统设备
+    pub fn commit_transaction_external<J, H>(
+        &mut self,
+        journal_dev: &mut J,
+        home_dev: &mut H,
+    ) -> BlockDevResult<bool>
+    where
+        J: BlockDevice + ?Sized,
+        H: BlockDevice + ?Sized,
+    {
+        if !self.write_log(journal_dev).map_err(|_| BlockDevError::WriteError)? {
+            return Ok(false);
+        }
+        self.checkpoint(home_dev).map_err(|_| BlockDevError::WriteError)?;
+        Ok(true)
+    }
+
+    ///写入descriptor、日志数据块和commit块，返回时事务已经持久化在日志区
+    fn write_log<B: BlockDevice + ?Sized>(&mut self, block_dev: &mut B) -> Result<bool, ()> {
         let tid = self.sequence; //事务id
         debug!(
             "[JBD2 commit] begin: tid={} updates_len={} head={} start_block={} max_len={} seq_in_superblock={} s_start={}",
@@ -69,47 +107,63 @@ impl JBD2DEVSYSTEM {
             return Ok(false);
         }
 
-        let mut desc_buffer = vec![0; BLOCK_SIZE];
-
-        //写header->内存缓存
-        let mut new_jbd_header = JournalHeaderS::default();
-        new_jbd_header.h_blocktype = 1; //Descriptor
-        new_jbd_header.h_sequence = tid; //设置事务id
-        new_jbd_header.to_disk_bytes(&mut desc_buffer[0..JournalHeaderS::disk_size()]);
-
-        let mut current_offset = 12; //跳过头
-        //写many tag，目前开发测试简化为一个descriptor块能塞下:)
-        for (idx, update) in self.commit_queue.iter().enumerate() {
-            //检查逃逸escape 如果数据块开头也是jbd2_magic 要标志逃逸
-            let mut tag = JournalBlockTagS {
-                t_blocknr: update.0 as u32,
-                t_checksum: 0,
-                t_flags: 0, //后面记得处理逃逸
+        //一个 descriptor 放不下时拆成多个，每个 descriptor 后面紧跟它描述的日志块
+        //tag 格式由日志特性决定，64 位日志带高 32 位块号
+        let tag_bytes = self.jbd2_super_block.tag_bytes();
+        let per_desc = self.jbd2_super_block.tags_per_descriptor();
+        let mut desc_buffers: Vec<Vec<u8>> = Vec::new();
+        for (chunk_idx, chunk) in self.commit_queue.chunks(per_desc).enumerate() {
+            let mut desc_buffer = vec![0; BLOCK_SIZE];
+
+            //写header->内存缓存
+            let new_jbd_header = JournalHeaderS {
+                h_blocktype: 1, //Descriptor
+                h_sequence: tid, //设置事务id
+                ..Default::default()
             };
-            let magic: u32 = u32::from_le_bytes(update.1[0..4].try_into().unwrap());
-            if magic == JBD2_MAGIC {
-                tag.t_flags |= JOURANL_ESCAPE;
-                debug!("JOURNAL ERROR ,Updates data escape!!!");
-            }
+            new_jbd_header.to_disk_bytes(&mut desc_buffer[0..JournalHeaderS::disk_size()]);
 
-            //最后一个
-            if idx == self.commit_queue.len() - 1 {
-                tag.t_flags |= JBD2_FLAG_LAST_TAG;
+            let mut current_offset = 12; //跳过头
+            for (i, update) in chunk.iter().enumerate() {
+                let idx = chunk_idx * per_desc + i;
+                //检查逃逸escape 如果数据块开头也是jbd2_magic 要标志逃逸
+                let mut tag = JouranlBlockTag3S {
+                    t_blocknr: update.0 as u32,
+                    t_flags: 0, //后面记得处理逃逸
+                    t_blocknr_high: (update.0 >> 32) as u32,
+                    t_checksum: 0,
+                };
+                //tag 只能存低 32 位块号时不能写出被截断的块号
+                if tag_bytes == 8 && tag.t_blocknr_high != 0 {
+                    error!("Block {} needs a 64-bit journal, transaction not written", update.0);
+                    return Err(());
+                }
+                let magic: u32 = u32::from_le_bytes(update.1[0..4].try_into().unwrap());
+                if magic == JBD2_MAGIC {
+                    tag.t_flags |= JOURANL_ESCAPE as u32;
+                    debug!("JOURNAL ERROR ,Updates data escape!!!");
+                }
+
+                //每个 descriptor 的最后一个 tag
+                if i == chunk.len() - 1 {
+                    tag.t_flags |= JBD2_FLAG_LAST_TAG as u32;
+                }
+                debug!(
+                    "[JBD2 commit] tid={} tag_idx={} t_blocknr={} t_flags=0x{:x}",
+                    tid, idx, tag.blocknr(), tag.t_flags,
+                );
+                tag.to_tag_bytes(&mut desc_buffer[current_offset..current_offset + tag_bytes], tag_bytes);
+                current_offset += tag_bytes;
             }
-            debug!(
-                "[JBD2 commit] tid={} tag_idx={} t_blocknr={} t_flags=0x{:x}",
-                tid, idx, tag.t_blocknr, tag.t_flags,
-            );
-            tag.to_disk_bytes(&mut desc_buffer[current_offset..current_offset + 8]);
-            current_offset += 8;
+            desc_buffers.push(desc_buffer);
         }
 
         //实际写入盘 这里可以直接写
-        let block_id = self.set_next_log_block(block_dev);
-        debug!(
-            "[JBD2 commit] tid={tid} descriptor_block_id={block_id} (absolute)"
-        );
-        block_dev.write(&desc_buffer, block_id, 1).expect("Jouranl block write failed!");
+        let async_commit = self.is_async_commit();
+        let use_chksum =
+            self.jbd2_super_block.s_feature_compat & JBD2_FEATURE_COMPAT_CHECKSUM != 0;
+        //commit块校验和按日志中的顺序覆盖descriptor和写入日志区的数据块（逃逸之后的内容）
+        let mut crc32_sum = !0u32;
 
         let mut no_escape: Vec<(u64, [u8; BLOCK_SIZE])> = Vec::new();
         //逃逸处理
@@ -126,26 +180,39 @@ impl JBD2DEVSYSTEM {
         }
 
         //写实际的metadata CORE!!!!!
-        for (idx, up) in no_escape.iter().enumerate() {
-            let metadata_journal_block_id = self.set_next_log_block(block_dev);
+        //所有descriptor和日志数据块一次write_vectored提交
+        let mut log_writes: Vec<(u64, &[u8])> =
+            Vec::with_capacity(no_escape.len() + desc_buffers.len());
+        for (desc_buffer, chunk) in desc_buffers.iter().zip(no_escape.chunks(per_desc)) {
+            let block_id = self.set_next_log_block(block_dev);
             debug!(
-                "[JBD2 commit] tid={} meta_idx={} journal_block_id={} (absolute) target_phys_block={}",
-                tid, idx, metadata_journal_block_id, up.0
+                "[JBD2 commit] tid={tid} descriptor_block_id={block_id} (absolute)"
             );
-            block_dev.write(&up.1, metadata_journal_block_id, 1).expect("Jouranl block write failed!");
+            crc32_sum = jbd2_crc32_be(crc32_sum, desc_buffer);
+            log_writes.push((block_id, desc_buffer));
+            for (idx, up) in chunk.iter().enumerate() {
+                let metadata_journal_block_id = self.set_next_log_block(block_dev);
+                debug!(
+                    "[JBD2 commit] tid={} meta_idx={} journal_block_id={} (absolute) target_phys_block={}",
+                    tid, idx, metadata_journal_block_id, up.0
+                );
+                log_writes.push((metadata_journal_block_id, &up.1));
+                crc32_sum = jbd2_crc32_be(crc32_sum, &up.1);
+            }
         }
+        block_dev.write_vectored(&log_writes).expect("Jouranl block write failed!");
 
-        block_dev.flush().expect("Jouranl block write failed!");
-
-        //清空update缓存
-        self.commit_queue.clear();
-        debug!("[JBD2 BUFFER] BUFFER ALREADY CLEA");
+        //屏障：同步提交时日志块必须先于commit块落盘；
+        //异步提交省去这次flush，由commit块中的校验和在重放时识别撕裂的事务
+        if !async_commit {
+            block_dev.flush().expect("Jouranl block flush failed!");
+        }
 
         //写入Commit Block
 
         let mut commit_buffer = [0_u8; BLOCK_SIZE];
 
-        let commit_block = CommitHeader {
+        let mut commit_block = CommitHeader {
             //commit block type 2
             h_header: JournalHeaderS {
                 h_magic: JBD2_MAGIC,
@@ -159,16 +226,25 @@ impl JBD2DEVSYSTEM {
             h_commit_sec: 0, //提交时间
             h_commit_nsec: 0,
         };
+        if use_chksum || async_commit {
+            commit_block.h_chksum_type = JBD2_CRC32_CHKSUM;
+            commit_block.h_chksum_size = JBD2_CRC32_CHKSUM_SIZE;
+            commit_block.h_chksum[0] = crc32_sum;
+        }
 
         commit_block.to_disk_bytes(&mut commit_buffer);
         let commit_block_id = self.set_next_log_block(block_dev);
         debug!(
-            "[JBD2 commit] tid={tid} commit_block_id={commit_block_id} (absolute)"
+            "[JBD2 commit] tid={tid} commit_block_id={commit_block_id} crc32=0x{crc32_sum:x} async={async_commit} (absolute)"
         );
-        block_dev.write(&commit_buffer, commit_block_id, 1).expect("Jouranl block write failed!");
+        //commit块使用FUA写入，返回时事务已经持久化
+        block_dev
+            .write_fua(&commit_buffer, commit_block_id, 1)
+            .expect("Jouranl commit block write failed!");
         //至此，commit已经完成，metadata数据已经安全:）
-        block_dev.flush().expect("Jouranl block write failed!");
         self.sequence += 1;
+        //旧事务的 fast commit 记录已被完整提交覆盖，新事务从区域开头写起
+        self.fc_off = 0;
         debug!(
             "[JBD2 commit] end: tid={} new_sequence={}",
             tid, self.sequence
@@ -178,10 +254,191 @@ impl JBD2DEVSYSTEM {
         Ok(true)
     }
 
+    ///checkpoint：commit落盘之后才把元数据写回主盘位置
+    fn checkpoint<B: BlockDevice + ?Sized>(&mut self, home_dev: &mut B) -> Result<(), ()> {
+        //同一个块在事务里可能出现多次，只写最新的一份，一次write_vectored提交
+        let mut latest: BTreeMap<u64, &[u8]> = BTreeMap::new();
+        for up in self.commit_queue.iter() {
+            latest.insert(up.0, &up.1);
+        }
+        let home_writes: Vec<(u64, &[u8])> = latest.into_iter().collect();
+        home_dev
+            .write_vectored(&home_writes)
+            .expect("Checkpoint write failed!");
+        home_dev.flush().expect("Checkpoint flush failed!");
+
+        //清空update缓存
+        self.commit_queue.clear();
+        debug!("[JBD2 BUFFER] BUFFER ALREADY CLEA");
+        Ok(())
+    }
+
+    /// 是否启用了异步提交
+    pub fn is_async_commit(&self) -> bool {
+        self.jbd2_super_block.s_feature_incompat & JBD2_FEATURE_INCOMPAT_ASYNC_COMMIT != 0
+    }
+
+    /// 设置异步提交特性，异步提交必须配合commit块校验和使用
+    /// 特性位随下一次写入的journal超级块落盘
+    pub fn set_async_commit(&mut self, enable: bool) {
+        if enable {
+            self.jbd2_super_block.s_feature_incompat |= JBD2_FEATURE_INCOMPAT_ASYNC_COMMIT;
+            self.jbd2_super_block.s_feature_compat |= JBD2_FEATURE_COMPAT_CHECKSUM;
+        } else {
+            self.jbd2_super_block.s_feature_incompat &= !JBD2_FEATURE_INCOMPAT_ASYNC_COMMIT;
+        }
+    }
+
+    /// 是否启用了 64 位块号（描述符使用 12/16 字节的 tag）
+    pub fn is_64bit(&self) -> bool {
+        self.jbd2_super_block.s_feature_incompat & JBD2_FEATURE_INCOMPAT_64BIT != 0
+    }
+
+    /// 设置 64 位块号特性，特性位随下一次写入的journal超级块落盘
+    pub fn set_64bit(&mut self) {
+        self.jbd2_super_block.s_feature_incompat |= JBD2_FEATURE_INCOMPAT_64BIT;
+    }
+
+    /// 是否启用了 fast commit
+    pub fn is_fast_commit(&self) -> bool {
+        self.jbd2_super_block.s_feature_incompat & JBD2_FEATURE_INCOMPAT_FAST_COMMIT != 0
+    }
+
+    /// 设置 fast commit 特性，日志末尾 s_num_fc_blks 个块划为 fast commit 区域
+    /// 调用前事务缓存必须已经提交；已提交的事务都已checkpoint，这里直接从头开始新的日志
+    pub fn set_fast_commit(&mut self, enable: bool) {
+        let sb = &mut self.jbd2_super_block;
+        if enable {
+            sb.s_feature_incompat |= JBD2_FEATURE_INCOMPAT_FAST_COMMIT;
+            if sb.s_num_fc_blks == 0 {
+                //日志太小时最多划出四分之一
+                sb.s_num_fc_blks = JBD2_DEFAULT_FAST_COMMIT_BLOCKS.min(sb.s_maxlen / 4);
+            }
+        } else {
+            sb.s_feature_incompat &= !JBD2_FEATURE_INCOMPAT_FAST_COMMIT;
+        }
+        sb.s_start = 0;
+        sb.s_sequence = self.sequence;
+        self.max_len = sb.log_maxlen();
+        self.head = 0;
+        self.fc_off = 0;
+    }
+
+    /// fast commit 区域（起始相对块号, 块数）
+    /// 与 Linux 一致：相对块号 [s_maxlen - num_fc + 1, s_maxlen)
+    pub fn fast_commit_area(&self) -> Option<(u32, u32)> {
+        let num_fc = self.jbd2_super_block.num_fc_blocks();
+        if num_fc < 2 {
+            return None;
+        }
+        let first_rel = self.jbd2_super_block.s_maxlen - num_fc + 1;
+        Some((first_rel, num_fc - 1))
+    }
+
+    /// 相对块号 [rel, rel + count) 拆成物理连续的段 (物理块号, 块数)
+    fn log_runs(&self, rel: u32, count: u32) -> Vec<(u64, u32)> {
+        let mut runs: Vec<(u64, u32)> = Vec::new();
+        for r in rel..rel + count {
+            let phys = self.log_block(r);
+            match runs.last_mut() {
+                Some((start, n)) if *start + *n as u64 == phys => *n += 1,
+                _ => runs.push((phys, 1)),
+            }
+        }
+        runs
+    }
+
+    /// 在 fast commit 区域追加整块数据，最后一块(含tail)使用FUA写入
+    /// 区域剩余空间不足时返回 false，由调用方回退到完整提交
+    pub fn write_fast_commit<B: BlockDevice + ?Sized>(
+        &mut self,
+        block_dev: &mut B,
+        blocks: &[u8],
+    ) -> BlockDevResult<bool> {
+        let Some((first, count)) = self.fast_commit_area() else {
+            return Ok(false);
+        };
+        let nblocks = (blocks.len() / BLOCK_SIZE) as u32;
+        if nblocks == 0 || self.fc_off + nblocks > count {
+            return Ok(false);
+        }
+        let start = first + self.fc_off;
+        let last_off = (nblocks as usize - 1) * BLOCK_SIZE;
+        let mut off = 0;
+        for (phys, n) in self.log_runs(start, nblocks - 1) {
+            let len = n as usize * BLOCK_SIZE;
+            block_dev.write(&blocks[off..off + len], phys, n)?;
+            off += len;
+        }
+        block_dev.write_fua(
+            &blocks[last_off..last_off + BLOCK_SIZE],
+            self.log_block(start + nblocks - 1),
+            1,
+        )?;
+        debug!(
+            "[JBD2 fast commit] tid={} blocks={} fc_off={} -> {}",
+            self.sequence,
+            nblocks,
+            self.fc_off,
+            self.fc_off + nblocks
+        );
+        self.fc_off += nblocks;
+        Ok(true)
+    }
+
+    /// 读出整个 fast commit 区域
+    pub fn read_fast_commit_area<B: BlockDevice + ?Sized>(
+        &self,
+        block_dev: &mut B,
+    ) -> BlockDevResult<Vec<u8>> {
+        let Some((first, count)) = self.fast_commit_area() else {
+            return Ok(Vec::new());
+        };
+        let mut buf = vec![0u8; count as usize * BLOCK_SIZE];
+        let mut off = 0;
+        for (phys, n) in self.log_runs(first, count) {
+            let len = n as usize * BLOCK_SIZE;
+            block_dev.read(&mut buf[off..off + len], phys, n)?;
+            off += len;
+        }
+        Ok(buf)
+    }
+
+    /// 把内存中的 journal 超级块写回日志设备（read-modify-write）
+    pub fn write_superblock<B: BlockDevice + ?Sized>(&self, block_dev: &mut B) -> BlockDevResult<()> {
+        let mut blk = [0u8; BLOCK_SIZE];
+        block_dev.read(&mut blk, self.start_block, 1)?;
+        self.jbd2_super_block.to_disk_bytes(&mut blk[0..1024]);
+        block_dev.write_fua(&blk, self.start_block, 1)
+    }
+
+    /// 查找尚未checkpoint的某个主盘块的最新事务内容
+    pub fn pending_update(&self, block_id: u64) -> Option<&Jbd2Update> {
+        self.commit_queue.iter().rev().find(|up| up.0 == block_id)
+    }
+
     ///事务重放：从当前 superblock 状态开始，尽可能重放连续的完整事务 replay前确保全部commit
-    pub fn replay<B: BlockDevice>(&mut self, block_dev: &mut B) {
+    pub fn replay<B: BlockDevice + ?Sized>(&mut self, block_dev: &mut B) {
+        self.replay_inner::<B, B>(block_dev, None);
+    }
+
+    ///从外部日志设备重放事务，写回文件系统设备
+    pub fn replay_external<J, H>(&mut self, journal_dev: &mut J, home_dev: &mut H)
+    where
+        J: BlockDevice + ?Sized,
+        H: BlockDevice + ?Sized,
+    {
+        self.replay_inner(journal_dev, Some(home_dev));
+    }
+
+    /// home_dev 为 None 时日志与文件系统在同一设备上
+    fn replay_inner<J, H>(&mut self, block_dev: &mut J, mut home_dev: Option<&mut H>)
+    where
+        J: BlockDevice + ?Sized,
+        H: BlockDevice + ?Sized,
+    {
         // 注意：journal_superblock_s 里的 s_first / s_start 是“日志区内部的相对块号”，
-        // 真实物理块号 = self.start_block + rel。
+        // 真实物理块号由 log_block(rel) 换算，日志区不一定物理连续。
 
         // 扫描起点（相对块号）：只使用 s_start。s_start==0 表示没有需要重放的事务。
         let mut journal_rel = self.jbd2_super_block.s_start;
@@ -190,7 +447,7 @@ impl JBD2DEVSYSTEM {
         }
 
         let first_rel = self.jbd2_super_block.s_first; // 第一个日志块（相对 superblock）
-        let maxlen = self.jbd2_super_block.s_maxlen; // 可用日志块数量（不含 superblock）
+        let maxlen = self.jbd2_super_block.log_maxlen(); // 可用日志块数量（不含 superblock 和 fast commit 区域）
         let last_rel = first_rel.saturating_add(maxlen.saturating_sub(1));
         let mut expect_seq = self.jbd2_super_block.s_sequence;
 
@@ -221,7 +478,7 @@ impl JBD2DEVSYSTEM {
         loop {
             // 1) 读取 descriptor 块并做基本校验
             let mut desc_buf = [0u8; BLOCK_SIZE];
-            let desc_phys = self.start_block + journal_rel; // descriptor 物理块号
+            let desc_phys = self.log_block(journal_rel); // descriptor 物理块号
             if let Err(e) = block_dev.read(&mut desc_buf, desc_phys, 1) {
                 debug!(
                     "[JBD2 replay] read descriptor failed at rel_block={journal_rel} phys_block={desc_phys} err={e:?}"
@@ -243,35 +500,57 @@ impl JBD2DEVSYSTEM {
                 break;
             }
 
-            // 2) 解析 descriptor 里的 tags
-            let mut tags: Vec<JournalBlockTagS> = Vec::new();
-            let mut off = 12usize; // 跳过 header
-            let mut tag_idx = 0usize;
-            while off + 8 <= BLOCK_SIZE {
-                let tag = JournalBlockTagS::from_disk_bytes(&desc_buf[off..off + 8]);
-
-                // 注意：t_blocknr==0 在 ext4 上是合法的（例如 superblock/group desc 等元数据），
-                // 不能直接用 "t_blocknr==0" 当作 tag 结束条件。
-                // 我们只在“当前 8 字节全 0 且后续全部为 0 padding”时，才认为 descriptor 结束。
-                if tag.t_blocknr == 0 && tag.t_checksum == 0 && tag.t_flags == 0 {
-                    if desc_buf[off + 8..].iter().all(|b| *b == 0) {
-                        break;
+            // 2) 解析 descriptor 里的 tags 并读取对应数量的 metadata 日志块；
+            //    一个事务可能有多个 descriptor，直到遇到 commit 块为止
+            let mut tags: Vec<JouranlBlockTag3S> = Vec::new();
+            let mut meta_blocks: Vec<[u8; BLOCK_SIZE]> = Vec::new();
+            let mut crc32_sum = !0u32;
+            let mut cbuf = [0u8; BLOCK_SIZE];
+            loop {
+                crc32_sum = jbd2_crc32_be(crc32_sum, &desc_buf);
+                let desc_tags = parse_descriptor_tags(&desc_buf, self.jbd2_super_block.tag_bytes());
+                if desc_tags.is_empty() {
+                    break;
+                }
+                for tag in desc_tags {
+                    debug!(
+                        "[JBD2 replay] tid={} tag_idx={} t_blocknr={} t_flags=0x{:x}",
+                        expect_seq, tags.len(), tag.blocknr(), tag.t_flags
+                    );
+                    // 下一个 journal 块（相对块号），注意处理回绕
+                    advance_rel(&mut journal_rel);
+                    let meta_phys = self.log_block(journal_rel);
+                    let mut mbuf = [0u8; BLOCK_SIZE];
+                    if let Err(e) = block_dev.read(&mut mbuf, meta_phys, 1) {
+                        debug!(
+                            "[JBD2 replay] read meta block failed: idx={} rel_block={journal_rel} phys_block={meta_phys} err={e:?}",
+                            tags.len()
+                        );
+                        return;
                     }
+                    crc32_sum = jbd2_crc32_be(crc32_sum, &mbuf);
+                    meta_blocks.push(mbuf);
+                    tags.push(tag);
                 }
 
-                debug!(
-                    "[JBD2 replay] tid={} tag_idx={} t_blocknr={} t_flags=0x{:x}",
-                    expect_seq, tag_idx, tag.t_blocknr, tag.t_flags
-                );
-
-                let last = (tag.t_flags & JBD2_FLAG_LAST_TAG) != 0;
-                tags.push(tag);
-                off += 8;
-                tag_idx += 1;
-
-                if last {
-                    break;
+                // 下一块是同一事务的 descriptor 时继续，否则应当是 commit 块
+                advance_rel(&mut journal_rel);
+                let next_phys = self.log_block(journal_rel);
+                if let Err(e) = block_dev.read(&mut cbuf, next_phys, 1) {
+                    debug!(
+                        "[JBD2 replay] read block failed at rel_block={journal_rel} phys_block={next_phys} err={e:?}"
+                    );
+                    return;
+                }
+                let nhdr = JournalHeaderS::from_disk_bytes(&cbuf[0..12]);
+                if nhdr.h_magic == JBD2_MAGIC
+                    && nhdr.h_blocktype == 1
+                    && nhdr.h_sequence == expect_seq
+                {
+                    desc_buf = cbuf;
+                    continue;
                 }
+                break;
             }
 
             if tags.is_empty() {
@@ -279,36 +558,9 @@ impl JBD2DEVSYSTEM {
                 break;
             }
 
-            // 3) 读取对应数量的 metadata 日志块
-            let mut meta_blocks: Vec<[u8; BLOCK_SIZE]> = Vec::new();
-            for (idx, _) in tags.iter().enumerate() {
-                // 下一个 journal 块（相对块号），注意处理回绕
-                advance_rel(&mut journal_rel);
-                let meta_phys = self.start_block + journal_rel;
-                let mut mbuf = [0u8; BLOCK_SIZE];
-                if let Err(e) = block_dev.read(&mut mbuf, meta_phys, 1) {
-                    debug!(
-                        "[JBD2 replay] read meta block failed: idx={idx} rel_block={journal_rel} phys_block={meta_phys} err={e:?}"
-                    );
-                    return;
-                }
-                debug!(
-                    "[JBD2 replay] tid={expect_seq} loaded meta_idx={idx} from rel_block={journal_rel} phys_block={meta_phys}"
-                );
-                meta_blocks.push(mbuf);
-            }
-
-            // 4) 读取 commit 块并验证
-            advance_rel(&mut journal_rel);
+            // 3) 验证 commit 块
             let commit_rel = journal_rel;
-            let commit_phys = self.start_block + commit_rel;
-            let mut cbuf = [0u8; BLOCK_SIZE];
-            if let Err(e) = block_dev.read(&mut cbuf, commit_phys, 1) {
-                debug!(
-                    "[JBD2 replay] read commit failed at rel_block={commit_rel} phys_block={commit_phys} err={e:?}"
-                );
-                return;
-            }
+            let commit_phys = self.log_block(commit_rel);
             let chdr = JournalHeaderS::from_disk_bytes(&cbuf[0..12]);
             debug!(
                 "[JBD2 replay] commit: rel_block={} phys_block={} h_magic=0x{:x} h_blocktype={} h_sequence={} expect_seq={}",
@@ -320,9 +572,22 @@ impl JBD2DEVSYSTEM {
                 break;
             }
 
+            // 4) commit 块带校验和时（异步提交必带），校验 descriptor + 日志数据块
+            let commit = CommitHeader::from_disk_bytes(&cbuf);
+            if commit.h_chksum_type == JBD2_CRC32_CHKSUM
+                && commit.h_chksum_size == JBD2_CRC32_CHKSUM_SIZE
+                && crc32_sum != commit.h_chksum[0]
+            {
+                warn!(
+                    "[JBD2 replay] tid={} commit checksum mismatch: expect=0x{:x} got=0x{:x}, stop replay",
+                    expect_seq, commit.h_chksum[0], crc32_sum
+                );
+                break;
+            }
+
             // 5) 真正重放：把每个 metadata 块写回主盘对应的 t_blocknr
             for (i, tag) in tags.iter().enumerate() {
-                let phys = tag.t_blocknr;
+                let phys = tag.blocknr();
                 let data = &mut meta_blocks[i];
 
                 //检查是否逃逸
@@ -339,9 +604,23 @@ impl JBD2DEVSYSTEM {
                     "[JBD2 replay] tid={expect_seq} apply meta_idx={i} to phys_block={phys} (journal data from idx={i})"
                 );
 
-                let _ = block_dev.write(data, phys, 1);
+                match home_dev.as_deref_mut() {
+                    Some(home) => {
+                        let _ = home.write(data, phys, 1);
+                    }
+                    None => {
+                        let _ = block_dev.write(data, phys, 1);
+                    }
+                }
+            }
+            match home_dev.as_deref_mut() {
+                Some(home) => {
+                    let _ = home.flush();
+                }
+                None => {
+                    let _ = block_dev.flush();
+                }
             }
-            let _ = block_dev.flush();
 
             // 6) 更新内存中的 journal superblock 状态
             expect_seq = expect_seq.wrapping_add(1);
@@ -409,22 +688,84 @@ pub fn dump_journal_inode<B: BlockDevice>(fs: &mut Ext4FileSystem, block_dev: &m
     debug!("Jouranl Inode:{indo:?}");
 }
 
+/// 按文件系统总块数选择默认日志块数（与 e2fsprogs ext2fs_default_journal_size 一致）
+/// 文件系统太小时返回 None，表示不创建日志
+pub fn default_journal_blocks(total_blocks: u64) -> Option<u32> {
+    let blocks = match total_blocks {
+        0..2048 => return None,
+        2048..32768 => 1024,
+        32768..262144 => 4096,
+        262144..524288 => 8192,
+        524288..4194304 => 16384,
+        4194304..8388608 => 32768,
+        8388608..16777216 => 65536,
+        16777216..33554432 => 131072,
+        _ => 262144,
+    };
+    Some(blocks)
+}
+
+/// 读出 journal inode 的块映射，返回（journal 超级块物理块号, 日志区块映射）
+/// 不使用 extent 的旧日志按物理连续处理，块映射为空
+pub fn journal_inode_block_map<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+) -> BlockDevResult<(u64, Vec<JournalRun>)> {
+    let ino = JOURNAL_FILE_INODE as u32;
+    let mut j_inode = fs.get_inode_by_num(block_dev, ino)?;
+    if !j_inode.have_extend_header_and_use_extend() {
+        let sb_block =
+            resolve_inode_block(block_dev, &mut j_inode, 0)?.ok_or(BlockDevError::Corrupted)?;
+        return Ok((sb_block, Vec::new()));
+    }
+
+    let total = j_inode.size().div_ceil(BLOCK_SIZE as u64).min(u32::MAX as u64) as u32;
+    let mut block_map = Vec::new();
+    let mut lbn = 0;
+    while lbn < total {
+        let (Some(phys), len) = map_blocks(fs, block_dev, ino, &j_inode, lbn)? else {
+            warn!("Journal inode has a hole at logical block {lbn}");
+            return Err(BlockDevError::Corrupted);
+        };
+        let len = len.clamp(1, total - lbn);
+        block_map.push((lbn, phys, len));
+        lbn += len;
+    }
+    let sb_block = block_map.first().map(|r| r.1).ok_or(BlockDevError::Corrupted)?;
+    Ok((sb_block, block_map))
+}
+
 ///jouranl目录创建 journal超级块写入
+/// 日志从中间块组开始分配，可以跨越多个块组，不要求物理连续（按 journal inode 的 extent 寻址），
+/// 并把 journal inode 的块映射备份到超级块 s_jnl_blocks
 pub fn create_journal_entry<B: BlockDevice>(
     fs: &mut Ext4FileSystem,
     block_dev: &mut Jbd2Dev<B>,
+    journal_blocks: u32,
 ) -> BlockDevResult<()> {
+    if journal_blocks < JBD2_MIN_JOURNAL_BLOCKS {
+        return Err(BlockDevError::InvalidInput);
+    }
     //分配新数据块放superblock
     let journal_inode_num = JOURNAL_FILE_INODE;
-    let free_block = fs
-        .alloc_blocks(block_dev, 4096)
-        .expect("No enough block can alloc out!");
+    let goal_group = fs.group_count / 2;
+    let free_block = fs.alloc_blocks_from_group(block_dev, goal_group, journal_blocks)?;
 
     // Ensure journal area starts clean: otherwise old image contents could look like valid
     // descriptor/commit blocks and replay would corrupt filesystem metadata.
-    let zero = [0u8; BLOCK_SIZE];
-    for &b in free_block.iter() {
-        block_dev.write_blocks(&zero, b as u32, 1, true)?;
+    let zero = vec![0u8; BLOCK_SIZE * 64];
+    let mut idx = 0;
+    while idx < free_block.len() {
+        let start = free_block[idx];
+        let mut run = 1;
+        while run < 64
+            && idx + run < free_block.len()
+            && free_block[idx + run] == start + run as u64
+        {
+            run += 1;
+        }
+        block_dev.write_blocks(&zero[..run * BLOCK_SIZE], start, run as u32, true)?;
+        idx += run;
     }
     //journal inode 额外参数
     let mut jour_inode = fs
@@ -433,18 +774,25 @@ pub fn create_journal_entry<B: BlockDevice>(
     jour_inode.write_extend_header();
     build_file_block_mapping(fs, &mut jour_inode, &free_block, block_dev);
     debug!("When create jouranl inode: iblock:{:?}", jour_inode.i_block);
-    let inode_size: usize = BLOCK_SIZE * free_block.len();
+    let inode_size = BLOCK_SIZE as u64 * free_block.len() as u64;
     //初始化 然后写入 journal inode
     fs.modify_inode(block_dev, journal_inode_num as u32, |inode| {
         inode.i_mode = Ext4Inode::S_IFREG | 0o600;
         inode.i_links_count = 1;
         inode.i_size_lo = inode_size as u32;
+        inode.i_size_high = (inode_size >> 32) as u32;
         inode.i_flags = Ext4Inode::EXT4_EXTENTS_FL;
         inode.i_blocks_lo = (inode_size / 512) as u32;
         inode.i_block = jour_inode.i_block;
     })
     .expect("Jouranl inode create faild!");
 
+    //备份 journal inode 的块映射，i_block 损坏时 fsck 可据此恢复
+    fs.superblock.s_jnl_blocks[..15].copy_from_slice(&jour_inode.i_block);
+    fs.superblock.s_jnl_blocks[15] = (inode_size >> 32) as u32;
+    fs.superblock.s_jnl_blocks[16] = inode_size as u32;
+    fs.superblock.s_jnl_backup_type = Ext4Superblock::EXT3_JNL_BACKUP_BLOCKS;
+
     let mut jbd2_sb = JournalSuperBllockS::default();
 
     jbd2_sb.s_maxlen = (free_block.len()-1) as u32; //修正块数 排除超级块
@@ -452,10 +800,453 @@ pub fn create_journal_entry<B: BlockDevice>(
     jbd2_sb.s_blocksize = BLOCK_SIZE_U32;
     jbd2_sb.s_sequence = 1;
     jbd2_sb.s_first = 1; //第一个日志块 相对于superblock
+    //块号超过 32 位的文件系统，descriptor 必须使用带高 32 位块号的 tag
+    if fs.superblock.blocks_count() > u32::MAX as u64 {
+        jbd2_sb.s_feature_incompat |= JBD2_FEATURE_INCOMPAT_64BIT;
+    }
 
     fs.datablock_cache.modify_new(free_block[0], |data| {
         jbd2_sb.to_disk_bytes(data);
     });
-    info!("Journal inode created!");
+    info!(
+        "Journal inode created: {} blocks starting at {}",
+        free_block.len(),
+        free_block[0]
+    );
     Ok(())
 }
+
+///格式化独立的外部日志设备
+/// 块0在1024偏移处写入带 JOURNAL_DEV 特性的 ext4 超级块，块1写入 journal 超级块，其后为日志区
+/// 返回日志设备UUID，文件系统通过 s_journal_uuid 引用它
+/// 只能按扇区访问的日志设备先用 `SectorDev` 包装
+pub fn mkfs_journal_device<J: BlockDevice + ?Sized>(journal_dev: &mut J) -> BlockDevResult<[u8; 16]> {
+    if journal_dev.block_size() != BLOCK_SIZE_U32 {
+        return Err(BlockDevError::InvalidBlockSize {
+            size: journal_dev.block_size() as usize,
+            expected: BLOCK_SIZE,
+        });
+    }
+    let total_blocks = journal_dev.total_blocks().min(u32::MAX as u64);
+    let log_start = EXTERNAL_JOURNAL_SB_BLOCK + 1;
+    if total_blocks <= log_start {
+        return Err(BlockDevError::NoSpace);
+    }
+    let maxlen = (total_blocks - log_start) as u32;
+
+    //与文件系统UUID区分开
+    let mut uuid = generate_uuid_8();
+    for (b, t) in uuid.iter_mut().zip(total_blocks.to_le_bytes()) {
+        *b ^= t;
+    }
+    uuid[15] ^= 0x4A;
+
+    //日志区清零，避免旧数据被当成合法的 descriptor/commit 块
+    let zero = vec![0u8; BLOCK_SIZE * 64];
+    let mut block = log_start;
+    while block < total_blocks {
+        let count = (total_blocks - block).min(64) as u32;
+        journal_dev.write(&zero[..count as usize * BLOCK_SIZE], block, count)?;
+        block += count as u64;
+    }
+
+    let sb = Ext4Superblock {
+        s_magic: EXT4_SUPER_MAGIC,
+        s_log_block_size: LOG_BLOCK_SIZE,
+        s_log_cluster_size: LOG_BLOCK_SIZE,
+        s_blocks_count_lo: (total_blocks & 0xFFFFFFFF) as u32,
+        s_blocks_count_hi: (total_blocks >> 32) as u32,
+        s_first_data_block: 0,
+        s_feature_compat: 0,
+        s_feature_incompat: Ext4Superblock::EXT4_FEATURE_INCOMPAT_JOURNAL_DEV,
+        s_feature_ro_compat: 0,
+        s_journal_inum: 0,
+        s_uuid: uuid,
+        ..Default::default()
+    };
+    let mut blk = [0u8; BLOCK_SIZE];
+    let offset = Ext4Superblock::SUPERBLOCK_OFFSET as usize;
+    sb.to_disk_bytes(&mut blk[offset..offset + Ext4Superblock::SUPERBLOCK_SIZE]);
+    journal_dev.write(&blk, 0, 1)?;
+
+    let jbd2_sb = JournalSuperBllockS {
+        s_maxlen: maxlen,
+        s_first: 1, //相对于journal超级块
+        s_start: 0,
+        s_sequence: 1,
+        s_uuid: uuid,
+        s_nr_users: 0, //文件系统格式化时登记
+        ..Default::default()
+    };
+    let mut blk = [0u8; BLOCK_SIZE];
+    jbd2_sb.to_disk_bytes(&mut blk);
+    journal_dev.write_fua(&blk, EXTERNAL_JOURNAL_SB_BLOCK, 1)?;
+
+    info!("External journal device formatted: {maxlen} log blocks");
+    Ok(uuid)
+}
+
+///读取外部日志设备的 journal 超级块
+pub fn read_external_journal_superblock<J: BlockDevice + ?Sized>(
+    journal_dev: &mut J,
+) -> BlockDevResult<JournalSuperBllockS> {
+    let mut blk = [0u8; BLOCK_SIZE];
+    journal_dev.read(&mut blk, EXTERNAL_JOURNAL_SB_BLOCK, 1)?;
+    Ok(JournalSuperBllockS::from_disk_bytes(&blk))
+}
+
+///把文件系统UUID登记到外部日志设备的用户列表
+pub fn register_journal_user<J: BlockDevice + ?Sized>(
+    journal_dev: &mut J,
+    fs_uuid: &[u8; 16],
+) -> BlockDevResult<()> {
+    let mut blk = [0u8; BLOCK_SIZE];
+    journal_dev.read(&mut blk, EXTERNAL_JOURNAL_SB_BLOCK, 1)?;
+    let mut jbd2_sb = JournalSuperBllockS::from_disk_bytes(&blk);
+    let nr_users = (jbd2_sb.s_nr_users as usize).min(48);
+    if jbd2_sb.s_users[..nr_users * 16]
+        .chunks(16)
+        .any(|user| user == fs_uuid)
+    {
+        return Ok(());
+    }
+    if nr_users >= 48 {
+        return Err(BlockDevError::NoSpace);
+    }
+    jbd2_sb.s_users[nr_users * 16..(nr_users + 1) * 16].copy_from_slice(fs_uuid);
+    jbd2_sb.s_nr_users = nr_users as u32 + 1;
+    jbd2_sb.to_disk_bytes(&mut blk);
+    journal_dev.write_fua(&blk, EXTERNAL_JOURNAL_SB_BLOCK, 1)
+}
+
+///挂载时校验外部日志设备并读出 journal 超级块
+/// 设备上的 ext4 超级块必须带 JOURNAL_DEV 特性，且UUID与文件系统的 s_journal_uuid 一致
+pub fn load_external_journal_superblock<J: BlockDevice + ?Sized>(
+    journal_dev: &mut J,
+    fs_sb: &Ext4Superblock,
+) -> Result<JournalSuperBllockS, RSEXT4Error> {
+    if journal_dev.block_size() != BLOCK_SIZE_U32 {
+        return Err(RSEXT4Error::UnsupportedFeature);
+    }
+    let mut blk = [0u8; BLOCK_SIZE];
+    journal_dev
+        .read(&mut blk, 0, 1)
+        .map_err(|_| RSEXT4Error::IoError)?;
+    let offset = Ext4Superblock::SUPERBLOCK_OFFSET as usize;
+    let dev_sb =
+        Ext4Superblock::from_disk_bytes(&blk[offset..offset + Ext4Superblock::SUPERBLOCK_SIZE]);
+    if dev_sb.s_magic != EXT4_SUPER_MAGIC
+        || !dev_sb.has_feature_incompat(Ext4Superblock::EXT4_FEATURE_INCOMPAT_JOURNAL_DEV)
+    {
+        return Err(RSEXT4Error::InvalidMagic);
+    }
+    if dev_sb.s_uuid != fs_sb.s_journal_uuid {
+        return Err(RSEXT4Error::JournalUuidMismatch);
+    }
+
+    let jbd2_sb =
+        read_external_journal_superblock(journal_dev).map_err(|_| RSEXT4Error::IoError)?;
+    if jbd2_sb.s_header.h_magic != JBD2_MAGIC {
+        return Err(RSEXT4Error::InvalidMagic);
+    }
+    if jbd2_sb.s_uuid != fs_sb.s_journal_uuid {
+        return Err(RSEXT4Error::JournalUuidMismatch);
+    }
+    Ok(jbd2_sb)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ext4_backend::memdev::{DevOp as Op, MemDev};
+
+    const JOURNAL_START: u64 = 10;
+    const HOME_BLOCK: u64 = 3;
+
+    fn new_system(dev: &mut MemDev) -> JBD2DEVSYSTEM {
+        let mut sb = JournalSuperBllockS::default();
+        sb.s_maxlen = 16;
+        let mut blk = [0u8; BLOCK_SIZE];
+        sb.to_disk_bytes(&mut blk);
+        dev.write(&blk, JOURNAL_START, 1).unwrap();
+        JBD2DEVSYSTEM {
+            start_block: JOURNAL_START,
+            block_map: Vec::new(),
+            max_len: sb.s_maxlen,
+            head: 0,
+            sequence: sb.s_sequence,
+            jbd2_super_block: sb,
+            commit_queue: Vec::new(),
+            fc_off: 0,
+        }
+    }
+
+    fn reload_system(dev: &mut MemDev) -> JBD2DEVSYSTEM {
+        let sb = JournalSuperBllockS::from_disk_bytes(&dev.block(JOURNAL_START));
+        JBD2DEVSYSTEM {
+            start_block: JOURNAL_START,
+            block_map: Vec::new(),
+            max_len: sb.s_maxlen,
+            head: 0,
+            sequence: sb.s_sequence,
+            jbd2_super_block: sb,
+            commit_queue: Vec::new(),
+            fc_off: 0,
+        }
+    }
+
+    #[test]
+    fn test_sync_commit_barrier_order() {
+        let mut dev = MemDev::new(32);
+        let mut sys = new_system(&mut dev);
+        sys.commit_queue.push(Jbd2Update(HOME_BLOCK, [0xAB; BLOCK_SIZE]));
+        dev.take_ops();
+
+        assert_eq!(sys.commit_transaction(&mut dev), Ok(true));
+        let ops = dev.take_ops();
+
+        // descriptor=11 data=12 commit=13
+        let pos = |op: Op| ops.iter().position(|o| *o == op).unwrap();
+        let data_w = pos(Op::Write(JOURNAL_START + 2));
+        let commit_w = pos(Op::Write(JOURNAL_START + 3));
+        let home_w = pos(Op::Write(HOME_BLOCK));
+        // 日志块与commit块之间有屏障
+        assert!(ops[data_w..commit_w].contains(&Op::Flush));
+        // commit块持久化之后才写回主盘
+        assert!(ops[commit_w..home_w].contains(&Op::Flush));
+        assert_eq!(dev.block(HOME_BLOCK)[0], 0xAB);
+        assert!(sys.commit_queue.is_empty());
+    }
+
+    #[test]
+    fn test_transaction_spans_multiple_descriptors() {
+        const HOME_START: u64 = 1300;
+        const COUNT: usize = 600;
+        let mut dev = MemDev::new(2000);
+        let mut sb = JournalSuperBllockS::default();
+        sb.s_maxlen = 1200;
+        let mut blk = [0u8; BLOCK_SIZE];
+        sb.to_disk_bytes(&mut blk);
+        dev.write(&blk, JOURNAL_START, 1).unwrap();
+        let mut sys = reload_system(&mut dev);
+        let per_desc = sys.jbd2_super_block.tags_per_descriptor();
+        assert!(COUNT > per_desc && COUNT <= sys.jbd2_super_block.max_transaction_blocks());
+
+        for i in 0..COUNT {
+            let mut data = [0u8; BLOCK_SIZE];
+            data[..8].copy_from_slice(&(i as u64 + 1).to_le_bytes());
+            sys.commit_queue.push(Jbd2Update(HOME_START + i as u64, data));
+        }
+        assert_eq!(sys.commit_transaction(&mut dev), Ok(true));
+
+        // 第二个 descriptor 紧跟在第一个 descriptor 描述的日志块之后
+        let second_desc = JOURNAL_START + 2 + per_desc as u64;
+        let hdr = JournalHeaderS::from_disk_bytes(&dev.block(second_desc));
+        assert_eq!((hdr.h_magic, hdr.h_blocktype), (JBD2_MAGIC, 1));
+        let commit_block = second_desc + 1 + (COUNT - per_desc) as u64;
+        let hdr = JournalHeaderS::from_disk_bytes(&dev.block(commit_block));
+        assert_eq!(hdr.h_blocktype, 2);
+
+        // 主盘写回丢失，重放把两个 descriptor 里的块都恢复
+        for i in 0..COUNT {
+            dev.modify_block(HOME_START + i as u64, |b| b.fill(0));
+        }
+        let mut replay_sys = reload_system(&mut dev);
+        replay_sys.replay(&mut dev);
+        for i in 0..COUNT {
+            assert_eq!(dev.block(HOME_START + i as u64)[..8], (i as u64 + 1).to_le_bytes());
+        }
+    }
+
+    #[test]
+    fn test_commit_and_replay_across_journal_runs() {
+        // 相对块 0..3 在 10..13，3..17 在 40..54
+        let block_map = alloc::vec![(0, JOURNAL_START, 3), (3, 40, 14)];
+        let mut dev = MemDev::new(64);
+        let mut sys = new_system(&mut dev);
+        sys.block_map = block_map.clone();
+        sys.commit_queue.push(Jbd2Update(HOME_BLOCK, [0xC3; BLOCK_SIZE]));
+        assert_eq!(sys.commit_transaction(&mut dev), Ok(true));
+
+        // descriptor=rel1 data=rel2 在第一段，commit=rel3 落在第二段开头
+        assert_eq!(dev.block(JOURNAL_START + 2)[0], 0xC3);
+        let hdr = JournalHeaderS::from_disk_bytes(&dev.block(40));
+        assert_eq!((hdr.h_magic, hdr.h_blocktype), (JBD2_MAGIC, 2));
+        assert!(dev.block(JOURNAL_START + 3).iter().all(|&b| b == 0));
+
+        dev.modify_block(HOME_BLOCK, |b| b.fill(0));
+        let mut replay_sys = reload_system(&mut dev);
+        replay_sys.block_map = block_map.clone();
+        replay_sys.replay(&mut dev);
+        assert_eq!(dev.block(HOME_BLOCK)[0], 0xC3);
+
+        let dump = scan_journal(&mut dev, JOURNAL_START, &block_map, JournalScanMode::Full).unwrap();
+        assert_eq!(dump.transactions.len(), 1);
+        assert_eq!(dump.transactions[0].commit_block, Some(3));
+    }
+
+    #[test]
+    fn test_async_commit_checksum_replay() {
+        let mut dev = MemDev::new(32);
+        let mut sys = new_system(&mut dev);
+        sys.set_async_commit(true);
+        sys.commit_queue.push(Jbd2Update(HOME_BLOCK, [0x5A; BLOCK_SIZE]));
+        dev.take_ops();
+        sys.commit_transaction(&mut dev).unwrap();
+        let ops = dev.take_ops();
+
+        // 异步提交：日志数据块与commit块之间没有flush
+        let data_w = ops.iter().position(|o| *o == Op::Write(JOURNAL_START + 2)).unwrap();
+        let commit_w = ops.iter().position(|o| *o == Op::Write(JOURNAL_START + 3)).unwrap();
+        assert!(!ops[data_w..commit_w].contains(&Op::Flush));
+        let commit = CommitHeader::from_disk_bytes(&dev.block(JOURNAL_START + 3));
+        assert_eq!(commit.h_chksum_type, JBD2_CRC32_CHKSUM);
+
+        // 模拟主盘写回丢失，重放恢复
+        dev.modify_block(HOME_BLOCK, |b| b.fill(0));
+        let mut replay_sys = reload_system(&mut dev);
+        assert!(replay_sys.is_async_commit());
+        replay_sys.replay(&mut dev);
+        assert_eq!(dev.block(HOME_BLOCK)[0], 0x5A);
+    }
+
+    #[test]
+    fn test_async_commit_torn_transaction_skipped() {
+        let mut dev = MemDev::new(32);
+        let mut sys = new_system(&mut dev);
+        sys.set_async_commit(true);
+        sys.commit_queue.push(Jbd2Update(HOME_BLOCK, [0x5A; BLOCK_SIZE]));
+        sys.commit_transaction(&mut dev).unwrap();
+
+        // 日志数据块未完整落盘，而commit块已经持久化
+        dev.modify_block(JOURNAL_START + 2, |b| b[100] ^= 0xFF);
+        dev.modify_block(HOME_BLOCK, |b| b.fill(0));
+        let mut replay_sys = reload_system(&mut dev);
+        replay_sys.replay(&mut dev);
+        assert_eq!(dev.block(HOME_BLOCK)[0], 0);
+        assert_eq!(replay_sys.jbd2_super_block.s_start, 0);
+    }
+
+    #[test]
+    fn test_external_journal_device_commit_and_replay() {
+        let mut journal_dev = MemDev::new(32);
+        let mut home_dev = MemDev::new(8);
+        let uuid = mkfs_journal_device(&mut journal_dev).unwrap();
+
+        let mut fs_sb = Ext4Superblock::default();
+        fs_sb.s_journal_inum = 0;
+        fs_sb.s_journal_uuid = uuid;
+        assert!(fs_sb.has_external_journal());
+        register_journal_user(&mut journal_dev, &fs_sb.s_uuid).unwrap();
+        let j_sb = load_external_journal_superblock(&mut journal_dev, &fs_sb).unwrap();
+        assert_eq!(j_sb.s_nr_users, 1);
+        assert_eq!(j_sb.s_maxlen, 30);
+
+        let mut sys = JBD2DEVSYSTEM {
+            start_block: EXTERNAL_JOURNAL_SB_BLOCK,
+            block_map: Vec::new(),
+            max_len: j_sb.s_maxlen,
+            head: 0,
+            sequence: j_sb.s_sequence,
+            jbd2_super_block: j_sb,
+            commit_queue: Vec::new(),
+            fc_off: 0,
+        };
+        sys.commit_queue.push(Jbd2Update(HOME_BLOCK, [0x77; BLOCK_SIZE]));
+        sys.commit_transaction_external(&mut journal_dev, &mut home_dev)
+            .unwrap();
+        // 日志只写日志设备，主盘只收到checkpoint
+        assert!(home_dev.take_ops().iter().all(|op| *op == Op::Write(HOME_BLOCK) || *op == Op::Flush));
+        assert_eq!(journal_dev.block(EXTERNAL_JOURNAL_SB_BLOCK + 2)[0], 0x77);
+
+        home_dev.modify_block(HOME_BLOCK, |b| b.fill(0));
+        let j_sb = load_external_journal_superblock(&mut journal_dev, &fs_sb).unwrap();
+        let mut replay_sys = JBD2DEVSYSTEM {
+            start_block: EXTERNAL_JOURNAL_SB_BLOCK,
+            block_map: Vec::new(),
+            max_len: j_sb.s_maxlen,
+            head: 0,
+            sequence: j_sb.s_sequence,
+            jbd2_super_block: j_sb,
+            commit_queue: Vec::new(),
+            fc_off: 0,
+        };
+        replay_sys.replay_external(&mut journal_dev, &mut home_dev);
+        assert_eq!(home_dev.block(HOME_BLOCK)[0], 0x77);
+
+        // UUID 不匹配的日志设备被拒绝
+        fs_sb.s_journal_uuid[0] ^= 0xFF;
+        assert_eq!(
+            load_external_journal_superblock(&mut journal_dev, &fs_sb).err(),
+            Some(RSEXT4Error::JournalUuidMismatch)
+        );
+    }
+
+    #[test]
+    fn test_mkfs_journal_size_and_placement() {
+        assert_eq!(default_journal_blocks(1024), None);
+        assert_eq!(default_journal_blocks(16 * 1024), Some(1024));
+        assert_eq!(default_journal_blocks(64 * 1024), Some(4096));
+
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, MemDev::new(64 * 1024), false);
+        let opts = MkfsOptions {
+            journal_blocks: Some(2048),
+        };
+        mkfs_with_options(&mut dev, &opts).unwrap();
+        let mut fs = mount(&mut dev).unwrap();
+        assert!(fs.superblock.has_journal());
+        assert!(fs.group_count > 1);
+
+        let mut ji = fs
+            .get_inode_by_num(&mut dev, JOURNAL_FILE_INODE as u32)
+            .unwrap();
+        assert_eq!(ji.size(), 2048 * BLOCK_SIZE as u64);
+        // s_jnl_blocks 备份了 journal inode 的块映射和大小
+        assert_eq!(
+            fs.superblock.s_jnl_backup_type,
+            Ext4Superblock::EXT3_JNL_BACKUP_BLOCKS
+        );
+        assert_eq!(fs.superblock.s_jnl_blocks[..15], ji.i_block);
+        assert_eq!(fs.superblock.s_jnl_blocks[16], 2048 * BLOCK_SIZE_U32);
+
+        // 日志在中间块组连续分配
+        let first = resolve_inode_block(&mut dev, &mut ji, 0).unwrap().unwrap();
+        let last = resolve_inode_block(&mut dev, &mut ji, 2047).unwrap().unwrap();
+        assert_eq!(last - first, 2047);
+        let (group, _) = fs.block_allocator.global_to_group(first as u64);
+        assert_eq!(group, fs.group_count / 2);
+
+        // 一个块组放不下的日志跨块组分配，按 journal inode 的 extent 寻址
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, MemDev::new(64 * 1024), false);
+        let opts = MkfsOptions {
+            journal_blocks: Some(32 * 1024),
+        };
+        mkfs_with_options(&mut dev, &opts).unwrap();
+        dev.set_journal_use(true);
+        let mut fs = mount(&mut dev).unwrap();
+        let ji = fs
+            .get_inode_by_num(&mut dev, JOURNAL_FILE_INODE as u32)
+            .unwrap();
+        assert_eq!(ji.size(), 32 * 1024 * BLOCK_SIZE as u64);
+        let (sb_block, block_map) = journal_inode_block_map(&mut fs, &mut dev).unwrap();
+        assert_eq!(Some(sb_block), fs.journal_sb_block_start);
+        assert!(block_map.len() > 1);
+        assert_eq!(block_map.iter().map(|r| r.2).sum::<u32>(), 32 * 1024);
+        let data: Vec<u8> = (0..4 * BLOCK_SIZE).map(|i| (i % 253) as u8).collect();
+        mkfile(&mut dev, &mut fs, "/f", Some(&data), None).unwrap();
+        umount(fs, &mut dev).unwrap();
+        let mut fs = mount(&mut dev).unwrap();
+        assert_eq!(read_file(&mut dev, &mut fs, "/f").unwrap().unwrap(), data);
+        umount(fs, &mut dev).unwrap();
+
+        // 显式大小过小被拒绝
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, MemDev::new(16 * 1024), false);
+        let opts = MkfsOptions {
+            journal_blocks: Some(512),
+        };
+        assert_eq!(
+            mkfs_with_options(&mut dev, &opts),
+            Err(BlockDevError::InvalidInput)
+        );
+    }
+}
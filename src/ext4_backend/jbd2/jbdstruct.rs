@@ -8,17 +8,75 @@ pub const JBD2_MAGIC: u32 = 0xC03B_3998u32; // jbd2 magic number (on-disk big-en
 pub const JOURNAL_BLOCK_COUNT: u32 = 32 * 1024 * 1024 / BLOCK_SIZE_U32;
 pub const JOURANL_ESCAPE: u16 = 0x1;
 pub const JBD2_FLAG_LAST_TAG: u16 = 0x8;
+/// 外部日志设备上 journal 超级块所在块号（块0放置带 JOURNAL_DEV 特性的 ext4 超级块）
+pub const EXTERNAL_JOURNAL_SB_BLOCK: u64 = 1;
+/// 日志块类型：descriptor 块
+pub const JBD2_DESCRIPTOR_BLOCK: u32 = 1;
+/// 日志块类型：commit 块
+pub const JBD2_COMMIT_BLOCK: u32 = 2;
+/// 日志块类型：revoke 块
+pub const JBD2_REVOKE_BLOCK: u32 = 5;
+/// 兼容特性：commit 块携带事务校验和
+pub const JBD2_FEATURE_COMPAT_CHECKSUM: u32 = 0x1;
+/// 不兼容特性：块号为 64 位（revoke 记录 8 字节，tag 带高 32 位块号）
+pub const JBD2_FEATURE_INCOMPAT_64BIT: u32 = 0x2;
+/// 不兼容特性：异步提交（commit 块不等待数据块落盘，依赖校验和判断事务完整性）
+pub const JBD2_FEATURE_INCOMPAT_ASYNC_COMMIT: u32 = 0x4;
+/// 不兼容特性：日志块带 crc32c 校验和，descriptor 末尾保留 tail
+pub const JBD2_FEATURE_INCOMPAT_CSUM_V2: u32 = 0x8;
+/// 不兼容特性：descriptor 使用 tag3 格式（16 字节，带 32 位校验和）
+pub const JBD2_FEATURE_INCOMPAT_CSUM_V3: u32 = 0x10;
+/// 不兼容特性：日志末尾保留 fast commit 区域
+pub const JBD2_FEATURE_INCOMPAT_FAST_COMMIT: u32 = 0x20;
+/// s_num_fc_blks 为0时使用的默认 fast commit 区域块数
+pub const JBD2_DEFAULT_FAST_COMMIT_BLOCKS: u32 = 256;
+/// 内部日志最少块数（与 e2fsprogs 相同）
+pub const JBD2_MIN_JOURNAL_BLOCKS: u32 = 1024;
+/// commit 块校验和类型：crc32
+pub const JBD2_CRC32_CHKSUM: u8 = 1;
+/// crc32 校验和字节数
+pub const JBD2_CRC32_CHKSUM_SIZE: u8 = 4;
+
+/// jbd2 commit 块使用的大端 crc32（多项式 0x04C11DB7，不反射，无最终异或）
+pub fn jbd2_crc32_be(seed: u32, data: &[u8]) -> u32 {
+    let mut crc = seed;
+    for &byte in data {
+        crc ^= (byte as u32) << 24;
+        for _ in 0..8 {
+            crc = if crc & 0x8000_0000 != 0 {
+                (crc << 1) ^ 0x04C1_1DB7
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
 #[repr(C)]
 ///（主物理块号，元数据内容）
 pub struct Jbd2Update(pub u64, pub [u8; BLOCK_SIZE]);
+/// 日志区的一段物理连续块：(起始相对块号, 物理块号, 块数)
+pub type JournalRun = (u32, u64, u32);
+
+/// 日志区相对块号换算成物理块号
+/// block_map 为空（外部日志设备、物理连续的日志）时按 sb_block + rel 计算
+pub fn journal_block(sb_block: u64, block_map: &[JournalRun], rel: u32) -> u64 {
+    block_map
+        .iter()
+        .find(|&&(first, _, len)| rel >= first && rel - first < len)
+        .map_or(sb_block + rel as u64, |&(first, phys, _)| phys + (rel - first) as u64)
+}
+
 #[repr(C)]
 pub struct JBD2DEVSYSTEM {
     pub jbd2_super_block: JournalSuperBllockS,
-    pub start_block: u32, // Journal 超级块 开始块号
-    pub max_len: u32,     // 日志总块数
+    pub start_block: u64, // Journal 超级块 开始块号
+    pub block_map: Vec<JournalRun>, // 日志区块映射，为空时从 start_block 起物理连续
+    pub max_len: u32,     // 常规日志可用的最大相对块号（不含 fast commit 区域）
     pub head: u32,        //commit游标(相对块号)
     pub sequence: u32,    //当前期待事务ID(验证和写commit用)
     pub commit_queue: Vec<Jbd2Update>, //事务缓存
+    pub fc_off: u32,      //当前事务已写入的 fast commit 块数
 }
 
 #[repr(C)]
@@ -85,9 +143,11 @@ pub struct JournalSuperBllockS {
     pub s_max_trans_data: u32,    // 0x4C __be32
     pub s_checksum_type: u8,      // 0x50 __u8
     pub s_padding2: [u8; 3],      // 0x51 padding
+    pub s_num_fc_blks: u32,       // 0x54 __be32: number of fast commit blocks
+    pub s_head: u32,              // 0x58 __be32: block number of head of log
 
     // padding up to 0xFC
-    pub s_padding: [u32; 42], // 0x54..0xFC
+    pub s_padding: [u32; 40], // 0x5C..0xFC
     pub s_checksum: u32,      // 0xFC __be32: checksum of superblock (with this zeroed)
 
     // 0x100 .. 0x3FF: list of users (16 * 48 = 768 bytes)
@@ -116,13 +176,64 @@ impl Default for JournalSuperBllockS {
             s_max_trans_data: JOURNAL_BLOCK_COUNT * 10,
             s_checksum_type: 0,
             s_padding2: [0; 3],
-            s_padding: [0; 42],
+            s_num_fc_blks: 0,
+            s_head: 0,
+            s_padding: [0; 40],
             s_checksum: 0,
             s_users: [0; 768],
         }
     }
 }
 
+impl JournalSuperBllockS {
+    /// 日志末尾保留给 fast commit 的块数，未启用时为0
+    pub fn num_fc_blocks(&self) -> u32 {
+        if self.s_feature_incompat & JBD2_FEATURE_INCOMPAT_FAST_COMMIT == 0 {
+            return 0;
+        }
+        match self.s_num_fc_blks {
+            0 => JBD2_DEFAULT_FAST_COMMIT_BLOCKS,
+            n => n,
+        }
+    }
+
+    /// 常规日志可用的块数，fast commit 区域紧随其后
+    pub fn log_maxlen(&self) -> u32 {
+        self.s_maxlen.saturating_sub(self.num_fc_blocks())
+    }
+
+    /// 一个 descriptor 块能容纳的 tag 数，带校验和的日志在块末尾保留 tail
+    pub fn tags_per_descriptor(&self) -> usize {
+        let csum = JBD2_FEATURE_INCOMPAT_CSUM_V2 | JBD2_FEATURE_INCOMPAT_CSUM_V3;
+        let tail = if self.s_feature_incompat & csum != 0 {
+            core::mem::size_of::<u32>()
+        } else {
+            0
+        };
+        (BLOCK_SIZE - JournalHeaderS::disk_size() - tail) / self.tag_bytes()
+    }
+
+    /// 一个事务最多能记录的块数：数据块、descriptor 块和 commit 块要一起放进日志区
+    pub fn max_transaction_blocks(&self) -> usize {
+        let per_desc = self.tags_per_descriptor();
+        // 留出 commit 块，以及最后一个不满的 descriptor 向上取整多出的一块
+        let usable = (self.log_maxlen() as usize).saturating_sub(2);
+        usable * per_desc / (per_desc + 1)
+    }
+
+    /// descriptor 中每个 tag 的字节数（与 Linux journal_tag_bytes 一致）
+    /// CSUM_V3 为 tag3；否则 64 位日志的 tag 带高 32 位块号
+    pub fn tag_bytes(&self) -> usize {
+        if self.s_feature_incompat & JBD2_FEATURE_INCOMPAT_CSUM_V3 != 0 {
+            16
+        } else if self.s_feature_incompat & JBD2_FEATURE_INCOMPAT_64BIT != 0 {
+            12
+        } else {
+            8
+        }
+    }
+}
+
 impl DiskFormat for JournalSuperBllockS {
     fn from_disk_bytes(bytes: &[u8]) -> Self {
         // expect 1024 bytes
@@ -152,11 +263,12 @@ impl DiskFormat for JournalSuperBllockS {
         let mut s_padding2 = [0u8; 3];
         s_padding2.copy_from_slice(&bytes[81..84]);
 
-        let mut s_padding = [0u32; 42];
-        let mut off = 84usize;
-        for i in 0..42 {
-            s_padding[i] = u32::from_be_bytes(bytes[off..off + 4].try_into().unwrap());
-            off += 4;
+        let s_num_fc_blks = u32::from_be_bytes(bytes[84..88].try_into().unwrap());
+        let s_head = u32::from_be_bytes(bytes[88..92].try_into().unwrap());
+
+        let mut s_padding = [0u32; 40];
+        for (word, raw) in s_padding.iter_mut().zip(bytes[92..0xFC].chunks_exact(4)) {
+            *word = u32::from_be_bytes(raw.try_into().unwrap());
         }
 
         let s_checksum = u32::from_be_bytes(bytes[0xFC..0x100].try_into().unwrap());
@@ -182,6 +294,8 @@ impl DiskFormat for JournalSuperBllockS {
             s_max_trans_data,
             s_checksum_type,
             s_padding2,
+            s_num_fc_blks,
+            s_head,
             s_padding,
             s_checksum,
             s_users,
@@ -212,8 +326,11 @@ impl DiskFormat for JournalSuperBllockS {
         bytes[80] = self.s_checksum_type;
         bytes[81..84].copy_from_slice(&self.s_padding2);
 
-        let mut off = 84usize;
-        for i in 0..42 {
+        bytes[84..88].copy_from_slice(&self.s_num_fc_blks.to_be_bytes());
+        bytes[88..92].copy_from_slice(&self.s_head.to_be_bytes());
+
+        let mut off = 92usize;
+        for i in 0..40 {
             bytes[off..off + 4].copy_from_slice(&self.s_padding[i].to_be_bytes());
             off += 4;
         }
@@ -288,6 +405,49 @@ impl DiskFormat for JouranlBlockTag3S {
     }
 }
 
+impl JouranlBlockTag3S {
+    /// 重放时写回的目标块号
+    pub fn blocknr(&self) -> u64 {
+        (self.t_blocknr_high as u64) << 32 | self.t_blocknr as u64
+    }
+
+    /// 按 tag_bytes 解析 descriptor 中的 tag，统一成 tag3 表示
+    pub fn from_tag_bytes(bytes: &[u8], tag_bytes: usize) -> Self {
+        if tag_bytes >= 16 {
+            return Self::from_disk_bytes(bytes);
+        }
+        let tag = JournalBlockTagS::from_disk_bytes(&bytes[0..8]);
+        let t_blocknr_high = if tag_bytes >= 12 {
+            u32::from_be_bytes(bytes[8..12].try_into().unwrap())
+        } else {
+            0
+        };
+        JouranlBlockTag3S {
+            t_blocknr: tag.t_blocknr,
+            t_flags: tag.t_flags as u32,
+            t_blocknr_high,
+            t_checksum: tag.t_checksum as u32,
+        }
+    }
+
+    /// 按 tag_bytes 写入 descriptor，8 字节格式只能表示 32 位块号
+    pub fn to_tag_bytes(&self, bytes: &mut [u8], tag_bytes: usize) {
+        if tag_bytes >= 16 {
+            self.to_disk_bytes(bytes);
+            return;
+        }
+        let tag = JournalBlockTagS {
+            t_blocknr: self.t_blocknr,
+            t_checksum: self.t_checksum as u16,
+            t_flags: self.t_flags as u16,
+        };
+        tag.to_disk_bytes(&mut bytes[0..8]);
+        if tag_bytes >= 12 {
+            bytes[8..12].copy_from_slice(&self.t_blocknr_high.to_be_bytes());
+        }
+    }
+}
+
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
 pub struct Jbd2JournalBlockTail {
@@ -409,7 +569,9 @@ mod tests {
             s_max_trans_data: 0,
             s_checksum_type: 4,
             s_padding2: [0; 3],
-            s_padding: [0xDEAD_BEEFu32; 42],
+            s_num_fc_blks: 64,
+            s_head: 0,
+            s_padding: [0xDEAD_BEEFu32; 40],
             s_checksum: 0xFEED_FACE,
             s_users: [0x55u8; 16 * 48],
         };
@@ -424,6 +586,7 @@ mod tests {
         assert_eq!(&buf[0x14..0x18], &sb.s_first.to_be_bytes());
         assert_eq!(&buf[0x18..0x1C], &sb.s_sequence.to_be_bytes());
         assert_eq!(&buf[0x1C..0x20], &sb.s_start.to_be_bytes());
+        assert_eq!(&buf[0x54..0x58], &sb.s_num_fc_blks.to_be_bytes());
         assert_eq!(&buf[0xFC..0x100], &sb.s_checksum.to_be_bytes());
 
         let parsed = JournalSuperBllockS::from_disk_bytes(&buf);
@@ -433,6 +596,7 @@ mod tests {
         assert_eq!(parsed.s_first, sb.s_first);
         assert_eq!(parsed.s_sequence, sb.s_sequence);
         assert_eq!(parsed.s_start, sb.s_start);
+        assert_eq!(parsed.s_num_fc_blks, sb.s_num_fc_blks);
         assert_eq!(parsed.s_checksum, sb.s_checksum);
         assert_eq!(&parsed.s_users[..], &sb.s_users[..]);
     }
@@ -503,6 +667,15 @@ mod tests {
         assert_eq!(parsed_rt.r_checksum, rt.r_checksum);
     }
 
+    #[test]
+    fn test_jbd2_crc32_be_known_value() {
+        // CRC-32/MPEG-2 参考值
+        assert_eq!(jbd2_crc32_be(!0, b"123456789"), 0x0376_E6E7);
+        // 分段计算与一次性计算一致
+        let part = jbd2_crc32_be(!0, b"1234");
+        assert_eq!(jbd2_crc32_be(part, b"56789"), 0x0376_E6E7);
+    }
+
     #[test]
     fn test_commit_header_roundtrip() {
         let hdr = JournalHeaderS {
@@ -0,0 +1,407 @@
+//! 日志检查（类似 debugfs logdump）
+//!
+//! 只读遍历日志区，返回事务号、descriptor tag 及目标块、revoke 记录和 commit 校验结果。
+//! 扫描不会重放也不会修改日志；是否重放由调用者决定：以关闭日志的方式挂载后检查，
+//! 再决定是否以日志模式重新挂载（挂载时自动重放）。
+
+use crate::ext4_backend::blockdev::*;
+use crate::ext4_backend::config::*;
+use crate::ext4_backend::endian::*;
+use crate::ext4_backend::error::*;
+use crate::ext4_backend::ext4::*;
+use crate::ext4_backend::jbd2::jbd2::*;
+use crate::ext4_backend::jbd2::jbdstruct::*;
+use alloc::vec::Vec;
+use log::debug;
+
+/// 扫描起点
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JournalScanMode {
+    /// 从 s_start 开始，只包含重放时会处理的事务（s_start 为 0 表示日志干净）
+    FromStart,
+    /// 从 s_first 开始扫描整个日志区，包含已经 checkpoint 的旧事务
+    Full,
+}
+
+/// descriptor 中的一个 tag
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JournalTagRecord {
+    /// 日志数据块的相对块号
+    pub log_block: u32,
+    /// 重放时写回的目标块号
+    pub target_block: u64,
+    /// tag 标志（逃逸、最后一个 tag 等）
+    pub flags: u16,
+}
+
+/// 一个事务的扫描结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JournalTransactionRecord {
+    /// 事务号
+    pub tid: u32,
+    /// 事务第一个块的相对块号
+    pub start_block: u32,
+    /// descriptor tag，按日志顺序
+    pub tags: Vec<JournalTagRecord>,
+    /// revoke 的块号
+    pub revoked: Vec<u64>,
+    /// commit 块的相对块号，None 表示事务没有提交（日志在此撕裂）
+    pub commit_block: Option<u32>,
+    /// commit 校验和是否匹配，None 表示 commit 块不带校验和
+    pub checksum_valid: Option<bool>,
+}
+
+impl JournalTransactionRecord {
+    fn new(tid: u32, start_block: u32) -> Self {
+        Self {
+            tid,
+            start_block,
+            tags: Vec::new(),
+            revoked: Vec::new(),
+            commit_block: None,
+            checksum_valid: None,
+        }
+    }
+
+    /// 重放时是否会被应用：已提交且校验和没有失败
+    pub fn is_committed(&self) -> bool {
+        self.commit_block.is_some() && self.checksum_valid != Some(false)
+    }
+}
+
+/// 扫描停止的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JournalScanEnd {
+    /// s_start 为 0，没有需要重放的事务
+    Clean,
+    /// 块不是日志块（magic 不匹配），正常的日志末尾
+    NoMagic { block: u32 },
+    /// 事务号不连续
+    SequenceMismatch { block: u32, expected: u32, found: u32 },
+    /// 未知的日志块类型
+    UnknownBlockType { block: u32, blocktype: u32 },
+    /// commit 校验和不匹配，重放会在这里停止
+    ChecksumMismatch { block: u32, tid: u32 },
+    /// 已经绕回扫描了整个日志区
+    Wrapped,
+}
+
+/// 日志扫描结果
+#[derive(Debug, Clone)]
+pub struct JournalDump {
+    /// journal 超级块
+    pub superblock: JournalSuperBllockS,
+    /// 扫描起点（相对块号）
+    pub scan_start: u32,
+    /// 按顺序扫描到的事务，最后一个可能没有提交
+    pub transactions: Vec<JournalTransactionRecord>,
+    /// 停止原因
+    pub end: JournalScanEnd,
+}
+
+impl JournalDump {
+    /// 重放时会被应用的事务
+    pub fn committed(&self) -> impl Iterator<Item = &JournalTransactionRecord> {
+        self.transactions.iter().take_while(|t| t.is_committed())
+    }
+}
+
+/// 解析 descriptor 块中的 tag，遇到 LAST_TAG 或全 0 填充结束
+/// tag_bytes 由 journal 超级块特性决定（JournalSuperBllockS::tag_bytes）
+pub fn parse_descriptor_tags(desc: &[u8], tag_bytes: usize) -> Vec<JouranlBlockTag3S> {
+    let mut tags = Vec::new();
+    let mut off = JournalHeaderS::disk_size();
+    while off + tag_bytes <= desc.len() {
+        // t_blocknr==0 是合法的，只有后面全为 0 时才视为结束
+        if desc[off..].iter().all(|b| *b == 0) {
+            break;
+        }
+        let tag = JouranlBlockTag3S::from_tag_bytes(&desc[off..off + tag_bytes], tag_bytes);
+        let last = tag.t_flags & JBD2_FLAG_LAST_TAG as u32 != 0;
+        tags.push(tag);
+        off += tag_bytes;
+        if last {
+            break;
+        }
+    }
+    tags
+}
+
+/// 解析 revoke 块：r_count 为包含头部的已用字节数，记录按 64 位特性为 4 或 8 字节
+fn parse_revoke_block(blk: &[u8], is_64bit: bool) -> Vec<u64> {
+    let hdr_len = JournalHeaderS::disk_size();
+    let r_count = u32::from_be_bytes(blk[hdr_len..hdr_len + 4].try_into().unwrap()) as usize;
+    let end = r_count.min(blk.len());
+    let rec_len = if is_64bit { 8 } else { 4 };
+    let mut out = Vec::new();
+    let mut off = hdr_len + 4;
+    while off + rec_len <= end {
+        let blocknr = if is_64bit {
+            u64::from_be_bytes(blk[off..off + 8].try_into().unwrap())
+        } else {
+            u32::from_be_bytes(blk[off..off + 4].try_into().unwrap()) as u64
+        };
+        out.push(blocknr);
+        off += rec_len;
+    }
+    out
+}
+
+/// 扫描日志设备上的日志区，sb_block 为 journal 超级块所在块号
+/// block_map 为日志区的块映射，为空时日志从 sb_block 起物理连续
+pub fn scan_journal<J: BlockDevice + ?Sized>(
+    journal_dev: &mut J,
+    sb_block: u64,
+    block_map: &[JournalRun],
+    mode: JournalScanMode,
+) -> BlockDevResult<JournalDump> {
+    let mut blk = [0u8; BLOCK_SIZE];
+    journal_dev.read(&mut blk, sb_block, 1)?;
+    let sb = JournalSuperBllockS::from_disk_bytes(&blk);
+    if sb.s_header.h_magic != JBD2_MAGIC {
+        return Err(BlockDevError::Corrupted);
+    }
+
+    let first_rel = sb.s_first;
+    let maxlen = sb.log_maxlen();
+    let last_rel = first_rel.saturating_add(maxlen.saturating_sub(1));
+    let advance = |rel: u32| if rel >= last_rel { first_rel } else { rel + 1 };
+    let is_64bit = sb.s_feature_incompat & JBD2_FEATURE_INCOMPAT_64BIT != 0;
+    let log_block = |rel: u32| journal_block(sb_block, block_map, rel);
+
+    let scan_start = match mode {
+        JournalScanMode::FromStart => sb.s_start,
+        JournalScanMode::Full => first_rel,
+    };
+    let mut dump = JournalDump {
+        superblock: sb,
+        scan_start,
+        transactions: Vec::new(),
+        end: JournalScanEnd::Clean,
+    };
+    if scan_start == 0 || maxlen == 0 {
+        return Ok(dump);
+    }
+
+    // 完整扫描时从第一个日志块的事务号开始
+    let mut expected = sb.s_sequence;
+    if mode == JournalScanMode::Full {
+        journal_dev.read(&mut blk, log_block(scan_start), 1)?;
+        let hdr = JournalHeaderS::from_disk_bytes(&blk);
+        if hdr.h_magic == JBD2_MAGIC {
+            expected = hdr.h_sequence;
+        }
+    }
+
+    let mut rel = scan_start;
+    let mut visited = 0u32;
+    let mut cur: Option<JournalTransactionRecord> = None;
+    let mut crc32_sum = !0u32;
+    dump.end = loop {
+        if visited >= maxlen {
+            break JournalScanEnd::Wrapped;
+        }
+        journal_dev.read(&mut blk, log_block(rel), 1)?;
+        let hdr = JournalHeaderS::from_disk_bytes(&blk);
+        if hdr.h_magic != JBD2_MAGIC {
+            break JournalScanEnd::NoMagic { block: rel };
+        }
+        if hdr.h_sequence != expected {
+            break JournalScanEnd::SequenceMismatch {
+                block: rel,
+                expected,
+                found: hdr.h_sequence,
+            };
+        }
+
+        match hdr.h_blocktype {
+            JBD2_DESCRIPTOR_BLOCK => {
+                let txn = cur.get_or_insert_with(|| JournalTransactionRecord::new(expected, rel));
+                crc32_sum = jbd2_crc32_be(crc32_sum, &blk);
+                let desc = blk;
+                for tag in parse_descriptor_tags(&desc, sb.tag_bytes()) {
+                    rel = advance(rel);
+                    visited += 1;
+                    journal_dev.read(&mut blk, log_block(rel), 1)?;
+                    crc32_sum = jbd2_crc32_be(crc32_sum, &blk);
+                    txn.tags.push(JournalTagRecord {
+                        log_block: rel,
+                        target_block: tag.blocknr(),
+                        flags: tag.t_flags as u16,
+                    });
+                }
+            }
+            JBD2_REVOKE_BLOCK => {
+                let txn = cur.get_or_insert_with(|| JournalTransactionRecord::new(expected, rel));
+                txn.revoked.extend(parse_revoke_block(&blk, is_64bit));
+            }
+            JBD2_COMMIT_BLOCK => {
+                let mut txn = cur
+                    .take()
+                    .unwrap_or_else(|| JournalTransactionRecord::new(expected, rel));
+                txn.commit_block = Some(rel);
+                let commit = CommitHeader::from_disk_bytes(&blk);
+                if commit.h_chksum_type == JBD2_CRC32_CHKSUM
+                    && commit.h_chksum_size == JBD2_CRC32_CHKSUM_SIZE
+                {
+                    txn.checksum_valid = Some(commit.h_chksum[0] == crc32_sum);
+                }
+                let bad_checksum = txn.checksum_valid == Some(false);
+                debug!(
+                    "[JBD2 logdump] tid={} start={} tags={} revoked={} commit={} checksum={:?}",
+                    txn.tid,
+                    txn.start_block,
+                    txn.tags.len(),
+                    txn.revoked.len(),
+                    rel,
+                    txn.checksum_valid
+                );
+                dump.transactions.push(txn);
+                if bad_checksum {
+                    break JournalScanEnd::ChecksumMismatch {
+                        block: rel,
+                        tid: expected,
+                    };
+                }
+                expected = expected.wrapping_add(1);
+                crc32_sum = !0u32;
+            }
+            blocktype => break JournalScanEnd::UnknownBlockType { block: rel, blocktype },
+        }
+        rel = advance(rel);
+        visited += 1;
+    };
+
+    // 没有 commit 的尾部事务同样返回，便于定位撕裂位置
+    if let Some(txn) = cur {
+        dump.transactions.push(txn);
+    }
+    Ok(dump)
+}
+
+/// 检查已挂载文件系统的日志（内部 journal inode 或外部日志设备）
+/// 如需在重放之前检查，先关闭日志挂载（Jbd2Dev::set_journal_use(false)）
+pub fn inspect_journal<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+    mode: JournalScanMode,
+) -> BlockDevResult<JournalDump> {
+    if !fs.superblock.has_journal() {
+        return Err(BlockDevError::Unsupported);
+    }
+    if fs.superblock.has_external_journal() {
+        let journal_dev = block_dev
+            .journal_device_mut()
+            .ok_or(BlockDevError::DeviceNotOpen)?;
+        return scan_journal(journal_dev, EXTERNAL_JOURNAL_SB_BLOCK, &[], mode);
+    }
+    let (sb_block, block_map) = journal_inode_block_map(fs, block_dev)?;
+    block_dev.scan_home_journal(sb_block, &block_map, mode)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+    use crate::ext4_backend::memdev::MemDev;
+
+    const JOURNAL_START: u64 = 10;
+
+    fn new_system(dev: &mut MemDev) -> JBD2DEVSYSTEM {
+        let mut sb = JournalSuperBllockS::default();
+        sb.s_maxlen = 16;
+        let mut blk = [0u8; BLOCK_SIZE];
+        sb.to_disk_bytes(&mut blk);
+        dev.write(&blk, JOURNAL_START, 1).unwrap();
+        JBD2DEVSYSTEM {
+            start_block: JOURNAL_START,
+            block_map: Vec::new(),
+            max_len: sb.s_maxlen,
+            head: 0,
+            sequence: sb.s_sequence,
+            jbd2_super_block: sb,
+            commit_queue: Vec::new(),
+            fc_off: 0,
+        }
+    }
+
+    fn commit(sys: &mut JBD2DEVSYSTEM, dev: &mut MemDev, targets: &[u64]) {
+        for &t in targets {
+            sys.commit_queue.push(Jbd2Update(t, [t as u8; BLOCK_SIZE]));
+        }
+        sys.commit_transaction(dev).unwrap();
+    }
+
+    #[test]
+    fn test_scan_transactions_and_torn_revoke() {
+        let mut dev = MemDev::new(32);
+        let mut sys = new_system(&mut dev);
+        commit(&mut sys, &mut dev, &[3, 4]);
+        commit(&mut sys, &mut dev, &[5]);
+
+        // 第三个事务只写了 revoke 块就断电
+        let mut revoke = [0u8; BLOCK_SIZE];
+        JournalHeaderS {
+            h_magic: JBD2_MAGIC,
+            h_blocktype: JBD2_REVOKE_BLOCK,
+            h_sequence: 3,
+        }
+        .to_disk_bytes(&mut revoke);
+        revoke[12..16].copy_from_slice(&24u32.to_be_bytes());
+        revoke[16..20].copy_from_slice(&77u32.to_be_bytes());
+        revoke[20..24].copy_from_slice(&88u32.to_be_bytes());
+        dev.write(&revoke, JOURNAL_START + 8, 1).unwrap();
+
+        let dump = scan_journal(&mut dev, JOURNAL_START, &[], JournalScanMode::FromStart).unwrap();
+        assert_eq!(dump.scan_start, 1);
+        assert_eq!(dump.transactions.len(), 3);
+
+        let t1 = &dump.transactions[0];
+        assert_eq!(t1.tid, 1);
+        assert_eq!(t1.start_block, 1);
+        assert_eq!(
+            t1.tags.iter().map(|t| (t.log_block, t.target_block)).collect::<Vec<_>>(),
+            vec![(2, 3), (3, 4)]
+        );
+        assert_eq!(t1.commit_block, Some(4));
+        assert_eq!(t1.checksum_valid, None);
+
+        let t2 = &dump.transactions[1];
+        assert_eq!(t2.tid, 2);
+        assert_eq!(t2.commit_block, Some(7));
+
+        let t3 = &dump.transactions[2];
+        assert_eq!(t3.tid, 3);
+        assert_eq!(t3.revoked, vec![77, 88]);
+        assert!(!t3.is_committed());
+        assert_eq!(dump.committed().count(), 2);
+        assert_eq!(dump.end, JournalScanEnd::NoMagic { block: 9 });
+    }
+
+    #[test]
+    fn test_scan_checksum_mismatch_and_full_scan() {
+        let mut dev = MemDev::new(32);
+        let mut sys = new_system(&mut dev);
+        sys.set_async_commit(true);
+        commit(&mut sys, &mut dev, &[3]);
+        commit(&mut sys, &mut dev, &[4]);
+        // 破坏第二个事务的日志数据块
+        dev.modify_block(JOURNAL_START + 5, |b| b[0] ^= 0xFF);
+
+        let dump = scan_journal(&mut dev, JOURNAL_START, &[], JournalScanMode::FromStart).unwrap();
+        assert_eq!(dump.transactions.len(), 2);
+        assert_eq!(dump.transactions[0].checksum_valid, Some(true));
+        assert_eq!(dump.transactions[1].checksum_valid, Some(false));
+        assert_eq!(dump.end, JournalScanEnd::ChecksumMismatch { block: 6, tid: 2 });
+
+        // 重放后日志干净，完整扫描仍能看到旧事务
+        sys.replay(&mut dev);
+        let dump = scan_journal(&mut dev, JOURNAL_START, &[], JournalScanMode::FromStart).unwrap();
+        assert_eq!(dump.end, JournalScanEnd::Clean);
+        assert!(dump.transactions.is_empty());
+        let dump = scan_journal(&mut dev, JOURNAL_START, &[], JournalScanMode::Full).unwrap();
+        assert_eq!(dump.scan_start, 1);
+        assert_eq!(dump.transactions[0].tid, 1);
+        assert_eq!(dump.committed().count(), 1);
+    }
+}
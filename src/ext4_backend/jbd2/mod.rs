@@ -1,3 +1,5 @@
 pub mod jbd2;
 ///jbd2 system
 pub mod jbdstruct;
+///日志检查（logdump）
+pub mod logdump;
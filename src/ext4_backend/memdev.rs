@@ -0,0 +1,138 @@
+//! 单元测试共用的内存块设备
+//!
+//! 克隆出的设备共享同一份存储：丢弃 Jbd2Dev 后用克隆重新挂载可以模拟掉电。
+//! 设备按顺序记录收到的写和 flush，供检查写入顺序和写入范围的测试使用。
+
+extern crate std;
+
+use crate::ext4_backend::blockdev::BlockDevice;
+use crate::ext4_backend::config::BLOCK_SIZE;
+use crate::ext4_backend::error::{BlockDevError, BlockDevResult};
+use alloc::vec;
+use alloc::vec::Vec;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// 设备收到的请求
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DevOp {
+    /// 写入一个块
+    Write(u64),
+    Flush,
+}
+
+struct MemDevInner {
+    data: Vec<u8>,
+    ops: Vec<DevOp>,
+}
+
+/// 按文件系统块寻址的内存设备，可以跨线程共享
+#[derive(Clone)]
+pub struct MemDev {
+    inner: Arc<Mutex<MemDevInner>>,
+    total_blocks: u64,
+}
+
+impl MemDev {
+    pub fn new(total_blocks: u64) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(MemDevInner {
+                data: vec![0u8; total_blocks as usize * BLOCK_SIZE],
+                ops: Vec::new(),
+            })),
+            total_blocks,
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, MemDevInner> {
+        self.inner.lock().unwrap()
+    }
+
+    fn range(&self, block_id: u64, count: u32) -> BlockDevResult<core::ops::Range<usize>> {
+        if block_id + count as u64 > self.total_blocks {
+            return Err(BlockDevError::BlockOutOfRange {
+                block_id,
+                max_blocks: self.total_blocks,
+            });
+        }
+        let start = block_id as usize * BLOCK_SIZE;
+        Ok(start..start + count as usize * BLOCK_SIZE)
+    }
+
+    /// 块 id 当前内容的拷贝
+    pub fn block(&self, id: u64) -> Vec<u8> {
+        let range = self.range(id, 1).unwrap();
+        self.lock().data[range].to_vec()
+    }
+
+    /// 直接修改块 id 的内容，不记录为写请求
+    pub fn modify_block(&self, id: u64, f: impl FnOnce(&mut [u8])) {
+        let range = self.range(id, 1).unwrap();
+        f(&mut self.lock().data[range]);
+    }
+
+    /// 取出并清空请求记录
+    pub fn take_ops(&self) -> Vec<DevOp> {
+        core::mem::take(&mut self.lock().ops)
+    }
+
+    /// 取出并清空请求记录，只返回排好序的写入块号
+    pub fn take_writes(&self) -> Vec<u64> {
+        let mut writes: Vec<u64> = self
+            .take_ops()
+            .into_iter()
+            .filter_map(|op| match op {
+                DevOp::Write(block) => Some(block),
+                DevOp::Flush => None,
+            })
+            .collect();
+        writes.sort_unstable();
+        writes
+    }
+}
+
+impl BlockDevice for MemDev {
+    fn write(&mut self, buffer: &[u8], block_id: u64, count: u32) -> BlockDevResult<()> {
+        let range = self.range(block_id, count)?;
+        if buffer.len() < range.len() {
+            return Err(BlockDevError::BufferTooSmall {
+                provided: buffer.len(),
+                required: range.len(),
+            });
+        }
+        let mut inner = self.lock();
+        let len = range.len();
+        inner.data[range].copy_from_slice(&buffer[..len]);
+        inner.ops.extend((block_id..block_id + count as u64).map(DevOp::Write));
+        Ok(())
+    }
+
+    fn read(&mut self, buffer: &mut [u8], block_id: u64, count: u32) -> BlockDevResult<()> {
+        let range = self.range(block_id, count)?;
+        if buffer.len() < range.len() {
+            return Err(BlockDevError::BufferTooSmall {
+                provided: buffer.len(),
+                required: range.len(),
+            });
+        }
+        let len = range.len();
+        buffer[..len].copy_from_slice(&self.lock().data[range]);
+        Ok(())
+    }
+
+    fn open(&mut self) -> BlockDevResult<()> {
+        Ok(())
+    }
+
+    fn close(&mut self) -> BlockDevResult<()> {
+        Ok(())
+    }
+
+    fn total_blocks(&self) -> u64 {
+        self.total_blocks
+    }
+
+    fn flush(&mut self) -> BlockDevResult<()> {
+        self.lock().ops.push(DevOp::Flush);
+        Ok(())
+    }
+}
@@ -1,22 +1,34 @@
 pub mod api;
+pub mod async_api;
+pub mod async_dev;
 pub mod bitmap;
 pub mod bitmap_cache;
 pub mod blockdev;
 pub mod blockgroup_description;
 pub mod bmalloc;
+pub mod buffer_cache;
+pub mod casefold;
 pub mod config;
 pub mod datablock_cache;
+pub mod dentry_cache;
 pub mod dir;
 pub mod disknode;
 pub mod endian;
 pub mod entries;
 pub mod ext4;
+pub mod extent_status;
 pub mod extents_tree;
+pub mod fast_commit;
 pub mod file;
 pub mod hashtree;
 pub mod error;
 pub mod inodetable_cache;
 pub mod jbd2;
 pub mod loopfile;
+#[cfg(test)]
+pub mod memdev;
+pub mod namei;
+pub mod readahead;
+pub mod shared;
 pub mod superblock;
 pub mod tool;
@@ -0,0 +1,404 @@
+//! 路径解析模块
+//!
+//! 按 namei 的方式逐级解析路径：处理 "." 和 ".."、中间分量上的符号链接、
+//! 相对路径（从给定目录或当前工作目录开始），以及最后一个分量是否跟随符号链接
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use crate::ext4_backend::blockdev::*;
+use crate::ext4_backend::casefold::*;
+use crate::ext4_backend::config::*;
+use crate::ext4_backend::dir::*;
+use crate::ext4_backend::disknode::*;
+use crate::ext4_backend::entries::*;
+use crate::ext4_backend::error::*;
+use crate::ext4_backend::ext4::*;
+use crate::ext4_backend::loopfile::*;
+
+/// 一次路径解析最多跟随的符号链接数，超过返回 SymlinkLoop（ELOOP）
+pub const MAX_SYMLINK_FOLLOWS: u32 = 40;
+
+/// 从目录 base_dir 开始解析 path，返回最终的 (inode 号, inode)
+///
+/// 绝对路径从根目录开始；follow_last 为 false 时最后一个分量是符号链接也不跟随，
+/// 但以 '/' 结尾的路径总是跟随并且要求结果是目录
+pub fn namei<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    device: &mut Jbd2Dev<B>,
+    base_dir: u32,
+    path: &str,
+    follow_last: bool,
+) -> BlockDevResult<(u32, Ext4Inode)> {
+    let base = (base_dir, fs.get_inode_by_num(device, base_dir)?);
+    let mut follows = 0;
+    walk(fs, device, base, path, follow_last, &mut follows)
+}
+
+/// 解析 path 的父目录，返回 (父目录 inode 号, 父目录 inode, 最后一个分量)
+///
+/// 最后一个分量原样返回、不做解析，供创建、删除和重命名使用；
+/// 没有最后一个分量的路径（例如 "/"）返回 InvalidInput
+pub fn namei_parent<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    device: &mut Jbd2Dev<B>,
+    base_dir: u32,
+    path: &str,
+) -> BlockDevResult<(u32, Ext4Inode, String)> {
+    let trimmed = path.trim_end_matches('/');
+    let (dir_part, name) = match trimmed.rfind('/') {
+        Some(pos) => (&trimmed[..pos + 1], &trimmed[pos + 1..]),
+        None => ("", trimmed),
+    };
+    if name.is_empty() {
+        return Err(BlockDevError::InvalidInput);
+    }
+    let (parent_ino, parent) = namei(fs, device, base_dir, dir_part, true)?;
+    if !parent.is_dir() {
+        return Err(BlockDevError::NotDirectory);
+    }
+    Ok((parent_ino, parent, name.to_string()))
+}
+
+/// 从当前工作目录解析路径，follow 决定最后一个分量上的符号链接是否跟随
+pub fn lookup_path<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    device: &mut Jbd2Dev<B>,
+    path: &str,
+    follow: bool,
+) -> BlockDevResult<(u32, Ext4Inode)> {
+    let cwd = fs.cwd;
+    namei(fs, device, cwd, path, follow)
+}
+
+/// 从当前工作目录解析路径的父目录和最后一个分量
+pub fn lookup_parent<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    device: &mut Jbd2Dev<B>,
+    path: &str,
+) -> BlockDevResult<(u32, Ext4Inode, String)> {
+    let cwd = fs.cwd;
+    namei_parent(fs, device, cwd, path)
+}
+
+/// 把"不存在"类错误转换成 None，其余错误（例如符号链接成环）继续向上返回
+pub fn found<T>(result: BlockDevResult<T>) -> BlockDevResult<Option<T>> {
+    match result {
+        Ok(v) => Ok(Some(v)),
+        Err(BlockDevError::NotFound | BlockDevError::NotDirectory) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// 读取目录 ".." 指向的父目录 inode 号
+pub fn dir_parent<B: BlockDevice>(
+    device: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    dir_ino: u32,
+) -> BlockDevResult<u32> {
+    let mut dir = fs.get_inode_by_num(device, dir_ino)?;
+    let phys = resolve_inode_block(device, &mut dir, 0)?.ok_or(BlockDevError::Corrupted)?;
+    let cached = fs.datablock_cache.get_or_load(device, phys as u64)?;
+    DirEntryIterator::new(&cached.data[..BLOCK_SIZE])
+        .find(|(entry, _)| entry.is_dotdot())
+        .map(|(entry, _)| entry.inode)
+        .ok_or(BlockDevError::Corrupted)
+}
+
+/// 读取符号链接的目标：不超过 60 字节的快速符号链接直接存放在 i_block 中
+pub fn read_symlink_target<B: BlockDevice>(
+    device: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    inode: &mut Ext4Inode,
+) -> BlockDevResult<Vec<u8>> {
+    let size = inode.size() as usize;
+    if size == 0 {
+        return Ok(Vec::new());
+    }
+
+    if size <= 60 {
+        let mut raw = [0u8; 60];
+        for (i, word) in inode.i_block.iter().take(15).enumerate() {
+            raw[i * 4..i * 4 + 4].copy_from_slice(&word.to_le_bytes());
+        }
+        return Ok(raw[..size].to_vec());
+    }
+
+    let block_bytes = BLOCK_SIZE;
+    let total_blocks = size.div_ceil(block_bytes);
+    let mut buf = Vec::with_capacity(size);
+
+    if inode.have_extend_header_and_use_extend() {
+        let blocks = resolve_inode_block_allextend(fs, device, inode)?;
+        for &phys in blocks.values() {
+            let cached = fs.datablock_cache.get_or_load(device, phys)?;
+            let data = &cached.data[..block_bytes];
+            buf.extend_from_slice(data);
+            if buf.len() >= size {
+                break;
+            }
+        }
+    } else {
+        for lbn in 0..total_blocks {
+            let phys = match resolve_inode_block(device, inode, lbn as u32)? {
+                Some(b) => b,
+                None => break,
+            };
+            let cached = fs.datablock_cache.get_or_load(device, phys as u64)?;
+            let data = &cached.data[..block_bytes];
+            buf.extend_from_slice(data);
+        }
+    }
+
+    buf.truncate(size);
+    Ok(buf)
+}
+
+/// 在目录 dir_ino 中查找 name，先查 dentry 缓存，未命中时读目录并把结果（包括不存在）缓存下来
+fn lookup_child<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    device: &mut Jbd2Dev<B>,
+    dir_ino: u32,
+    dir: &Ext4Inode,
+    name: &[u8],
+) -> BlockDevResult<Option<u32>> {
+    let key = DirNameKey::new(&fs.superblock, dir, name);
+    if let Some(cached) = fs.dentry_cache.lookup(dir_ino, key.hash_name()) {
+        return Ok(cached);
+    }
+    let child = find_dir_entry(fs, device, dir, name)?.map(|(ino, _, _)| ino);
+    fs.dentry_cache.insert(dir_ino, key.hash_name(), child);
+    Ok(child)
+}
+
+/// 从 start 目录出发逐个分量解析 path，follows 记录整个解析过程已经跟随的符号链接数
+fn walk<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    device: &mut Jbd2Dev<B>,
+    start: (u32, Ext4Inode),
+    path: &str,
+    follow_last: bool,
+    follows: &mut u32,
+) -> BlockDevResult<(u32, Ext4Inode)> {
+    let (mut ino, mut inode) = if path.starts_with('/') {
+        (fs.root_inode, fs.get_root(device)?)
+    } else {
+        start
+    };
+
+    let must_be_dir = path.ends_with('/');
+    let components: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+
+    for (idx, name) in components.iter().enumerate() {
+        if !inode.is_dir() {
+            return Err(BlockDevError::NotDirectory);
+        }
+        match *name {
+            "." => continue,
+            ".." => {
+                // 根目录的 ".." 仍然是根目录
+                if ino != fs.root_inode {
+                    ino = dir_parent(device, fs, ino)?;
+                    inode = fs.get_inode_by_num(device, ino)?;
+                }
+                continue;
+            }
+            _ => {}
+        }
+        if name.len() > Ext4DirEntry2::MAX_NAME_LEN as usize {
+            return Err(BlockDevError::InvalidInput);
+        }
+
+        let child_ino = lookup_child(fs, device, ino, &inode, name.as_bytes())?
+            .ok_or(BlockDevError::NotFound)?;
+        let mut child = fs.get_inode_by_num(device, child_ino)?;
+
+        let is_last = idx + 1 == components.len();
+        if child.is_symlink() && (!is_last || follow_last || must_be_dir) {
+            *follows += 1;
+            if *follows > MAX_SYMLINK_FOLLOWS {
+                return Err(BlockDevError::SymlinkLoop);
+            }
+            let target = read_symlink_target(device, fs, &mut child)?;
+            let target = core::str::from_utf8(&target).map_err(|_| BlockDevError::Corrupted)?;
+            if target.is_empty() {
+                return Err(BlockDevError::NotFound);
+            }
+            // 相对目标从符号链接所在的目录开始解析
+            (ino, inode) = walk(fs, device, (ino, inode), target, true, follows)?;
+        } else {
+            ino = child_ino;
+            inode = child;
+        }
+    }
+
+    if must_be_dir && !inode.is_dir() {
+        return Err(BlockDevError::NotDirectory);
+    }
+    Ok((ino, inode))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ext4_backend::api::*;
+    use crate::ext4_backend::file::*;
+    use crate::ext4_backend::memdev::MemDev;
+
+    fn setup() -> (Jbd2Dev<MemDev>, Ext4FileSystem) {
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, MemDev::new(16 * 1024), false);
+        mkfs(&mut dev).unwrap();
+        let mut fs = mount(&mut dev).unwrap();
+        mkdir(&mut dev, &mut fs, "/a/b/c");
+        mkfile(&mut dev, &mut fs, "/a/b/f", Some(b"hello"), None);
+        (dev, fs)
+    }
+
+    fn ino_of(dev: &mut Jbd2Dev<MemDev>, fs: &mut Ext4FileSystem, path: &str) -> u32 {
+        lookup_path(fs, dev, path, false).unwrap().0
+    }
+
+    #[test]
+    fn test_dot_and_dotdot() {
+        let (mut dev, mut fs) = setup();
+        let b = ino_of(&mut dev, &mut fs, "/a/b");
+        let a = ino_of(&mut dev, &mut fs, "/a");
+
+        assert_eq!(ino_of(&mut dev, &mut fs, "/a/./b/."), b);
+        assert_eq!(ino_of(&mut dev, &mut fs, "/a/b/c/.."), b);
+        assert_eq!(ino_of(&mut dev, &mut fs, "/a/b/c/../../"), a);
+        // 根目录的 ".." 仍然是根目录
+        assert_eq!(ino_of(&mut dev, &mut fs, "/../../a"), a);
+        assert_eq!(ino_of(&mut dev, &mut fs, "/.."), fs.root_inode);
+
+        // 穿过普通文件或者以 '/' 结尾指向普通文件都是 ENOTDIR
+        assert_eq!(
+            lookup_path(&mut fs, &mut dev, "/a/b/f/x", false).unwrap_err(),
+            BlockDevError::NotDirectory
+        );
+        assert_eq!(
+            lookup_path(&mut fs, &mut dev, "/a/b/f/", false).unwrap_err(),
+            BlockDevError::NotDirectory
+        );
+        assert_eq!(
+            lookup_path(&mut fs, &mut dev, "/a/missing", false).unwrap_err(),
+            BlockDevError::NotFound
+        );
+    }
+
+    #[test]
+    fn test_relative_paths_and_cwd() {
+        let (mut dev, mut fs) = setup();
+        let b = ino_of(&mut dev, &mut fs, "/a/b");
+        let f = ino_of(&mut dev, &mut fs, "/a/b/f");
+
+        chdir(&mut dev, &mut fs, "a/b").unwrap();
+        assert_eq!(fs.cwd, b);
+        assert_eq!(ino_of(&mut dev, &mut fs, "f"), f);
+        assert_eq!(ino_of(&mut dev, &mut fs, "./c/../f"), f);
+        assert_eq!(ino_of(&mut dev, &mut fs, "."), b);
+        assert_eq!(
+            chdir(&mut dev, &mut fs, "f").unwrap_err(),
+            BlockDevError::NotDirectory
+        );
+
+        // 相对路径创建的文件落在当前工作目录下
+        mkfile(&mut dev, &mut fs, "g", Some(b"rel"), None).unwrap();
+        assert_eq!(read_file(&mut dev, &mut fs, "/a/b/g").unwrap().unwrap(), b"rel");
+        let (parent, _, name) = lookup_parent(&mut fs, &mut dev, "c/new").unwrap();
+        assert_eq!(parent, ino_of(&mut dev, &mut fs, "/a/b/c"));
+        assert_eq!(name, "new");
+
+        let a = ino_of(&mut dev, &mut fs, "/a");
+        chdir(&mut dev, &mut fs, "..").unwrap();
+        assert_eq!(fs.cwd, a);
+    }
+
+    #[test]
+    fn test_symlink_follow_and_loops() {
+        let (mut dev, mut fs) = setup();
+        let b = ino_of(&mut dev, &mut fs, "/a/b");
+        let f = ino_of(&mut dev, &mut fs, "/a/b/f");
+
+        create_symbol_link(&mut dev, &mut fs, "/a/b", "/lb").unwrap();
+        create_symbol_link(&mut dev, &mut fs, "f", "/a/b/rel").unwrap();
+
+        // 中间分量上的符号链接总是跟随，最后一个分量由 follow 决定
+        assert_eq!(ino_of(&mut dev, &mut fs, "/lb/f"), f);
+        let (lb, lb_inode) = lookup_path(&mut fs, &mut dev, "/lb", false).unwrap();
+        assert!(lb_inode.is_symlink());
+        assert_ne!(lb, b);
+        assert_eq!(lookup_path(&mut fs, &mut dev, "/lb", true).unwrap().0, b);
+        assert_eq!(ino_of(&mut dev, &mut fs, "/lb/"), b);
+        // 相对目标从链接所在目录解析
+        assert_eq!(lookup_path(&mut fs, &mut dev, "/a/b/rel", true).unwrap().0, f);
+        assert_eq!(read_file(&mut dev, &mut fs, "/lb/rel").unwrap().unwrap(), b"hello");
+
+        // 两个互相指向的链接：跟随时报 ELOOP，不跟随时仍能拿到链接本身
+        create_symbol_link(&mut dev, &mut fs, "/", "/loop1").unwrap();
+        create_symbol_link(&mut dev, &mut fs, "/", "/loop2").unwrap();
+        rewrite_link(&mut dev, &mut fs, "/loop1", "/loop2");
+        rewrite_link(&mut dev, &mut fs, "/loop2", "/loop1");
+        assert_eq!(
+            lookup_path(&mut fs, &mut dev, "/loop1", true).unwrap_err(),
+            BlockDevError::SymlinkLoop
+        );
+        assert_eq!(
+            lookup_path(&mut fs, &mut dev, "/loop1/x", false).unwrap_err(),
+            BlockDevError::SymlinkLoop
+        );
+        assert!(lookup_path(&mut fs, &mut dev, "/loop1", false).unwrap().1.is_symlink());
+        assert!(matches!(
+            found(lookup_path(&mut fs, &mut dev, "/loop2", true)),
+            Err(BlockDevError::SymlinkLoop)
+        ));
+    }
+
+    #[test]
+    fn test_dentry_cache_invalidation() {
+        let (mut dev, mut fs) = setup();
+        let b = ino_of(&mut dev, &mut fs, "/a/b");
+        let f = ino_of(&mut dev, &mut fs, "/a/b/f");
+        assert_eq!(fs.dentry_cache.lookup(b, b"f"), Some(Some(f)));
+
+        // 负缓存条目在创建后失效
+        assert!(found(lookup_path(&mut fs, &mut dev, "/a/b/new", false)).unwrap().is_none());
+        assert_eq!(fs.dentry_cache.lookup(b, b"new"), Some(None));
+        let (new, _) = mkfile_with_ino(&mut dev, &mut fs, "/a/b/new", None, None).unwrap();
+        assert_eq!(ino_of(&mut dev, &mut fs, "/a/b/new"), new);
+
+        rename(&mut dev, &mut fs, "/a/b/f", "/a/b/g").unwrap();
+        assert!(found(lookup_path(&mut fs, &mut dev, "/a/b/f", false)).unwrap().is_none());
+        assert_eq!(ino_of(&mut dev, &mut fs, "/a/b/g"), f);
+
+        link(&mut fs, &mut dev, "/a/b/f", "/a/b/g");
+        assert_eq!(ino_of(&mut dev, &mut fs, "/a/b/f"), f);
+        unlink(&mut fs, &mut dev, "/a/b/f");
+        assert!(found(lookup_path(&mut fs, &mut dev, "/a/b/f", false)).unwrap().is_none());
+
+        // 目录被删掉后 inode 号会被复用，旧目录下的缓存条目不能再命中
+        let c = ino_of(&mut dev, &mut fs, "/a/b/c");
+        mkfile(&mut dev, &mut fs, "/a/b/c/x", None, None);
+        assert!(lookup_path(&mut fs, &mut dev, "/a/b/c/x", false).is_ok());
+        delete_dir(&mut fs, &mut dev, "/a/b/c");
+        assert!(found(lookup_path(&mut fs, &mut dev, "/a/b/c", false)).unwrap().is_none());
+        assert_eq!(fs.dentry_cache.lookup(c, b"x"), None);
+        let (c2, _) = mkdir_with_ino(&mut dev, &mut fs, "/a/b/c").unwrap();
+        assert!(found(lookup_path(&mut fs, &mut dev, "/a/b/c/x", false)).unwrap().is_none());
+        assert_eq!(ino_of(&mut dev, &mut fs, "/a/b/c"), c2);
+    }
+
+    /// 直接改写快速符号链接的目标，用来构造 create_symbol_link 不允许的悬空环
+    fn rewrite_link(dev: &mut Jbd2Dev<MemDev>, fs: &mut Ext4FileSystem, link: &str, target: &str) {
+        let (ino, _) = lookup_path(fs, dev, link, false).unwrap();
+        let mut raw = [0u8; 60];
+        raw[..target.len()].copy_from_slice(target.as_bytes());
+        fs.modify_inode(dev, ino, |inode| {
+            for (i, word) in inode.i_block.iter_mut().enumerate() {
+                *word = u32::from_le_bytes(raw[i * 4..i * 4 + 4].try_into().unwrap());
+            }
+            inode.i_size_lo = target.len() as u32;
+        })
+        .unwrap();
+    }
+}
@@ -0,0 +1,411 @@
+//! 文件读取与顺序预读
+//!
+//! 按 extent 映射把物理上连续的块合并成一次 read_blocks 读入数据块缓存。
+//! 每个打开的文件记录上次读到的位置，顺序读用完上一批预读时窗口翻倍，随机访问时关闭预读。
+//! Direct 模式下完整的块直接读到调用者的缓冲区，不经过缓存
+
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use crate::ext4_backend::blockdev::*;
+use crate::ext4_backend::config::*;
+use crate::ext4_backend::disknode::*;
+use crate::ext4_backend::error::*;
+use crate::ext4_backend::ext4::*;
+use crate::ext4_backend::extent_status::*;
+
+/// Direct 模式下一次读写设备的最大块数
+pub const MAX_DIRECT_RUN: u64 = 1024;
+
+/// 打开文件的预读状态
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Readahead {
+    /// 当前预读窗口（块），0 表示未在顺序读
+    window: u32,
+    /// 顺序读时下一次读取应当开始的逻辑块
+    next_lbn: u64,
+    /// 已经预读到的逻辑块（不含）
+    ra_end: u64,
+}
+
+impl Readahead {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 当前预读窗口（块）
+    pub fn window(&self) -> u32 {
+        self.window
+    }
+
+    /// 记录一次 [start_off, end_off) 的读取，返回需要预读的逻辑块范围
+    pub fn advance(&mut self, start_off: u64, end_off: u64, file_blocks: u64) -> Range<u64> {
+        let block_bytes = BLOCK_SIZE as u64;
+        let start_lbn = start_off / block_bytes;
+        let read_end = end_off.div_ceil(block_bytes);
+
+        if start_lbn == self.next_lbn {
+            if self.window == 0 {
+                self.window = READAHEAD_MIN_BLOCKS;
+            }
+        } else {
+            // 随机访问：关闭预读，重新开始探测
+            self.window = 0;
+            self.ra_end = 0;
+        }
+        self.next_lbn = end_off / block_bytes;
+
+        if self.window == 0 {
+            return 0..0;
+        }
+        // 已经预读的部分还剩不到半个窗口时再发起下一批
+        let ahead = self.ra_end.saturating_sub(read_end);
+        if ahead > (self.window / 2) as u64 {
+            return 0..0;
+        }
+        // 上一批预读被用到了，窗口翻倍
+        if self.ra_end != 0 {
+            self.window = self.window.saturating_mul(2).min(READAHEAD_MAX_BLOCKS);
+        }
+        let ra_start = self.ra_end.max(read_end);
+        let ra_end = (read_end + self.window as u64).min(file_blocks);
+        if ra_start >= ra_end {
+            return 0..0;
+        }
+        self.ra_end = ra_end;
+        ra_start..ra_end
+    }
+}
+
+/// 读取方式
+pub enum ReadMode<'a> {
+    /// 经过数据块缓存
+    Cached,
+    /// 经过数据块缓存，按顺序读的情况顺带预读后面的块
+    Readahead(&'a mut Readahead),
+    /// 完整的块不经过缓存直接读到调用者的缓冲区（类似 O_DIRECT），不足一块的首尾部分仍走缓存
+    Direct,
+}
+
+/// 读取文件 [start_off, end_off) 的内容（不超过文件末尾），空洞补零
+/// 传入 ra 时按顺序读的情况顺带预读后面的块
+pub fn read_mapped<B: BlockDevice>(
+    dev: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    ino: u32,
+    inode: &Ext4Inode,
+    start_off: u64,
+    end_off: u64,
+    ra: Option<&mut Readahead>,
+) -> BlockDevResult<Vec<u8>> {
+    let len = end_off.min(inode.size()).saturating_sub(start_off) as usize;
+    let mut out = alloc::vec![0u8; len];
+    let mode = match ra {
+        Some(ra) => ReadMode::Readahead(ra),
+        None => ReadMode::Cached,
+    };
+    let n = read_mapped_into(dev, fs, ino, inode, start_off, &mut out, mode)?;
+    out.truncate(n);
+    Ok(out)
+}
+
+/// 从 start_off 开始读满 out（不超过文件末尾），空洞补零，返回读到的字节数
+/// 逻辑块到物理块的映射经过 extent 状态缓存按段查找，不展开整个文件
+pub fn read_mapped_into<B: BlockDevice>(
+    dev: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    ino: u32,
+    inode: &Ext4Inode,
+    start_off: u64,
+    out: &mut [u8],
+    mode: ReadMode<'_>,
+) -> BlockDevResult<usize> {
+    let block_bytes = BLOCK_SIZE as u64;
+    let file_size = inode.size();
+    let end_off = start_off.saturating_add(out.len() as u64).min(file_size);
+    if start_off >= end_off {
+        return Ok(0);
+    }
+    let start_lbn = start_off / block_bytes;
+    let read_end = end_off.div_ceil(block_bytes);
+    let direct = matches!(mode, ReadMode::Direct);
+    let prefetch = match mode {
+        ReadMode::Readahead(ra) => ra.advance(start_off, end_off, file_size.div_ceil(block_bytes)),
+        _ => 0..0,
+    };
+    let load_end = read_end.max(prefetch.end);
+    let run_limit = if direct { u64::MAX } else { fs.datablock_cache.run_limit() };
+    // 逻辑块 lbn 在 out 中的起始位置
+    let out_pos = |lbn: u64| ((lbn * block_bytes).max(start_off) - start_off) as usize;
+
+    let mut lbn = start_lbn;
+    while lbn < load_end {
+        let (phys, len) = map_blocks(fs, dev, ino, inode, lbn as u32)?;
+        let mut n = (len as u64).min(load_end - lbn);
+        let Some(phys) = phys else {
+            if lbn < read_end {
+                let to = ((lbn + n) * block_bytes).min(end_off) - start_off;
+                out[out_pos(lbn)..to as usize].fill(0);
+            }
+            lbn += n;
+            continue;
+        };
+
+        // 物理上首尾相接的下一个 extent 并入同一段
+        while n < run_limit && lbn + n < load_end {
+            match map_blocks(fs, dev, ino, inode, (lbn + n) as u32)? {
+                (Some(next), len) if next == phys + n => n += (len as u64).min(load_end - lbn - n),
+                _ => break,
+            }
+        }
+        let n = n.min(run_limit);
+
+        let wanted = n.min(read_end.saturating_sub(lbn));
+        if direct {
+            // 完整且不在缓存中的块直接读到 out；首尾不足一块的部分以及缓存里可能有更新内容的块从缓存复制
+            let full = |lbn: u64| {
+                let (from, to) = block_span(lbn, start_off, end_off);
+                to - from == BLOCK_SIZE
+            };
+            let mut i = 0;
+            while i < wanted {
+                let pos = out_pos(lbn + i);
+                if !full(lbn + i) || fs.datablock_cache.get(phys + i).is_some() {
+                    let (from, to) = block_span(lbn + i, start_off, end_off);
+                    let cached = fs.datablock_cache.get_or_load(dev, phys + i)?;
+                    out[pos..pos + to - from].copy_from_slice(&cached.data[from..to]);
+                    i += 1;
+                    continue;
+                }
+                let mut k = i + 1;
+                while k < wanted
+                    && k - i < MAX_DIRECT_RUN
+                    && full(lbn + k)
+                    && fs.datablock_cache.get(phys + k).is_none()
+                {
+                    k += 1;
+                }
+                let count = (k - i) as usize;
+                dev.read_blocks(&mut out[pos..pos + count * BLOCK_SIZE], phys + i, count as u32)?;
+                i = k;
+            }
+        } else {
+            fs.datablock_cache.load_run(dev, phys, n)?;
+            for i in 0..wanted {
+                let (from, to) = block_span(lbn + i, start_off, end_off);
+                let pos = out_pos(lbn + i);
+                let cached = fs.datablock_cache.get_or_load(dev, phys + i)?;
+                out[pos..pos + to - from].copy_from_slice(&cached.data[from..to]);
+            }
+        }
+        lbn += n;
+    }
+
+    Ok((end_off - start_off) as usize)
+}
+
+/// 逻辑块 lbn 中落在 [start_off, end_off) 内的字节范围
+fn block_span(lbn: u64, start_off: u64, end_off: u64) -> (usize, usize) {
+    let block_start = lbn * BLOCK_SIZE as u64;
+    let from = start_off.max(block_start) - block_start;
+    let to = end_off.min(block_start + BLOCK_SIZE as u64) - block_start;
+    (from as usize, to as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::ext4_backend::api::*;
+    use crate::ext4_backend::file::*;
+    use crate::ext4_backend::loopfile::*;
+    use core::cell::RefCell;
+    use std::rc::Rc;
+
+    /// 记录每次 read 调用的内存设备
+    #[derive(Clone)]
+    struct CountingDev {
+        data: Rc<RefCell<Vec<u8>>>,
+        reads: Rc<RefCell<Vec<(u64, u32)>>>,
+    }
+
+    impl CountingDev {
+        fn new(blocks: usize) -> Self {
+            Self {
+                data: Rc::new(RefCell::new(alloc::vec![0u8; blocks * BLOCK_SIZE])),
+                reads: Rc::new(RefCell::new(Vec::new())),
+            }
+        }
+
+        fn take_reads(&self) -> Vec<(u64, u32)> {
+            core::mem::take(&mut *self.reads.borrow_mut())
+        }
+    }
+
+    impl BlockDevice for CountingDev {
+        fn write(&mut self, buffer: &[u8], block_id: u64, count: u32) -> BlockDevResult<()> {
+            let start = block_id as usize * BLOCK_SIZE;
+            let len = count as usize * BLOCK_SIZE;
+            self.data.borrow_mut()[start..start + len].copy_from_slice(&buffer[..len]);
+            Ok(())
+        }
+
+        fn read(&mut self, buffer: &mut [u8], block_id: u64, count: u32) -> BlockDevResult<()> {
+            let start = block_id as usize * BLOCK_SIZE;
+            let len = count as usize * BLOCK_SIZE;
+            buffer[..len].copy_from_slice(&self.data.borrow()[start..start + len]);
+            self.reads.borrow_mut().push((block_id, count));
+            Ok(())
+        }
+
+        fn open(&mut self) -> BlockDevResult<()> {
+            Ok(())
+        }
+
+        fn close(&mut self) -> BlockDevResult<()> {
+            Ok(())
+        }
+
+        fn total_blocks(&self) -> u64 {
+            (self.data.borrow().len() / BLOCK_SIZE) as u64
+        }
+    }
+
+    fn pattern(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i / BLOCK_SIZE + i) as u8).collect()
+    }
+
+    #[test]
+    fn test_readahead_window() {
+        let mut ra = Readahead::new();
+        // 第一次从头读开启预读
+        assert_eq!(ra.advance(0, 4096, 100), 1..5);
+        assert_eq!(ra.window(), READAHEAD_MIN_BLOCKS);
+        // 预读还剩一半以上时不再发起
+        assert_eq!(ra.advance(4096, 8192, 100), 0..0);
+        // 剩下不到半个窗口时发起下一批，窗口翻倍
+        assert_eq!(ra.advance(8192, 12288, 100), 5..11);
+        assert_eq!(ra.window(), 2 * READAHEAD_MIN_BLOCKS);
+        for i in 3..40 {
+            ra.advance(i * 4096, (i + 1) * 4096, 100);
+        }
+        assert_eq!(ra.window(), READAHEAD_MAX_BLOCKS);
+        // 不超出文件末尾
+        assert_eq!(ra.advance(40 * 4096, 90 * 4096, 100).end, 100);
+        // 随机访问关闭预读
+        assert_eq!(ra.advance(3 * 4096, 4 * 4096, 100), 0..0);
+        assert_eq!(ra.window(), 0);
+    }
+
+    #[test]
+    fn test_batched_and_sequential_reads() {
+        let storage = CountingDev::new(16 * 1024);
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, storage.clone(), false);
+        mkfs(&mut dev).unwrap();
+        let mut fs = mount(&mut dev).unwrap();
+        let data = pattern(256 * BLOCK_SIZE + 100);
+        mkfile(&mut dev, &mut fs, "/big", Some(&data), None);
+        mkfile(&mut dev, &mut fs, "/sparse", None, None);
+        write_file(&mut dev, &mut fs, "/sparse", 3 * BLOCK_SIZE as u64, b"tail").unwrap();
+        umount(fs, &mut dev).unwrap();
+
+        // 整个文件：连续段合并读取
+        let mut fs = mount(&mut dev).unwrap();
+        storage.take_reads();
+        assert_eq!(read_file(&mut dev, &mut fs, "/big").unwrap().unwrap(), data);
+        let reads = storage.take_reads();
+        assert!(reads.len() < 16, "{} reads", reads.len());
+        assert!(reads.iter().any(|&(_, count)| count as u64 == fs.datablock_cache.run_limit()));
+
+        // 空洞读出来是零
+        let sparse = read_file(&mut dev, &mut fs, "/sparse").unwrap().unwrap();
+        assert_eq!(sparse.len(), 3 * BLOCK_SIZE + 4);
+        assert!(sparse[..3 * BLOCK_SIZE].iter().all(|&b| b == 0));
+        assert_eq!(&sparse[3 * BLOCK_SIZE..], b"tail");
+        umount(fs, &mut dev).unwrap();
+
+        // 按 1KiB 顺序读：预读窗口增长到上限，设备读次数远少于块数
+        let mut fs = mount(&mut dev).unwrap();
+        let mut file = open(&mut dev, &mut fs, "/big", false).unwrap();
+        storage.take_reads();
+        let mut out = Vec::new();
+        loop {
+            let chunk = read_at(&mut dev, &mut fs, &mut file, 1024).unwrap();
+            if chunk.is_empty() {
+                break;
+            }
+            out.extend_from_slice(&chunk);
+        }
+        assert_eq!(out, data);
+        assert_eq!(file.ra.window(), READAHEAD_MAX_BLOCKS);
+        let data_reads = storage.take_reads().into_iter().filter(|&(_, count)| count > 1).count();
+        assert!(data_reads <= 20, "{data_reads} batched reads");
+
+        // 跳回开头属于随机访问
+        lseek(&mut file, 0);
+        read_at(&mut dev, &mut fs, &mut file, 10).unwrap();
+        lseek(&mut file, 100 * BLOCK_SIZE as u64);
+        assert_eq!(read_at(&mut dev, &mut fs, &mut file, 10).unwrap(), data[100 * BLOCK_SIZE..][..10]);
+        assert_eq!(file.ra.window(), 0);
+        umount(fs, &mut dev).unwrap();
+    }
+
+    #[test]
+    fn test_direct_read_into_and_write_from() {
+        let storage = CountingDev::new(16 * 1024);
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, storage.clone(), false);
+        mkfs(&mut dev).unwrap();
+        let mut fs = mount(&mut dev).unwrap();
+
+        // 首尾不足一块，中间 9 个完整块直接写到设备
+        let data = pattern(10 * BLOCK_SIZE);
+        let mut file = open(&mut dev, &mut fs, "/d", true).unwrap();
+        lseek(&mut file, 100);
+        assert_eq!(write_from(&mut dev, &mut fs, &mut file, &data).unwrap(), data.len());
+        assert_eq!(file.offset, 100 + data.len() as u64);
+        let map = resolve_inode_block_allextend(&mut fs, &mut dev, &mut file.inode).unwrap();
+        assert!(fs.datablock_cache.get(map[&0]).is_some());
+        assert!((1..10).all(|lbn| fs.datablock_cache.get(map[&lbn]).is_none()));
+        assert!(fs.datablock_cache.get(map[&10]).is_some());
+
+        // 缓存里的脏块比设备上新，直接读时以缓存为准
+        write_file(&mut dev, &mut fs, "/d", 2 * BLOCK_SIZE as u64, b"dirty").unwrap();
+        let mut buf = alloc::vec![0u8; 3 * BLOCK_SIZE];
+        lseek(&mut file, BLOCK_SIZE as u64);
+        assert_eq!(read_into(&mut dev, &mut fs, &mut file, &mut buf).unwrap(), buf.len());
+        assert_eq!(&buf[BLOCK_SIZE..BLOCK_SIZE + 5], b"dirty");
+        assert_eq!(buf[..BLOCK_SIZE], data[BLOCK_SIZE - 100..][..BLOCK_SIZE]);
+
+        // 直接写覆盖缓存里的脏块：旧内容不会在写回时盖掉新数据
+        let block = alloc::vec![0xeeu8; BLOCK_SIZE];
+        lseek(&mut file, 2 * BLOCK_SIZE as u64);
+        write_from(&mut dev, &mut fs, &mut file, &block).unwrap();
+        umount(fs, &mut dev).unwrap();
+
+        let mut expected = alloc::vec![0u8; 100];
+        expected.extend_from_slice(&data);
+        expected[2 * BLOCK_SIZE..3 * BLOCK_SIZE].fill(0xee);
+        let mut fs = mount(&mut dev).unwrap();
+        assert_eq!(read_file(&mut dev, &mut fs, "/d").unwrap().unwrap(), expected);
+        umount(fs, &mut dev).unwrap();
+
+        // 对齐的读：一次 read_blocks 读完且不进缓存；读到文件末尾为止
+        let mut fs = mount(&mut dev).unwrap();
+        let mut file = open(&mut dev, &mut fs, "/d", false).unwrap();
+        let cached_before = fs.datablock_cache.stats().total_entries;
+        storage.take_reads();
+        let mut buf = alloc::vec![0u8; 8 * BLOCK_SIZE];
+        assert_eq!(read_into(&mut dev, &mut fs, &mut file, &mut buf).unwrap(), buf.len());
+        assert_eq!(buf, expected[..8 * BLOCK_SIZE]);
+        assert!(storage.take_reads().iter().any(|&(_, count)| count == 8));
+        assert_eq!(fs.datablock_cache.stats().total_entries, cached_before);
+
+        let mut rest = alloc::vec![0u8; 8 * BLOCK_SIZE];
+        let n = read_into(&mut dev, &mut fs, &mut file, &mut rest).unwrap();
+        assert_eq!(n, expected.len() - 8 * BLOCK_SIZE);
+        assert_eq!(rest[..n], expected[8 * BLOCK_SIZE..]);
+        assert_eq!(read_into(&mut dev, &mut fs, &mut file, &mut rest).unwrap(), 0);
+        umount(fs, &mut dev).unwrap();
+    }
+}
@@ -0,0 +1,340 @@
+//! 多核共享的文件系统句柄
+//!
+//! `SharedExt4` 是 `Send + Sync` 的，可以放进 `Arc` 在多个核之间共享，不需要外面再包一把全局锁：
+//! - 按 inode 号分片的读写锁：同一文件的读可以并行，写与读、写互斥；
+//! - 按块号分片的只读数据块缓存：文件读命中时只拿对应分片的锁，不进入引擎；
+//! - 引擎锁：`Ext4FileSystem` 和 `Jbd2Dev` 仍然是单线程实现，元数据、块分配、日志和写回缓存
+//!   都在这一把锁后面，没有按块组拆分的分配器锁，也没有单独的日志锁。
+//!
+//! 因此 open、write_at、sync 和读缓存未命中会在整个操作期间持有引擎锁，
+//! 不同文件的写入也是串行的，只有命中分片缓存的读可以并行。
+//!
+//! 锁顺序固定为 inode 分片锁 -> 引擎锁 -> 数据块分片锁，`with_fs` 按分片下标依次拿全部 inode 锁
+//!
+//! TODO: 按块组拆分的分配器锁和单独的日志锁还没有实现，需要先把 `Ext4FileSystem` 里的分配状态、
+//! 缓存和 `Jbd2Dev` 的事务拆成可以分别加锁的部分，不同文件的写入才能并行
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use spin::{Mutex, RwLock};
+
+use crate::ext4_backend::api::*;
+use crate::ext4_backend::blockdev::*;
+use crate::ext4_backend::buffer_cache::*;
+use crate::ext4_backend::config::*;
+use crate::ext4_backend::error::*;
+use crate::ext4_backend::ext4::*;
+use crate::ext4_backend::extent_status::*;
+use crate::ext4_backend::file::*;
+
+/// 单线程的文件系统引擎
+pub struct Engine<B: BlockDevice> {
+    pub fs: Ext4FileSystem,
+    pub dev: Jbd2Dev<B>,
+}
+
+/// 一个数据块缓存分片
+struct BlockShard {
+    blocks: LruCache<u64, Arc<[u8]>>,
+    max_entries: usize,
+}
+
+/// 多核共享的文件系统句柄
+pub struct SharedExt4<B: BlockDevice> {
+    engine: Mutex<Engine<B>>,
+    inode_locks: Vec<RwLock<()>>,
+    block_shards: Vec<Mutex<BlockShard>>,
+}
+
+impl<B: BlockDevice> SharedExt4<B> {
+    /// 包装已经挂载的文件系统，共享数据块缓存的大小取默认配置
+    pub fn new(fs: Ext4FileSystem, dev: Jbd2Dev<B>) -> Self {
+        Self::with_cache_bytes(fs, dev, CacheConfig::default().data_bytes)
+    }
+
+    /// 包装已经挂载的文件系统，共享数据块缓存总共 cache_bytes 字节
+    pub fn with_cache_bytes(fs: Ext4FileSystem, dev: Jbd2Dev<B>, cache_bytes: usize) -> Self {
+        let per_shard = entries_for_budget(cache_bytes / SHARED_BLOCK_SHARDS, BLOCK_SIZE);
+        Self {
+            engine: Mutex::new(Engine { fs, dev }),
+            inode_locks: (0..SHARED_INODE_SHARDS).map(|_| RwLock::new(())).collect(),
+            block_shards: (0..SHARED_BLOCK_SHARDS)
+                .map(|_| {
+                    Mutex::new(BlockShard {
+                        blocks: LruCache::new(),
+                        max_entries: per_shard,
+                    })
+                })
+                .collect(),
+        }
+    }
+
+    /// 挂载并包装成共享句柄
+    pub fn mount(mut dev: Jbd2Dev<B>, cache: &CacheConfig) -> BlockDevResult<Self> {
+        let fs = mount_with_config(&mut dev, cache)?;
+        Ok(Self::with_cache_bytes(fs, dev, cache.data_bytes))
+    }
+
+    /// 卸载，返回块设备
+    pub fn umount(self) -> BlockDevResult<Jbd2Dev<B>> {
+        let Engine { fs, mut dev } = self.engine.into_inner();
+        umount(fs, &mut dev)?;
+        Ok(dev)
+    }
+
+    /// 独占访问引擎，期间没有其他读写在进行；
+    /// 闭包里可能释放或重新分配数据块，结束后清空共享数据块缓存
+    pub fn with_fs<R>(&self, f: impl FnOnce(&mut Ext4FileSystem, &mut Jbd2Dev<B>) -> R) -> R {
+        let _inodes: Vec<_> = self.inode_locks.iter().map(|l| l.write()).collect();
+        let mut engine = self.engine.lock();
+        let Engine { fs, dev } = &mut *engine;
+        let ret = f(fs, dev);
+        for shard in &self.block_shards {
+            shard.lock().blocks.clear();
+        }
+        ret
+    }
+
+    /// 打开文件：可选自动创建（创建不会释放数据块，不需要独占）
+    pub fn open(&self, path: &str, create: bool) -> BlockDevResult<OpenFile> {
+        let mut engine = self.engine.lock();
+        let Engine { fs, dev } = &mut *engine;
+        open(dev, fs, path, create)
+    }
+
+    /// 从当前 offset 读满 buf，返回读到的字节数
+    pub fn read_at(&self, file: &mut OpenFile, buf: &mut [u8]) -> BlockDevResult<usize> {
+        let _inode = self.inode_lock(file.ino).read();
+        let block_bytes = BLOCK_SIZE as u64;
+
+        // 只在引擎里查映射，数据块从分片缓存取
+        let start_off = file.offset;
+        let (end_off, runs) = {
+            let mut engine = self.engine.lock();
+            let Engine { fs, dev } = &mut *engine;
+            file.inode = fs.get_inode_by_num(dev, file.ino)?;
+            let end_off = start_off
+                .saturating_add(buf.len() as u64)
+                .min(file.inode.size());
+            if start_off >= end_off {
+                return Ok(0);
+            }
+            let end_lbn = end_off.div_ceil(block_bytes);
+            let mut runs = Vec::new();
+            let mut lbn = start_off / block_bytes;
+            while lbn < end_lbn {
+                let (phys, len) = map_blocks(fs, dev, file.ino, &file.inode, lbn as u32)?;
+                let n = (len as u64).min(end_lbn - lbn);
+                runs.push((lbn, phys, n));
+                lbn += n;
+            }
+            (end_off, runs)
+        };
+
+        for (lbn, phys, n) in runs {
+            for i in 0..n {
+                let block_start = (lbn + i) * block_bytes;
+                let from = start_off.max(block_start);
+                let to = end_off.min(block_start + block_bytes);
+                let dst = &mut buf[(from - start_off) as usize..(to - start_off) as usize];
+                let src = (from - block_start) as usize..(to - block_start) as usize;
+                match phys {
+                    Some(phys) => {
+                        let data = self.block(phys + i, n - i)?;
+                        dst.copy_from_slice(&data[src]);
+                    }
+                    None => dst.fill(0),
+                }
+            }
+        }
+
+        let read = (end_off - start_off) as usize;
+        file.offset = end_off;
+        Ok(read)
+    }
+
+    /// 写到当前 offset，返回写入的字节数
+    pub fn write_at(&self, file: &mut OpenFile, data: &[u8]) -> BlockDevResult<usize> {
+        if data.is_empty() {
+            return Ok(0);
+        }
+        let _inode = self.inode_lock(file.ino).write();
+        let mut engine = self.engine.lock();
+        let Engine { fs, dev } = &mut *engine;
+        write_file_with_ino(dev, fs, file.ino, file.offset, data)?;
+        file.inode = fs.get_inode_by_num(dev, file.ino)?;
+
+        // 写过的物理块在分片缓存里的旧内容作废
+        let block_bytes = BLOCK_SIZE as u64;
+        let end_off = file.offset + data.len() as u64;
+        let mut lbn = file.offset / block_bytes;
+        while lbn < end_off.div_ceil(block_bytes) {
+            let (phys, len) = map_blocks(fs, dev, file.ino, &file.inode, lbn as u32)?;
+            let n = (len as u64).min(end_off.div_ceil(block_bytes) - lbn);
+            if let Some(phys) = phys {
+                for blk in phys..phys + n {
+                    self.shard(blk).lock().blocks.remove(&blk);
+                }
+            }
+            lbn += n;
+        }
+
+        file.offset = end_off;
+        Ok(data.len())
+    }
+
+    /// 持久化单个文件
+    pub fn sync_file(&self, file: &OpenFile) -> BlockDevResult<()> {
+        let _inode = self.inode_lock(file.ino).read();
+        let mut engine = self.engine.lock();
+        let Engine { fs, dev } = &mut *engine;
+        fs.sync_file(dev, file.ino)
+    }
+
+    /// 写回所有缓存并提交日志
+    pub fn sync_fs(&self) -> BlockDevResult<()> {
+        let mut engine = self.engine.lock();
+        let Engine { fs, dev } = &mut *engine;
+        fs.sync_fs(dev)
+    }
+
+    fn inode_lock(&self, ino: u32) -> &RwLock<()> {
+        &self.inode_locks[ino as usize % self.inode_locks.len()]
+    }
+
+    fn shard(&self, block: u64) -> &Mutex<BlockShard> {
+        &self.block_shards[block as usize % self.block_shards.len()]
+    }
+
+    /// 取一个数据块：分片缓存命中时不进入引擎，未命中时把从它开始最多 run 个连续块一起读进来
+    fn block(&self, block: u64, run: u64) -> BlockDevResult<Arc<[u8]>> {
+        if let Some(data) = self.shard(block).lock().blocks.get_mut(&block) {
+            return Ok(data.clone());
+        }
+
+        let mut engine = self.engine.lock();
+        let Engine { fs, dev } = &mut *engine;
+        fs.datablock_cache.load_run(dev, block, run)?;
+        let mut first = None;
+        for blk in block..block + run.min(fs.datablock_cache.run_limit()) {
+            let Some(cached) = fs.datablock_cache.get(blk) else {
+                break;
+            };
+            let data: Arc<[u8]> = Arc::from(&cached.data[..BLOCK_SIZE]);
+            first.get_or_insert_with(|| data.clone());
+            let mut shard = self.shard(blk).lock();
+            if !shard.blocks.contains_key(&blk)
+                && shard.blocks.len() >= shard.max_entries
+                && let Some(lru) = shard.blocks.lru_key()
+            {
+                shard.blocks.remove(&lru);
+            }
+            shard.blocks.insert(blk, data);
+        }
+        match first {
+            Some(data) => Ok(data),
+            None => {
+                let cached = fs.datablock_cache.get_or_load(dev, block)?;
+                Ok(Arc::from(&cached.data[..BLOCK_SIZE]))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::ext4_backend::dir::*;
+    use alloc::vec;
+    use crate::ext4_backend::memdev::MemDev;
+    use std::thread;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    fn pattern(seed: u8, len: usize) -> Vec<u8> {
+        (0..len).map(|i| seed.wrapping_add((i / 7) as u8)).collect()
+    }
+
+    #[test]
+    fn test_concurrent_readers_and_writers() {
+        assert_send_sync::<SharedExt4<MemDev>>();
+
+        let mut dev = Jbd2Dev::initial_jbd2dev(
+            0,
+            MemDev::new(16 * 1024),
+            false,
+        );
+        mkfs(&mut dev).unwrap();
+        // 共享缓存故意开小，读的过程中会不断淘汰和重新载入
+        let shared =
+            Arc::new(SharedExt4::mount(dev, &CacheConfig::with_budget(256 * BLOCK_SIZE)).unwrap());
+
+        let common = pattern(1, 40 * BLOCK_SIZE + 123);
+        let mut f = shared.open("/common", true).unwrap();
+        shared.write_at(&mut f, &common).unwrap();
+
+        let mut handles = Vec::new();
+        // 多个核同时读同一个文件
+        for t in 0..4usize {
+            let shared = shared.clone();
+            let common = common.clone();
+            handles.push(thread::spawn(move || {
+                let mut f = shared.open("/common", false).unwrap();
+                for round in 0..8 {
+                    f.offset = ((t * 3 + round) * 1000) as u64;
+                    let mut buf = vec![0u8; 3 * BLOCK_SIZE + 17];
+                    let n = shared.read_at(&mut f, &mut buf).unwrap();
+                    let start = ((t * 3 + round) * 1000).min(common.len());
+                    assert_eq!(&buf[..n], &common[start..start + n]);
+                }
+            }));
+        }
+        // 同时写各自的文件，并反复读回
+        for t in 0..4u8 {
+            let shared = shared.clone();
+            handles.push(thread::spawn(move || {
+                let path = alloc::format!("/w{t}");
+                let mut f = shared.open(&path, true).unwrap();
+                let data = pattern(t * 50, 5 * BLOCK_SIZE + 9);
+                for chunk in data.chunks(BLOCK_SIZE + 1) {
+                    shared.write_at(&mut f, chunk).unwrap();
+                }
+                // 覆盖写之后读到的必须是新内容，不能是分片缓存里的旧块
+                f.offset = BLOCK_SIZE as u64;
+                shared.write_at(&mut f, &[0xee; 100]).unwrap();
+                f.offset = 0;
+                let mut buf = vec![0u8; data.len()];
+                assert_eq!(shared.read_at(&mut f, &mut buf).unwrap(), data.len());
+                assert_eq!(&buf[..BLOCK_SIZE], &data[..BLOCK_SIZE]);
+                assert!(buf[BLOCK_SIZE..BLOCK_SIZE + 100].iter().all(|&b| b == 0xee));
+                assert_eq!(&buf[BLOCK_SIZE + 100..], &data[BLOCK_SIZE + 100..]);
+                shared.sync_file(&f).unwrap();
+            }));
+        }
+        for h in handles {
+            h.join().unwrap();
+        }
+
+        // 独占操作之后共享缓存清空，读到的仍然正确
+        assert!(shared.with_fs(|fs, dev| mkdir(dev, fs, "/after").is_some()));
+        let mut f = shared.open("/common", false).unwrap();
+        let mut buf = vec![0u8; common.len() + 10];
+        assert_eq!(shared.read_at(&mut f, &mut buf).unwrap(), common.len());
+        assert_eq!(&buf[..common.len()], &common[..]);
+        shared.sync_fs().unwrap();
+
+        let shared = Arc::try_unwrap(shared).ok().unwrap();
+        let mut dev = shared.umount().unwrap();
+        let mut fs = mount(&mut dev).unwrap();
+        for t in 0..4u8 {
+            let path = alloc::format!("/w{t}");
+            let content = crate::ext4_backend::file::read_file(&mut dev, &mut fs, &path)
+                .unwrap()
+                .unwrap();
+            assert_eq!(content.len(), 5 * BLOCK_SIZE + 9);
+        }
+        umount(fs, &mut dev).unwrap();
+    }
+}
@@ -1,5 +1,6 @@
 use crate::ext4_backend::config::*;
 use crate::ext4_backend::endian::*;
+use crate::ext4_backend::fast_commit::crc32c;
 use crate::ext4_backend::jbd2::jbdstruct::*;
 ///UUID
 pub struct UUID(pub [u32; 4]);
@@ -366,6 +367,40 @@ impl Ext4Superblock {
     pub fn has_journal(&self) -> bool {
         self.has_feature_compat(Self::EXT4_FEATURE_COMPAT_HAS_JOURNAL)
     }
+
+    /// 日志是否位于外部日志设备（不使用 journal inode，按 s_journal_uuid 匹配设备）
+    pub fn has_external_journal(&self) -> bool {
+        self.has_journal() && self.s_journal_inum == 0 && self.s_journal_uuid != [0; 16]
+    }
+
+    /// 是否启用了 fast commit 特性（依赖 journal）
+    pub fn has_fast_commit(&self) -> bool {
+        self.has_journal() && self.has_feature_compat(Self::EXT4_FEATURE_COMPAT_FAST_COMMIT)
+    }
+
+    /// 目录项是否记录 file_type（filetype 特性）
+    pub fn has_filetype(&self) -> bool {
+        self.has_feature_incompat(Self::EXT4_FEATURE_INCOMPAT_FILETYPE)
+    }
+
+    /// 是否启用了 casefold 特性（目录可以按大小写不敏感的方式比较名字）
+    pub fn has_casefold(&self) -> bool {
+        self.has_feature_incompat(Self::EXT4_FEATURE_INCOMPAT_CASEFOLD)
+    }
+
+    /// 是否启用了 metadata_csum 特性
+    pub fn has_metadata_csum(&self) -> bool {
+        self.has_feature_ro_compat(Self::EXT4_FEATURE_RO_COMPAT_METADATA_CSUM)
+    }
+
+    /// 元数据校验和的文件系统级种子：csum_seed 特性下取 s_checksum_seed，否则由 UUID 计算
+    pub fn csum_seed(&self) -> u32 {
+        if self.has_feature_incompat(Self::EXT4_FEATURE_INCOMPAT_CSUM_SEED) {
+            self.s_checksum_seed
+        } else {
+            crc32c(!0, &self.s_uuid)
+        }
+    }
 }
 
 // 文件系统状态常量
@@ -382,6 +417,24 @@ impl Ext4Superblock {
     pub const EXT4_ERRORS_PANIC: u16 = 3; // 内核恐慌
 }
 
+// 日志备份类型常量
+impl Ext4Superblock {
+    pub const EXT3_JNL_BACKUP_BLOCKS: u8 = 1; // s_jnl_blocks 保存 journal inode 的 i_block 和大小
+}
+
+// 杂项标志常量（s_flags）
+impl Ext4Superblock {
+    pub const EXT2_FLAGS_SIGNED_HASH: u32 = 0x0001; // 目录哈希按有符号 char 计算
+    pub const EXT2_FLAGS_UNSIGNED_HASH: u32 = 0x0002; // 目录哈希按无符号 char 计算
+    pub const EXT2_FLAGS_TEST_FILESYS: u32 = 0x0004; // 测试用文件系统
+}
+
+// 文件名编码常量（s_encoding / s_encoding_flags）
+impl Ext4Superblock {
+    pub const EXT4_ENC_UTF8_12_1: u16 = 1; // UTF-8，Unicode 12.1
+    pub const EXT4_ENC_STRICT_MODE_FL: u16 = 0x0001; // 拒绝不合法的 UTF-8 名字
+}
+
 // 创建者操作系统常量
 impl Ext4Superblock {
     pub const EXT4_OS_LINUX: u32 = 0;
@@ -431,6 +484,7 @@ impl Ext4Superblock {
     pub const EXT4_FEATURE_INCOMPAT_LARGEDIR: u32 = 0x4000;
     pub const EXT4_FEATURE_INCOMPAT_INLINE_DATA: u32 = 0x8000;
     pub const EXT4_FEATURE_INCOMPAT_ENCRYPT: u32 = 0x10000;
+    pub const EXT4_FEATURE_INCOMPAT_CASEFOLD: u32 = 0x20000;
 }
 
 // 只读兼容特性标志
@@ -103,7 +103,7 @@ pub fn cloc_group_layout(
     }
 
     // 普通块组从其起始块开始布置
-    let group_start = gid * blocks_per_group;
+    let group_start = gid as u64 * blocks_per_group as u64;
 
     // 是否启用 sparse super
     let sparse_feature =
@@ -113,7 +113,7 @@ pub fn cloc_group_layout(
     let has_backup = sparse_feature && need_redundant_backup(gid);
 
     let (block_bitmap, inode_bitmap, inode_table, meta_blocks) = if has_backup {
-        let bb = group_start + 1 + gdt_blocks;
+        let bb = group_start + 1 + gdt_blocks as u64;
         let ib = bb + 1;
         let it = ib + 1;
         let meta = 1 + gdt_blocks + 1 + 1 + inode_table_blocks;
@@ -127,10 +127,10 @@ pub fn cloc_group_layout(
     };
 
     BlcokGroupLayout {
-        group_start_block: group_start as u64,
-        group_blcok_bitmap_startblocks: block_bitmap as u64,
-        group_inode_bitmap_startblocks: inode_bitmap as u64,
-        group_inode_table_startblocks: inode_table as u64,
+        group_start_block: group_start,
+        group_blcok_bitmap_startblocks: block_bitmap,
+        group_inode_bitmap_startblocks: inode_bitmap,
+        group_inode_table_startblocks: inode_table,
         metadata_blocks_in_group: meta_blocks,
     }
 }
@@ -7,9 +7,13 @@
 extern crate alloc;
 pub mod ext4_backend;
 pub use ext4_backend::api::*;
+pub use ext4_backend::async_api::{AsyncExt4, AsyncJbd2Dev};
+pub use ext4_backend::async_dev::{AsyncBlockDevice, AsyncStaging, SyncAdapter};
 pub use ext4_backend::blockdev::*;
+pub use ext4_backend::buffer_cache::CacheConfig;
 pub use ext4_backend::config::*;
 pub use ext4_backend::dir::*;
 pub use ext4_backend::ext4::*;
 pub use ext4_backend::file::*;
+pub use ext4_backend::shared::SharedExt4;
 pub use ext4_backend::error::*;
@@ -77,7 +77,7 @@ impl FileBlockDev {
 }
 
 impl BlockDevice for FileBlockDev {
-    fn write(&mut self, buffer: &[u8], block_id: u32, count: u32) -> BlockDevResult<()> {
+    fn write(&mut self, buffer: &[u8], block_id: u64, count: u32) -> BlockDevResult<()> {
         let block_size = self.block_size() as usize;
         let required = block_size * count as usize;
         if buffer.len() < required {
@@ -100,7 +100,7 @@ impl BlockDevice for FileBlockDev {
         Ok(())
     }
 
-    fn read(&mut self, buffer: &mut [u8], block_id: u32, count: u32) -> BlockDevResult<()> {
+    fn read(&mut self, buffer: &mut [u8], block_id: u64, count: u32) -> BlockDevResult<()> {
         let block_size = self.block_size() as usize;
         let required = block_size * count as usize;
         if buffer.len() < required {